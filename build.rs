@@ -1,13 +1,17 @@
+#[cfg(feature = "regenerate-bindings")]
 extern crate bindgen;
 
-use std::env;
-use std::path::PathBuf;
-
+#[cfg(feature = "regenerate-bindings")]
 fn main() {
+    use std::env;
+    use std::path::PathBuf;
+
     let bindings = bindgen::Builder::default()
         .header_contents("ffi.h", "#include <ucontext.h>")
         .whitelisted_function("getcontext")
         .whitelisted_var("REG_.*")
+        // NetBSD's names for the `gregs` indices.
+        .whitelisted_var("_REG_.*")
         .clang_arg("-D_XOPEN_SOURCE")
         .generate()
         .expect("Should generate FFI bindings OK");
@@ -17,3 +21,8 @@ fn main() {
         .write_to_file(out_path.join("ffi.rs"))
         .expect("Should write ffi.rs OK");
 }
+
+// By default, the FFI bindings come from the `libc` crate, and there is nothing
+// to generate. See `src/ffi.rs`.
+#[cfg(not(feature = "regenerate-bindings"))]
+fn main() {}
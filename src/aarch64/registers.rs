@@ -0,0 +1,315 @@
+//! Architecture specific concerns for AArch64 registers.
+
+use super::{Error, MemoryReader, Registers, Result, TaggedWord, TargetEndianBuf};
+use expression;
+#[cfg(feature = "live")]
+use ffi;
+use gimli;
+#[cfg(all(feature = "live", not(feature = "inline-asm")))]
+use std::io;
+#[cfg(all(feature = "live", not(feature = "inline-asm")))]
+use std::mem;
+
+// From the DWARF for the ARM 64-bit Architecture (AArch64), section 3.1 DWARF
+// register names:
+//
+// > ...
+// > 29       X29      (frame pointer)
+// > 30       X30      (link register)
+// > 31       SP
+// > ...
+//
+// The CIE's return address column is X30, so the value recovered for the link
+// register is the caller's program counter.
+pub(crate) const BP: u8 = 29;
+pub(crate) const LR: u8 = 30;
+pub(crate) const SP: u8 = 31;
+pub(crate) const IP: u8 = LR;
+
+/// The bits that may hold an ARMv8.3 pointer authentication code in a signed
+/// return address. User-space addresses have at most 48 significant bits.
+pub(crate) const POINTER_AUTH_MASK: usize = 0xffff_0000_0000_0000;
+
+// A signal handler returns into the trampoline with the stack pointer at the
+// kernel's `struct rt_sigframe`: a 128 byte `siginfo_t`, then a `struct
+// ucontext` whose `uc_mcontext` is 176 bytes in. This is the offset of that
+// `struct sigcontext`'s `regs`, which are followed by `sp` and `pc`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const SIGFRAME_REGS: usize = 128 + 176 + 8;
+
+/// The registers needed to unwind a frame on AArch64.
+#[derive(Clone, Debug)]
+pub struct FrameRegisters {
+    /// The `x29` frame pointer register for this frame.
+    fp: TaggedWord,
+
+    /// The `sp` stack pointer register for this frame.
+    sp: TaggedWord,
+
+    /// The `x30` link register for this frame. This is only known for the
+    /// youngest frame, and is needed to unwind out of leaf functions that
+    /// never spill it to the stack.
+    lr: TaggedWord,
+
+    /// The program counter for this frame.
+    pc: TaggedWord,
+}
+
+impl FrameRegisters {
+    /// The name Breakpad symbol files use for the frame base register.
+    pub(crate) const BREAKPAD_BP: &'static str = "x29";
+
+    /// Construct a register set from its individual registers.
+    pub(crate) fn from_parts(bp: TaggedWord, sp: TaggedWord, ip: TaggedWord) -> FrameRegisters {
+        FrameRegisters {
+            fp: bp,
+            sp,
+            lr: TaggedWord::invalid(),
+            pc: ip,
+        }
+    }
+
+    /// Get the register with the given Breakpad name, e.g. `sp`.
+    pub(crate) fn breakpad_register(&self, name: &str) -> TaggedWord {
+        match name {
+            "x29" | "fp" => self.fp,
+            "x30" | "lr" => self.lr,
+            "sp" => self.sp,
+            "pc" => self.pc,
+            _ => TaggedWord::invalid(),
+        }
+    }
+
+    /// Construct a register set from a `ucontext_t`, such as the one the
+    /// kernel passes to an `SA_SIGINFO` signal handler.
+    ///
+    /// This is unsafe because `ctx` must point to a valid `ucontext_t`.
+    #[cfg(feature = "live")]
+    pub unsafe fn from_ucontext(ctx: *const ffi::ucontext_t) -> FrameRegisters {
+        #[cfg(any(target_os = "macos", target_os = "ios"))]
+        let (fp, sp, lr, pc) = {
+            let mcontext = (*ctx).uc_mcontext;
+            assert!(!mcontext.is_null());
+            let ss = &(*mcontext).__ss;
+            (ss.__fp, ss.__sp, ss.__lr, ss.__pc)
+        };
+
+        #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+        let (fp, sp, lr, pc) = {
+            let mcontext = &(*ctx).uc_mcontext;
+            (
+                mcontext.regs[BP as usize],
+                mcontext.sp,
+                mcontext.regs[LR as usize],
+                mcontext.pc,
+            )
+        };
+
+        FrameRegisters {
+            fp: TaggedWord::valid(fp as usize),
+            sp: TaggedWord::valid(sp as usize),
+            lr: TaggedWord::valid(lr as usize),
+            pc: TaggedWord::valid(pc as usize),
+        }
+    }
+
+    /// Set the link register of the youngest frame.
+    #[cfg(any(test, all(feature = "live", target_os = "linux")))]
+    pub(crate) fn set_lr(&mut self, lr: TaggedWord) {
+        self.lr = lr;
+    }
+
+    /// Construct a register set from a suspended mach thread's state,
+    /// fetched with `thread_get_state`.
+    #[cfg(all(feature = "live", target_os = "macos"))]
+    pub(crate) fn from_thread_state(state: &ffi::thread_state_t) -> FrameRegisters {
+        FrameRegisters {
+            fp: TaggedWord::valid(state.__fp as usize),
+            sp: TaggedWord::valid(state.__sp as usize),
+            lr: TaggedWord::valid(state.__lr as usize),
+            pc: TaggedWord::valid(state.__pc as usize),
+        }
+    }
+
+    pub(crate) fn get_register(&self, register_num: u8) -> Result<TaggedWord> {
+        match register_num {
+            r if r == BP => Ok(self.fp),
+            r if r == LR => Ok(self.lr),
+            r if r == SP => Ok(self.sp),
+            otherwise => Err(Error::UnknownRegister(otherwise)),
+        }
+    }
+
+    /// Set a register in a caller's register set. The value recovered for the
+    /// link register is the caller's return address, so it becomes the
+    /// caller's program counter.
+    pub(crate) fn set_register(&mut self, register_num: u8, value: TaggedWord) -> Result<()> {
+        match register_num {
+            r if r == BP => self.fp = value,
+            r if r == LR => self.pc = value,
+            r if r == SP => self.sp = value,
+            otherwise => return Err(Error::UnknownRegister(otherwise)),
+        }
+        Ok(())
+    }
+
+    unsafe fn eval_register_rule<R>(
+        &self,
+        register: u8,
+        rule: gimli::RegisterRule<TargetEndianBuf>,
+        cfa: usize,
+        scratch: &mut expression::Scratch,
+        reader: &R,
+    ) -> TaggedWord
+    where
+        R: MemoryReader,
+    {
+        match rule {
+            gimli::RegisterRule::Undefined |
+            gimli::RegisterRule::Architectural => TaggedWord::invalid(),
+
+            gimli::RegisterRule::SameValue => self.get_register(register).unwrap_or_default(),
+
+            gimli::RegisterRule::Offset(offset) => reader.read_offset(cfa, offset as isize).into(),
+
+            gimli::RegisterRule::ValOffset(offset) => {
+                TaggedWord::valid((cfa as isize).wrapping_add(offset as isize) as usize)
+            }
+
+            gimli::RegisterRule::Register(r) => self.get_register(r).unwrap_or_default(),
+
+            gimli::RegisterRule::Expression(expr) => {
+                expression::evaluate(expr.buf(), self, Some(cfa), scratch, reader)
+                    .and_then(|addr| reader.read(addr))
+                    .into()
+            }
+            gimli::RegisterRule::ValExpression(expr) => {
+                expression::evaluate(expr.buf(), self, Some(cfa), scratch, reader).into()
+            }
+        }
+    }
+}
+
+impl Registers for FrameRegisters {
+    unsafe fn from_unwind_table_row<R>(
+        row: &gimli::UnwindTableRow<TargetEndianBuf>,
+        return_address_register: u8,
+        scratch: &mut expression::Scratch,
+        old_registers: &FrameRegisters,
+        reader: &R,
+    ) -> Result<Self>
+    where
+        R: MemoryReader,
+    {
+        let cfa = match *row.cfa() {
+            gimli::CfaRule::RegisterAndOffset { register, offset } => {
+                let tagged_word = old_registers.get_register(register)?;
+                let word: Result<_> = tagged_word.into();
+                (word? as isize).wrapping_add(offset as isize) as usize
+            }
+            gimli::CfaRule::Expression(ref expr) => {
+                expression::evaluate(expr.buf(), old_registers, None, scratch, reader)?
+            }
+        };
+
+        // Functions that never touch the frame pointer have no rule for it,
+        // and it is callee-saved, so it still holds the caller's value.
+        let fp = match row.register(BP) {
+            gimli::RegisterRule::Undefined => old_registers.fp,
+            rule => old_registers.eval_register_rule(BP, rule, cfa, scratch, reader),
+        };
+        // Leaf functions have no rule for the return address, which is still
+        // in the link register.
+        let pc = match row.register(return_address_register) {
+            gimli::RegisterRule::Undefined => old_registers
+                .get_register(return_address_register)
+                .unwrap_or_default(),
+            rule => old_registers.eval_register_rule(
+                return_address_register,
+                rule,
+                cfa,
+                scratch,
+                reader,
+            ),
+        };
+
+        Ok(FrameRegisters {
+            fp,
+            // The CFA is the value of the stack pointer at the call site.
+            sp: TaggedWord::valid(cfa),
+            // The caller's link register was clobbered by the call, and
+            // cannot be recovered.
+            lr: TaggedWord::invalid(),
+            pc,
+        })
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    unsafe fn from_sigframe<R>(registers: &FrameRegisters, reader: &R) -> Option<Result<Self>>
+    where
+        R: MemoryReader,
+    {
+        let sp: Result<usize> = registers.sp.into();
+        Some(sp.and_then(|sp| {
+            let regs = sp + SIGFRAME_REGS;
+            let register = |index: usize| reader.read(regs + index * 8).map(TaggedWord::valid);
+            Ok(FrameRegisters {
+                fp: register(BP as usize)?,
+                sp: register(31)?,
+                // Unlike a caller's, the interrupted frame's link register
+                // is saved, which matters if it is a leaf function.
+                lr: register(LR as usize)?,
+                pc: register(32)?,
+            })
+        }))
+    }
+
+    #[cfg(feature = "inline-asm")]
+    fn with_current<F, T>(mut f: F) -> Result<T>
+    where
+        F: FnMut(&Self) -> Result<T>,
+    {
+        let (fp, sp, lr, pc): (usize, usize, usize, usize);
+        unsafe {
+            ::std::arch::asm!(
+                "mov {fp}, x29",
+                "mov {sp}, sp",
+                "mov {lr}, x30",
+                "adr {pc}, .",
+                fp = out(reg) fp,
+                sp = out(reg) sp,
+                lr = out(reg) lr,
+                pc = out(reg) pc,
+                options(nomem, nostack, preserves_flags),
+            );
+        }
+
+        f(&FrameRegisters {
+            fp: TaggedWord::valid(fp),
+            sp: TaggedWord::valid(sp),
+            lr: TaggedWord::valid(lr),
+            pc: TaggedWord::valid(pc),
+        })
+    }
+
+    #[cfg(all(feature = "live", not(feature = "inline-asm")))]
+    fn with_current<F, T>(mut f: F) -> Result<T>
+    where
+        F: FnMut(&Self) -> Result<T>,
+    {
+        unsafe {
+            let mut context: ffi::ucontext_t = mem::zeroed();
+
+            let r = ffi::getcontext(&mut context);
+            if r != 0 {
+                return Err(Error::Io(io::Error::last_os_error()));
+            }
+
+            f(&FrameRegisters::from_ucontext(&context))
+        }
+    }
+
+    fn bp(&self) -> TaggedWord { self.fp }
+    fn sp(&self) -> TaggedWord { self.sp }
+    fn ip(&self) -> TaggedWord { self.pc }
+}
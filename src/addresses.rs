@@ -0,0 +1,39 @@
+//! Address types for builds without the `live` feature.
+//!
+//! These mirror the types of the same names in `findshlibs`, which are used
+//! instead when the `live` feature is enabled.
+
+use std::fmt;
+
+/// A stated virtual memory address: an address as it appears in an object
+/// file, before the module is loaded and relocated.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Svma(pub *const u8);
+
+/// An actual virtual memory address: an address in the process's address
+/// space, after the module was loaded and relocated.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Avma(pub *const u8);
+
+/// The difference between a module's actual and stated virtual memory
+/// addresses.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Bias(pub isize);
+
+impl fmt::Display for Svma {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:p}", self.0)
+    }
+}
+
+impl fmt::Display for Avma {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:p}", self.0)
+    }
+}
+
+impl fmt::Display for Bias {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#x}", self.0)
+    }
+}
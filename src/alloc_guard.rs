@@ -0,0 +1,146 @@
+//! Checking that walking stacks does not allocate.
+//!
+//! Walking must not allocate, so that stacks can be walked from signal
+//! handlers, where the interrupted thread may hold the allocator's lock. Every
+//! walk wraps its own work in `forbid_allocations`, but not the callbacks it
+//! calls with each frame. `AllocGuard` is a global allocator that wraps
+//! another and, in debug builds, counts the allocations made inside
+//! `forbid_allocations`. When the outermost `forbid_allocations` returns, it
+//! panics if there were any.
+//!
+//! Install it in tests to check that walking keeps its promise:
+//!
+//! ```
+//! use pancakes::alloc_guard::AllocGuard;
+//! use std::alloc::System;
+//!
+//! #[global_allocator]
+//! static ALLOCATOR: AllocGuard<System> = AllocGuard::new(System);
+//! # fn main() {}
+//! ```
+//!
+//! The allocator itself only counts: unwinding out of a global allocator is
+//! undefined behavior, so the panic waits for `forbid_allocations` to return.
+
+use std::alloc::{GlobalAlloc, Layout};
+use std::cell::Cell;
+
+thread_local! {
+    /// How many `forbid_allocations` calls this thread is inside.
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+
+    /// How many times this thread allocated, reallocated, or freed inside
+    /// `forbid_allocations`.
+    static FORBIDDEN: Cell<usize> = const { Cell::new(0) };
+}
+
+/// A global allocator that wraps another, and counts the allocations made
+/// inside `forbid_allocations` in debug builds.
+#[derive(Debug, Default)]
+pub struct AllocGuard<A> {
+    inner: A,
+}
+
+impl<A> AllocGuard<A> {
+    /// Wrap the given allocator.
+    pub const fn new(inner: A) -> AllocGuard<A> {
+        AllocGuard { inner }
+    }
+}
+
+unsafe impl<A> GlobalAlloc for AllocGuard<A>
+where
+    A: GlobalAlloc,
+{
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record();
+        self.inner.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        record();
+        self.inner.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record();
+        self.inner.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // Freeing takes the allocator's lock just like allocating does.
+        record();
+        self.inner.dealloc(ptr, layout)
+    }
+}
+
+/// Count an allocation, if this thread is inside `forbid_allocations`.
+fn record() {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+    // The thread locals are `const` initialized and need no destructor, so
+    // reading them never allocates. They are gone while the thread exits.
+    let inside = DEPTH.try_with(|depth| depth.get() > 0).unwrap_or(false);
+    if inside {
+        let _ = FORBIDDEN.try_with(|count| count.set(count.get() + 1));
+    }
+}
+
+/// Call `f`, and if `AllocGuard` is the global allocator in a debug build,
+/// panic once `f` returns if it allocated or freed on this thread.
+pub fn forbid_allocations<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    if !cfg!(debug_assertions) {
+        return f();
+    }
+
+    /// Leaves the `forbid_allocations` scope, even when `f` panics.
+    struct Leave;
+
+    impl Drop for Leave {
+        fn drop(&mut self) {
+            let _ = DEPTH.try_with(|depth| depth.set(depth.get().saturating_sub(1)));
+        }
+    }
+
+    let outermost = DEPTH.with(|depth| {
+        depth.set(depth.get() + 1);
+        depth.get() == 1
+    });
+    if outermost {
+        // Forget whatever an earlier scope counted before it panicked.
+        FORBIDDEN.with(|count| count.set(0));
+    }
+    let result = {
+        let _leave = Leave;
+        f()
+    };
+
+    if outermost {
+        let forbidden = FORBIDDEN.with(|count| count.replace(0));
+        if forbidden > 0 {
+            panic!(
+                "walking the stack allocated or freed {} times, but it must be signal safe",
+                forbidden
+            );
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_scopes() {
+        // Without `AllocGuard` installed nothing is counted, so this only
+        // checks that scopes nest and return their results.
+        let result = forbid_allocations(|| forbid_allocations(|| 1) + 1);
+        assert_eq!(result, 2);
+        assert_eq!(DEPTH.with(|depth| depth.get()), 0);
+    }
+}
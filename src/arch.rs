@@ -0,0 +1,152 @@
+//! Descriptions of the architectures whose stacks `pancakes` knows how to
+//! walk.
+//!
+//! A `TargetArch` describes an architecture independently of the host that
+//! `pancakes` is running on: its DWARF register numbering, and the width of its
+//! pointers. `HostArch` is the architecture `pancakes` was compiled for.
+
+/// The properties of an architecture that matter for stack walking.
+pub trait TargetArch {
+    /// The architecture's name, as used by `target_arch`.
+    const NAME: &'static str;
+
+    /// The size of a pointer, in bytes.
+    const POINTER_WIDTH: usize;
+
+    /// The DWARF register number of the frame pointer.
+    const BP: u8;
+
+    /// The DWARF register number of the stack pointer.
+    const SP: u8;
+
+    /// The DWARF register number of the return address column.
+    const RA: u8;
+
+    /// The bits of a return address that may hold a pointer authentication
+    /// code, and should be stripped before looking the address up.
+    const POINTER_AUTH_MASK: u64 = 0;
+}
+
+/// 64-bit x86.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct X86_64;
+
+impl TargetArch for X86_64 {
+    const NAME: &'static str = "x86_64";
+    const POINTER_WIDTH: usize = 8;
+    const BP: u8 = 6;
+    const SP: u8 = 7;
+    const RA: u8 = 16;
+}
+
+/// 32-bit x86.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct X86;
+
+impl TargetArch for X86 {
+    const NAME: &'static str = "x86";
+    const POINTER_WIDTH: usize = 4;
+    const BP: u8 = 5;
+    const SP: u8 = 4;
+    const RA: u8 = 8;
+}
+
+/// 64-bit ARM.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Aarch64;
+
+impl TargetArch for Aarch64 {
+    const NAME: &'static str = "aarch64";
+    const POINTER_WIDTH: usize = 8;
+    const BP: u8 = 29;
+    const SP: u8 = 31;
+    const RA: u8 = 30;
+    const POINTER_AUTH_MASK: u64 = 0xffff_0000_0000_0000;
+}
+
+/// 32-bit ARM, in ARM (not Thumb) mode, where `r11` is the frame pointer.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Arm;
+
+impl TargetArch for Arm {
+    const NAME: &'static str = "arm";
+    const POINTER_WIDTH: usize = 4;
+    const BP: u8 = 11;
+    const SP: u8 = 13;
+    const RA: u8 = 14;
+}
+
+/// Little-endian 64-bit PowerPC, under the ELFv2 ABI.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct PowerPc64;
+
+impl TargetArch for PowerPc64 {
+    const NAME: &'static str = "powerpc64";
+    const POINTER_WIDTH: usize = 8;
+    const BP: u8 = 31;
+    const SP: u8 = 1;
+    const RA: u8 = 65;
+}
+
+/// 64-bit MIPS.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Mips64;
+
+impl TargetArch for Mips64 {
+    const NAME: &'static str = "mips64";
+    const POINTER_WIDTH: usize = 8;
+    const BP: u8 = 30;
+    const SP: u8 = 29;
+    const RA: u8 = 31;
+}
+
+/// 64-bit LoongArch.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct LoongArch64;
+
+impl TargetArch for LoongArch64 {
+    const NAME: &'static str = "loongarch64";
+    const POINTER_WIDTH: usize = 8;
+    const BP: u8 = 22;
+    const SP: u8 = 3;
+    const RA: u8 = 1;
+}
+
+cfg_if! {
+    if #[cfg(target_arch = "x86_64")] {
+        /// The architecture `pancakes` was compiled for.
+        pub type HostArch = X86_64;
+    } else if #[cfg(target_arch = "x86")] {
+        /// The architecture `pancakes` was compiled for.
+        pub type HostArch = X86;
+    } else if #[cfg(target_arch = "aarch64")] {
+        /// The architecture `pancakes` was compiled for.
+        pub type HostArch = Aarch64;
+    } else if #[cfg(all(target_arch = "powerpc64", target_endian = "little"))] {
+        /// The architecture `pancakes` was compiled for.
+        pub type HostArch = PowerPc64;
+    } else if #[cfg(target_arch = "mips64")] {
+        /// The architecture `pancakes` was compiled for.
+        pub type HostArch = Mips64;
+    } else if #[cfg(target_arch = "loongarch64")] {
+        /// The architecture `pancakes` was compiled for.
+        pub type HostArch = LoongArch64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use registers;
+    use std::mem;
+
+    #[test]
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86", target_arch = "aarch64"))]
+    fn host_arch_matches_registers() {
+        assert_eq!(HostArch::POINTER_WIDTH, mem::size_of::<usize>());
+        assert_eq!(HostArch::BP, registers::BP);
+        assert_eq!(HostArch::SP, registers::SP);
+        assert_eq!(HostArch::RA, registers::IP);
+        assert_eq!(HostArch::POINTER_AUTH_MASK, registers::POINTER_AUTH_MASK as u64);
+    }
+}
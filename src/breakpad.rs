@@ -0,0 +1,794 @@
+//! Support for Breakpad text symbol files as a source of unwind information.
+//!
+//! Breakpad `.sym` files describe call frame information with `STACK CFI INIT`
+//! records, which give the rules in effect at the start of a function, and
+//! `STACK CFI` records, which give changes to those rules at later
+//! addresses. Each rule is a register name followed by a postfix expression:
+//!
+//! ```text
+//! STACK CFI INIT 1000 40 .cfa: $rsp 8 + .ra: .cfa -8 + ^
+//! STACK CFI 1001 .cfa: $rsp 16 + $rbp: .cfa -16 + ^
+//! ```
+//!
+//! Function names are taken from `FUNC` and `PUBLIC` records, and all other
+//! records in the file are ignored.
+//!
+//! This module can also format walked stacks in the layout that Breakpad's
+//! `minidump_stackwalk` emits, so that they can be fed into existing crash
+//! processing pipelines. See `TraceFormatter`.
+
+use super::{Error, Frame, FrameRegisters, MemoryReader, Registers, Result, TaggedWord};
+use super::UnwindStrategy;
+#[cfg(feature = "live")]
+use findshlibs::{self, NamedMemoryRange, SectionIterable, SharedLibrary};
+#[cfg(feature = "live")]
+use std::ffi::OsStr;
+use std::io::{self, BufRead};
+use std::ops::Range;
+#[cfg(feature = "live")]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(feature = "live")]
+use std::path::Path;
+#[cfg(feature = "live")]
+use std::usize;
+
+/// The maximum depth of the evaluation stack for postfix expressions.
+///
+/// Expressions that would need a deeper stack are rejected at parse time, so
+/// that evaluation never needs to allocate.
+const MAX_STACK_DEPTH: usize = 16;
+
+/// A parsed Breakpad symbol file.
+///
+/// Only the call frame information and function names are retained.
+#[derive(Clone, Debug, Default)]
+pub struct SymbolFile {
+    functions: Vec<Function>,
+    publics: Vec<(u64, String)>,
+    cfi: Vec<StackCfi>,
+}
+
+#[derive(Clone, Debug)]
+struct Function {
+    address: u64,
+    size: u64,
+    name: String,
+}
+
+impl SymbolFile {
+    /// Parse a Breakpad symbol file from its textual contents.
+    ///
+    /// ```
+    /// use pancakes::breakpad::SymbolFile;
+    ///
+    /// let sym = SymbolFile::parse("STACK CFI INIT 10 4 .cfa: $rsp 8 + .ra: .cfa -8 + ^\n")
+    ///     .expect("should parse OK");
+    /// assert_eq!(sym.stack_cfi().len(), 1);
+    /// ```
+    pub fn parse(contents: &str) -> Result<SymbolFile> {
+        let mut sym = SymbolFile::default();
+        for (idx, line) in contents.lines().enumerate() {
+            sym.parse_line(idx + 1, line)?;
+        }
+        sym.finish();
+        Ok(sym)
+    }
+
+    /// Parse a Breakpad symbol file from the given reader.
+    pub fn from_reader<R>(reader: R) -> Result<SymbolFile>
+    where
+        R: io::Read,
+    {
+        let mut sym = SymbolFile::default();
+        for (idx, line) in io::BufReader::new(reader).lines().enumerate() {
+            sym.parse_line(idx + 1, &line.map_err(Error::Io)?)?;
+        }
+        sym.finish();
+        Ok(sym)
+    }
+
+    /// Get the `STACK CFI INIT` records in this file, sorted by address.
+    pub fn stack_cfi(&self) -> &[StackCfi] {
+        &self.cfi
+    }
+
+    /// Find the `STACK CFI INIT` record covering the given module-relative
+    /// address, if any.
+    pub fn find_stack_cfi(&self, address: u64) -> Option<&StackCfi> {
+        let idx = match self.cfi.binary_search_by_key(&address, |cfi| cfi.address) {
+            Ok(idx) => idx,
+            Err(0) => return None,
+            Err(idx) => idx - 1,
+        };
+        let cfi = &self.cfi[idx];
+        if cfi.contains(address) {
+            Some(cfi)
+        } else {
+            None
+        }
+    }
+
+    /// Find the name and start address of the function containing the given
+    /// module-relative address, if any.
+    ///
+    /// `FUNC` records are preferred, falling back to the closest preceding
+    /// `PUBLIC` record.
+    pub fn find_function(&self, address: u64) -> Option<(&str, u64)> {
+        let func = match self.functions.binary_search_by_key(&address, |f| f.address) {
+            Ok(idx) => Some(&self.functions[idx]),
+            Err(0) => None,
+            Err(idx) => Some(&self.functions[idx - 1]),
+        };
+        if let Some(func) = func {
+            if address - func.address < func.size {
+                return Some((&func.name, func.address));
+            }
+        }
+
+        let idx = match self.publics.binary_search_by_key(&address, |p| p.0) {
+            Ok(idx) => idx,
+            Err(0) => return None,
+            Err(idx) => idx - 1,
+        };
+        let public = &self.publics[idx];
+        Some((&public.1, public.0))
+    }
+
+    fn parse_line(&mut self, line_number: usize, line: &str) -> Result<()> {
+        let parsed = if line.starts_with("STACK CFI ") {
+            self.parse_stack_cfi(&line["STACK CFI ".len()..])
+        } else if line.starts_with("FUNC ") {
+            self.parse_func(&line["FUNC ".len()..])
+        } else if line.starts_with("PUBLIC ") {
+            self.parse_public(&line["PUBLIC ".len()..])
+        } else {
+            Some(())
+        };
+        parsed.ok_or(Error::InvalidBreakpadSymbols(line_number))
+    }
+
+    fn parse_stack_cfi(&mut self, line: &str) -> Option<()> {
+        let mut words = line.split_whitespace();
+
+        let first = words.next()?;
+        if first == "INIT" {
+            let address = words.next().and_then(parse_hex)?;
+            let size = words.next().and_then(parse_hex)?;
+            let init = parse_rules(words)?;
+            self.cfi.push(StackCfi {
+                address,
+                size,
+                init,
+                deltas: vec![],
+            });
+            return Some(());
+        }
+
+        let address = parse_hex(first)?;
+        let rules = parse_rules(words)?;
+        let cfi = self.cfi.last_mut()?;
+        if !cfi.contains(address) {
+            return None;
+        }
+        cfi.deltas.push(CfiDelta { address, rules });
+        Some(())
+    }
+
+    fn parse_func(&mut self, line: &str) -> Option<()> {
+        let line = strip_multiple(line);
+        let mut parts = line.splitn(4, ' ');
+        let address = parts.next().and_then(parse_hex)?;
+        let size = parts.next().and_then(parse_hex)?;
+        let _parameter_size = parts.next().and_then(parse_hex)?;
+        let name = parts.next().unwrap_or("").to_string();
+        self.functions.push(Function {
+            address,
+            size,
+            name,
+        });
+        Some(())
+    }
+
+    fn parse_public(&mut self, line: &str) -> Option<()> {
+        let line = strip_multiple(line);
+        let mut parts = line.splitn(3, ' ');
+        let address = parts.next().and_then(parse_hex)?;
+        let _parameter_size = parts.next().and_then(parse_hex)?;
+        let name = parts.next().unwrap_or("").to_string();
+        self.publics.push((address, name));
+        Some(())
+    }
+
+    fn finish(&mut self) {
+        self.functions.sort_by_key(|f| f.address);
+        self.publics.sort_by_key(|p| p.0);
+        self.cfi.sort_by_key(|cfi| cfi.address);
+        for cfi in &mut self.cfi {
+            cfi.deltas.sort_by_key(|delta| delta.address);
+        }
+    }
+}
+
+/// A `STACK CFI INIT` record, along with all of the `STACK CFI` records that
+/// modify its rules.
+#[derive(Clone, Debug)]
+pub struct StackCfi {
+    address: u64,
+    size: u64,
+    init: Vec<CfiRule>,
+    deltas: Vec<CfiDelta>,
+}
+
+impl StackCfi {
+    /// The module-relative address of the start of the covered range.
+    pub fn address(&self) -> u64 {
+        self.address
+    }
+
+    /// The size of the covered range, in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Does this record cover the given module-relative address?
+    pub fn contains(&self, address: u64) -> bool {
+        self.address <= address && address - self.address < self.size
+    }
+
+    /// Find the rule for the given register that is in effect at the given
+    /// module-relative address.
+    fn rule(&self, address: u64, register: &str) -> Option<&[PostfixToken]> {
+        let mut found = find_rule(&self.init, register);
+        for delta in self.deltas.iter().take_while(|d| d.address <= address) {
+            if let Some(expr) = find_rule(&delta.rules, register) {
+                found = Some(expr);
+            }
+        }
+        found
+    }
+
+    /// Recover the caller's registers from the callee's registers, using the
+    /// rules in effect at the given module-relative address.
+    pub(crate) unsafe fn unwind<Reader>(
+        &self,
+        address: u64,
+        regs: &FrameRegisters,
+        reader: &Reader,
+    ) -> Result<FrameRegisters>
+    where
+        Reader: MemoryReader,
+    {
+        let cfa = self.rule(address, CFA)
+            .ok_or(Error::NoUnwindInfoForAddress(address as usize))?;
+        let cfa = evaluate(cfa, regs, None, reader)?;
+
+        let ra = self.rule(address, RA)
+            .ok_or(Error::NoUnwindInfoForAddress(address as usize))?;
+        let ra = TaggedWord::valid(evaluate(ra, regs, Some(cfa), reader)?);
+
+        let bp = match self.rule(address, FrameRegisters::BREAKPAD_BP) {
+            Some(expr) => evaluate(expr, regs, Some(cfa), reader).into(),
+            None => regs.bp(),
+        };
+
+        Ok(FrameRegisters::from_parts(bp, TaggedWord::valid(cfa), ra))
+    }
+}
+
+#[derive(Clone, Debug)]
+struct CfiDelta {
+    address: u64,
+    rules: Vec<CfiRule>,
+}
+
+#[derive(Clone, Debug)]
+struct CfiRule {
+    register: String,
+    expr: Vec<PostfixToken>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum PostfixToken {
+    Literal(isize),
+    Register(String),
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Align,
+    Deref,
+}
+
+const CFA: &'static str = ".cfa";
+const RA: &'static str = ".ra";
+
+fn parse_hex(s: &str) -> Option<u64> {
+    u64::from_str_radix(s, 16).ok()
+}
+
+/// Strip the optional `m` marker that `FUNC` and `PUBLIC` records use to
+/// denote code shared between multiple symbols.
+fn strip_multiple(line: &str) -> &str {
+    if line.starts_with("m ") {
+        &line[2..]
+    } else {
+        line
+    }
+}
+
+fn find_rule<'a>(rules: &'a [CfiRule], register: &str) -> Option<&'a [PostfixToken]> {
+    rules
+        .iter()
+        .rev()
+        .find(|r| r.register == register)
+        .map(|r| &r.expr[..])
+}
+
+fn parse_rules<'a, I>(words: I) -> Option<Vec<CfiRule>>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let mut rules: Vec<CfiRule> = vec![];
+    for word in words {
+        if word.ends_with(':') {
+            rules.push(CfiRule {
+                register: word[..word.len() - 1].to_string(),
+                expr: vec![],
+            });
+            continue;
+        }
+
+        let token = match word {
+            "+" => PostfixToken::Add,
+            "-" => PostfixToken::Sub,
+            "*" => PostfixToken::Mul,
+            "/" => PostfixToken::Div,
+            "%" => PostfixToken::Rem,
+            "@" => PostfixToken::Align,
+            "^" => PostfixToken::Deref,
+            _ => match word.parse() {
+                Ok(n) => PostfixToken::Literal(n),
+                Err(_) => PostfixToken::Register(word.to_string()),
+            },
+        };
+        rules.last_mut()?.expr.push(token);
+    }
+
+    if rules.is_empty() || !rules.iter().all(|r| is_well_formed(&r.expr)) {
+        return None;
+    }
+    Some(rules)
+}
+
+/// Check that the expression leaves exactly one value on the stack, and never
+/// underflows or exceeds `MAX_STACK_DEPTH`.
+fn is_well_formed(expr: &[PostfixToken]) -> bool {
+    let mut depth = 0;
+    for token in expr {
+        match *token {
+            PostfixToken::Literal(_) | PostfixToken::Register(_) => depth += 1,
+            PostfixToken::Deref => {
+                if depth < 1 {
+                    return false;
+                }
+            }
+            _ => {
+                if depth < 2 {
+                    return false;
+                }
+                depth -= 1;
+            }
+        }
+        if depth > MAX_STACK_DEPTH {
+            return false;
+        }
+    }
+    depth == 1
+}
+
+unsafe fn evaluate<Reader>(
+    expr: &[PostfixToken],
+    regs: &FrameRegisters,
+    cfa: Option<usize>,
+    reader: &Reader,
+) -> Result<usize>
+where
+    Reader: MemoryReader,
+{
+    let mut stack = [0usize; MAX_STACK_DEPTH];
+    let mut len = 0;
+
+    for token in expr {
+        let value = match *token {
+            PostfixToken::Literal(n) => n as usize,
+            PostfixToken::Register(ref name) if name == CFA => {
+                cfa.ok_or(Error::InvalidTaggedWord)?
+            }
+            PostfixToken::Register(ref name) => {
+                let word: Result<_> = regs.breakpad_register(name).into();
+                word?
+            }
+            PostfixToken::Deref => {
+                let addr = stack[len - 1];
+                stack[len - 1] = reader.read(addr)?;
+                continue;
+            }
+            ref op => {
+                let rhs = stack[len - 1];
+                let lhs = stack[len - 2];
+                len -= 2;
+                match *op {
+                    PostfixToken::Add => lhs.wrapping_add(rhs),
+                    PostfixToken::Sub => lhs.wrapping_sub(rhs),
+                    PostfixToken::Mul => lhs.wrapping_mul(rhs),
+                    PostfixToken::Div => lhs.checked_div(rhs).ok_or(Error::InvalidTaggedWord)?,
+                    PostfixToken::Rem => lhs.checked_rem(rhs).ok_or(Error::InvalidTaggedWord)?,
+                    PostfixToken::Align => lhs & rhs.wrapping_neg(),
+                    _ => unreachable!(),
+                }
+            }
+        };
+        stack[len] = value;
+        len += 1;
+    }
+
+    debug_assert_eq!(len, 1, "checked by is_well_formed at parse time");
+    Ok(stack[0])
+}
+
+/// How a frame was found, in Breakpad's terminology.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FrameTrust {
+    /// The frame's registers came directly from a captured context. This is
+    /// always the case for the youngest frame.
+    Context,
+
+    /// The frame was recovered using call frame information.
+    CallFrameInfo,
+
+    /// The frame was recovered by following the frame pointer chain.
+    FramePointer,
+
+    /// The frame was recovered by scanning the stack for plausible return
+    /// addresses.
+    Scan,
+}
+
+impl FrameTrust {
+    /// The description `minidump_stackwalk` uses for this trust level.
+    pub fn description(&self) -> &'static str {
+        match *self {
+            FrameTrust::Context => "given as instruction pointer in context",
+            FrameTrust::CallFrameInfo => "call frame info",
+            FrameTrust::FramePointer => "previous frame's frame pointer",
+            FrameTrust::Scan => "stack scanning",
+        }
+    }
+}
+
+/// The trust level we have in a walked frame, from how it was found.
+impl<'a, 'u> From<&'a Frame<'u>> for FrameTrust {
+    fn from(frame: &'a Frame<'u>) -> FrameTrust {
+        match frame.found_by() {
+            None => FrameTrust::Context,
+            Some(UnwindStrategy::FramePointer) => FrameTrust::FramePointer,
+            Some(UnwindStrategy::Manual) |
+            Some(UnwindStrategy::Dwarf) |
+            Some(UnwindStrategy::Breakpad) => FrameTrust::CallFrameInfo,
+        }
+    }
+}
+
+/// A loaded module, as it should be described in Breakpad-style output.
+#[derive(Clone, Debug)]
+pub struct Module {
+    name: String,
+    range: Range<usize>,
+    bias: isize,
+    symbols: Option<SymbolFile>,
+}
+
+impl Module {
+    /// Construct a new `Module` with the given name, loaded at the given
+    /// address range with the given bias.
+    pub fn new(name: String, range: Range<usize>, bias: isize) -> Module {
+        Module {
+            name,
+            range,
+            bias,
+            symbols: None,
+        }
+    }
+
+    /// Use the given symbol file to name functions in this module.
+    pub fn with_symbols(mut self, symbols: SymbolFile) -> Module {
+        self.symbols = Some(symbols);
+        self
+    }
+
+    /// Get the modules currently loaded in this process.
+    #[cfg(feature = "live")]
+    pub fn this_process() -> Vec<Module> {
+        let mut modules = vec![];
+        findshlibs::TargetSharedLibrary::each(|shlib| {
+            let mut start = usize::MAX;
+            let mut end = 0;
+            for section in shlib.sections() {
+                let avma = section.actual_virtual_memory_address(shlib).0 as usize;
+                start = start.min(avma);
+                end = end.max(avma + section.len());
+            }
+
+            if start < end {
+                let path = Path::new(OsStr::from_bytes(shlib.name().to_bytes()));
+                let name = path.file_name()
+                    .unwrap_or(path.as_os_str())
+                    .to_string_lossy()
+                    .into_owned();
+                modules.push(Module::new(name, start..end, shlib.virtual_memory_bias().0));
+            }
+
+            findshlibs::IterationControl::Continue
+        });
+        modules
+    }
+}
+
+/// Formats walked stacks in the layouts that Breakpad's `minidump_stackwalk`
+/// emits.
+///
+/// ```
+/// use pancakes::breakpad::{FrameTrust, Module, TraceFormatter};
+///
+/// let libfoo = Module::new("libfoo.so".into(), 0x1000..0x2000, 0x1000);
+/// let formatter = TraceFormatter::new(vec![libfoo]);
+///
+/// let mut out = vec![];
+/// formatter.write_frame(&mut out, 0, 0x1234, FrameTrust::Context).unwrap();
+/// assert_eq!(
+///     String::from_utf8(out).unwrap(),
+///     " 0  libfoo.so + 0x234\n    Found by: given as instruction pointer in context\n"
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct TraceFormatter {
+    modules: Vec<Module>,
+}
+
+impl TraceFormatter {
+    /// Construct a new `TraceFormatter` that describes addresses in terms of
+    /// the given modules.
+    pub fn new(mut modules: Vec<Module>) -> TraceFormatter {
+        modules.sort_by_key(|m| m.range.start);
+        TraceFormatter { modules }
+    }
+
+    /// Write a frame in `minidump_stackwalk`'s human readable layout:
+    ///
+    /// ```text
+    ///  1  libfoo.so!foo + 0x12
+    ///     Found by: call frame info
+    /// ```
+    pub fn write_frame<W>(
+        &self,
+        out: &mut W,
+        index: usize,
+        ip: usize,
+        trust: FrameTrust,
+    ) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        write!(out, "{:2}  ", index)?;
+        match self.describe(ip) {
+            Location::Function(module, function, offset) => {
+                writeln!(out, "{}!{} + {:#x}", module, function, offset)?
+            }
+            Location::Module(module, offset) => writeln!(out, "{} + {:#x}", module, offset)?,
+            Location::Unknown => writeln!(out, "{:#x}", ip)?,
+        }
+        writeln!(out, "    Found by: {}", trust.description())
+    }
+
+    /// Write a frame in `minidump_stackwalk`'s machine readable layout:
+    ///
+    /// ```text
+    /// thread|frame|module|function|source_file|source_line|offset
+    /// ```
+    pub fn write_machine_readable_frame<W>(
+        &self,
+        out: &mut W,
+        thread: usize,
+        index: usize,
+        ip: usize,
+    ) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        match self.describe(ip) {
+            Location::Function(module, function, offset) => writeln!(
+                out,
+                "{}|{}|{}|{}|||{:#x}",
+                thread,
+                index,
+                module,
+                function,
+                offset
+            ),
+            Location::Module(module, offset) => {
+                writeln!(out, "{}|{}|{}||||{:#x}", thread, index, module, offset)
+            }
+            Location::Unknown => writeln!(out, "{}|{}|||||{:#x}", thread, index, ip),
+        }
+    }
+
+    fn describe(&self, ip: usize) -> Location {
+        let idx = match self.modules.binary_search_by_key(&ip, |m| m.range.start) {
+            Ok(idx) => idx,
+            Err(0) => return Location::Unknown,
+            Err(idx) => idx - 1,
+        };
+        let module = &self.modules[idx];
+        if ip >= module.range.end {
+            return Location::Unknown;
+        }
+
+        let address = (ip as isize).wrapping_sub(module.bias) as usize as u64;
+        let function = module
+            .symbols
+            .as_ref()
+            .and_then(|symbols| symbols.find_function(address));
+        match function {
+            Some((name, start)) => Location::Function(&module.name, name, address - start),
+            None => Location::Module(&module.name, address),
+        }
+    }
+}
+
+enum Location<'a> {
+    Function(&'a str, &'a str, u64),
+    Module(&'a str, u64),
+    Unknown,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(Debug)]
+    struct MapMemory(HashMap<usize, usize>);
+
+    impl MemoryReader for MapMemory {
+        unsafe fn read(&self, addr: usize) -> Result<usize> {
+            self.0
+                .get(&addr)
+                .cloned()
+                .ok_or(Error::NoUnwindInfoForAddress(addr))
+        }
+    }
+
+    const SYM: &'static str = "\
+MODULE Linux x86_64 000000000000000000000000000000000 libfoo.so
+FUNC 1000 40 0 foo
+STACK CFI INIT 1000 40 .cfa: $rsp 8 + .ra: .cfa -8 + ^
+STACK CFI 1001 .cfa: $rsp 16 + $rbp: .cfa -16 + ^
+STACK CFI INIT 2000 10 .cfa: $rsp 8 + .ra: .cfa -8 + ^
+";
+
+    #[test]
+    fn parse_stack_cfi() {
+        let sym = SymbolFile::parse(SYM).expect("should parse OK");
+        assert_eq!(sym.stack_cfi().len(), 2);
+        assert!(sym.find_stack_cfi(0xfff).is_none());
+        assert_eq!(sym.find_stack_cfi(0x1000).unwrap().address(), 0x1000);
+        assert_eq!(sym.find_stack_cfi(0x103f).unwrap().address(), 0x1000);
+        assert!(sym.find_stack_cfi(0x1040).is_none());
+        assert_eq!(sym.find_stack_cfi(0x2008).unwrap().size(), 0x10);
+    }
+
+    #[test]
+    fn parse_rejects_malformed_expressions() {
+        assert!(SymbolFile::parse("STACK CFI INIT 0 4 .cfa: $rsp +\n").is_err());
+        assert!(SymbolFile::parse("STACK CFI INIT 0 4 .cfa: $rsp 8\n").is_err());
+        assert!(SymbolFile::parse("STACK CFI 0 .cfa: $rsp 8 +\n").is_err());
+        assert!(SymbolFile::parse("STACK CFI INIT 0 zz .cfa: $rsp\n").is_err());
+    }
+
+    #[test]
+    fn unwind_with_deltas() {
+        let sym = SymbolFile::parse(SYM).expect("should parse OK");
+        let cfi = sym.find_stack_cfi(0x1010).unwrap();
+
+        let mut memory = HashMap::new();
+        memory.insert(0x7f00, 0xdead);
+        memory.insert(0x7ef8, 0x7fff);
+        let reader = MapMemory(memory);
+
+        // At the start of the function, only the INIT rules apply.
+        let regs = FrameRegisters::from_parts(
+            TaggedWord::valid(0x1234),
+            TaggedWord::valid(0x7f00),
+            TaggedWord::valid(0x1000),
+        );
+        let caller = unsafe { cfi.unwind(0x1000, &regs, &reader).unwrap() };
+        assert_eq!(caller.sp(), TaggedWord::valid(0x7f08));
+        assert_eq!(caller.ip(), TaggedWord::valid(0xdead));
+        assert_eq!(caller.bp(), TaggedWord::valid(0x1234));
+
+        // After the push, the delta's rules take effect.
+        let regs = FrameRegisters::from_parts(
+            TaggedWord::valid(0x1234),
+            TaggedWord::valid(0x7ef8),
+            TaggedWord::valid(0x1010),
+        );
+        let caller = unsafe { cfi.unwind(0x1010, &regs, &reader).unwrap() };
+        assert_eq!(caller.sp(), TaggedWord::valid(0x7f08));
+        assert_eq!(caller.ip(), TaggedWord::valid(0xdead));
+        assert_eq!(caller.bp(), TaggedWord::valid(0x7fff));
+    }
+
+    #[test]
+    fn find_function() {
+        let sym = SymbolFile::parse(
+            "\
+FUNC 1000 40 0 foo(int, char)
+FUNC m 2000 10 0 bar
+PUBLIC 3000 0 baz
+",
+        ).expect("should parse OK");
+        assert_eq!(sym.find_function(0x1010), Some(("foo(int, char)", 0x1000)));
+        assert_eq!(sym.find_function(0x2000), Some(("bar", 0x2000)));
+        assert_eq!(sym.find_function(0x3020), Some(("baz", 0x3000)));
+        assert_eq!(sym.find_function(0x10), None);
+    }
+
+    #[test]
+    fn format_frames() {
+        let symbols = SymbolFile::parse("FUNC 200 40 0 foo\n").expect("should parse OK");
+        let libfoo = Module::new("libfoo.so".into(), 0x1000..0x2000, 0x1000).with_symbols(symbols);
+        let formatter = TraceFormatter::new(vec![libfoo]);
+
+        let mut out = vec![];
+        formatter
+            .write_frame(&mut out, 1, 0x1210, FrameTrust::CallFrameInfo)
+            .unwrap();
+        formatter
+            .write_frame(&mut out, 2, 0x1500, FrameTrust::CallFrameInfo)
+            .unwrap();
+        formatter
+            .write_frame(&mut out, 3, 0x9000, FrameTrust::Scan)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\
+ 1  libfoo.so!foo + 0x10
+    Found by: call frame info
+ 2  libfoo.so + 0x500
+    Found by: call frame info
+ 3  0x9000
+    Found by: stack scanning
+"
+        );
+
+        let mut out = vec![];
+        formatter
+            .write_machine_readable_frame(&mut out, 0, 1, 0x1210)
+            .unwrap();
+        formatter
+            .write_machine_readable_frame(&mut out, 0, 2, 0x1500)
+            .unwrap();
+        formatter
+            .write_machine_readable_frame(&mut out, 0, 3, 0x9000)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\
+0|1|libfoo.so|foo|||0x10
+0|2|libfoo.so||||0x500
+0|3|||||0x9000
+"
+        );
+    }
+}
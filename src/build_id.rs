@@ -0,0 +1,64 @@
+//! Finding separate debug files by build ID.
+//!
+//! Distributions install the debug file for a binary whose
+//! `.note.gnu.build-id` is `abcdef...` at `.build-id/ab/cdef....debug` under
+//! their debug file directory, usually `/usr/lib/debug`.
+
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The path of the debug file with the given build ID under the given debug
+/// file directory.
+pub(crate) fn path(root: &Path, build_id: &[u8]) -> Option<PathBuf> {
+    let (first, rest) = build_id.split_first()?;
+    if rest.is_empty() {
+        return None;
+    }
+
+    let file = to_hex(rest) + ".debug";
+    Some(root.join(".build-id").join(format!("{:02x}", first)).join(file))
+}
+
+/// Format a build ID as lowercase hex, the way debug file paths and
+/// debuginfod URLs spell it.
+pub(crate) fn to_hex(build_id: &[u8]) -> String {
+    let mut hex = String::with_capacity(build_id.len() * 2);
+    for byte in build_id {
+        write!(hex, "{:02x}", byte).unwrap();
+    }
+    hex
+}
+
+/// Find and read the debug file with the given build ID, searching the given
+/// debug file directories in order.
+pub(crate) fn find(roots: &[PathBuf], build_id: &[u8]) -> Option<Vec<u8>> {
+    roots
+        .iter()
+        .filter_map(|root| path(root, build_id))
+        .filter_map(|path| fs::read(path).ok())
+        .next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{path, to_hex};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn build_id_path() {
+        let root = Path::new("/usr/lib/debug");
+        assert_eq!(
+            path(root, &[0xab, 0xcd, 0x01, 0x23]),
+            Some(PathBuf::from("/usr/lib/debug/.build-id/ab/cd0123.debug"))
+        );
+        assert_eq!(path(root, &[0xab]), None);
+        assert_eq!(path(root, &[]), None);
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(to_hex(&[0x00, 0x0f, 0xf0, 0xff]), "000ff0ff");
+        assert_eq!(to_hex(&[]), "");
+    }
+}
@@ -0,0 +1,86 @@
+//! Capturing just the instruction pointers of a stack's frames, into a
+//! fixed buffer, which is all that sampling profilers and allocation
+//! trackers record at sample time.
+
+/// The instruction pointers of up to `N` frames of a stack, captured by
+/// `Walker::capture`, outermost last.
+///
+/// The buffer is inline, so captures can be made in signal handlers into one
+/// that was allocated beforehand, e.g. in a static, and copied out later.
+///
+/// The first instruction pointer is where the stack was interrupted or
+/// captured. The rest are return addresses, which point just after their
+/// calls: subtract one from them before symbolicating.
+///
+/// ```
+/// # fn f() {
+/// use pancakes::StackCapture;
+///
+/// let mut walker = pancakes::Options::new().build();
+/// let mut capture = StackCapture::<64>::new();
+///
+/// # let get_frame_regs = || unimplemented!();
+/// walker.capture(get_frame_regs(), &mut capture);
+/// for ip in capture.ips() {
+///     println!("{:#x}", ip);
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct StackCapture<const N: usize> {
+    ips: [usize; N],
+    len: usize,
+    truncated: bool,
+}
+
+impl<const N: usize> StackCapture<N> {
+    /// Construct an empty capture.
+    pub const fn new() -> StackCapture<N> {
+        StackCapture {
+            ips: [0; N],
+            len: 0,
+            truncated: false,
+        }
+    }
+
+    /// Get the captured instruction pointers.
+    pub fn ips(&self) -> &[usize] {
+        &self.ips[..self.len]
+    }
+
+    /// Get how many instruction pointers were captured.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Were no instruction pointers captured?
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Did the stack have more than `N` frames, whose outermost frames were
+    /// left out?
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Get the buffer to capture into, emptying the capture.
+    pub(crate) fn start(&mut self) -> &mut [usize; N] {
+        self.len = 0;
+        self.truncated = false;
+        &mut self.ips
+    }
+
+    /// Record how many instruction pointers were captured, and whether there
+    /// were more.
+    pub(crate) fn finish(&mut self, len: usize, truncated: bool) {
+        self.len = len;
+        self.truncated = truncated;
+    }
+}
+
+impl<const N: usize> Default for StackCapture<N> {
+    fn default() -> StackCapture<N> {
+        StackCapture::new()
+    }
+}
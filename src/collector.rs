@@ -0,0 +1,296 @@
+//! Aggregating captured stacks in place, so that a sampling profiler's signal
+//! handler can count each distinct stack instead of queueing every sample for
+//! another thread.
+
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// The key of a slot that no stack has claimed.
+const EMPTY: u64 = 0;
+
+/// The key of a slot whose stack is still being written.
+const WRITING: u64 = 1;
+
+/// How many slots are probed for a stack before it is dropped.
+const MAX_PROBES: usize = 64;
+
+/// A fixed-size hash table counting how many times each distinct stack was
+/// recorded.
+///
+/// `record` can be called from signal handlers: it never allocates, takes
+/// locks, or waits for other threads. Stacks are counted in one of two
+/// tables while `drain` reports and empties the other, so a reporter thread
+/// can drain periodically without stopping the handlers. Stacks that do not
+/// fit are dropped and counted.
+///
+/// ```
+/// use pancakes::StackCollector;
+///
+/// let collector = StackCollector::new(1024, 64);
+///
+/// // In the signal handler, after capturing a stack's instruction pointers:
+/// # let ips = [0x1000, 0x2000];
+/// collector.record(&ips);
+///
+/// // On the reporter thread:
+/// collector.drain(|ips, count| {
+///     println!("{} samples: {:x?}", count, ips);
+/// });
+/// ```
+pub struct StackCollector {
+    tables: [Table; 2],
+    /// The index of the table that `record` counts in.
+    active: AtomicUsize,
+    /// How many `record`s are counting in each table.
+    writers: [AtomicUsize; 2],
+    depth: usize,
+    dropped: AtomicU64,
+    /// Serializes `drain`s.
+    draining: Mutex<()>,
+}
+
+// Each slot's stack is only written by the `record` that claimed it, and only
+// read once it is published, or by `drain` once no `record` uses its table.
+unsafe impl Sync for StackCollector {}
+
+impl StackCollector {
+    /// Construct a collector with room for `slots` distinct stacks, rounded up
+    /// to a power of two, of up to `depth` instruction pointers each. Longer
+    /// stacks are counted by their innermost `depth` instruction pointers.
+    ///
+    /// Both tables are allocated here: `2 * slots * (depth + 3)` words.
+    pub fn new(slots: usize, depth: usize) -> StackCollector {
+        let slots = slots.max(1).next_power_of_two();
+        let depth = depth.max(1);
+        StackCollector {
+            tables: [Table::new(slots, depth), Table::new(slots, depth)],
+            active: AtomicUsize::new(0),
+            writers: [AtomicUsize::new(0), AtomicUsize::new(0)],
+            depth,
+            dropped: AtomicU64::new(0),
+            draining: Mutex::new(()),
+        }
+    }
+
+    /// Count one sample of the stack `ips`, innermost first. Returns whether
+    /// it was counted, rather than dropped because the table was too full.
+    ///
+    /// This is safe to call from signal handlers.
+    pub fn record(&self, ips: &[usize]) -> bool {
+        let ips = &ips[..ips.len().min(self.depth)];
+        let key = hash(ips).max(WRITING + 1);
+
+        // Register as a writer of the active table, making sure `drain` did
+        // not swap tables before it could see the registration.
+        let index = loop {
+            let index = self.active.load(Ordering::SeqCst);
+            self.writers[index].fetch_add(1, Ordering::SeqCst);
+            if self.active.load(Ordering::SeqCst) == index {
+                break index;
+            }
+            self.writers[index].fetch_sub(1, Ordering::SeqCst);
+        };
+        let counted = self.tables[index].count(key, ips, self.depth);
+        self.writers[index].fetch_sub(1, Ordering::SeqCst);
+
+        if !counted {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        counted
+    }
+
+    /// Call `f` with each distinct stack recorded since the last drain,
+    /// innermost first, and how many times it was recorded, and forget them.
+    ///
+    /// This waits for the `record`s in progress to finish, so it must not be
+    /// called from a signal handler.
+    pub fn drain<F>(&self, mut f: F)
+    where
+        F: FnMut(&[usize], u64),
+    {
+        let _draining = self.draining.lock().unwrap_or_else(|e| e.into_inner());
+
+        let index = self.active.load(Ordering::SeqCst);
+        self.active.store(1 - index, Ordering::SeqCst);
+        while self.writers[index].load(Ordering::SeqCst) != 0 {
+            thread::yield_now();
+        }
+
+        let table = &self.tables[index];
+        for (i, slot) in table.slots.iter().enumerate() {
+            if slot.key.load(Ordering::Acquire) > WRITING {
+                let ips = unsafe { table.ips(i, self.depth, *slot.len.get()) };
+                f(ips, slot.count.load(Ordering::Relaxed));
+            }
+            slot.key.store(EMPTY, Ordering::Relaxed);
+        }
+    }
+
+    /// Get how many samples were dropped because the table was too full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl fmt::Debug for StackCollector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StackCollector")
+            .field("slots", &self.tables[0].slots.len())
+            .field("depth", &self.depth)
+            .field("dropped", &self.dropped())
+            .finish()
+    }
+}
+
+/// One of a `StackCollector`'s tables.
+struct Table {
+    slots: Box<[Slot]>,
+    /// Each slot's stack, `depth` words per slot.
+    ips: Box<[UnsafeCell<usize>]>,
+}
+
+struct Slot {
+    /// `EMPTY`, `WRITING`, or the hash of the slot's stack once it can be
+    /// read.
+    key: AtomicU64,
+    len: UnsafeCell<usize>,
+    count: AtomicU64,
+}
+
+impl Table {
+    fn new(slots: usize, depth: usize) -> Table {
+        Table {
+            slots: (0..slots)
+                .map(|_| Slot {
+                    key: AtomicU64::new(EMPTY),
+                    len: UnsafeCell::new(0),
+                    count: AtomicU64::new(0),
+                })
+                .collect(),
+            ips: (0..slots * depth).map(|_| UnsafeCell::new(0)).collect(),
+        }
+    }
+
+    /// Get the stack of the slot `i`, which must be published.
+    unsafe fn ips(&self, i: usize, depth: usize, len: usize) -> &[usize] {
+        let start = self.ips[i * depth].get() as *const usize;
+        ::std::slice::from_raw_parts(start, len)
+    }
+
+    /// Count a sample of `ips` in the slot with its stack, claiming an empty
+    /// one if there is none. Returns whether it was counted.
+    fn count(&self, key: u64, ips: &[usize], depth: usize) -> bool {
+        let mask = self.slots.len() - 1;
+        let mut i = key as usize & mask;
+        let mut probes = 0;
+        while probes < MAX_PROBES.min(self.slots.len()) {
+            let slot = &self.slots[i];
+            match slot.key.load(Ordering::Acquire) {
+                EMPTY => {
+                    if slot
+                        .key
+                        .compare_exchange(EMPTY, WRITING, Ordering::Acquire, Ordering::Relaxed)
+                        .is_err()
+                    {
+                        // Another `record` claimed it first: look again.
+                        continue;
+                    }
+                    unsafe {
+                        for (j, &ip) in ips.iter().enumerate() {
+                            *self.ips[i * depth + j].get() = ip;
+                        }
+                        *slot.len.get() = ips.len();
+                    }
+                    slot.count.store(1, Ordering::Relaxed);
+                    slot.key.store(key, Ordering::Release);
+                    return true;
+                }
+                // A stack still being written can't be compared, so an
+                // identical one may be counted in another slot. `drain`
+                // reports both.
+                k if k == key && unsafe { self.ips(i, depth, *slot.len.get()) } == ips => {
+                    slot.count.fetch_add(1, Ordering::Relaxed);
+                    return true;
+                }
+                _ => {}
+            }
+            i = (i + 1) & mask;
+            probes += 1;
+        }
+        false
+    }
+}
+
+/// FNV-1a over the words of a stack.
+fn hash(ips: &[usize]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for &ip in ips {
+        hash ^= ip as u64;
+        hash = hash.wrapping_mul(0x100_0000_01b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    fn drained(collector: &StackCollector) -> HashMap<Vec<usize>, u64> {
+        let mut stacks = HashMap::new();
+        collector.drain(|ips, count| *stacks.entry(ips.to_vec()).or_insert(0) += count);
+        stacks
+    }
+
+    #[test]
+    fn count_stacks() {
+        let collector = StackCollector::new(16, 3);
+        assert!(collector.record(&[1, 2, 3]));
+        assert!(collector.record(&[1, 2, 3]));
+        assert!(collector.record(&[4, 5]));
+        // Truncated to its innermost three.
+        assert!(collector.record(&[1, 2, 3, 4]));
+
+        let stacks = drained(&collector);
+        assert_eq!(stacks.len(), 2);
+        assert_eq!(stacks[&vec![1, 2, 3]], 3);
+        assert_eq!(stacks[&vec![4, 5]], 1);
+        assert!(drained(&collector).is_empty());
+
+        // Full.
+        let collector = StackCollector::new(2, 1);
+        assert!(collector.record(&[1]));
+        assert!(collector.record(&[2]));
+        assert!(!collector.record(&[3]));
+        assert_eq!(collector.dropped(), 1);
+    }
+
+    #[test]
+    fn record_while_draining() {
+        let collector = Arc::new(StackCollector::new(64, 4));
+        let recorders: Vec<_> = (0..4)
+            .map(|t| {
+                let collector = collector.clone();
+                thread::spawn(move || {
+                    for i in 0..10_000 {
+                        assert!(collector.record(&[t, i % 8]));
+                    }
+                })
+            })
+            .collect();
+
+        let mut total = 0;
+        while recorders.iter().any(|r| !r.is_finished()) {
+            total += drained(&collector).values().sum::<u64>();
+        }
+        for recorder in recorders {
+            recorder.join().unwrap();
+        }
+        total += drained(&collector).values().sum::<u64>();
+        assert_eq!(total, 40_000);
+    }
+}
@@ -0,0 +1,805 @@
+//! Unwind tables compiled ahead of time from DWARF call frame information.
+//!
+//! Evaluating an FDE's call frame instructions for every frame walked is
+//! slow, so `Options::compile_unwind_tables` runs each FDE's instructions
+//! once, and flattens the resulting unwind table rows into a sorted,
+//! delta-encoded table per module. Each row only records the rules needed to
+//! recover the caller's frame: the CFA, the frame base pointer, and the
+//! return address.
+//!
+//! Rows whose rules involve DWARF expressions are not compiled, and frames in
+//! them are still walked by evaluating their FDE.
+
+use super::{Bias, FrameRegisters, MemoryReader, Registers, Result, TaggedWord, TargetEndianBuf};
+use gimli::{self, UnwindSection};
+use registers;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::env;
+use std::io::{self, Write};
+use std::mem;
+use std::ops::Range;
+
+/// A compiled rule for recovering one of the caller's registers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum CompiledRule {
+    /// The register cannot be recovered.
+    Undefined,
+    /// The caller's value is the callee's value.
+    SameValue,
+    /// The caller's value is saved at CFA + offset.
+    Offset(i32),
+    /// The caller's value is CFA + offset.
+    ValOffset(i32),
+    /// The caller's value is the callee's value of the given register.
+    Register(u8),
+}
+
+impl CompiledRule {
+    fn compile(rule: gimli::RegisterRule<TargetEndianBuf>) -> Option<CompiledRule> {
+        Some(match rule {
+            gimli::RegisterRule::Undefined | gimli::RegisterRule::Architectural => {
+                CompiledRule::Undefined
+            }
+            gimli::RegisterRule::SameValue => CompiledRule::SameValue,
+            gimli::RegisterRule::Offset(offset) => {
+                CompiledRule::Offset(i32::try_from(offset as i64).ok()?)
+            }
+            gimli::RegisterRule::ValOffset(offset) => {
+                CompiledRule::ValOffset(i32::try_from(offset as i64).ok()?)
+            }
+            gimli::RegisterRule::Register(register) => CompiledRule::Register(register),
+            gimli::RegisterRule::Expression(_) | gimli::RegisterRule::ValExpression(_) => {
+                return None
+            }
+        })
+    }
+
+    fn encode(self) -> (u8, i32) {
+        match self {
+            CompiledRule::Undefined => (0, 0),
+            CompiledRule::SameValue => (1, 0),
+            CompiledRule::Offset(offset) => (2, offset),
+            CompiledRule::ValOffset(offset) => (3, offset),
+            CompiledRule::Register(register) => (4, register as i32),
+        }
+    }
+
+    fn decode(kind: u8, value: i32) -> Option<CompiledRule> {
+        Some(match kind {
+            0 => CompiledRule::Undefined,
+            1 => CompiledRule::SameValue,
+            2 => CompiledRule::Offset(value),
+            3 => CompiledRule::ValOffset(value),
+            4 => CompiledRule::Register(u8::try_from(value).ok()?),
+            _ => return None,
+        })
+    }
+
+    unsafe fn eval<Reader>(
+        self,
+        register: u8,
+        cfa: usize,
+        regs: &FrameRegisters,
+        reader: &Reader,
+    ) -> TaggedWord
+    where
+        Reader: MemoryReader,
+    {
+        match self {
+            CompiledRule::Undefined => TaggedWord::invalid(),
+            CompiledRule::SameValue => regs.get_register(register).unwrap_or_default(),
+            CompiledRule::Offset(offset) => reader.read_offset(cfa, offset as isize).into(),
+            CompiledRule::ValOffset(offset) => {
+                TaggedWord::valid((cfa as isize).wrapping_add(offset as isize) as usize)
+            }
+            CompiledRule::Register(r) => regs.get_register(r).unwrap_or_default(),
+        }
+    }
+}
+
+/// A single compiled unwind table row, covering the stated virtual memory
+/// addresses `start .. end`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct CompiledRow {
+    pub(crate) start: u64,
+    pub(crate) end: u64,
+    pub(crate) cfa_register: u8,
+    pub(crate) cfa_offset: i32,
+    pub(crate) bp: CompiledRule,
+    pub(crate) ra: CompiledRule,
+}
+
+impl CompiledRow {
+    /// Compile the given row, unless its rules use DWARF expressions.
+    pub(crate) fn compile(
+        row: &gimli::UnwindTableRow<TargetEndianBuf>,
+        return_address_register: u8,
+    ) -> Option<CompiledRow> {
+        let (cfa_register, cfa_offset) = match *row.cfa() {
+            gimli::CfaRule::RegisterAndOffset { register, offset } => {
+                (register, i32::try_from(offset as i64).ok()?)
+            }
+            gimli::CfaRule::Expression(_) => return None,
+        };
+
+        Some(CompiledRow {
+            start: row.start_address(),
+            end: row.end_address(),
+            cfa_register,
+            cfa_offset,
+            bp: CompiledRule::compile(row.register(registers::BP))?,
+            ra: CompiledRule::compile(row.register(return_address_register))?,
+        })
+    }
+
+    /// Recover the caller's registers from the callee's registers.
+    pub(crate) unsafe fn unwind<Reader>(
+        &self,
+        regs: &FrameRegisters,
+        reader: &Reader,
+    ) -> Result<FrameRegisters>
+    where
+        Reader: MemoryReader,
+    {
+        let base: Result<usize> = regs.get_register(self.cfa_register)?.into();
+        let cfa = (base? as isize).wrapping_add(self.cfa_offset as isize) as usize;
+
+        // The frame pointer is callee-saved, so functions without a rule for
+        // it still hold the caller's value.
+        let bp = match self.bp {
+            CompiledRule::Undefined => regs.bp(),
+            rule => rule.eval(registers::BP, cfa, regs, reader),
+        };
+        let ip = match self.ra {
+            // Leaf functions have no rule for the return address, which is
+            // still in the link register.
+            #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+            CompiledRule::Undefined => regs.get_register(registers::IP).unwrap_or_default(),
+            rule => rule.eval(registers::IP, cfa, regs, reader),
+        };
+        Ok(FrameRegisters::from_parts(bp, TaggedWord::valid(cfa), ip))
+    }
+}
+
+/// The compiled unwind table rows of a single module.
+///
+/// Rows are stored delta-encoded, like llvm-libunwind's FDE cache, rather
+/// than as `CompiledRow`s, which keeps the tables of gigantic modules small.
+/// They are split into blocks of `BLOCK_ROWS` rows. Each block's rows are
+/// encoded one after another, each relative to the previous one, and the
+/// first relative to a row starting and ending at the block's first address
+/// with no rules. So a lookup binary searches the blocks' first addresses,
+/// then decodes at most one block.
+///
+/// Each row is a flags byte, then:
+///
+/// * if `GAP` is set, how far the row starts after the end of the previous
+///   row, as a signed LEB128;
+/// * the length of the row, as an unsigned LEB128;
+/// * the change in the CFA offset, as a signed LEB128;
+/// * if `CFA_REGISTER` is set, the CFA register's DWARF register number;
+/// * if `BP` is set, the frame base pointer rule's kind, as in `encode_rows`,
+///   and its operand, as a signed LEB128;
+/// * if `RA` is set, the return address rule, in the same way.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct CompiledTable {
+    pub(crate) bias: Bias,
+    /// Each block's first address, and where its rows start in `data`.
+    blocks: Vec<(u64, usize)>,
+    data: Vec<u8>,
+    len: usize,
+}
+
+/// How many rows are encoded relative to each other, and must be decoded to
+/// find one of them.
+const BLOCK_ROWS: usize = 16;
+
+/// A row's flags: the row does not start where the previous one ended.
+const GAP: u8 = 1 << 0;
+/// A row's flags: the CFA register differs from the previous row's.
+const CFA_REGISTER: u8 = 1 << 1;
+/// A row's flags: the frame base pointer rule differs from the previous
+/// row's.
+const BP: u8 = 1 << 2;
+/// A row's flags: the return address rule differs from the previous row's.
+const RA: u8 = 1 << 3;
+
+/// How much memory a module's compiled unwind table takes. See
+/// `Unwinder::compiled_table_memory`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompiledTableMemory {
+    /// The bias of the module.
+    pub bias: Bias,
+    /// How many rows the table has.
+    pub rows: usize,
+    /// How many bytes the delta-encoded rows and their index take.
+    pub bytes: usize,
+    /// How many bytes the rows would take as full row structures.
+    pub unencoded_bytes: usize,
+}
+
+impl CompiledTableMemory {
+    /// Get how many bytes delta-encoding the rows saved.
+    pub fn saved(&self) -> usize {
+        self.unencoded_bytes.saturating_sub(self.bytes)
+    }
+}
+
+/// The size of an encoded row.
+const ROW_SIZE: usize = 32;
+
+impl CompiledTable {
+    /// Construct a table of the given rows, sorted by their first address,
+    /// for a module with the given bias.
+    pub(crate) fn new(bias: Bias, rows: &[CompiledRow]) -> CompiledTable {
+        let mut blocks = Vec::with_capacity((rows.len() + BLOCK_ROWS - 1) / BLOCK_ROWS);
+        let mut data = vec![];
+        for block in rows.chunks(BLOCK_ROWS) {
+            blocks.push((block[0].start, data.len()));
+            let mut previous = origin(block[0].start);
+            for row in block {
+                encode_row(&previous, row, &mut data);
+                previous = *row;
+            }
+        }
+        data.shrink_to_fit();
+
+        CompiledTable {
+            bias,
+            blocks,
+            data,
+            len: rows.len(),
+        }
+    }
+
+    /// How many rows this table has.
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Does this table have no rows?
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Decode this table's rows, in order.
+    pub(crate) fn rows<'t>(&'t self) -> impl Iterator<Item = CompiledRow> + 't {
+        (0..self.blocks.len()).flat_map(move |block| self.block(block))
+    }
+
+    /// Decode the rows of the given block.
+    fn block(&self, block: usize) -> Rows {
+        let (start, offset) = self.blocks[block];
+        let end = self
+            .blocks
+            .get(block + 1)
+            .map_or(self.data.len(), |&(_, end)| end);
+        Rows {
+            data: &self.data[..end],
+            offset,
+            previous: origin(start),
+        }
+    }
+
+    /// Keep only the rows for which `f` returns `true`.
+    pub(crate) fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&CompiledRow) -> bool,
+    {
+        let rows: Vec<_> = self.rows().filter(|row| f(row)).collect();
+        if rows.len() != self.len {
+            *self = CompiledTable::new(self.bias, &rows);
+        }
+    }
+
+    /// Get the stated virtual memory addresses from the start of the first
+    /// row to the end of the last, or `None` if there are no rows.
+    pub(crate) fn svma_range(&self) -> Option<Range<u64>> {
+        let &(start, _) = self.blocks.first()?;
+        let end = self.block(self.blocks.len() - 1).last()?.end;
+        Some(start..end)
+    }
+
+    /// Get how much memory this table takes.
+    pub(crate) fn memory(&self) -> CompiledTableMemory {
+        CompiledTableMemory {
+            bias: self.bias,
+            rows: self.len,
+            bytes: self.data.len() + self.blocks.len() * mem::size_of::<(u64, usize)>(),
+            unencoded_bytes: self.len * mem::size_of::<CompiledRow>(),
+        }
+    }
+
+    /// Encode this table's rows.
+    ///
+    /// Each row is 32 little-endian bytes:
+    ///
+    /// | Offset | Type  | Field                                      |
+    /// |--------|-------|--------------------------------------------|
+    /// | 0      | `u64` | first stated virtual memory address        |
+    /// | 8      | `u64` | one past the last address                  |
+    /// | 16     | `i32` | CFA offset                                 |
+    /// | 20     | `i32` | frame base pointer rule's operand          |
+    /// | 24     | `i32` | return address rule's operand              |
+    /// | 28     | `u8`  | CFA register's DWARF register number       |
+    /// | 29     | `u8`  | frame base pointer rule's kind             |
+    /// | 30     | `u8`  | return address rule's kind                 |
+    /// | 31     | `u8`  | reserved, zero                             |
+    ///
+    /// A rule's kind is 0 for undefined, 1 for same value, 2 for saved at
+    /// CFA + operand, 3 for CFA + operand, and 4 for the value of the
+    /// register numbered by the operand.
+    pub(crate) fn encode_rows(&self, out: &mut Vec<u8>) {
+        out.reserve(self.len * ROW_SIZE);
+        for row in self.rows() {
+            let (bp_kind, bp_value) = row.bp.encode();
+            let (ra_kind, ra_value) = row.ra.encode();
+            out.extend_from_slice(&row.start.to_le_bytes());
+            out.extend_from_slice(&row.end.to_le_bytes());
+            out.extend_from_slice(&row.cfa_offset.to_le_bytes());
+            out.extend_from_slice(&bp_value.to_le_bytes());
+            out.extend_from_slice(&ra_value.to_le_bytes());
+            out.extend_from_slice(&[row.cfa_register, bp_kind, ra_kind, 0]);
+        }
+    }
+
+    /// Decode rows encoded by `encode_rows`, for a module with the given
+    /// bias.
+    pub(crate) fn decode_rows(bias: Bias, data: &[u8]) -> Option<CompiledTable> {
+        if data.len() % ROW_SIZE != 0 {
+            return None;
+        }
+
+        let rows = data.chunks(ROW_SIZE)
+            .map(|row| {
+                Some(CompiledRow {
+                    start: u64_at(row, 0),
+                    end: u64_at(row, 8),
+                    cfa_offset: i32_at(row, 16),
+                    cfa_register: row[28],
+                    bp: CompiledRule::decode(row[29], i32_at(row, 20))?,
+                    ra: CompiledRule::decode(row[30], i32_at(row, 24))?,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(CompiledTable::new(bias, &rows))
+    }
+
+    /// Find the row covering the given actual virtual memory address.
+    ///
+    /// This does not allocate.
+    pub(crate) fn lookup(&self, ip: usize) -> Option<CompiledRow> {
+        let svma = (ip as isize).wrapping_sub(self.bias.0) as usize as u64;
+        let block = self.blocks.partition_point(|&(start, _)| start <= svma);
+        if block == 0 {
+            return None;
+        }
+        self.block(block - 1)
+            .take_while(|row| row.start <= svma)
+            .find(|row| svma < row.end)
+    }
+}
+
+/// The row that a block's first row is encoded relative to.
+fn origin(start: u64) -> CompiledRow {
+    CompiledRow {
+        start,
+        end: start,
+        cfa_register: 0,
+        cfa_offset: 0,
+        bp: CompiledRule::Undefined,
+        ra: CompiledRule::Undefined,
+    }
+}
+
+fn encode_row(previous: &CompiledRow, row: &CompiledRow, out: &mut Vec<u8>) {
+    let mut flags = 0;
+    if row.start != previous.end {
+        flags |= GAP;
+    }
+    if row.cfa_register != previous.cfa_register {
+        flags |= CFA_REGISTER;
+    }
+    if row.bp != previous.bp {
+        flags |= BP;
+    }
+    if row.ra != previous.ra {
+        flags |= RA;
+    }
+
+    out.push(flags);
+    if flags & GAP != 0 {
+        write_sleb128(out, row.start.wrapping_sub(previous.end) as i64);
+    }
+    write_uleb128(out, row.end.wrapping_sub(row.start));
+    write_sleb128(out, row.cfa_offset as i64 - previous.cfa_offset as i64);
+    if flags & CFA_REGISTER != 0 {
+        out.push(row.cfa_register);
+    }
+    for &(flag, rule) in &[(BP, row.bp), (RA, row.ra)] {
+        if flags & flag != 0 {
+            let (kind, value) = rule.encode();
+            out.push(kind);
+            write_sleb128(out, value as i64);
+        }
+    }
+}
+
+/// The rows of one block of a `CompiledTable`, decoded as they are iterated.
+struct Rows<'t> {
+    /// The table's encoded rows, up to the end of the block.
+    data: &'t [u8],
+    offset: usize,
+    previous: CompiledRow,
+}
+
+impl<'t> Rows<'t> {
+    fn byte(&mut self) -> u8 {
+        let byte = self.data[self.offset];
+        self.offset += 1;
+        byte
+    }
+
+    fn uleb128(&mut self) -> u64 {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte();
+            value |= ((byte & 0x7f) as u64).wrapping_shl(shift);
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return value;
+            }
+        }
+    }
+
+    fn sleb128(&mut self) -> i64 {
+        let mut value = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte();
+            value |= ((byte & 0x7f) as i64).wrapping_shl(shift);
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1i64 << shift;
+                }
+                return value;
+            }
+        }
+    }
+
+    fn rule(&mut self) -> CompiledRule {
+        let kind = self.byte();
+        let value = self.sleb128() as i32;
+        // Only `encode_row` writes rules, so they always decode.
+        CompiledRule::decode(kind, value).unwrap_or(CompiledRule::Undefined)
+    }
+}
+
+impl<'t> Iterator for Rows<'t> {
+    type Item = CompiledRow;
+
+    fn next(&mut self) -> Option<CompiledRow> {
+        if self.offset == self.data.len() {
+            return None;
+        }
+
+        let flags = self.byte();
+        let mut row = self.previous;
+        row.start = if flags & GAP != 0 {
+            self.previous.end.wrapping_add(self.sleb128() as u64)
+        } else {
+            self.previous.end
+        };
+        row.end = row.start.wrapping_add(self.uleb128());
+        row.cfa_offset = (self.previous.cfa_offset as i64 + self.sleb128()) as i32;
+        if flags & CFA_REGISTER != 0 {
+            row.cfa_register = self.byte();
+        }
+        if flags & BP != 0 {
+            row.bp = self.rule();
+        }
+        if flags & RA != 0 {
+            row.ra = self.rule();
+        }
+
+        self.previous = row;
+        Some(row)
+    }
+}
+
+fn write_uleb128(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_sleb128(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// The magic bytes at the start of exported unwind tables.
+const EXPORT_MAGIC: &'static [u8; 8] = b"PCUNWIND";
+
+/// The version of the export format. See `Options::export_unwind_tables`.
+const EXPORT_VERSION: u32 = 1;
+
+/// Write the given tables in the export format described by
+/// `Options::export_unwind_tables`.
+pub(crate) fn export<W>(tables: &[CompiledTable], mut out: W) -> io::Result<()>
+where
+    W: Write,
+{
+    let mut arch = [0; 16];
+    arch[..env::consts::ARCH.len()].copy_from_slice(env::consts::ARCH.as_bytes());
+
+    out.write_all(EXPORT_MAGIC)?;
+    out.write_all(&EXPORT_VERSION.to_le_bytes())?;
+    out.write_all(&(tables.len() as u32).to_le_bytes())?;
+    out.write_all(&arch)?;
+
+    let mut rows = vec![];
+    for table in tables {
+        rows.clear();
+        table.encode_rows(&mut rows);
+        out.write_all(&(table.bias.0 as i64).to_le_bytes())?;
+        out.write_all(&(table.len() as u64).to_le_bytes())?;
+        out.write_all(&rows)?;
+    }
+    Ok(())
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    let mut le = [0; 8];
+    le.copy_from_slice(&bytes[offset..offset + 8]);
+    u64::from_le_bytes(le)
+}
+
+fn i32_at(bytes: &[u8], offset: usize) -> i32 {
+    let mut le = [0; 4];
+    le.copy_from_slice(&bytes[offset..offset + 4]);
+    i32::from_le_bytes(le)
+}
+
+/// Collects compiled rows, grouped by module.
+#[derive(Debug, Default)]
+pub(crate) struct Compiler {
+    modules: BTreeMap<isize, Vec<CompiledRow>>,
+}
+
+impl Compiler {
+    /// Compile every FDE in the given `.eh_frame` or `.debug_frame` section.
+    pub(crate) fn add_section<'a, Section>(
+        &mut self,
+        bias: Bias,
+        bases: &gimli::BaseAddresses,
+        section: &Section,
+    ) -> Result<()>
+    where
+        Section: UnwindSection<TargetEndianBuf<'a>>,
+    {
+        let mut entries = section.entries(bases);
+        while let Some(entry) = entries.next()? {
+            if let gimli::CieOrFde::Fde(partial) = entry {
+                let fde = partial.parse(|offset| section.cie_from_offset(bases, offset))?;
+                self.add_fde(bias, &fde)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Compile every row of the given FDE's unwind table.
+    pub(crate) fn add_fde<'a, Section>(
+        &mut self,
+        bias: Bias,
+        fde: &gimli::FrameDescriptionEntry<Section, TargetEndianBuf<'a>>,
+    ) -> Result<()>
+    where
+        Section: UnwindSection<TargetEndianBuf<'a>>,
+    {
+        // Signal frames are walked from the registers the kernel saved, not
+        // their rows.
+        if fde.is_signal_trampoline() {
+            return Ok(());
+        }
+
+        let return_address_register = fde.cie().return_address_register() as u8;
+        let rows = self.modules.entry(bias.0).or_insert_with(Vec::new);
+
+        // This runs while configuring, not while walking, so a fresh context
+        // per FDE is fine.
+        let mut ctx = gimli::UninitializedUnwindContext::new()
+            .initialize(fde.cie())
+            .map_err(|(e, _)| e)?;
+        let mut table = gimli::UnwindTable::new(&mut ctx, fde);
+        while let Some(row) = table.next_row()? {
+            if let Some(row) = CompiledRow::compile(row, return_address_register) {
+                rows.push(row);
+            }
+        }
+        Ok(())
+    }
+
+    /// Finish compiling, and get the compiled table of each module.
+    pub(crate) fn finish(self) -> Vec<CompiledTable> {
+        self.modules
+            .into_iter()
+            .map(|(bias, mut rows)| {
+                rows.sort_by_key(|row| row.start);
+                CompiledTable::new(Bias(bias), &rows)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reader::SliceMemory;
+    use std::mem;
+
+    fn row(start: u64, end: u64) -> CompiledRow {
+        CompiledRow {
+            start,
+            end,
+            cfa_register: registers::SP,
+            cfa_offset: 2 * mem::size_of::<usize>() as i32,
+            bp: CompiledRule::Offset(-2 * mem::size_of::<usize>() as i32),
+            ra: CompiledRule::Offset(-(mem::size_of::<usize>() as i32)),
+        }
+    }
+
+    #[test]
+    fn lookup() {
+        let table = CompiledTable::new(
+            Bias(0x1000),
+            &[row(0x10, 0x20), row(0x20, 0x24), row(0x30, 0x40)],
+        );
+        assert_eq!(table.lookup(0x1010), Some(row(0x10, 0x20)));
+        assert_eq!(table.lookup(0x101f), Some(row(0x10, 0x20)));
+        assert_eq!(table.lookup(0x1020), Some(row(0x20, 0x24)));
+        assert_eq!(table.lookup(0x1024), None);
+        assert_eq!(table.lookup(0x103f), Some(row(0x30, 0x40)));
+        assert_eq!(table.lookup(0x1040), None);
+        assert_eq!(table.lookup(0x10), None);
+        assert_eq!(table.svma_range(), Some(0x10..0x40));
+    }
+
+    #[test]
+    fn delta_encoding() {
+        // Rows with gaps, overlaps, and changing rules, spanning blocks.
+        let rows: Vec<_> = (0..100u64)
+            .map(|i| {
+                let mut row = row(i * 0x10 + i % 3, i * 0x10 + 0x10 + i % 5);
+                row.cfa_offset = (i as i32 % 7 - 3) * 8;
+                if i % 4 == 0 {
+                    row.cfa_register = registers::BP;
+                    row.bp = CompiledRule::SameValue;
+                }
+                if i % 9 == 0 {
+                    row.ra = CompiledRule::Register(30);
+                }
+                row
+            })
+            .collect();
+        let mut table = CompiledTable::new(Bias(0), &rows);
+        assert_eq!(table.len(), rows.len());
+        assert_eq!(table.rows().collect::<Vec<_>>(), rows);
+        for row in &rows {
+            let found = table.lookup(row.start as usize).unwrap();
+            assert!(found.start <= row.start && row.start < found.end);
+        }
+
+        let memory = table.memory();
+        assert_eq!(memory.rows, 100);
+        assert!(memory.bytes < memory.unencoded_bytes / 4, "{:?}", memory);
+        assert_eq!(memory.saved(), memory.unencoded_bytes - memory.bytes);
+
+        table.retain(|row| row.start % 2 == 0);
+        let even: Vec<_> = rows
+            .iter()
+            .filter(|row| row.start % 2 == 0)
+            .cloned()
+            .collect();
+        assert_eq!(table.rows().collect::<Vec<_>>(), even);
+
+        let empty = CompiledTable::new(Bias(0), &[]);
+        assert!(empty.is_empty());
+        assert_eq!(empty.lookup(0), None);
+        assert_eq!(empty.svma_range(), None);
+    }
+
+    #[test]
+    fn unwind() {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&0x5000usize.to_ne_bytes());
+        bytes.extend_from_slice(&0xdeadusize.to_ne_bytes());
+        let reader = SliceMemory::new(0x2000, &bytes);
+
+        let regs = FrameRegisters::from_parts(
+            TaggedWord::valid(0x1234),
+            TaggedWord::valid(0x2000),
+            TaggedWord::valid(0x4000),
+        );
+        let caller = unsafe { row(0, 1).unwind(&regs, &reader).unwrap() };
+        assert_eq!(caller.sp(), TaggedWord::valid(0x2000 + bytes.len()));
+        assert_eq!(caller.bp(), TaggedWord::valid(0x5000));
+        assert_eq!(caller.ip(), TaggedWord::valid(0xdead));
+
+        // A frameless function leaves the caller's frame pointer alone.
+        let mut frameless = row(0, 1);
+        frameless.bp = CompiledRule::Undefined;
+        let caller = unsafe { frameless.unwind(&regs, &reader).unwrap() };
+        assert_eq!(caller.bp(), TaggedWord::valid(0x1234));
+        assert_eq!(caller.ip(), TaggedWord::valid(0xdead));
+    }
+
+    #[test]
+    fn encode_decode() {
+        let mut other = row(0x20, 0x28);
+        other.bp = CompiledRule::SameValue;
+        other.ra = CompiledRule::Register(30);
+        let mut undefined = row(0x28, 0x30);
+        undefined.bp = CompiledRule::Undefined;
+        undefined.ra = CompiledRule::ValOffset(-4);
+        let table = CompiledTable::new(Bias(0x1000), &[row(0x10, 0x20), other, undefined]);
+
+        let mut encoded = vec![];
+        table.encode_rows(&mut encoded);
+        assert_eq!(encoded.len(), 3 * ROW_SIZE);
+        assert_eq!(CompiledTable::decode_rows(Bias(0x1000), &encoded), Some(table));
+
+        assert_eq!(CompiledTable::decode_rows(Bias(0), &encoded[1..]), None);
+        encoded[29] = 5;
+        assert_eq!(CompiledTable::decode_rows(Bias(0), &encoded), None);
+    }
+
+    #[test]
+    fn export_tables() {
+        let tables = vec![
+            CompiledTable::new(Bias(0x1000), &[row(0x10, 0x20), row(0x20, 0x30)]),
+            CompiledTable::new(Bias(-0x10), &[row(0x40, 0x50)]),
+        ];
+
+        let mut exported = vec![];
+        export(&tables, &mut exported).unwrap();
+        assert_eq!(exported.len(), 32 + 2 * 16 + 3 * ROW_SIZE);
+        assert_eq!(&exported[..8], b"PCUNWIND");
+        assert_eq!(&exported[8..16], &[1, 0, 0, 0, 2, 0, 0, 0]);
+        assert!(exported[16..32].starts_with(env::consts::ARCH.as_bytes()));
+
+        let first = &exported[32..];
+        assert_eq!(u64_at(first, 0), 0x1000);
+        assert_eq!(u64_at(first, 8), 2);
+        assert_eq!(
+            CompiledTable::decode_rows(Bias(0x1000), &first[16..16 + 2 * ROW_SIZE]),
+            Some(tables[0].clone())
+        );
+
+        let second = &first[16 + 2 * ROW_SIZE..];
+        assert_eq!(u64_at(second, 0) as i64, -0x10);
+        assert_eq!(u64_at(second, 8), 1);
+        assert_eq!(
+            CompiledTable::decode_rows(Bias(-0x10), &second[16..]),
+            Some(tables[1].clone())
+        );
+    }
+}
@@ -1,5 +1,8 @@
 //! Control flow for continuing or halting stack walking.
 
+use error::{Error, Result};
+use std::ops::ControlFlow;
+
 /// Whether to continue unwinding or stop.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum StackWalkControl {
@@ -39,3 +42,143 @@ impl<T, E> AsStackWalkControl for Result<T, E> {
         }
     }
 }
+
+/// `None` stops unwinding, so that callbacks can use `?` on options to stop
+/// when something is missing.
+impl<T> AsStackWalkControl for Option<T> {
+    fn as_stack_walk_control(&self) -> StackWalkControl {
+        if self.is_some() {
+            StackWalkControl::Continue
+        } else {
+            StackWalkControl::Break
+        }
+    }
+}
+
+impl<B, C> AsStackWalkControl for ControlFlow<B, C> {
+    fn as_stack_walk_control(&self) -> StackWalkControl {
+        match *self {
+            ControlFlow::Continue(_) => StackWalkControl::Continue,
+            ControlFlow::Break(_) => StackWalkControl::Break,
+        }
+    }
+}
+
+/// Why a stack walk stopped.
+#[derive(Debug)]
+pub enum WalkStop {
+    /// The callback returned a value whose `AsStackWalkControl` is
+    /// `StackWalkControl::Break`.
+    Break,
+
+    /// `Options::max_frames` frames were walked.
+    MaxFrames,
+
+    /// The outermost frame was walked: its caller's return address is
+    /// undefined or null, or it is the end of the frame pointer chain.
+    End,
+
+    /// The last frame walked could not be walked to its caller, e.g. because
+    /// its unwind information is missing or the stack could not be read.
+    Error(Error),
+}
+
+/// The result of walking a stack: how many frames were walked before it
+/// stopped, and why it stopped.
+///
+/// Walks that fail part way up the stack still report the frames they
+/// walked, rather than throwing them away for the error.
+#[derive(Debug)]
+pub struct WalkOutcome<T> {
+    /// The number of frames the callback was called on.
+    pub frames_walked: usize,
+
+    /// What the callback returned for the last frame it was called on, or
+    /// `None` if no frame could be walked.
+    pub last: Option<T>,
+
+    /// Why the walk stopped.
+    pub stopped_because: WalkStop,
+}
+
+impl<T> WalkOutcome<T> {
+    /// A walk that failed before any frame was walked.
+    pub(crate) fn failed(error: Error) -> WalkOutcome<T> {
+        WalkOutcome {
+            frames_walked: 0,
+            last: None,
+            stopped_because: WalkStop::Error(error),
+        }
+    }
+
+    /// Get the error that stopped the walk, if any.
+    pub fn error(&self) -> Option<&Error> {
+        match self.stopped_because {
+            WalkStop::Error(ref e) => Some(e),
+            WalkStop::Break | WalkStop::MaxFrames | WalkStop::End => None,
+        }
+    }
+
+    /// Treat the walk as all or nothing: get what the callback returned for
+    /// the last frame, unless an error stopped the walk.
+    pub fn into_result(self) -> Result<T> {
+        match self.stopped_because {
+            WalkStop::Error(e) => Err(e),
+            WalkStop::Break | WalkStop::MaxFrames | WalkStop::End => {
+                Ok(self.last.expect("walks that do not fail call the callback"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn option() {
+        assert_eq!(Some(1).as_stack_walk_control(), StackWalkControl::Continue);
+        assert_eq!(None::<u32>.as_stack_walk_control(), StackWalkControl::Break);
+    }
+
+    #[test]
+    fn control_flow() {
+        let flow: ControlFlow<&str> = ControlFlow::Continue(());
+        assert_eq!(flow.as_stack_walk_control(), StackWalkControl::Continue);
+        let flow: ControlFlow<&str> = ControlFlow::Break("found it");
+        assert_eq!(flow.as_stack_walk_control(), StackWalkControl::Break);
+    }
+
+    #[test]
+    fn outcome_into_result() {
+        let outcome = WalkOutcome {
+            frames_walked: 3,
+            last: Some(7),
+            stopped_because: WalkStop::MaxFrames,
+        };
+        assert!(outcome.error().is_none());
+        assert_eq!(outcome.into_result().unwrap(), 7);
+
+        let outcome = WalkOutcome {
+            frames_walked: 4,
+            last: Some(7),
+            stopped_because: WalkStop::End,
+        };
+        assert!(outcome.error().is_none());
+        assert_eq!(outcome.into_result().unwrap(), 7);
+
+        let outcome = WalkOutcome {
+            frames_walked: 2,
+            last: Some(7),
+            stopped_because: WalkStop::Error(Error::NoUnwindInfoForAddress(0x1234)),
+        };
+        match outcome.error() {
+            Some(&Error::NoUnwindInfoForAddress(0x1234)) => {}
+            otherwise => panic!("unexpected error: {:?}", otherwise),
+        }
+        match outcome.into_result() {
+            Err(Error::NoUnwindInfoForAddress(0x1234)) => {}
+            otherwise => panic!("unexpected result: {:?}", otherwise),
+        }
+    }
+}
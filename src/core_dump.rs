@@ -0,0 +1,270 @@
+//! Reading memory and registers from ELF core dumps, for offline stack
+//! walking of crashed processes.
+//!
+//! Only 64-bit core dumps with this host's endianness are supported.
+
+use super::{Error, MemoryReader, Result};
+#[cfg(target_arch = "x86_64")]
+use super::{FrameRegisters, TaggedWord};
+use std::fs;
+use std::mem;
+use std::path::Path;
+
+const ELFCLASS64: u8 = 2;
+#[cfg(target_endian = "little")]
+const ELFDATA_NATIVE: u8 = 1;
+#[cfg(target_endian = "big")]
+const ELFDATA_NATIVE: u8 = 2;
+const ET_CORE: u16 = 4;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+#[cfg(target_arch = "x86_64")]
+const NT_PRSTATUS: u32 = 1;
+
+/// A loaded segment of the crashed process's memory.
+#[derive(Clone, Debug)]
+struct Segment {
+    vaddr: usize,
+    memsz: usize,
+    offset: usize,
+    filesz: usize,
+}
+
+/// A `MemoryReader` that serves reads from the `PT_LOAD` segments of an ELF
+/// core dump.
+///
+/// Addresses inside a segment but beyond the part of it that was written to
+/// the core read as zero, like the `.bss` of an executable.
+#[derive(Debug)]
+pub struct CoreDump {
+    data: Vec<u8>,
+    segments: Vec<Segment>,
+    notes: Vec<(usize, usize)>,
+}
+
+impl CoreDump {
+    /// Read and parse the core dump at the given path.
+    pub fn open<P>(path: P) -> Result<CoreDump>
+    where
+        P: AsRef<Path>,
+    {
+        let data = fs::read(path).map_err(Error::Io)?;
+        CoreDump::parse(data)
+    }
+
+    /// Parse a core dump from its contents.
+    pub fn parse(data: Vec<u8>) -> Result<CoreDump> {
+        if data.get(0..4) != Some(&b"\x7fELF"[..]) ||
+            data.get(4) != Some(&ELFCLASS64) ||
+            data.get(5) != Some(&ELFDATA_NATIVE) ||
+            read_u16(&data, 16)? != ET_CORE
+        {
+            return Err(Error::InvalidCoreDump);
+        }
+
+        let phoff = read_u64(&data, 32)? as usize;
+        let phentsize = read_u16(&data, 54)? as usize;
+        let phnum = read_u16(&data, 56)? as usize;
+
+        let mut segments = vec![];
+        let mut notes = vec![];
+        for i in 0..phnum {
+            let ph = phoff + i * phentsize;
+            let p_offset = read_u64(&data, ph + 8)? as usize;
+            let p_filesz = read_u64(&data, ph + 32)? as usize;
+            if p_offset.checked_add(p_filesz).map_or(true, |end| end > data.len()) {
+                return Err(Error::InvalidCoreDump);
+            }
+
+            match read_u32(&data, ph)? {
+                PT_LOAD => segments.push(Segment {
+                    vaddr: read_u64(&data, ph + 16)? as usize,
+                    memsz: read_u64(&data, ph + 40)? as usize,
+                    offset: p_offset,
+                    filesz: p_filesz,
+                }),
+                PT_NOTE => notes.push((p_offset, p_filesz)),
+                _ => {}
+            }
+        }
+        segments.sort_by_key(|s| s.vaddr);
+
+        Ok(CoreDump {
+            data,
+            segments,
+            notes,
+        })
+    }
+
+    /// Iterate over the `(type, descriptor)` pairs of every note in the core
+    /// dump.
+    fn each_note<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(u32, &[u8]) -> Result<()>,
+    {
+        fn align4(n: usize) -> usize {
+            (n + 3) & !3
+        }
+
+        for &(offset, size) in &self.notes {
+            let notes = &self.data[offset..offset + size];
+            let mut at = 0;
+            while at + 12 <= notes.len() {
+                let name_size = read_u32(notes, at)? as usize;
+                let desc_size = read_u32(notes, at + 4)? as usize;
+                let note_type = read_u32(notes, at + 8)?;
+                let desc_start = at + 12 + align4(name_size);
+                let desc = notes
+                    .get(desc_start..desc_start + desc_size)
+                    .ok_or(Error::InvalidCoreDump)?;
+                f(note_type, desc)?;
+                at = desc_start + align4(desc_size);
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the id and registers of every thread in the core dump, from its
+    /// `NT_PRSTATUS` notes. The crashing thread comes first.
+    #[cfg(target_arch = "x86_64")]
+    pub fn threads(&self) -> Result<Vec<(u32, FrameRegisters)>> {
+        // Offsets into `struct elf_prstatus`, and indices into its `pr_reg`,
+        // which is a `struct user_regs_struct`.
+        const PR_PID: usize = 32;
+        const PR_REG: usize = 112;
+        const RBP: usize = 4;
+        const RIP: usize = 16;
+        const RSP: usize = 19;
+
+        let mut threads = vec![];
+        self.each_note(|note_type, desc| {
+            if note_type == NT_PRSTATUS {
+                let reg = |i| {
+                    read_u64(desc, PR_REG + i * 8).map(|r| TaggedWord::valid(r as usize))
+                };
+                let registers = FrameRegisters::from_parts(reg(RBP)?, reg(RSP)?, reg(RIP)?);
+                threads.push((read_u32(desc, PR_PID)?, registers));
+            }
+            Ok(())
+        })?;
+        Ok(threads)
+    }
+}
+
+impl MemoryReader for CoreDump {
+    unsafe fn read(&self, addr: usize) -> Result<usize> {
+        let size = mem::size_of::<usize>();
+        let idx = match self.segments.binary_search_by_key(&addr, |s| s.vaddr) {
+            Ok(idx) => idx,
+            Err(0) => return Err(Error::InvalidAddress(addr)),
+            Err(idx) => idx - 1,
+        };
+        let segment = &self.segments[idx];
+
+        let start = addr - segment.vaddr;
+        if start.checked_add(size).map_or(true, |end| end > segment.memsz) {
+            return Err(Error::InvalidAddress(addr));
+        }
+
+        let mut word = [0; mem::size_of::<usize>()];
+        for (i, byte) in word.iter_mut().enumerate() {
+            if start + i < segment.filesz {
+                *byte = self.data[segment.offset + start + i];
+            }
+        }
+        Ok(usize::from_ne_bytes(word))
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    let bytes = data.get(offset..offset + 2).ok_or(Error::InvalidCoreDump)?;
+    let mut word = [0; 2];
+    word.copy_from_slice(bytes);
+    Ok(u16::from_ne_bytes(word))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes = data.get(offset..offset + 4).ok_or(Error::InvalidCoreDump)?;
+    let mut word = [0; 4];
+    word.copy_from_slice(bytes);
+    Ok(u32::from_ne_bytes(word))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64> {
+    let bytes = data.get(offset..offset + 8).ok_or(Error::InvalidCoreDump)?;
+    let mut word = [0; 8];
+    word.copy_from_slice(bytes);
+    Ok(u64::from_ne_bytes(word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_u16(out: &mut Vec<u8>, x: u16) {
+        out.extend_from_slice(&x.to_ne_bytes());
+    }
+
+    fn push_u32(out: &mut Vec<u8>, x: u32) {
+        out.extend_from_slice(&x.to_ne_bytes());
+    }
+
+    fn push_u64(out: &mut Vec<u8>, x: u64) {
+        out.extend_from_slice(&x.to_ne_bytes());
+    }
+
+    /// Build a core dump with one `PT_LOAD` segment at 0x1000, with 16 bytes
+    /// of memory of which the first 8 are in the file.
+    fn core_dump() -> Vec<u8> {
+        let mut core = vec![0x7f, b'E', b'L', b'F', ELFCLASS64, ELFDATA_NATIVE, 1];
+        core.resize(16, 0);
+        push_u16(&mut core, ET_CORE);
+        push_u16(&mut core, 62);
+        push_u32(&mut core, 1);
+        push_u64(&mut core, 0);
+        push_u64(&mut core, 64);
+        push_u64(&mut core, 0);
+        push_u32(&mut core, 0);
+        push_u16(&mut core, 64);
+        push_u16(&mut core, 56);
+        push_u16(&mut core, 1);
+        push_u16(&mut core, 0);
+        push_u16(&mut core, 0);
+        push_u16(&mut core, 0);
+        assert_eq!(core.len(), 64);
+
+        push_u32(&mut core, PT_LOAD);
+        push_u32(&mut core, 0);
+        push_u64(&mut core, 120);
+        push_u64(&mut core, 0x1000);
+        push_u64(&mut core, 0);
+        push_u64(&mut core, 8);
+        push_u64(&mut core, 16);
+        push_u64(&mut core, 0x1000);
+        assert_eq!(core.len(), 120);
+
+        push_u64(&mut core, 0xdead_beef);
+        core
+    }
+
+    #[test]
+    fn read_segments() {
+        let core = CoreDump::parse(core_dump()).expect("should parse core dump OK");
+        unsafe {
+            assert_eq!(core.read(0x1000).unwrap(), 0xdead_beef);
+            assert_eq!(core.read(0x1008).unwrap(), 0);
+            assert!(core.read(0xff8).is_err());
+            assert!(core.read(0x1010).is_err());
+        }
+    }
+
+    #[test]
+    fn invalid_core_dump() {
+        let mut core = core_dump();
+        core[16] = 2;
+        match CoreDump::parse(core) {
+            Err(Error::InvalidCoreDump) => {}
+            otherwise => panic!("unexpected result: {:?}", otherwise),
+        }
+    }
+}
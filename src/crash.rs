@@ -0,0 +1,428 @@
+//! An in-process crash reporter.
+//!
+//! The crash reporter is installed once at startup, when it is still safe to
+//! allocate: it takes ownership of a `Walker`, preallocates a buffer for the
+//! walked instruction pointers, and renders the list of loaded modules. When
+//! a fatal signal arrives, the handler captures the interrupted thread's
+//! registers from the signal's `ucontext`, walks its stack, and writes a crash
+//! record to a pre-opened file descriptor using only async-signal-safe calls.
+//!
+//! The crash record is line oriented:
+//!
+//! ```text
+//! pancakes-crash 1
+//! signal 11 code 1 addr 0x0
+//! tid 4242
+//! ip 0x55d0c0ffee10
+//! ip 0x55d0c0ffe0a4
+//! module 0x55d0c0ff0000 0x55d0c1000000 8f1c0e3e2d5a4b6f /usr/bin/example
+//! end
+//! ```
+//!
+//! Modules without a build id have `-` in place of it. After the record is
+//! written, the previously installed handlers are restored and the signal is
+//! left to take its course: faults re-fault when the faulting instruction is
+//! retried, and signals sent with `kill`, `raise`, or `abort` are sent again.
+//!
+//! Install an alternate signal stack with `sigaltstack` if you want crashes
+//! caused by stack overflow to be reported.
+
+use super::{Registers, Result, StackWalkControl, Walker};
+use error::Error;
+use ffi;
+use findshlibs::{self, NamedMemoryRange, SectionIterable, SharedLibrary};
+use libc;
+use std::io;
+use std::mem;
+use std::os::unix::io::RawFd;
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use std::usize;
+
+/// The signals that the crash reporter handles.
+pub const FATAL_SIGNALS: &'static [libc::c_int] = &[
+    libc::SIGSEGV,
+    libc::SIGBUS,
+    libc::SIGILL,
+    libc::SIGFPE,
+    libc::SIGABRT,
+    libc::SIGTRAP,
+];
+
+/// The version of the crash record format.
+const RECORD_VERSION: usize = 1;
+
+static STATE: AtomicPtr<CrashState> = AtomicPtr::new(0 as *mut CrashState);
+static HANDLING: AtomicBool = AtomicBool::new(false);
+
+struct CrashState {
+    fd: RawFd,
+    walker: Walker<'static>,
+    ips: Vec<usize>,
+    modules: Vec<u8>,
+    previous: Vec<(libc::c_int, libc::sigaction)>,
+}
+
+/// Install the crash reporter for every signal in `FATAL_SIGNALS`.
+///
+/// Crash records are written to `fd`, which must remain open for the rest of
+/// the process's lifetime. At most `max_frames` frames are recorded.
+///
+/// The list of loaded modules is captured now, so install the crash reporter
+/// after loading any shared libraries whose frames you want reported.
+pub fn install(fd: RawFd, walker: Walker<'static>, max_frames: usize) -> Result<()> {
+    // Record every previous handler before the state is published, so the
+    // handler never sees `previous` change.
+    let mut previous = Vec::with_capacity(FATAL_SIGNALS.len());
+    for &signal in FATAL_SIGNALS {
+        let mut action: libc::sigaction = unsafe { mem::zeroed() };
+        if unsafe { libc::sigaction(signal, ptr::null(), &mut action) } != 0 {
+            return Err(Error::Io(io::Error::last_os_error()));
+        }
+        previous.push((signal, action));
+    }
+
+    let state = Box::new(CrashState {
+        fd,
+        walker,
+        ips: vec![0; max_frames],
+        modules: render_modules(),
+        previous,
+    });
+    let state = Box::into_raw(state);
+
+    if STATE
+        .compare_exchange(ptr::null_mut(), state, Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        unsafe {
+            drop(Box::from_raw(state));
+        }
+        return Err(Error::CrashReporterAlreadyInstalled);
+    }
+
+    unsafe {
+        for (installed, &signal) in FATAL_SIGNALS.iter().enumerate() {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = handle_fatal_signal as usize;
+            action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+            libc::sigemptyset(&mut action.sa_mask);
+
+            if libc::sigaction(signal, &action, ptr::null_mut()) != 0 {
+                let e = io::Error::last_os_error();
+                // Roll back: restore the handlers replaced so far, and
+                // unpublish the state so that `install` can be retried.
+                for &(signal, ref previous) in &(*state).previous[..installed] {
+                    libc::sigaction(signal, previous, ptr::null_mut());
+                }
+                STATE.store(ptr::null_mut(), Ordering::SeqCst);
+                drop(Box::from_raw(state));
+                return Err(Error::Io(e));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+extern "C" fn handle_fatal_signal(
+    signal: libc::c_int,
+    info: *mut libc::siginfo_t,
+    context: *mut libc::c_void,
+) {
+    unsafe {
+        let state = STATE.load(Ordering::SeqCst);
+        if state.is_null() {
+            return;
+        }
+
+        // Only report the first crash. If the reporter itself crashes, or
+        // another thread crashes concurrently, just restore the previous
+        // handlers and let the signal proceed.
+        if !HANDLING.swap(true, Ordering::SeqCst) {
+            write_record(&mut *state, signal, info, context as *const ffi::ucontext_t);
+        }
+
+        for &(signal, ref previous) in &(*state).previous {
+            libc::sigaction(signal, previous, ptr::null_mut());
+        }
+
+        // A fault is raised again when the faulting instruction is retried
+        // on return, but a signal that was sent is gone once handled, so
+        // send it again. It stays blocked, and so pending, until the handler
+        // returns, and is then delivered to the previous handler.
+        if was_sent(info) {
+            resend(signal);
+        }
+    }
+}
+
+/// Was the signal sent by a process, rather than raised by a fault?
+#[cfg(any(target_os = "linux", target_os = "android"))]
+unsafe fn was_sent(info: *const libc::siginfo_t) -> bool {
+    (*info).si_code <= 0
+}
+
+/// Was the signal sent by a process, rather than raised by a fault? The BSDs
+/// and macOS number `SI_USER` and the other sent codes from 0x10001.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+unsafe fn was_sent(info: *const libc::siginfo_t) -> bool {
+    (*info).si_code <= 0 || (*info).si_code >= 0x10001
+}
+
+/// Send `signal` to the current thread.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+unsafe fn resend(signal: libc::c_int) {
+    let tid = libc::syscall(libc::SYS_gettid);
+    libc::syscall(libc::SYS_tgkill, libc::getpid() as libc::c_long, tid, signal as libc::c_long);
+}
+
+/// Send `signal` to the current thread.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+unsafe fn resend(signal: libc::c_int) {
+    libc::pthread_kill(libc::pthread_self(), signal);
+}
+
+unsafe fn write_record(
+    state: &mut CrashState,
+    signal: libc::c_int,
+    info: *const libc::siginfo_t,
+    context: *const ffi::ucontext_t,
+) {
+    let CrashState {
+        fd,
+        ref mut walker,
+        ref mut ips,
+        ref modules,
+        ..
+    } = *state;
+
+    let mut out = FdWriter::new(fd);
+    out.push(b"pancakes-crash ");
+    out.push_dec(RECORD_VERSION);
+    out.push(b"\nsignal ");
+    out.push_dec(signal as usize);
+    out.push(b" code ");
+    out.push_dec((*info).si_code as usize);
+    out.push(b" addr ");
+    out.push_hex((*info).si_addr() as usize);
+    out.push(b"\ntid ");
+    out.push_dec(current_tid());
+    out.push(b"\n");
+
+    let mut frames = 0;
+    if !ips.is_empty() {
+        let _ = walker.walk_from_ucontext(context, |frame| {
+            ips[frames] = frame.ip().unwrap_or(0);
+            frames += 1;
+            if frames == ips.len() {
+                StackWalkControl::Break
+            } else {
+                StackWalkControl::Continue
+            }
+        });
+    }
+    for ip in &ips[..frames] {
+        out.push(b"ip ");
+        out.push_hex(*ip);
+        out.push(b"\n");
+    }
+
+    out.push(modules);
+    out.push(b"end\n");
+    out.flush();
+}
+
+#[cfg(target_os = "linux")]
+fn current_tid() -> usize {
+    unsafe { libc::syscall(libc::SYS_gettid) as usize }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn current_tid() -> usize {
+    unsafe { libc::pthread_self() as usize }
+}
+
+/// Render the `module` lines of the crash record for every loaded module.
+fn render_modules() -> Vec<u8> {
+    let mut rendered = vec![];
+    findshlibs::TargetSharedLibrary::each(|shlib| {
+        let mut start = usize::MAX;
+        let mut end = 0;
+        let mut build_id = None;
+        for section in shlib.sections() {
+            let avma = section.actual_virtual_memory_address(shlib).0 as usize;
+            start = start.min(avma);
+            end = end.max(avma + section.len());
+
+            if section.name().to_bytes() == b".note.gnu.build-id" {
+                let note = unsafe { slice::from_raw_parts(avma as *const u8, section.len()) };
+                build_id = parse_build_id_note(note);
+            }
+        }
+
+        if start < end {
+            rendered.extend_from_slice(format!("module {:#x} {:#x} ", start, end).as_bytes());
+            match build_id {
+                Some(id) => {
+                    for byte in id {
+                        rendered.extend_from_slice(format!("{:02x}", byte).as_bytes());
+                    }
+                }
+                None => rendered.push(b'-'),
+            }
+            rendered.push(b' ');
+            rendered.extend_from_slice(shlib.name().to_bytes());
+            rendered.push(b'\n');
+        }
+
+        findshlibs::IterationControl::Continue
+    });
+    rendered
+}
+
+/// Extract the build id from the contents of a `.note.gnu.build-id`
+/// section.
+fn parse_build_id_note(note: &[u8]) -> Option<&[u8]> {
+    const NT_GNU_BUILD_ID: u32 = 3;
+
+    fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+        let bytes = bytes.get(offset..offset + 4)?;
+        let mut word = [0; 4];
+        word.copy_from_slice(bytes);
+        Some(u32::from_ne_bytes(word))
+    }
+
+    fn align4(n: usize) -> usize {
+        (n + 3) & !3
+    }
+
+    let name_size = read_u32(note, 0)? as usize;
+    let desc_size = read_u32(note, 4)? as usize;
+    let note_type = read_u32(note, 8)?;
+    if note_type != NT_GNU_BUILD_ID {
+        return None;
+    }
+
+    let desc_start = 12 + align4(name_size);
+    note.get(desc_start..desc_start + desc_size)
+}
+
+/// A tiny buffered writer that only uses async-signal-safe operations.
+struct FdWriter {
+    fd: RawFd,
+    buf: [u8; 512],
+    len: usize,
+}
+
+impl FdWriter {
+    fn new(fd: RawFd) -> FdWriter {
+        FdWriter {
+            fd,
+            buf: [0; 512],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            if self.len == self.buf.len() {
+                self.flush();
+            }
+            let n = bytes.len().min(self.buf.len() - self.len);
+            self.buf[self.len..self.len + n].copy_from_slice(&bytes[..n]);
+            self.len += n;
+            bytes = &bytes[n..];
+        }
+    }
+
+    fn push_dec(&mut self, mut n: usize) {
+        let mut digits = [0; 20];
+        let mut i = digits.len();
+        loop {
+            i -= 1;
+            digits[i] = b'0' + (n % 10) as u8;
+            n /= 10;
+            if n == 0 {
+                break;
+            }
+        }
+        self.push(&digits[i..]);
+    }
+
+    fn push_hex(&mut self, mut n: usize) {
+        let mut digits = [0; 18];
+        let mut i = digits.len();
+        loop {
+            i -= 1;
+            digits[i] = b"0123456789abcdef"[n & 0xf];
+            n >>= 4;
+            if n == 0 {
+                break;
+            }
+        }
+        i -= 2;
+        digits[i] = b'0';
+        digits[i + 1] = b'x';
+        self.push(&digits[i..]);
+    }
+
+    fn flush(&mut self) {
+        let mut written = 0;
+        while written < self.len {
+            let buf = &self.buf[written..self.len];
+            let r = unsafe { libc::write(self.fd, buf.as_ptr() as *const libc::c_void, buf.len()) };
+            if r < 0 {
+                if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                break;
+            }
+            written += r as usize;
+        }
+        self.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_id_note() {
+        let mut note = vec![];
+        note.extend_from_slice(&4u32.to_ne_bytes());
+        note.extend_from_slice(&3u32.to_ne_bytes());
+        note.extend_from_slice(&3u32.to_ne_bytes());
+        note.extend_from_slice(b"GNU\0");
+        note.extend_from_slice(&[0xab, 0xcd, 0xef]);
+        assert_eq!(parse_build_id_note(&note), Some(&[0xab, 0xcd, 0xef][..]));
+        assert_eq!(parse_build_id_note(&note[..8]), None);
+    }
+
+    #[test]
+    fn fd_writer_numbers() {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+
+        let mut out = FdWriter::new(fds[1]);
+        out.push_dec(0);
+        out.push(b" ");
+        out.push_dec(1234);
+        out.push(b" ");
+        out.push_hex(0);
+        out.push(b" ");
+        out.push_hex(usize::MAX);
+        out.flush();
+
+        let mut buf = [0u8; 64];
+        let n = unsafe { libc::read(fds[0], buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+        assert_eq!(&buf[..n as usize], b"0 1234 0x0 0xffffffffffffffff");
+
+        unsafe {
+            libc::close(fds[0]);
+            libc::close(fds[1]);
+        }
+    }
+}
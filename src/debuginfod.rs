@@ -0,0 +1,152 @@
+//! A client for [debuginfod] servers, which serve debug files by build ID.
+//!
+//! The client is configured the same way as elfutils' `debuginfod-find`:
+//!
+//! * `DEBUGINFOD_URLS`: a space-separated list of server URLs, which are
+//!   tried in order.
+//!
+//! * `DEBUGINFOD_CACHE_PATH`: the directory that fetched files are cached
+//!   in. Defaults to `$XDG_CACHE_HOME/debuginfod_client`, or
+//!   `$HOME/.cache/debuginfod_client`.
+//!
+//! Hand the client to `Options::debuginfod`, and `Options::add_module_from_file`
+//! will fetch the debug files of modules whose own unwind information is
+//! incomplete, and which have no local debug file.
+//!
+//! [debuginfod]: https://sourceware.org/elfutils/Debuginfod.html
+
+use super::Result;
+use build_id;
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use ureq;
+
+/// A debuginfod client with a local cache of fetched files.
+#[derive(Clone, Debug)]
+pub struct Client {
+    urls: Vec<String>,
+    cache_path: PathBuf,
+}
+
+impl Client {
+    /// Construct a client for the given server URLs, that caches fetched
+    /// files in the given directory.
+    pub fn new<I, S, P>(urls: I, cache_path: P) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+        P: Into<PathBuf>,
+    {
+        Client {
+            urls: urls.into_iter().map(Into::into).collect(),
+            cache_path: cache_path.into(),
+        }
+    }
+
+    /// Construct a client configured by the `DEBUGINFOD_URLS` and
+    /// `DEBUGINFOD_CACHE_PATH` environment variables.
+    ///
+    /// Returns `None` when `DEBUGINFOD_URLS` is unset or empty, or when no
+    /// cache directory can be determined.
+    pub fn from_env() -> Option<Self> {
+        let urls = env::var("DEBUGINFOD_URLS").ok()?;
+        let urls: Vec<_> = urls.split_whitespace().map(String::from).collect();
+        if urls.is_empty() {
+            return None;
+        }
+
+        let cache_path = env::var_os("DEBUGINFOD_CACHE_PATH")
+            .map(PathBuf::from)
+            .or_else(|| {
+                env::var_os("XDG_CACHE_HOME")
+                    .map(PathBuf::from)
+                    .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+                    .map(|cache| cache.join("debuginfod_client"))
+            })?;
+
+        Some(Client::new(urls, cache_path))
+    }
+
+    /// Get the debug file for the given build ID, from the cache or else from
+    /// the first server that has it.
+    ///
+    /// Returns `Ok(None)` when no server has it.
+    pub fn debuginfo(&self, build_id: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.fetch(build_id, "debuginfo")
+    }
+
+    /// Get the executable for the given build ID, from the cache or else from
+    /// the first server that has it.
+    ///
+    /// Returns `Ok(None)` when no server has it.
+    pub fn executable(&self, build_id: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.fetch(build_id, "executable")
+    }
+
+    fn fetch(&self, build_id: &[u8], kind: &str) -> Result<Option<Vec<u8>>> {
+        if build_id.is_empty() {
+            return Ok(None);
+        }
+
+        let build_id = build_id::to_hex(build_id);
+        let dir = self.cache_path.join(&build_id);
+        let cached = dir.join(kind);
+        match fs::read(&cached) {
+            Ok(data) => return Ok(Some(data)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        for url in &self.urls {
+            let url = format!("{}/buildid/{}/{}", url.trim_end_matches('/'), build_id, kind);
+            let response = match ureq::get(&url).call() {
+                Ok(response) => response,
+                // This server doesn't have it, or is down; try the next one.
+                Err(_) => continue,
+            };
+
+            let mut data = vec![];
+            if response.into_reader().read_to_end(&mut data).is_err() {
+                continue;
+            }
+
+            // Write to a temporary file of this process's own and rename it
+            // into place, so that a concurrent reader never sees a partial
+            // file, even with other processes fetching the same one.
+            fs::create_dir_all(&dir)?;
+            let temporary = dir.join(format!(".{}.{}.tmp", kind, ::std::process::id()));
+            fs::write(&temporary, &data)?;
+            fs::rename(&temporary, &cached)?;
+
+            return Ok(Some(data));
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Client;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn cached() {
+        let cache_path = env::temp_dir()
+            .join(format!("pancakes-debuginfod-{}", ::std::process::id()));
+        fs::create_dir_all(cache_path.join("abcd")).unwrap();
+        fs::write(cache_path.join("abcd").join("debuginfo"), b"cached").unwrap();
+
+        // No servers, so anything that isn't cached is missing.
+        let client = Client::new(Vec::<String>::new(), cache_path.clone());
+        assert_eq!(client.debuginfo(&[0xab, 0xcd]).unwrap(), Some(b"cached".to_vec()));
+        assert_eq!(client.executable(&[0xab, 0xcd]).unwrap(), None);
+        assert_eq!(client.debuginfo(&[0x12, 0x34]).unwrap(), None);
+        assert_eq!(client.debuginfo(&[]).unwrap(), None);
+
+        fs::remove_dir_all(&cache_path).unwrap();
+    }
+}
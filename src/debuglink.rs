@@ -0,0 +1,84 @@
+//! Finding separate debug files through a `.gnu_debuglink` section.
+//!
+//! A stripped binary's `.gnu_debuglink` section holds the file name of its
+//! debug file, padded to four bytes, followed by the CRC-32 of that file's
+//! contents. See "Separate Debug Files" in the GDB manual for the directories
+//! that are searched.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Parse a `.gnu_debuglink` section into the debug file's name and its
+/// expected CRC-32.
+pub(crate) fn parse(section: &[u8]) -> Option<(&[u8], u32)> {
+    let name_len = section.iter().position(|&b| b == 0)?;
+    let crc_offset = (name_len + 1 + 3) & !3;
+    let crc = section.get(crc_offset..crc_offset + 4)?;
+    let crc = (crc[0] as u32) | (crc[1] as u32) << 8 | (crc[2] as u32) << 16 |
+        (crc[3] as u32) << 24;
+    Some((&section[..name_len], crc))
+}
+
+/// Find and read the debug file with the given name and CRC-32, for the
+/// binary at the given path.
+///
+/// The binary's directory and its `.debug` subdirectory are searched first,
+/// then the binary's directory within each of the given debug file
+/// directories. Candidates whose contents do not match the CRC belong to some
+/// other build of the binary, and are skipped.
+pub(crate) fn find(
+    binary: &Path,
+    roots: &[PathBuf],
+    name: &[u8],
+    crc: u32,
+) -> Option<Vec<u8>> {
+    let name = ::std::str::from_utf8(name).ok()?;
+    let binary = fs::canonicalize(binary).ok()?;
+    let dir = binary.parent()?;
+    let relative_dir = dir.strip_prefix("/").unwrap_or(dir);
+
+    let mut candidates = vec![dir.join(name), dir.join(".debug").join(name)];
+    candidates.extend(roots.iter().map(|root| root.join(relative_dir).join(name)));
+    candidates
+        .iter()
+        .filter(|candidate| **candidate != binary)
+        .filter_map(|candidate| fs::read(candidate).ok())
+        .find(|data| crc32(data) == crc)
+}
+
+/// The CRC-32 used by `.gnu_debuglink`: the IEEE polynomial, reflected.
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32, parse};
+
+    #[test]
+    fn check_value() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn parse_section() {
+        let section = b"foo.debug\0\0\0\x26\x39\xf4\xcb";
+        assert_eq!(parse(section), Some((&b"foo.debug"[..], 0xcbf4_3926)));
+
+        // The name is padded to four bytes, including its terminator.
+        let section = b"abc\0\x01\x00\x00\x00";
+        assert_eq!(parse(section), Some((&b"abc"[..], 1)));
+
+        assert_eq!(parse(b"foo.debug"), None);
+        assert_eq!(parse(b"foo.debug\0\0\0\x26\x39"), None);
+    }
+}
@@ -0,0 +1,105 @@
+//! Finding the `.dSYM` bundle that holds a Mach-O image's DWARF.
+//!
+//! A bundle is matched to an image by the UUID in the image's `LC_UUID` load
+//! command. Bundles are looked for next to the image, then in the given
+//! search directories, and then, on macOS, through Spotlight, which indexes
+//! the UUIDs of every `.dSYM` on the system.
+
+use object::{self, Object};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Find and read the DWARF file of the `.dSYM` bundle for the image at the
+/// given path, with the given UUID.
+pub(crate) fn find(binary: &Path, roots: &[PathBuf], uuid: [u8; 16]) -> Option<Vec<u8>> {
+    let mut bundles = vec![];
+    if let Some(name) = binary.file_name() {
+        let mut bundle = name.to_os_string();
+        bundle.push(".dSYM");
+        bundles.push(binary.with_file_name(bundle));
+    }
+
+    for root in roots {
+        if let Ok(entries) = fs::read_dir(root) {
+            bundles.extend(
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path())
+                    .filter(|path| path.extension() == Some(OsStr::new("dSYM"))),
+            );
+        }
+    }
+
+    bundles.extend(spotlight(uuid));
+
+    bundles
+        .iter()
+        .flat_map(|bundle| dwarf_files(bundle))
+        .filter_map(|path| fs::read(path).ok())
+        .find(|data| {
+            object::File::parse(&data[..])
+                .ok()
+                .and_then(|file| file.mach_uuid().ok())
+                == Some(Some(uuid))
+        })
+}
+
+/// The DWARF files in the given `.dSYM` bundle. There is usually just one,
+/// named after the image.
+fn dwarf_files(bundle: &Path) -> Vec<PathBuf> {
+    let dir = bundle.join("Contents").join("Resources").join("DWARF");
+    match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect(),
+        Err(_) => vec![],
+    }
+}
+
+/// Ask Spotlight for the `.dSYM` bundles with the given UUID.
+#[cfg(target_os = "macos")]
+fn spotlight(uuid: [u8; 16]) -> Vec<PathBuf> {
+    use std::process::Command;
+
+    let query = format!("com_apple_xcode_dsym_uuids == {}", format_uuid(uuid));
+    match Command::new("mdfind").arg(query).output() {
+        Ok(ref output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(PathBuf::from)
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Spotlight only exists on macOS.
+#[cfg(not(target_os = "macos"))]
+fn spotlight(_uuid: [u8; 16]) -> Vec<PathBuf> {
+    vec![]
+}
+
+/// Format a UUID the way Spotlight indexes it, e.g.
+/// `01234567-89AB-CDEF-0123-456789ABCDEF`.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn format_uuid(uuid: [u8; 16]) -> String {
+    let mut formatted = String::with_capacity(36);
+    for (i, byte) in uuid.iter().enumerate() {
+        if i == 4 || i == 6 || i == 8 || i == 10 {
+            formatted.push('-');
+        }
+        formatted.push_str(&format!("{:02X}", byte));
+    }
+    formatted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_uuid;
+
+    #[test]
+    fn uuid() {
+        let uuid = [
+            0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0x01, 0x23, 0x45, 0x67, 0x89, 0xab,
+            0xcd, 0xef,
+        ];
+        assert_eq!(format_uuid(uuid), "01234567-89AB-CDEF-0123-456789ABCDEF");
+    }
+}
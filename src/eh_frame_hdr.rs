@@ -0,0 +1,214 @@
+//! Parsing the `.eh_frame_hdr` section's binary search table.
+//!
+//! The linker sorts every FDE in `.eh_frame` by the address it starts at, and
+//! records them in a table in `.eh_frame_hdr`. With it, the FDE for an address
+//! can be found with a binary search, and only that FDE needs to be parsed,
+//! rather than parsing every FDE ahead of time.
+//!
+//! See the Linux Standard Base Core Specification, section 10.6.2 "The
+//! .eh_frame_hdr section".
+
+use super::{Error, Result};
+
+const DW_EH_PE_OMIT: u8 = 0xff;
+
+const DW_EH_PE_UDATA4: u8 = 0x03;
+const DW_EH_PE_UDATA8: u8 = 0x04;
+const DW_EH_PE_SDATA4: u8 = 0x0b;
+const DW_EH_PE_SDATA8: u8 = 0x0c;
+
+const DW_EH_PE_ABSPTR: u8 = 0x00;
+const DW_EH_PE_PCREL: u8 = 0x10;
+const DW_EH_PE_DATAREL: u8 = 0x30;
+
+/// A parsed `.eh_frame_hdr` section.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct EhFrameHdr<'a> {
+    /// The stated virtual memory address of the `.eh_frame_hdr` section.
+    address: usize,
+    /// The stated virtual memory address of the `.eh_frame` section.
+    eh_frame_address: usize,
+    table_encoding: u8,
+    fde_count: usize,
+    table: &'a [u8],
+}
+
+impl<'a> EhFrameHdr<'a> {
+    /// Parse the contents of an `.eh_frame_hdr` section, which is at the
+    /// stated virtual memory address `address`.
+    pub(crate) fn parse(data: &'a [u8], address: usize) -> Result<EhFrameHdr<'a>> {
+        let byte = |offset: usize| data.get(offset).cloned().ok_or(Error::InvalidEhFrameHdr);
+
+        let version = byte(0)?;
+        let eh_frame_ptr_encoding = byte(1)?;
+        let fde_count_encoding = byte(2)?;
+        let table_encoding = byte(3)?;
+        if version != 1 {
+            return Err(Error::InvalidEhFrameHdr);
+        }
+
+        let (eh_frame_address, offset) = read_encoded(data, 4, eh_frame_ptr_encoding, address)?;
+        let (fde_count, offset) = read_encoded(data, offset, fde_count_encoding, address)?;
+
+        // The table can only be binary searched if it has fixed size entries
+        // relative to the start of this section.
+        if table_encoding & 0x70 != DW_EH_PE_DATAREL || encoded_size(table_encoding).is_none() {
+            return Err(Error::InvalidEhFrameHdr);
+        }
+
+        let entry_size = 2 * encoded_size(table_encoding).unwrap();
+        let table = fde_count
+            .checked_mul(entry_size)
+            .and_then(|len| data.get(offset..offset.checked_add(len)?))
+            .ok_or(Error::InvalidEhFrameHdr)?;
+
+        Ok(EhFrameHdr {
+            address,
+            eh_frame_address,
+            table_encoding,
+            fde_count,
+            table,
+        })
+    }
+
+    /// Find the offset within `.eh_frame` of the FDE that may cover the given
+    /// stated virtual memory address. The FDE must still be checked to see
+    /// whether it actually covers the address.
+    pub(crate) fn lookup(&self, address: usize) -> Option<usize> {
+        // Find the last entry starting at or before the address.
+        let (mut lo, mut hi) = (0, self.fde_count);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if self.entry(mid).0 <= address {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        if lo == 0 {
+            return None;
+        }
+
+        let fde_address = self.entry(lo - 1).1;
+        fde_address.checked_sub(self.eh_frame_address)
+    }
+
+    /// Get the stated virtual memory address of the first FDE's initial
+    /// location, if there is any FDE.
+    pub(crate) fn first_address(&self) -> Option<usize> {
+        if self.fde_count == 0 {
+            None
+        } else {
+            Some(self.entry(0).0)
+        }
+    }
+
+    /// Get the initial location and FDE address of the `i`th table entry.
+    fn entry(&self, i: usize) -> (usize, usize) {
+        // The table's bounds and encoding were checked when it was parsed.
+        let size = encoded_size(self.table_encoding).unwrap();
+        let read = |offset| {
+            read_encoded(self.table, offset, self.table_encoding, self.address)
+                .unwrap()
+                .0
+        };
+        (read(i * 2 * size), read((i * 2 + 1) * size))
+    }
+}
+
+/// The size of a value with the given pointer encoding, if it has a fixed
+/// size.
+fn encoded_size(encoding: u8) -> Option<usize> {
+    match encoding & 0x0f {
+        DW_EH_PE_UDATA4 | DW_EH_PE_SDATA4 => Some(4),
+        DW_EH_PE_UDATA8 | DW_EH_PE_SDATA8 => Some(8),
+        _ => None,
+    }
+}
+
+/// Read a value with the given pointer encoding at `offset`, where
+/// `section_address` is the address of `data`. Return the value and the
+/// offset just past it.
+fn read_encoded(
+    data: &[u8],
+    offset: usize,
+    encoding: u8,
+    section_address: usize,
+) -> Result<(usize, usize)> {
+    if encoding == DW_EH_PE_OMIT {
+        return Err(Error::InvalidEhFrameHdr);
+    }
+
+    let size = encoded_size(encoding).ok_or(Error::InvalidEhFrameHdr)?;
+    let bytes = data.get(offset..offset + size).ok_or(Error::InvalidEhFrameHdr)?;
+    let value = match encoding & 0x0f {
+        DW_EH_PE_UDATA4 => {
+            let mut word = [0; 4];
+            word.copy_from_slice(bytes);
+            u32::from_ne_bytes(word) as usize
+        }
+        DW_EH_PE_SDATA4 => {
+            let mut word = [0; 4];
+            word.copy_from_slice(bytes);
+            i32::from_ne_bytes(word) as isize as usize
+        }
+        _ => {
+            let mut word = [0; 8];
+            word.copy_from_slice(bytes);
+            u64::from_ne_bytes(word) as usize
+        }
+    };
+
+    let base = match encoding & 0x70 {
+        DW_EH_PE_ABSPTR => 0,
+        DW_EH_PE_PCREL => section_address.wrapping_add(offset),
+        DW_EH_PE_DATAREL => section_address,
+        _ => return Err(Error::InvalidEhFrameHdr),
+    };
+
+    Ok((base.wrapping_add(value), offset + size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_i32(out: &mut Vec<u8>, x: i32) {
+        out.extend_from_slice(&x.to_ne_bytes());
+    }
+
+    /// An `.eh_frame_hdr` at 0x2000, for an `.eh_frame` at 0x3000 with FDEs
+    /// at offsets 0x10 and 0x40, for functions at 0x1000 and 0x1100.
+    fn eh_frame_hdr() -> Vec<u8> {
+        let mut data = vec![1, 0x1b, 0x03, 0x3b];
+        push_i32(&mut data, 0x3000 - 0x2004);
+        push_i32(&mut data, 2);
+        push_i32(&mut data, 0x1000 - 0x2000);
+        push_i32(&mut data, 0x3010 - 0x2000);
+        push_i32(&mut data, 0x1100 - 0x2000);
+        push_i32(&mut data, 0x3040 - 0x2000);
+        data
+    }
+
+    #[test]
+    fn lookup() {
+        let data = eh_frame_hdr();
+        let hdr = EhFrameHdr::parse(&data, 0x2000).expect("should parse OK");
+        assert_eq!(hdr.eh_frame_address, 0x3000);
+        assert_eq!(hdr.lookup(0xfff), None);
+        assert_eq!(hdr.lookup(0x1000), Some(0x10));
+        assert_eq!(hdr.lookup(0x10ff), Some(0x10));
+        assert_eq!(hdr.lookup(0x1100), Some(0x40));
+        assert_eq!(hdr.lookup(0x9999), Some(0x40));
+    }
+
+    #[test]
+    fn invalid() {
+        let mut data = eh_frame_hdr();
+        data[0] = 2;
+        assert!(EhFrameHdr::parse(&data, 0x2000).is_err());
+
+        let data = eh_frame_hdr();
+        assert!(EhFrameHdr::parse(&data[..data.len() - 1], 0x2000).is_err());
+    }
+}
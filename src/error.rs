@@ -13,14 +13,82 @@ pub enum Error {
     /// An error parsing debug information with `gimli`.
     Gimli(gimli::Error),
 
+    /// Evaluating a DWARF expression in call frame information executed more
+    /// operations than `Options::expression_fuel` allows.
+    CfiExpressionOutOfFuel,
+
+    /// The crash reporter was already installed.
+    CrashReporterAlreadyInstalled,
+
+    /// A stack dump signal handler was already installed.
+    DumpHandlerAlreadyInstalled,
+
+    /// The given address cannot be read by this `MemoryReader`.
+    InvalidAddress(usize),
+
+    /// A Breakpad symbol file was malformed at the given line.
+    InvalidBreakpadSymbols(usize),
+
+    /// A DWARF expression in call frame information was malformed, or used
+    /// an operation that is not supported there.
+    InvalidCfiExpression,
+
+    /// An ELF core dump was malformed, or is not a 64-bit core dump with this
+    /// host's endianness.
+    InvalidCoreDump,
+
+    /// An `.eh_frame_hdr` section was malformed, or has an unsupported
+    /// version or encoding.
+    InvalidEhFrameHdr,
+
+    /// The environment variable with the given name has an invalid value.
+    InvalidEnvironmentVariable(&'static str),
+
+    /// A perf jitdump file was malformed.
+    InvalidJitDump,
+
+    /// An object file was malformed, or is not in a supported format.
+    InvalidObjectFile,
+
+    /// A remote debugging stub sent a malformed response.
+    InvalidRemoteResponse,
+
+    /// A `.llvm_stackmaps` section was malformed or has an unsupported
+    /// version.
+    InvalidStackMaps,
+
     /// Expected a valid word, but found an invalid one.
     InvalidTaggedWord,
 
     /// There is no unwinding information for the given address.
     NoUnwindInfoForAddress(usize),
 
+    /// Walking the frame at the given address did not make progress up the
+    /// stack: the caller's stack pointer was not above the frame's, or the
+    /// caller's registers were the same as the frame's. This happens with
+    /// corrupt frame pointers or bad unwind information, which would
+    /// otherwise loop forever.
+    NonProgressingUnwind(usize),
+
+    /// Allocating scratch space for walking failed.
+    OutOfMemory,
+
+    /// The profiler was already running.
+    ProfilerAlreadyRunning,
+
+    /// The thread with the given id could not be stopped to walk its stack:
+    /// it did not respond in time, or has exited.
+    ThreadNotResponding(usize),
+
     /// An unknown DWARF register number.
     UnknownRegister(u8),
+
+    /// Stack walking is not supported on this architecture.
+    UnsupportedArchitecture,
+
+    /// Pointers of the given width in bytes cannot be read: only 4 and 8 byte
+    /// pointers can.
+    UnsupportedPointerWidth(usize),
 }
 use Error::*;
 
@@ -37,9 +105,36 @@ impl fmt::Display for Error {
         match *self {
             Io(ref e) => write!(f, "{}", e),
             Gimli(ref e) => write!(f, "Error parsing debug info: {}", e),
+            CfiExpressionOutOfFuel => write!(f, "{}", self.description()),
+            CrashReporterAlreadyInstalled => write!(f, "{}", self.description()),
+            DumpHandlerAlreadyInstalled => write!(f, "{}", self.description()),
+            InvalidAddress(addr) => write!(f, "Cannot read memory at {:#x}", addr),
+            InvalidBreakpadSymbols(line) => {
+                write!(f, "Invalid Breakpad symbol file at line {}", line)
+            }
+            InvalidCfiExpression => write!(f, "{}", self.description()),
+            InvalidCoreDump => write!(f, "{}", self.description()),
+            InvalidEhFrameHdr => write!(f, "{}", self.description()),
+            InvalidEnvironmentVariable(name) => {
+                write!(f, "Invalid value for environment variable {}", name)
+            }
+            InvalidJitDump => write!(f, "{}", self.description()),
+            InvalidObjectFile => write!(f, "{}", self.description()),
+            InvalidRemoteResponse => write!(f, "{}", self.description()),
+            InvalidStackMaps => write!(f, "{}", self.description()),
             InvalidTaggedWord => write!(f, "{}", self.description()),
             NoUnwindInfoForAddress(addr) => write!(f, "No unwind information for {:#x}", addr),
+            NonProgressingUnwind(addr) => {
+                write!(f, "Walking the frame at {:#x} did not make progress", addr)
+            }
+            OutOfMemory => write!(f, "{}", self.description()),
+            ProfilerAlreadyRunning => write!(f, "{}", self.description()),
+            ThreadNotResponding(tid) => write!(f, "Could not stop thread {}", tid),
             UnknownRegister(reg) => write!(f, "Unknown DWARF register number: {}", reg),
+            UnsupportedArchitecture => write!(f, "{}", self.description()),
+            UnsupportedPointerWidth(width) => {
+                write!(f, "Unsupported pointer width: {} bytes", width)
+            }
         }
     }
 }
@@ -49,11 +144,30 @@ impl ErrorTrait for Error {
         match *self {
             Io(ref e) => e.description(),
             Gimli(_) => "Error parsing debug info",
+            CfiExpressionOutOfFuel => "Evaluating a DWARF expression in call frame information ran out of fuel",
+            CrashReporterAlreadyInstalled => "The crash reporter was already installed",
+            DumpHandlerAlreadyInstalled => "A stack dump handler was already installed",
+            InvalidAddress(_) => "Cannot read memory at address",
+            InvalidBreakpadSymbols(_) => "Invalid Breakpad symbol file",
+            InvalidCfiExpression => "Invalid or unsupported DWARF expression in call frame information",
+            InvalidCoreDump => "Invalid or unsupported ELF core dump",
+            InvalidEhFrameHdr => "Invalid or unsupported .eh_frame_hdr section",
+            InvalidEnvironmentVariable(_) => "Invalid value for environment variable",
+            InvalidJitDump => "Invalid perf jitdump file",
+            InvalidObjectFile => "Invalid or unsupported object file",
+            InvalidRemoteResponse => "Malformed response from remote debugging stub",
+            InvalidStackMaps => "Invalid or unsupported LLVM stack maps",
             InvalidTaggedWord => "Invalid tagged word",
             NoUnwindInfoForAddress(_) => {
                 "Tried to walk across a frame we do not have unwind information for"
             }
+            NonProgressingUnwind(_) => "Walking a frame did not make progress up the stack",
+            OutOfMemory => "Allocating scratch space for walking failed",
+            ProfilerAlreadyRunning => "The profiler was already running",
+            ThreadNotResponding(_) => "Could not stop a thread to walk its stack",
             UnknownRegister(_) => "Unknown DWARF register number",
+            UnsupportedArchitecture => "Stack walking is not supported on this architecture",
+            UnsupportedPointerWidth(_) => "Unsupported pointer width",
         }
     }
 
@@ -61,7 +175,28 @@ impl ErrorTrait for Error {
         match *self {
             Io(ref e) => Some(e),
             Gimli(ref e) => Some(e),
-            InvalidTaggedWord | NoUnwindInfoForAddress(_) | UnknownRegister(_) => None,
+            CfiExpressionOutOfFuel |
+            CrashReporterAlreadyInstalled |
+            DumpHandlerAlreadyInstalled |
+            InvalidAddress(_) |
+            InvalidBreakpadSymbols(_) |
+            InvalidCfiExpression |
+            InvalidCoreDump |
+            InvalidEhFrameHdr |
+            InvalidEnvironmentVariable(_) |
+            InvalidJitDump |
+            InvalidObjectFile |
+            InvalidRemoteResponse |
+            InvalidStackMaps |
+            InvalidTaggedWord |
+            NoUnwindInfoForAddress(_) |
+            NonProgressingUnwind(_) |
+            OutOfMemory |
+            ProfilerAlreadyRunning |
+            ThreadNotResponding(_) |
+            UnknownRegister(_) |
+            UnsupportedArchitecture |
+            UnsupportedPointerWidth(_) => None,
         }
     }
 }
@@ -72,5 +207,11 @@ impl From<gimli::Error> for Error {
     }
 }
 
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
 /// Either a `T` or a `pancakes::Error`.
 pub type Result<T> = ::std::result::Result<T, Error>;
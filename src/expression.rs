@@ -0,0 +1,482 @@
+//! Evaluation of the DWARF expressions in call frame information.
+//!
+//! Most CFI describes a frame's CFA as a register plus an offset, but
+//! hand-written assembly, e.g. in OpenSSL, and glibc's signal trampolines
+//! compute it with a DWARF expression instead. Only the operations that make
+//! sense in CFI are supported: those computing a value from constants,
+//! registers and memory (DWARF 5, section 6.4.2). Location operations like
+//! `DW_OP_reg0` and anything referring to debugging information entries are
+//! rejected.
+//!
+//! Evaluation uses a fixed-size stack from the `UnwindContext`, allocated up
+//! front, so that it never allocates and can run in a signal handler. It is
+//! limited to a number of operations, its fuel, so that branches in corrupt
+//! or malicious CFI cannot loop forever.
+
+use super::{Error, FrameRegisters, MemoryReader, Result, TaggedWord};
+use std::mem;
+
+/// A register set that expressions can refer to the registers of.
+pub(crate) trait RegisterSource {
+    /// Get the register with the given DWARF register number.
+    fn register(&self, register_num: u8) -> Result<TaggedWord>;
+}
+
+impl RegisterSource for FrameRegisters {
+    fn register(&self, register_num: u8) -> Result<TaggedWord> {
+        self.get_register(register_num)
+    }
+}
+
+/// The default maximum depth of the evaluation stack, which
+/// `UnwindContext::prepare` changes.
+pub(crate) const DEFAULT_STACK_DEPTH: usize = 64;
+
+/// The default number of operations an evaluation may execute. Real CFI
+/// expressions are a handful of operations.
+pub(crate) const DEFAULT_FUEL: usize = 1000;
+
+/// What evaluating an expression may use.
+#[derive(Debug)]
+pub(crate) struct Scratch<'s> {
+    /// The evaluation stack's space, whose length is its maximum depth.
+    pub(crate) stack: &'s mut [usize],
+    /// How many operations an evaluation may execute.
+    pub(crate) fuel: usize,
+}
+
+/// Evaluate a DWARF expression against a frame's registers and memory, and
+/// get the value on top of the stack when it ends.
+///
+/// `initial` is pushed before evaluation starts, e.g. the CFA for register
+/// rules. Evaluation fails after executing `scratch.fuel` operations, or
+/// when it overflows `scratch.stack`.
+pub(crate) unsafe fn evaluate<Registers, Reader>(
+    expression: &[u8],
+    registers: &Registers,
+    initial: Option<usize>,
+    scratch: &mut Scratch,
+    reader: &Reader,
+) -> Result<usize>
+where
+    Registers: RegisterSource,
+    Reader: MemoryReader,
+{
+    let mut fuel = scratch.fuel;
+    let mut stack = Stack {
+        words: &mut *scratch.stack,
+        len: 0,
+    };
+    if let Some(initial) = initial {
+        stack.push(initial)?;
+    }
+
+    let mut pc = 0;
+    while pc < expression.len() {
+        if fuel == 0 {
+            return Err(Error::CfiExpressionOutOfFuel);
+        }
+        fuel -= 1;
+
+        let mut input = Input {
+            bytes: expression,
+            pos: pc + 1,
+        };
+        match expression[pc] {
+            // DW_OP_addr
+            0x03 => stack.push(input.word()?)?,
+            // DW_OP_deref
+            0x06 => {
+                let addr = stack.pop()?;
+                stack.push(reader.read(addr)?)?;
+            }
+            // DW_OP_const1u, DW_OP_const1s, ... DW_OP_const8s
+            0x08 => stack.push(input.fixed(1)? as usize)?,
+            0x09 => stack.push(input.fixed(1)? as u8 as i8 as usize)?,
+            0x0a => stack.push(input.fixed(2)? as usize)?,
+            0x0b => stack.push(input.fixed(2)? as u16 as i16 as usize)?,
+            0x0c => stack.push(input.fixed(4)? as usize)?,
+            0x0d => stack.push(input.fixed(4)? as u32 as i32 as usize)?,
+            0x0e | 0x0f => stack.push(input.fixed(8)? as usize)?,
+            // DW_OP_constu, DW_OP_consts
+            0x10 => stack.push(input.uleb128()? as usize)?,
+            0x11 => stack.push(input.sleb128()? as usize)?,
+            // DW_OP_dup
+            0x12 => stack.push(stack.peek(0)?)?,
+            // DW_OP_drop
+            0x13 => {
+                stack.pop()?;
+            }
+            // DW_OP_over
+            0x14 => stack.push(stack.peek(1)?)?,
+            // DW_OP_pick
+            0x15 => {
+                let index = input.fixed(1)? as usize;
+                stack.push(stack.peek(index)?)?;
+            }
+            // DW_OP_swap
+            0x16 => {
+                let a = stack.pop()?;
+                let b = stack.pop()?;
+                stack.push(a)?;
+                stack.push(b)?;
+            }
+            // DW_OP_rot
+            0x17 => {
+                let a = stack.pop()?;
+                let b = stack.pop()?;
+                let c = stack.pop()?;
+                stack.push(a)?;
+                stack.push(c)?;
+                stack.push(b)?;
+            }
+            // DW_OP_abs
+            0x19 => {
+                let a = stack.pop()? as isize;
+                stack.push(a.wrapping_abs() as usize)?;
+            }
+            // DW_OP_neg
+            0x1f => {
+                let a = stack.pop()? as isize;
+                stack.push(a.wrapping_neg() as usize)?;
+            }
+            // DW_OP_not
+            0x20 => {
+                let a = stack.pop()?;
+                stack.push(!a)?;
+            }
+            // DW_OP_plus_uconst
+            0x23 => {
+                let a = stack.pop()?;
+                stack.push(a.wrapping_add(input.uleb128()? as usize))?;
+            }
+            // DW_OP_and, DW_OP_div, ... DW_OP_xor, and the comparisons.
+            op @ 0x1a..=0x1e | op @ 0x21..=0x22 | op @ 0x24..=0x27 | op @ 0x29..=0x2e => {
+                let b = stack.pop()?;
+                let a = stack.pop()?;
+                stack.push(binary(op, a, b)?)?;
+            }
+            // DW_OP_bra
+            0x28 => {
+                let offset = input.fixed(2)? as u16 as i16;
+                if stack.pop()? != 0 {
+                    pc = branch(expression, input.pos, offset)?;
+                    continue;
+                }
+            }
+            // DW_OP_skip
+            0x2f => {
+                let offset = input.fixed(2)? as u16 as i16;
+                pc = branch(expression, input.pos, offset)?;
+                continue;
+            }
+            // DW_OP_lit0 ... DW_OP_lit31
+            op @ 0x30..=0x4f => stack.push((op - 0x30) as usize)?,
+            // DW_OP_breg0 ... DW_OP_breg31
+            op @ 0x70..=0x8f => {
+                let offset = input.sleb128()?;
+                stack.push(register(registers, (op - 0x70) as u64, offset)?)?;
+            }
+            // DW_OP_bregx
+            0x92 => {
+                let register_num = input.uleb128()?;
+                let offset = input.sleb128()?;
+                stack.push(register(registers, register_num, offset)?)?;
+            }
+            // DW_OP_deref_size
+            0x94 => {
+                let size = input.fixed(1)? as usize;
+                if size == 0 || size > mem::size_of::<usize>() {
+                    return Err(Error::InvalidCfiExpression);
+                }
+                let addr = stack.pop()?;
+                let mut bytes = [0; 8];
+                reader.read_bytes(addr, &mut bytes[..size])?;
+                stack.push(from_native_bytes(&bytes[..size]) as usize)?;
+            }
+            // DW_OP_nop
+            0x96 => {}
+            _ => return Err(Error::InvalidCfiExpression),
+        }
+        pc = input.pos;
+    }
+
+    stack.pop()
+}
+
+/// The fixed-size evaluation stack.
+struct Stack<'s> {
+    words: &'s mut [usize],
+    len: usize,
+}
+
+impl<'s> Stack<'s> {
+    fn push(&mut self, word: usize) -> Result<()> {
+        if self.len == self.words.len() {
+            return Err(Error::InvalidCfiExpression);
+        }
+        self.words[self.len] = word;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<usize> {
+        if self.len == 0 {
+            return Err(Error::InvalidCfiExpression);
+        }
+        self.len -= 1;
+        Ok(self.words[self.len])
+    }
+
+    /// Get the word `depth` words below the top of the stack.
+    fn peek(&self, depth: usize) -> Result<usize> {
+        if depth >= self.len {
+            return Err(Error::InvalidCfiExpression);
+        }
+        Ok(self.words[self.len - 1 - depth])
+    }
+}
+
+/// The operands of the operation being evaluated.
+struct Input<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Input<'a> {
+    fn byte(&mut self) -> Result<u8> {
+        let byte = *self.bytes.get(self.pos).ok_or(Error::InvalidCfiExpression)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    /// Read a fixed-size operand, in the target's byte order.
+    fn fixed(&mut self, size: usize) -> Result<u64> {
+        let bytes = self.bytes
+            .get(self.pos..self.pos + size)
+            .ok_or(Error::InvalidCfiExpression)?;
+        self.pos += size;
+        Ok(from_native_bytes(bytes))
+    }
+
+    fn word(&mut self) -> Result<usize> {
+        self.fixed(mem::size_of::<usize>()).map(|word| word as usize)
+    }
+
+    fn uleb128(&mut self) -> Result<u64> {
+        let mut result = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift < 64 {
+                result |= u64::from(byte & 0x7f) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+        }
+    }
+
+    fn sleb128(&mut self) -> Result<i64> {
+        let mut result = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift < 64 {
+                result |= i64::from(byte & 0x7f) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    result |= !0 << shift;
+                }
+                return Ok(result);
+            }
+        }
+    }
+}
+
+fn from_native_bytes(bytes: &[u8]) -> u64 {
+    let fold = |word: u64, &b: &u8| word << 8 | u64::from(b);
+    if cfg!(target_endian = "little") {
+        bytes.iter().rev().fold(0, fold)
+    } else {
+        bytes.iter().fold(0, fold)
+    }
+}
+
+/// The value of the given register plus an offset.
+fn register<Registers>(registers: &Registers, register_num: u64, offset: i64) -> Result<usize>
+where
+    Registers: RegisterSource,
+{
+    if register_num > u64::from(u8::max_value()) {
+        return Err(Error::InvalidCfiExpression);
+    }
+    let value: Result<usize> = registers.register(register_num as u8)?.into();
+    Ok((value? as isize).wrapping_add(offset as isize) as usize)
+}
+
+/// The target of a branch by `offset` bytes from the end of the branch
+/// operation at `pos`.
+fn branch(expression: &[u8], pos: usize, offset: i16) -> Result<usize> {
+    let target = pos as isize + offset as isize;
+    if target < 0 || target as usize > expression.len() {
+        return Err(Error::InvalidCfiExpression);
+    }
+    Ok(target as usize)
+}
+
+/// Apply a binary operation to `a`, the deeper operand, and `b`.
+fn binary(op: u8, a: usize, b: usize) -> Result<usize> {
+    let (sa, sb) = (a as isize, b as isize);
+    Ok(match op {
+        // DW_OP_and
+        0x1a => a & b,
+        // DW_OP_div, which is signed.
+        0x1b => {
+            if b == 0 {
+                return Err(Error::InvalidCfiExpression);
+            }
+            sa.wrapping_div(sb) as usize
+        }
+        // DW_OP_minus
+        0x1c => a.wrapping_sub(b),
+        // DW_OP_mod
+        0x1d => {
+            if b == 0 {
+                return Err(Error::InvalidCfiExpression);
+            }
+            a % b
+        }
+        // DW_OP_mul
+        0x1e => a.wrapping_mul(b),
+        // DW_OP_or
+        0x21 => a | b,
+        // DW_OP_plus
+        0x22 => a.wrapping_add(b),
+        // DW_OP_shl, DW_OP_shr, DW_OP_shra
+        0x24 => a.checked_shl(b as u32).unwrap_or(0),
+        0x25 => a.checked_shr(b as u32).unwrap_or(0),
+        0x26 => sa.checked_shr(b as u32).unwrap_or(if sa < 0 { -1 } else { 0 }) as usize,
+        // DW_OP_xor
+        0x27 => a ^ b,
+        // DW_OP_eq, DW_OP_ge, DW_OP_gt, DW_OP_le, DW_OP_lt, DW_OP_ne, which
+        // are signed.
+        0x29 => (sa == sb) as usize,
+        0x2a => (sa >= sb) as usize,
+        0x2b => (sa > sb) as usize,
+        0x2c => (sa <= sb) as usize,
+        0x2d => (sa < sb) as usize,
+        0x2e => (sa != sb) as usize,
+        _ => unreachable!("not a binary operation: {:#x}", op),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reader::SliceMemory;
+
+    fn registers() -> FrameRegisters {
+        FrameRegisters::from_parts(
+            TaggedWord::valid(0x2000),
+            TaggedWord::valid(0x1000),
+            TaggedWord::valid(0x3000),
+        )
+    }
+
+    fn eval(expression: &[u8], initial: Option<usize>) -> Result<usize> {
+        let bytes: Vec<u8> = [0x4000usize, 0x5000]
+            .iter()
+            .flat_map(|word| word.to_ne_bytes().to_vec())
+            .collect();
+        let reader = SliceMemory::new(0x1000, &bytes);
+        let mut stack = [0; DEFAULT_STACK_DEPTH];
+        let mut scratch = Scratch {
+            stack: &mut stack,
+            fuel: DEFAULT_FUEL,
+        };
+        unsafe { evaluate(expression, &registers(), initial, &mut scratch, &reader) }
+    }
+
+    #[test]
+    fn arithmetic() {
+        // DW_OP_lit3 DW_OP_lit4 DW_OP_mul DW_OP_plus_uconst 100
+        assert_eq!(eval(&[0x33, 0x34, 0x1e, 0x23, 100], None).unwrap(), 112);
+        // DW_OP_const1s -2 DW_OP_lit5 DW_OP_minus DW_OP_abs
+        assert_eq!(eval(&[0x09, 0xfe, 0x35, 0x1c, 0x19], None).unwrap(), 7);
+        // DW_OP_lit1 DW_OP_lit2 DW_OP_swap DW_OP_minus
+        assert_eq!(eval(&[0x31, 0x32, 0x16, 0x1c], None).unwrap(), 1);
+    }
+
+    #[test]
+    fn registers_and_memory() {
+        let w = mem::size_of::<usize>();
+        // The stack pointer's register number, as a DW_OP_bregN.
+        let breg_sp = 0x70 + ::registers::SP;
+        // DW_OP_bregN(sp) 0 DW_OP_deref
+        assert_eq!(eval(&[breg_sp, 0, 0x06], None).unwrap(), 0x4000);
+        // DW_OP_bregN(sp) w DW_OP_deref
+        assert_eq!(eval(&[breg_sp, w as u8, 0x06], None).unwrap(), 0x5000);
+        // The initial value, e.g. the CFA, less 16.
+        // DW_OP_lit16 DW_OP_minus
+        assert_eq!(eval(&[0x40, 0x1c], Some(0x1010)).unwrap(), 0x1000);
+    }
+
+    #[test]
+    fn branches() {
+        let bra = 4i16.to_ne_bytes();
+        let skip = 1i16.to_ne_bytes();
+        // <condition> DW_OP_bra +4 DW_OP_lit1 DW_OP_skip +1 DW_OP_lit2
+        let expression = |condition| {
+            vec![condition, 0x28, bra[0], bra[1], 0x31, 0x2f, skip[0], skip[1], 0x32]
+        };
+        // DW_OP_lit0
+        assert_eq!(eval(&expression(0x30), None).unwrap(), 1);
+        // DW_OP_lit1
+        assert_eq!(eval(&expression(0x31), None).unwrap(), 2);
+    }
+
+    #[test]
+    fn out_of_fuel() {
+        let skip = (-3i16).to_ne_bytes();
+        // DW_OP_skip -3, which skips to itself.
+        match eval(&[0x2f, skip[0], skip[1]], None) {
+            Err(Error::CfiExpressionOutOfFuel) => {}
+            otherwise => panic!("unexpected result: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn stack_overflow() {
+        let reader = SliceMemory::new(0, &[]);
+        let mut stack = [0; 2];
+        let mut scratch = Scratch {
+            stack: &mut stack,
+            fuel: DEFAULT_FUEL,
+        };
+        // DW_OP_lit1 DW_OP_lit2
+        let result = unsafe { evaluate(&[0x31, 0x32], &registers(), None, &mut scratch, &reader) };
+        assert_eq!(result.unwrap(), 2);
+        // DW_OP_lit1 DW_OP_lit2 DW_OP_lit3
+        let result =
+            unsafe { evaluate(&[0x31, 0x32, 0x33], &registers(), None, &mut scratch, &reader) };
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn invalid() {
+        // Empty stack.
+        assert!(eval(&[], None).is_err());
+        // DW_OP_drop on an empty stack.
+        assert!(eval(&[0x13], None).is_err());
+        // DW_OP_reg0, a location rather than a value.
+        assert!(eval(&[0x50], None).is_err());
+        // Truncated DW_OP_const2u.
+        assert!(eval(&[0x0a, 0x01], None).is_err());
+        // DW_OP_lit1 DW_OP_lit0 DW_OP_div
+        assert!(eval(&[0x31, 0x30, 0x1b], None).is_err());
+    }
+}
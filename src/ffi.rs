@@ -1,5 +1,98 @@
-//! Bindings to foreign functions, generated by `bindgen`.
+//! Bindings to foreign functions.
+//!
+//! By default these are declared by hand on top of the `libc` crate, so that
+//! building `pancakes` does not require libclang. With the
+//! `regenerate-bindings` feature they are generated by `bindgen` from the
+//! system's `<ucontext.h>` instead, which helps on targets where `libc` is
+//! missing a definition.
 
-#![allow(dead_code, non_camel_case_types, non_snake_case)]
+#![allow(dead_code, missing_docs, non_camel_case_types, non_snake_case)]
 
-include!(concat!(env!("OUT_DIR"), "/ffi.rs"));
+cfg_if! {
+    if #[cfg(feature = "regenerate-bindings")] {
+        include!(concat!(env!("OUT_DIR"), "/ffi.rs"));
+    } else {
+        use libc;
+
+        pub use libc::ucontext_t;
+
+        #[cfg(all(target_arch = "x86_64", any(target_os = "linux", target_os = "android")))]
+        pub use libc::{REG_RBP, REG_RIP, REG_RSP};
+
+        #[cfg(all(target_arch = "x86", any(target_os = "linux", target_os = "android")))]
+        pub use libc::{REG_EBP, REG_EIP, REG_ESP};
+
+        #[cfg(all(target_arch = "x86_64", target_os = "netbsd"))]
+        pub use libc::{_REG_RBP, _REG_RIP, _REG_RSP};
+
+        extern "C" {
+            pub fn getcontext(ucp: *mut ucontext_t) -> libc::c_int;
+        }
+    }
+}
+
+/// The mach interfaces for suspending tasks and threads and fetching their
+/// registers, which neither `libc` nor `<ucontext.h>` declare.
+#[cfg(target_os = "macos")]
+mod mach {
+    use libc;
+
+    pub type kern_return_t = libc::c_int;
+    pub type natural_t = libc::c_uint;
+    pub type mach_port_t = natural_t;
+    pub type thread_act_t = mach_port_t;
+    pub type mach_msg_type_number_t = natural_t;
+    pub type thread_state_flavor_t = libc::c_int;
+
+    pub const KERN_SUCCESS: kern_return_t = 0;
+
+    #[cfg(target_arch = "x86_64")]
+    pub type thread_state_t = libc::__darwin_x86_thread_state64;
+    /// `x86_THREAD_STATE64`.
+    #[cfg(target_arch = "x86_64")]
+    pub const THREAD_STATE_FLAVOR: thread_state_flavor_t = 4;
+
+    #[cfg(target_arch = "aarch64")]
+    pub type thread_state_t = libc::__darwin_arm_thread_state64;
+    /// `ARM_THREAD_STATE64`.
+    #[cfg(target_arch = "aarch64")]
+    pub const THREAD_STATE_FLAVOR: thread_state_flavor_t = 6;
+
+    extern "C" {
+        pub static mach_task_self_: mach_port_t;
+
+        pub fn mach_thread_self() -> mach_port_t;
+        pub fn mach_port_deallocate(task: mach_port_t, name: mach_port_t) -> kern_return_t;
+        pub fn task_threads(
+            task: mach_port_t,
+            threads: *mut *mut thread_act_t,
+            count: *mut mach_msg_type_number_t,
+        ) -> kern_return_t;
+        pub fn task_for_pid(
+            target: mach_port_t,
+            pid: libc::c_int,
+            task: *mut mach_port_t,
+        ) -> kern_return_t;
+        pub fn task_suspend(task: mach_port_t) -> kern_return_t;
+        pub fn task_resume(task: mach_port_t) -> kern_return_t;
+        pub fn thread_suspend(thread: thread_act_t) -> kern_return_t;
+        pub fn thread_resume(thread: thread_act_t) -> kern_return_t;
+        pub fn thread_get_state(
+            thread: thread_act_t,
+            flavor: thread_state_flavor_t,
+            state: *mut natural_t,
+            count: *mut mach_msg_type_number_t,
+        ) -> kern_return_t;
+        pub fn vm_deallocate(task: mach_port_t, address: usize, size: usize) -> kern_return_t;
+        pub fn mach_vm_read_overwrite(
+            task: mach_port_t,
+            address: u64,
+            size: u64,
+            data: u64,
+            out_size: *mut u64,
+        ) -> kern_return_t;
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub use self::mach::*;
@@ -0,0 +1,236 @@
+//! Unwind information for code emitted at runtime by a JIT.
+//!
+//! This is the equivalent of libgcc's `__register_frame` and
+//! `__deregister_frame`: a JIT registers the `.eh_frame` it emitted for some
+//! code with a `JitRegistry`, and frames in that code can then be walked by
+//! every `Walker` configured with the registry.
+//!
+//! The registry is shared between the JIT's threads and the threads walking
+//! stacks, possibly from signal handlers, so walking must not block. Each
+//! registration's unwind information is compiled up front, and kept in a
+//! `CompiledModules` list, like modules added to an `Unwinder` while it
+//! walks.
+
+use super::address_range;
+use super::{Avma, Bias, FrameRegisters, MemoryReader, Result};
+use gimli;
+use modules::CompiledModules;
+use std::fmt;
+use std::ops::Range;
+
+/// A handle for deregistering code registered with a `JitRegistry`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct JitRegistration(u64);
+
+/// A registry of unwind information for JIT code, shared between the JIT and
+/// the walkers configured with `Options::jit_registry`.
+///
+/// ```
+/// use pancakes::{JitRegistry, Options};
+/// use std::sync::Arc;
+///
+/// let registry = Arc::new(JitRegistry::new());
+/// let mut options = Options::new();
+/// options.jit_registry(registry.clone());
+/// let walker = options.build();
+///
+/// // Later, after the JIT emits some code and its `.eh_frame`...
+/// # let code = pancakes::Avma(0x1000 as *const u8) .. pancakes::Avma(0x2000 as *const u8);
+/// # let eh_frame: &[u8] = &[];
+/// let registration = registry.register(code, eh_frame).unwrap();
+///
+/// // ...and before it frees the code.
+/// registry.deregister(registration);
+/// # let _ = walker;
+/// ```
+pub struct JitRegistry {
+    /// The registered code.
+    code: CompiledModules,
+}
+
+impl JitRegistry {
+    /// Construct an empty registry.
+    pub fn new() -> JitRegistry {
+        JitRegistry {
+            code: CompiledModules::new(),
+        }
+    }
+
+    /// Register the given `.eh_frame` data, which describes the JIT code in
+    /// the given address range.
+    ///
+    /// The data must be at the address it was emitted for, since it may
+    /// contain addresses relative to itself, but it is not used after this
+    /// returns.
+    pub fn register(&self, code: Range<Avma>, eh_frame: &[u8]) -> Result<JitRegistration> {
+        self.register_at(code, eh_frame, Avma(eh_frame.as_ptr()))
+    }
+
+    /// Like `register`, but for `.eh_frame` data that was emitted for the
+    /// given address and has since been copied elsewhere, e.g. read from a
+    /// perf jitdump file.
+    pub fn register_at(
+        &self,
+        code: Range<Avma>,
+        eh_frame: &[u8],
+        eh_frame_address: Avma,
+    ) -> Result<JitRegistration> {
+        let bases = gimli::BaseAddresses::default().set_cfi(eh_frame_address.0 as u64);
+        self.code
+            .add(Bias(0), &bases, eh_frame, Some(address_range(&code)))
+            .map(JitRegistration)
+    }
+
+    /// Deregister code registered with `register`, so that the code can be
+    /// freed. Walkers never see the registration once this returns.
+    ///
+    /// Returns `false` if it was already deregistered.
+    pub fn deregister(&self, registration: JitRegistration) -> bool {
+        self.code.remove(registration.0)
+    }
+
+    /// Walk a frame in registered JIT code, or return `None` if `ip` is not
+    /// in any.
+    ///
+    /// This does not block or allocate.
+    pub(crate) unsafe fn unwind<Reader>(
+        &self,
+        ip: usize,
+        regs: &FrameRegisters,
+        reader: &Reader,
+    ) -> Option<Result<FrameRegisters>>
+    where
+        Reader: MemoryReader,
+    {
+        self.code
+            .unwind(ip, regs, reader)
+            .map(|result| result.map(|(registers, _)| registers))
+    }
+}
+
+impl Default for JitRegistry {
+    fn default() -> JitRegistry {
+        JitRegistry::new()
+    }
+}
+
+impl fmt::Debug for JitRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("JitRegistry")
+            .field("code", &self.code.ranges())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reader::SliceMemory;
+    #[cfg(target_arch = "x86_64")]
+    use tests::eh_frame_pushing_one_word;
+    use TaggedWord;
+    #[cfg(target_arch = "x86_64")]
+    use Registers;
+
+    fn range(start: usize, end: usize) -> Range<Avma> {
+        Avma(start as *const u8)..Avma(end as *const u8)
+    }
+
+    #[test]
+    fn register_and_deregister() {
+        let registry = JitRegistry::new();
+        let first = registry.register(range(0x1000, 0x2000), &[]).unwrap();
+        let second = registry.register(range(0x2000, 0x3000), &[]).unwrap();
+        assert!(first != second);
+
+        assert!(registry.deregister(first));
+        assert!(!registry.deregister(first));
+        assert!(registry.deregister(second));
+    }
+
+    #[test]
+    fn unwind_outside_registered_code() {
+        let registry = JitRegistry::new();
+        registry.register(range(0x1000, 0x2000), &[]).unwrap();
+
+        let regs = FrameRegisters::from_parts(
+            TaggedWord::valid(0),
+            TaggedWord::valid(0),
+            TaggedWord::valid(0x4000),
+        );
+        let reader = SliceMemory::new(0, &[]);
+        unsafe {
+            assert!(registry.unwind(0x4000, &regs, &reader).is_none());
+            // Registered, but without any unwind information.
+            assert!(registry.unwind(0x1800, &regs, &reader).is_none());
+        }
+    }
+
+    /// The registers of a frame at 0x1010, in `eh_frame_pushing_one_word`'s
+    /// code, and its stack at 0x8000, which holds the return address 0x4000.
+    #[cfg(target_arch = "x86_64")]
+    fn frame_in_code() -> (FrameRegisters, Vec<u8>) {
+        let regs = FrameRegisters::from_parts(
+            TaggedWord::valid(0),
+            TaggedWord::valid(0x8000),
+            TaggedWord::valid(0x1010),
+        );
+        (regs, ::tests::stack(&[0xdead, 0x4000]))
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn unwind_registered_code() {
+        let registry = JitRegistry::new();
+        let eh_frame = eh_frame_pushing_one_word();
+        let registration = registry.register(range(0x1000, 0x1100), &eh_frame).unwrap();
+
+        let (regs, stack) = frame_in_code();
+        let reader = SliceMemory::new(0x8000, &stack);
+        unsafe {
+            let caller = registry.unwind(0x1010, &regs, &reader).unwrap().unwrap();
+            assert_eq!(caller.ip(), TaggedWord::valid(0x4000));
+            assert_eq!(caller.sp(), TaggedWord::valid(0x8010));
+        }
+
+        assert!(registry.deregister(registration));
+        unsafe {
+            assert!(registry.unwind(0x1010, &regs, &reader).is_none());
+        }
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn deregister_while_walking() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+        use std::thread;
+
+        let registry = Arc::new(JitRegistry::new());
+        let done = Arc::new(AtomicBool::new(false));
+        let walker = {
+            let registry = registry.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                let (regs, stack) = frame_in_code();
+                let reader = SliceMemory::new(0x8000, &stack);
+                while !done.load(Ordering::SeqCst) {
+                    // Each walk sees the code either registered or not, and
+                    // never a table that was freed.
+                    if let Some(caller) = unsafe { registry.unwind(0x1010, &regs, &reader) } {
+                        assert_eq!(caller.unwrap().ip(), TaggedWord::valid(0x4000));
+                    }
+                }
+            })
+        };
+
+        let eh_frame = eh_frame_pushing_one_word();
+        for _ in 0..1000 {
+            let registration = registry.register(range(0x1000, 0x1100), &eh_frame).unwrap();
+            thread::yield_now();
+            assert!(registry.deregister(registration));
+        }
+        done.store(true, Ordering::SeqCst);
+        walker.join().unwrap();
+    }
+}
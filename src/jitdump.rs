@@ -0,0 +1,384 @@
+//! Parsing the `jit-<pid>.dump` files that JITs write for `perf`.
+//!
+//! A jitdump file describes the code a JIT emitted and when: where each
+//! function was loaded and moved to, its name, and optionally its `.eh_frame`
+//! unwind information. Because JIT code is freed and its addresses reused,
+//! resolving an address to the code that was there needs the time the
+//! address was seen, e.g. when a sample was taken; see `JitDump::code_at`.
+//! The timestamps are from the clock the JIT was configured with, usually
+//! `CLOCK_MONOTONIC`.
+//!
+//! See `tools/perf/Documentation/jitdump-specification.txt` in the Linux
+//! source for the format.
+
+use super::{Avma, Error, JitRegistration, JitRegistry, Result};
+use std::ops::Range;
+
+const MAGIC: u32 = 0x4A69_5444;
+const HEADER_SIZE: usize = 40;
+const RECORD_HEADER_SIZE: usize = 16;
+
+const JIT_CODE_LOAD: u32 = 0;
+const JIT_CODE_MOVE: u32 = 1;
+const JIT_CODE_DEBUG_INFO: u32 = 2;
+const JIT_CODE_CLOSE: u32 = 3;
+const JIT_CODE_UNWINDING_INFO: u32 = 4;
+
+/// A parsed jitdump file.
+#[derive(Clone, Debug)]
+pub struct JitDump<'a> {
+    /// The process the JIT ran in.
+    pub pid: u32,
+    /// When the file was created.
+    pub timestamp: u64,
+    /// The records, in the order they were written, which is also
+    /// timestamp order.
+    pub records: Vec<Record<'a>>,
+}
+
+/// A single jitdump record.
+#[derive(Clone, Debug)]
+pub struct Record<'a> {
+    /// When the record was written.
+    pub timestamp: u64,
+    /// What the record says.
+    pub kind: RecordKind<'a>,
+}
+
+/// The different kinds of jitdump records.
+#[derive(Clone, Debug)]
+pub enum RecordKind<'a> {
+    /// Code was loaded.
+    CodeLoad(CodeLoad<'a>),
+    /// Code was moved.
+    CodeMove(CodeMove),
+    /// The unwind information for the next loaded code.
+    CodeUnwindingInfo(CodeUnwindingInfo<'a>),
+    /// Line number information for some loaded code.
+    CodeDebugInfo,
+    /// The JIT stopped writing.
+    CodeClose,
+    /// A record kind from a newer version of the format.
+    Unknown(u32),
+}
+
+/// A `JIT_CODE_LOAD` record.
+#[derive(Clone, Debug)]
+pub struct CodeLoad<'a> {
+    /// The thread that emitted the code.
+    pub tid: u32,
+    /// Where the code was loaded.
+    pub code_addr: u64,
+    /// The code's size, in bytes.
+    pub code_size: u64,
+    /// A unique identifier for the code, used by later moves.
+    pub code_index: u64,
+    /// The function's name.
+    pub name: &'a [u8],
+    /// The code itself.
+    pub code: &'a [u8],
+}
+
+/// A `JIT_CODE_MOVE` record.
+#[derive(Clone, Copy, Debug)]
+pub struct CodeMove {
+    /// The thread that moved the code.
+    pub tid: u32,
+    /// Where the code was.
+    pub old_code_addr: u64,
+    /// Where the code is now.
+    pub new_code_addr: u64,
+    /// The code's size, in bytes.
+    pub code_size: u64,
+    /// The `code_index` of the moved code's load record.
+    pub code_index: u64,
+}
+
+/// A `JIT_CODE_UNWINDING_INFO` record, which describes the code loaded by
+/// the next `JIT_CODE_LOAD` record.
+#[derive(Clone, Debug)]
+pub struct CodeUnwindingInfo<'a> {
+    /// The `.eh_frame` section, which is placed right after the code.
+    pub eh_frame: &'a [u8],
+    /// The `.eh_frame_hdr` section, which is placed right after the
+    /// `.eh_frame`.
+    pub eh_frame_hdr: &'a [u8],
+}
+
+/// Reads integers of the dump's endianness.
+#[derive(Clone, Copy, Debug)]
+struct Endian {
+    swap: bool,
+}
+
+impl Endian {
+    fn u32(self, data: &[u8], offset: usize) -> Result<u32> {
+        let bytes = data.get(offset..offset + 4).ok_or(Error::InvalidJitDump)?;
+        let mut word = [0; 4];
+        word.copy_from_slice(bytes);
+        let word = u32::from_ne_bytes(word);
+        Ok(if self.swap { word.swap_bytes() } else { word })
+    }
+
+    fn u64(self, data: &[u8], offset: usize) -> Result<u64> {
+        let bytes = data.get(offset..offset + 8).ok_or(Error::InvalidJitDump)?;
+        let mut word = [0; 8];
+        word.copy_from_slice(bytes);
+        let word = u64::from_ne_bytes(word);
+        Ok(if self.swap { word.swap_bytes() } else { word })
+    }
+}
+
+fn slice(data: &[u8], start: usize, len: u64) -> Result<&[u8]> {
+    let end = (len as usize).checked_add(start).ok_or(Error::InvalidJitDump)?;
+    data.get(start..end).ok_or(Error::InvalidJitDump)
+}
+
+impl<'a> JitDump<'a> {
+    /// Parse a jitdump file.
+    ///
+    /// The JIT may still be appending to the file, so a truncated last
+    /// record is ignored rather than an error.
+    pub fn parse(data: &'a [u8]) -> Result<JitDump<'a>> {
+        let endian = match (Endian { swap: false }).u32(data, 0)? {
+            MAGIC => Endian { swap: false },
+            magic if magic.swap_bytes() == MAGIC => Endian { swap: true },
+            _ => return Err(Error::InvalidJitDump),
+        };
+
+        let header_size = endian.u32(data, 8)? as usize;
+        if header_size < HEADER_SIZE || header_size > data.len() {
+            return Err(Error::InvalidJitDump);
+        }
+        let pid = endian.u32(data, 20)?;
+        let timestamp = endian.u64(data, 24)?;
+
+        let mut records = vec![];
+        let mut rest = &data[header_size..];
+        while rest.len() >= RECORD_HEADER_SIZE {
+            let id = endian.u32(rest, 0)?;
+            let size = endian.u32(rest, 4)? as usize;
+            if size < RECORD_HEADER_SIZE {
+                return Err(Error::InvalidJitDump);
+            }
+            if size > rest.len() {
+                break;
+            }
+
+            let timestamp = endian.u64(rest, 8)?;
+            let body = &rest[RECORD_HEADER_SIZE..size];
+            let kind = Self::parse_record(endian, id, body)?;
+            records.push(Record { timestamp, kind });
+            rest = &rest[size..];
+        }
+
+        Ok(JitDump {
+            pid,
+            timestamp,
+            records,
+        })
+    }
+
+    fn parse_record(endian: Endian, id: u32, body: &'a [u8]) -> Result<RecordKind<'a>> {
+        Ok(match id {
+            JIT_CODE_LOAD => {
+                let code_size = endian.u64(body, 24)?;
+                let name = body.get(40..).ok_or(Error::InvalidJitDump)?;
+                let name_len = name.iter().position(|&b| b == 0).ok_or(Error::InvalidJitDump)?;
+                RecordKind::CodeLoad(CodeLoad {
+                    tid: endian.u32(body, 4)?,
+                    code_addr: endian.u64(body, 16)?,
+                    code_size,
+                    code_index: endian.u64(body, 32)?,
+                    name: &name[..name_len],
+                    code: slice(name, name_len + 1, code_size)?,
+                })
+            }
+            JIT_CODE_MOVE => RecordKind::CodeMove(CodeMove {
+                tid: endian.u32(body, 4)?,
+                old_code_addr: endian.u64(body, 16)?,
+                new_code_addr: endian.u64(body, 24)?,
+                code_size: endian.u64(body, 32)?,
+                code_index: endian.u64(body, 40)?,
+            }),
+            JIT_CODE_UNWINDING_INFO => {
+                let unwinding_size = endian.u64(body, 0)?;
+                let eh_frame_hdr_size = endian.u64(body, 8)?;
+                let eh_frame_size = unwinding_size
+                    .checked_sub(eh_frame_hdr_size)
+                    .ok_or(Error::InvalidJitDump)?;
+                RecordKind::CodeUnwindingInfo(CodeUnwindingInfo {
+                    eh_frame: slice(body, 24, eh_frame_size)?,
+                    eh_frame_hdr: slice(body, 24 + eh_frame_size as usize, eh_frame_hdr_size)?,
+                })
+            }
+            JIT_CODE_DEBUG_INFO => RecordKind::CodeDebugInfo,
+            JIT_CODE_CLOSE => RecordKind::CodeClose,
+            id => RecordKind::Unknown(id),
+        })
+    }
+
+    /// Find the code that was at the given address at the given time.
+    pub fn code_at(&self, addr: u64, timestamp: u64) -> Option<&CodeLoad<'a>> {
+        // The code live at `timestamp`, and where it was then.
+        let mut live: Vec<(Range<u64>, &CodeLoad<'a>)> = vec![];
+        for record in self.records.iter().take_while(|r| r.timestamp <= timestamp) {
+            match record.kind {
+                RecordKind::CodeLoad(ref load) => {
+                    let range = load.code_addr..load.code_addr.saturating_add(load.code_size);
+                    live.retain(|&(ref r, _)| r.end <= range.start || range.end <= r.start);
+                    live.push((range, load));
+                }
+                RecordKind::CodeMove(ref mv) => {
+                    let range = mv.new_code_addr..mv.new_code_addr.saturating_add(mv.code_size);
+                    let moved = live.iter()
+                        .position(|&(_, load)| load.code_index == mv.code_index)
+                        .map(|i| live.swap_remove(i).1);
+                    live.retain(|&(ref r, _)| r.end <= range.start || range.end <= r.start);
+                    if let Some(load) = moved {
+                        live.push((range, load));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        live.into_iter()
+            .find(|&(ref range, _)| range.start <= addr && addr < range.end)
+            .map(|(_, load)| load)
+    }
+
+    /// Register the unwind information of every loaded function that has
+    /// some with the given registry.
+    ///
+    /// This is for walking the JIT's stacks in its own process, while its
+    /// code is still loaded where the dump says it is.
+    pub fn register_unwind_info(&self, registry: &JitRegistry) -> Result<Vec<JitRegistration>> {
+        let mut registrations = vec![];
+        let mut unwinding_info = None;
+        for record in &self.records {
+            match record.kind {
+                RecordKind::CodeUnwindingInfo(ref info) => unwinding_info = Some(info),
+                RecordKind::CodeLoad(ref load) => {
+                    if let Some(info) = unwinding_info.take() {
+                        let start = load.code_addr as usize;
+                        let end = start + load.code_size as usize;
+                        let code = Avma(start as *const u8)..Avma(end as *const u8);
+                        let eh_frame_address = Avma(end as *const u8);
+                        registrations.push(registry.register_at(
+                            code,
+                            info.eh_frame,
+                            eh_frame_address,
+                        )?);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(registrations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> Vec<u8> {
+        let mut data = vec![];
+        data.extend_from_slice(&MAGIC.to_ne_bytes());
+        data.extend_from_slice(&1u32.to_ne_bytes());
+        data.extend_from_slice(&(HEADER_SIZE as u32).to_ne_bytes());
+        data.extend_from_slice(&62u32.to_ne_bytes());
+        data.extend_from_slice(&0u32.to_ne_bytes());
+        data.extend_from_slice(&1234u32.to_ne_bytes());
+        data.extend_from_slice(&100u64.to_ne_bytes());
+        data.extend_from_slice(&0u64.to_ne_bytes());
+        data
+    }
+
+    fn record(data: &mut Vec<u8>, id: u32, timestamp: u64, body: &[u8]) {
+        data.extend_from_slice(&id.to_ne_bytes());
+        data.extend_from_slice(&((RECORD_HEADER_SIZE + body.len()) as u32).to_ne_bytes());
+        data.extend_from_slice(&timestamp.to_ne_bytes());
+        data.extend_from_slice(body);
+    }
+
+    fn load(data: &mut Vec<u8>, timestamp: u64, addr: u64, index: u64, name: &[u8]) {
+        let mut body = vec![];
+        body.extend_from_slice(&1234u32.to_ne_bytes());
+        body.extend_from_slice(&5678u32.to_ne_bytes());
+        body.extend_from_slice(&addr.to_ne_bytes());
+        body.extend_from_slice(&addr.to_ne_bytes());
+        body.extend_from_slice(&4u64.to_ne_bytes());
+        body.extend_from_slice(&index.to_ne_bytes());
+        body.extend_from_slice(name);
+        body.push(0);
+        body.extend_from_slice(&[0x90, 0x90, 0x90, 0xc3]);
+        record(data, JIT_CODE_LOAD, timestamp, &body);
+    }
+
+    fn code_move(data: &mut Vec<u8>, timestamp: u64, old: u64, new: u64, index: u64) {
+        let mut body = vec![];
+        body.extend_from_slice(&1234u32.to_ne_bytes());
+        body.extend_from_slice(&5678u32.to_ne_bytes());
+        body.extend_from_slice(&new.to_ne_bytes());
+        body.extend_from_slice(&old.to_ne_bytes());
+        body.extend_from_slice(&new.to_ne_bytes());
+        body.extend_from_slice(&4u64.to_ne_bytes());
+        body.extend_from_slice(&index.to_ne_bytes());
+        record(data, JIT_CODE_MOVE, timestamp, &body);
+    }
+
+    #[test]
+    fn parse() {
+        let mut data = header();
+        load(&mut data, 110, 0x1000, 1, b"foo");
+        record(&mut data, JIT_CODE_CLOSE, 120, &[]);
+
+        let dump = JitDump::parse(&data).unwrap();
+        assert_eq!(dump.pid, 1234);
+        assert_eq!(dump.timestamp, 100);
+        assert_eq!(dump.records.len(), 2);
+        match dump.records[0].kind {
+            RecordKind::CodeLoad(ref load) => {
+                assert_eq!(load.tid, 5678);
+                assert_eq!(load.code_addr, 0x1000);
+                assert_eq!(load.code_index, 1);
+                assert_eq!(load.name, b"foo");
+                assert_eq!(load.code, &[0x90, 0x90, 0x90, 0xc3]);
+            }
+            ref otherwise => panic!("unexpected record: {:?}", otherwise),
+        }
+        match dump.records[1].kind {
+            RecordKind::CodeClose => {}
+            ref otherwise => panic!("unexpected record: {:?}", otherwise),
+        }
+
+        // A record that is still being written is ignored.
+        let len = data.len();
+        let dump = JitDump::parse(&data[..len - 1]).unwrap();
+        assert_eq!(dump.records.len(), 1);
+
+        assert!(JitDump::parse(&data[1..]).is_err());
+    }
+
+    #[test]
+    fn code_at() {
+        let mut data = header();
+        load(&mut data, 110, 0x1000, 1, b"foo");
+        load(&mut data, 120, 0x2000, 2, b"bar");
+        code_move(&mut data, 130, 0x1000, 0x3000, 1);
+        load(&mut data, 140, 0x2000, 3, b"baz");
+
+        let dump = JitDump::parse(&data).unwrap();
+        let name = |addr, timestamp| dump.code_at(addr, timestamp).map(|load| load.name);
+        assert_eq!(name(0x1000, 100), None);
+        assert_eq!(name(0x1000, 110), Some(&b"foo"[..]));
+        assert_eq!(name(0x1003, 125), Some(&b"foo"[..]));
+        assert_eq!(name(0x1004, 125), None);
+        assert_eq!(name(0x1000, 130), None);
+        assert_eq!(name(0x3000, 130), Some(&b"foo"[..]));
+        assert_eq!(name(0x2000, 135), Some(&b"bar"[..]));
+        assert_eq!(name(0x2000, 140), Some(&b"baz"[..]));
+    }
+}
@@ -10,35 +10,179 @@
 
 #[macro_use]
 extern crate cfg_if;
+#[cfg(feature = "live")]
 extern crate findshlibs;
 extern crate gimli;
+extern crate libc;
+#[cfg(feature = "log")]
+extern crate log as log_crate;
+extern crate object;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+#[cfg(feature = "debuginfod")]
+extern crate ureq;
 
+pub mod alloc_guard;
+pub mod arch;
+pub mod breakpad;
+mod build_id;
+mod capture;
+mod collector;
+mod compiled;
 mod control;
+mod core_dump;
+#[cfg(all(feature = "live", unix))]
+pub mod crash;
+#[cfg(feature = "debuginfod")]
+pub mod debuginfod;
+mod debuglink;
+mod dsym;
+mod eh_frame_hdr;
+mod expression;
 pub mod error;
+mod jit;
+pub mod jitdump;
+#[cfg(feature = "live")]
 mod ffi;
 pub mod log;
+mod manual;
+mod modules;
+pub mod observer;
+#[cfg(all(
+    feature = "live",
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+pub mod perf_event;
+pub mod perf_map;
+#[cfg(all(feature = "live", any(target_os = "linux", target_os = "android")))]
+pub mod profiler;
+mod published;
+#[cfg(feature = "live")]
+mod rescan;
 pub mod reader;
+pub mod remote;
+mod ring;
+mod row_cache;
+pub mod stackmaps;
+mod stats;
+pub mod suspend;
 mod tagged_word;
+#[cfg(all(
+    feature = "live",
+    any(
+        target_os = "linux",
+        target_os = "android",
+        all(target_os = "macos", any(target_arch = "x86_64", target_arch = "aarch64"))
+    )
+))]
+pub mod threads;
+mod unwind_cache;
+#[cfg(all(feature = "live", any(target_os = "linux", target_os = "android")))]
+mod vdso;
 
 cfg_if! {
     if #[cfg(target_arch = "x86_64")] {
         #[path = "./x86_64/registers.rs"]
         mod registers;
+    } else if #[cfg(target_arch = "x86")] {
+        #[path = "./x86/registers.rs"]
+        mod registers;
+    } else if #[cfg(target_arch = "aarch64")] {
+        #[path = "./aarch64/registers.rs"]
+        mod registers;
+    } else if #[cfg(all(target_arch = "powerpc64", target_endian = "little"))] {
+        #[path = "./powerpc64/registers.rs"]
+        mod registers;
+    } else if #[cfg(target_arch = "mips64")] {
+        #[path = "./mips64/registers.rs"]
+        mod registers;
+    } else if #[cfg(target_arch = "loongarch64")] {
+        #[path = "./loongarch64/registers.rs"]
+        mod registers;
+    } else {
+        #[path = "./registers_unsupported.rs"]
+        mod registers;
+    }
+}
+
+cfg_if! {
+    if #[cfg(feature = "live")] {
+        pub use findshlibs::{Avma, Bias, Svma};
+        use findshlibs::{NamedMemoryRange, SectionIterable, SharedLibrary};
     } else {
-        compile_error!("Unsupported architecture; only x86_64 is currently supported");
+        mod addresses;
+        pub use addresses::{Avma, Bias, Svma};
     }
 }
 
-pub use control::{AsStackWalkControl, StackWalkControl};
+/// Get the addresses of a range of `Avma`s.
+///
+/// `Avma` wraps a raw pointer, which is neither `Send` nor `Sync`, so the
+/// address ranges that an `Unwinder` keeps, and only ever compares, are kept
+/// as plain addresses instead.
+pub(crate) fn address_range(range: &Range<Avma>) -> Range<usize> {
+    range.start.0 as usize..range.end.0 as usize
+}
+
+/// Get the range of `Avma`s of some addresses.
+pub(crate) fn avma_range(range: &Range<usize>) -> Range<Avma> {
+    Avma(range.start as *const u8)..Avma(range.end as *const u8)
+}
+
+pub use capture::StackCapture;
+pub use collector::StackCollector;
+use compiled::{CompiledRow, CompiledTable, Compiler};
+pub use compiled::CompiledTableMemory;
+pub use control::{AsStackWalkControl, StackWalkControl, WalkOutcome, WalkStop};
+use eh_frame_hdr::EhFrameHdr;
 pub use error::{Error, Result};
-use findshlibs::{Avma, Bias, NamedMemoryRange, SectionIterable, SharedLibrary, Svma};
-use gimli::UnwindSection;
+#[cfg(feature = "live")]
+pub use ffi::ucontext_t;
+pub use jit::{JitRegistration, JitRegistry};
+use gimli::{Endianity, UnwindSection};
+use manual::ManualEntry;
+pub use manual::ManualRule;
+use modules::{overlaps, LiveModules};
+pub use modules::ModuleId;
+use object::{Object, ObjectSection, ObjectSegment};
+use observer::{ObservedReader, UnwindObserver};
 pub use registers::FrameRegisters;
-use std::cmp::Ordering;
+#[cfg(target_arch = "x86_64")]
+pub use registers::FullRegisters;
+use row_cache::RowCache;
+pub use row_cache::RowCacheStats;
+use stats::Counters;
+pub use stats::{ErrorCounts, StrategyCounts, UnwindStats};
+
+/// The minimal register set for walking stacks at high frequency, e.g. when
+/// sampling for a profiler.
+///
+/// This is `FrameRegisters`, which only tracks the registers needed to walk
+/// to the next frame: the stack pointer, the instruction pointer, and the
+/// frame pointer, plus the link register on architectures that have one. The
+/// rules of every other register in the unwind table are never evaluated.
+pub type SampleRegisters = FrameRegisters;
+use std::cmp::{self, Ordering};
 use std::collections::HashMap;
+use std::env;
 use std::fmt;
-use std::ops::Range;
+use std::fs;
+#[cfg(feature = "live")]
+use std::hint;
+use std::io;
+use std::mem;
+use std::ops::{self, Range};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+#[cfg(feature = "live")]
+use std::sync::{Mutex, OnceLock};
+#[cfg(feature = "live")]
 use std::slice;
+#[cfg(all(feature = "live", feature = "parallel-setup"))]
+use std::thread;
+use std::time::Instant;
+use std::usize;
 pub use tagged_word::TaggedWord;
 
 /// A trait for things that can read memory from the process whose stack is
@@ -52,8 +196,8 @@ pub use tagged_word::TaggedWord;
 /// If we are walking a different process's stack -- perhaps we are sampling
 /// stacks for an out-of-process profiler like `perf` -- then we would need to
 /// use OS-specific APIs like `ptrace` or `mach` message passing to read memory
-/// from that process's address space. We don't have an implementation of
-/// `MemoryReader` to do this yet, but that is expected to change.
+/// from that process's address space. See `reader::PtraceMemory` for a
+/// `MemoryReader` implementation that uses `ptrace` on Linux.
 ///
 /// This trait can also be backed by a mock implementation during testing that
 /// asserts that the expected addresses are queried, and returns deterministic
@@ -64,7 +208,7 @@ pub use tagged_word::TaggedWord;
 /// It is the caller's responsibility to ensure that every address provided to
 /// one of these methods is valid. Failure to do so will likely result in
 /// dereferencing random memory.
-pub trait MemoryReader: fmt::Debug + Sized {
+pub trait MemoryReader: fmt::Debug {
     /// Read the word at the given address.
     unsafe fn read(&self, addr: usize) -> Result<usize>;
 
@@ -72,12 +216,70 @@ pub trait MemoryReader: fmt::Debug + Sized {
 
     /// Read the word at the given offset from the given address.
     unsafe fn read_offset(&self, addr: usize, offset: isize) -> Result<usize> {
-        let addr = if offset < 0 {
-            addr + (-offset as usize)
-        } else {
-            addr + (offset as usize)
-        };
-        self.read(addr)
+        self.read((addr as isize).wrapping_add(offset) as usize)
+    }
+
+    /// Read consecutive words starting at the given address into `words`.
+    ///
+    /// Readers for which each read is expensive, such as those reading from
+    /// another process, should override this to read all of the words at
+    /// once.
+    unsafe fn read_words(&self, addr: usize, words: &mut [usize]) -> Result<()> {
+        for (i, word) in words.iter_mut().enumerate() {
+            *word = self.read(addr.wrapping_add(i * mem::size_of::<usize>()))?;
+        }
+        Ok(())
+    }
+
+    /// Read `bytes.len()` bytes starting at the given address, which need not
+    /// be aligned.
+    ///
+    /// The default implementation reads each aligned word that overlaps the
+    /// range.
+    unsafe fn read_bytes(&self, addr: usize, bytes: &mut [u8]) -> Result<()> {
+        let word_size = mem::size_of::<usize>();
+        let mut i = 0;
+        while i < bytes.len() {
+            let at = addr.wrapping_add(i);
+            let offset = at % word_size;
+            let word = self.read(at - offset)?.to_ne_bytes();
+            let n = (word_size - offset).min(bytes.len() - i);
+            bytes[i..i + n].copy_from_slice(&word[offset..offset + n]);
+            i += n;
+        }
+        Ok(())
+    }
+
+    /// Read a `u32` with the given endianness at the given address.
+    unsafe fn read_u32(&self, addr: usize, endian: gimli::RunTimeEndian) -> Result<u32> {
+        let mut bytes = [0; 4];
+        self.read_bytes(addr, &mut bytes)?;
+        Ok(endian.read_u32(&bytes))
+    }
+
+    /// Read a `u64` with the given endianness at the given address.
+    unsafe fn read_u64(&self, addr: usize, endian: gimli::RunTimeEndian) -> Result<u64> {
+        let mut bytes = [0; 8];
+        self.read_bytes(addr, &mut bytes)?;
+        Ok(endian.read_u64(&bytes))
+    }
+
+    /// Read a pointer of the given width in bytes, and with the given
+    /// endianness, at the given address. This is for reading the memory of a
+    /// target whose pointers differ from this host's.
+    ///
+    /// Fails with `Error::UnsupportedPointerWidth` if `width` is not 4 or 8.
+    unsafe fn read_ptr(
+        &self,
+        addr: usize,
+        width: usize,
+        endian: gimli::RunTimeEndian,
+    ) -> Result<u64> {
+        match width {
+            4 => self.read_u32(addr, endian).map(u64::from),
+            8 => self.read_u64(addr, endian),
+            _ => Err(Error::UnsupportedPointerWidth(width)),
+        }
     }
 }
 
@@ -88,17 +290,40 @@ pub trait MemoryReader: fmt::Debug + Sized {
 /// that could be useful for debuggers, and an implementation that tracks the
 /// subset of registers needed to perform fast-path stack walking in the 99%
 /// case for profilers.
+///
+/// `FrameRegisters`, also known as `SampleRegisters`, is the latter. On
+/// x86_64, `FullRegisters` is the former.
 pub trait Registers: fmt::Debug + Sized {
-    /// Construct this register set from the given DWARF unwind table row.
+    /// Construct this register set from the given DWARF unwind table row,
+    /// recovering the return address from the given register's rule, which
+    /// is the CIE's return address column.
     unsafe fn from_unwind_table_row<Reader>(
         row: &gimli::UnwindTableRow<TargetEndianBuf>,
+        return_address_register: u8,
         old_registers: &Self,
         reader: &Reader,
     ) -> Result<Self>
     where
         Reader: MemoryReader;
 
-    /// TODO FITZGEN
+    /// Recover the registers of the frame a signal interrupted from the
+    /// signal frame that the kernel pushed, given the registers of the
+    /// signal trampoline's frame, which the handler returns to.
+    ///
+    /// Returns `None` where the signal frame's layout is unknown, in which
+    /// case the trampoline's CFI is used instead.
+    unsafe fn from_sigframe<Reader>(registers: &Self, reader: &Reader) -> Option<Result<Self>>
+    where
+        Reader: MemoryReader,
+    {
+        let _ = (registers, reader);
+        None
+    }
+
+    /// Capture the registers of the calling frame, and call `f` with them
+    /// while that frame is still live, so that the stack they point into
+    /// stays valid.
+    #[cfg(feature = "live")]
     fn with_current<F, T>(f: F) -> Result<T>
     where
         F: FnMut(&Self) -> Result<T>;
@@ -113,20 +338,46 @@ pub trait Registers: fmt::Debug + Sized {
     fn ip(&self) -> TaggedWord;
 }
 
-type TargetEndianBuf<'a> = gimli::EndianBuf<'a, gimli::NativeEndian>;
+type TargetEndianBuf<'a> = gimli::EndianBuf<'a, gimli::RunTimeEndian>;
 type TargetEhFrame<'a> = gimli::EhFrame<TargetEndianBuf<'a>>;
 type TargetFde<'a> = gimli::FrameDescriptionEntry<TargetEhFrame<'a>, TargetEndianBuf<'a>>;
 type TargetUninitializedUnwindContext<'a> = gimli::UninitializedUnwindContext<
     TargetEhFrame<'a>,
     TargetEndianBuf<'a>,
 >;
+type TargetDebugFrame<'a> = gimli::DebugFrame<TargetEndianBuf<'a>>;
+type TargetDebugFrameFde<'a> =
+    gimli::FrameDescriptionEntry<TargetDebugFrame<'a>, TargetEndianBuf<'a>>;
+type TargetDebugFrameUnwindContext<'a> = gimli::UninitializedUnwindContext<
+    TargetDebugFrame<'a>,
+    TargetEndianBuf<'a>,
+>;
+
+/// An FDE from either an `.eh_frame` or a `.debug_frame` section.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Fde<'a> {
+    EhFrame(TargetFde<'a>),
+    DebugFrame(TargetDebugFrameFde<'a>),
+}
 
 /// Unwinding information for a particular address range.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct UnwindEntry<'a> {
-    range: Range<Avma>,
+    range: Range<usize>,
     bias: Bias,
-    fde: TargetFde<'a>,
+    fde: Fde<'a>,
+}
+
+impl<'a> UnwindEntry<'a> {
+    /// The addresses this entry covers.
+    pub fn range(&self) -> Range<Avma> {
+        avma_range(&self.range)
+    }
+
+    /// The bias of the module this entry is from.
+    pub fn bias(&self) -> Bias {
+        self.bias
+    }
 }
 
 impl<'a> PartialOrd for UnwindEntry<'a> {
@@ -140,10 +391,406 @@ impl<'a> Ord for UnwindEntry<'a> {
     }
 }
 
+/// Two unwind entries of the same module whose address ranges overlapped
+/// when an `Unwinder` was built, e.g. because the same `.eh_frame` was added
+/// twice. Entries are looked up by binary search, which needs them not to
+/// overlap, so only one of the two was kept.
+///
+/// See `Unwinder::entry_conflicts`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EntryConflict {
+    bias: Bias,
+    kept: Range<usize>,
+    dropped: Range<usize>,
+}
+
+impl EntryConflict {
+    /// The bias of the module both entries are from.
+    pub fn bias(&self) -> Bias {
+        self.bias
+    }
+
+    /// The range of the entry that was kept.
+    pub fn kept(&self) -> Range<Avma> {
+        avma_range(&self.kept)
+    }
+
+    /// The range of the entry that was dropped.
+    pub fn dropped(&self) -> Range<Avma> {
+        avma_range(&self.dropped)
+    }
+}
+
+impl fmt::Display for EntryConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (kept, dropped) = (self.kept(), self.dropped());
+        write!(
+            f,
+            "kept {} .. {} over overlapping {} .. {} in the module with bias {}",
+            kept.start, kept.end, dropped.start, dropped.end, self.bias
+        )
+    }
+}
+
+/// The entries of a single module: every entry with the same bias.
+///
+/// An address's entry is looked up in two steps: a binary search for the
+/// modules whose ranges cover the address, and then a binary search of those
+/// modules' entries. Walking looks an entry up for nearly every frame, so
+/// once built, the entries' starts and ends are also kept in arrays of their
+/// own, parallel to `entries`. The binary search only touches the densely
+/// packed starts, and then one end and one entry.
+#[derive(Clone, Debug)]
+struct EntryModule<'a> {
+    bias: Bias,
+    /// From the start of the module's first entry to the end of its last.
+    range: Range<usize>,
+    /// The furthest end of the range of this module or any module sorted
+    /// before it, which bounds the search for the modules covering an
+    /// address. Only valid once built.
+    reach: usize,
+    /// Sorted by address once built.
+    entries: Vec<UnwindEntry<'a>>,
+    /// The start of each entry. Only valid once built.
+    starts: Vec<usize>,
+    /// The end of each entry. Only valid once built.
+    ends: Vec<usize>,
+}
+
+impl<'a> EntryModule<'a> {
+    fn new(entry: UnwindEntry<'a>) -> EntryModule<'a> {
+        EntryModule {
+            bias: entry.bias,
+            range: entry.range.clone(),
+            reach: entry.range.end,
+            entries: vec![entry],
+            starts: vec![],
+            ends: vec![],
+        }
+    }
+
+    fn push(&mut self, entry: UnwindEntry<'a>) {
+        self.range.start = cmp::min(self.range.start, entry.range.start);
+        self.range.end = cmp::max(self.range.end, entry.range.end);
+        self.entries.push(entry);
+    }
+
+    /// Recompute the range after removing entries.
+    fn update_range(&mut self) {
+        let mut entries = self.entries.iter();
+        if let Some(first) = entries.next() {
+            self.range = entries.fold(first.range.clone(), |range, entry| {
+                cmp::min(range.start, entry.range.start)..cmp::max(range.end, entry.range.end)
+            });
+        }
+    }
+
+    /// Sort the entries, drop the ones that overlap another, recording each
+    /// in `conflicts`, and fill in the arrays of their starts and ends.
+    ///
+    /// Where entries overlap, the one starting first is kept. Of entries
+    /// starting at the same address, the longest is kept, and of identical
+    /// entries, the one added first.
+    fn build(&mut self, conflicts: &mut Vec<EntryConflict>) {
+        self.entries.sort_by(|a, b| {
+            a.range.start.cmp(&b.range.start).then(b.range.end.cmp(&a.range.end))
+        });
+        let sorted = mem::replace(&mut self.entries, vec![]);
+        for entry in sorted {
+            if let Some(kept) = self.entries.last() {
+                if entry.range.start < kept.range.end {
+                    conflicts.push(EntryConflict {
+                        bias: self.bias,
+                        kept: kept.range.clone(),
+                        dropped: entry.range.clone(),
+                    });
+                    continue;
+                }
+            }
+            self.entries.push(entry);
+        }
+
+        self.starts = self.entries.iter().map(|entry| entry.range.start).collect();
+        self.ends = self.entries.iter().map(|entry| entry.range.end).collect();
+    }
+
+    /// Find the entry covering the given address: the last one starting at
+    /// or before it, if that one reaches it.
+    fn lookup(&self, ip: usize) -> Option<&UnwindEntry<'a>> {
+        let idx = self.starts.partition_point(|&start| start <= ip);
+        if idx == 0 || self.ends[idx - 1] < ip {
+            return None;
+        }
+        Some(&self.entries[idx - 1])
+    }
+}
+
+/// An `.eh_frame` section whose FDEs are looked up lazily, through the search
+/// table in its `.eh_frame_hdr` section.
+#[derive(Clone, Debug)]
+struct EhFrameHdrModule<'a> {
+    bias: Bias,
+    bases: gimli::BaseAddresses,
+    hdr: EhFrameHdr<'a>,
+    eh_frame: TargetEhFrame<'a>,
+}
+
+/// Breakpad call frame information for a single module.
+#[derive(Clone, Debug)]
+struct BreakpadModule {
+    bias: Bias,
+    symbols: breakpad::SymbolFile,
+}
+
+/// A source of unwind information that a `Walker` may consult.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum UnwindStrategy {
+    /// Hand-written rules registered with `Options::add_manual_rule`.
+    Manual,
+
+    /// DWARF call frame information from `.eh_frame` sections.
+    Dwarf,
+
+    /// `STACK CFI` records from Breakpad symbol files.
+    Breakpad,
+
+    /// Following the chain of saved frame pointers, for code without any
+    /// unwind information. This only works for code compiled to keep a frame
+    /// pointer, and only on architectures with a standard frame record of the
+    /// caller's frame pointer followed by the return address: x86, x86_64,
+    /// and aarch64.
+    FramePointer,
+}
+
+impl UnwindStrategy {
+    /// The strategies consulted by default, in order.
+    pub const DEFAULT: &'static [UnwindStrategy] = &[
+        UnwindStrategy::Manual,
+        UnwindStrategy::Dwarf,
+        UnwindStrategy::Breakpad,
+        UnwindStrategy::FramePointer,
+    ];
+
+    /// Get the strategy with the given name, as used in the
+    /// `PANCAKES_STRATEGIES` environment variable.
+    pub fn from_name(name: &str) -> Option<UnwindStrategy> {
+        match name {
+            "manual" => Some(UnwindStrategy::Manual),
+            "dwarf" => Some(UnwindStrategy::Dwarf),
+            "breakpad" => Some(UnwindStrategy::Breakpad),
+            "frame-pointer" => Some(UnwindStrategy::FramePointer),
+            _ => None,
+        }
+    }
+}
+
+/// What a frame's instruction pointer points to.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum PcKind {
+    /// The instruction the frame was about to execute when it was
+    /// interrupted, by a signal or by capturing its registers. This is the
+    /// case for the first frame of a walk, and for the caller of a signal
+    /// frame.
+    Interrupted,
+
+    /// The return address of a call the frame is making: the instruction
+    /// after the call. This is the case for every other frame.
+    ///
+    /// When the call is a function's last instruction, e.g. a call to a
+    /// `noreturn` function, the return address is the first instruction of
+    /// the next function, so unwind information is looked up by the address
+    /// before it.
+    ReturnAddress,
+}
+
+impl PcKind {
+    /// The address to look up a frame's unwind information by, given its
+    /// instruction pointer.
+    pub fn lookup_address(self, pc: usize) -> usize {
+        match self {
+            PcKind::Interrupted => pc,
+            PcKind::ReturnAddress => pc.wrapping_sub(1),
+        }
+    }
+}
+
+/// How a frame was walked to its caller.
+#[derive(Clone, Copy, Debug)]
+struct Walked<'u> {
+    strategy: UnwindStrategy,
+    bias: Option<Bias>,
+    entry: Option<&'u UnwindEntry<'u>>,
+    signal_frame: bool,
+}
+
+impl<'u> Walked<'u> {
+    fn by(strategy: UnwindStrategy) -> Walked<'u> {
+        Walked {
+            strategy,
+            bias: None,
+            entry: None,
+            signal_frame: false,
+        }
+    }
+
+    /// What the caller's instruction pointer points to: a signal interrupts
+    /// its frame rather than calling out of it.
+    fn caller_pc_kind(&self) -> PcKind {
+        if self.signal_frame {
+            PcKind::Interrupted
+        } else {
+            PcKind::ReturnAddress
+        }
+    }
+}
+
+/// A frame found by walking the stack, as given to the `walk` callbacks and
+/// yielded by `Frames`.
+///
+/// Besides the frame's registers, which it dereferences to, a `Frame` carries
+/// what walking it to its caller found: the unwind information covering its
+/// pc, and the module that information came from. When walking to its
+/// caller failed, or an outermost frame was found by frame pointer and has no
+/// unwind information, none of that is known.
+#[derive(Clone, Debug)]
+pub struct Frame<'u> {
+    index: usize,
+    registers: FrameRegisters,
+    pc_kind: PcKind,
+    cfa: Option<usize>,
+    found_by: Option<UnwindStrategy>,
+    walked: Option<Walked<'u>>,
+}
+
+impl<'u> Frame<'u> {
+    /// The frame's position in the walk, counting from zero for the first
+    /// frame reported, after any `Options::skip_frames`.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The frame's registers.
+    pub fn registers(&self) -> &FrameRegisters {
+        &self.registers
+    }
+
+    /// What the frame's instruction pointer points to.
+    pub fn pc_kind(&self) -> PcKind {
+        self.pc_kind
+    }
+
+    /// The frame's canonical frame address: the caller's stack pointer
+    /// before the call into this frame.
+    pub fn cfa(&self) -> Option<usize> {
+        self.cfa
+    }
+
+    /// The strategy that walked this frame's callee to this frame, or `None`
+    /// for the frame the walk started at, whose registers were given.
+    pub fn found_by(&self) -> Option<UnwindStrategy> {
+        self.found_by
+    }
+
+    /// The strategy that walked this frame to its caller.
+    pub fn strategy(&self) -> Option<UnwindStrategy> {
+        self.walked.map(|walked| walked.strategy)
+    }
+
+    /// Whether this frame was walked by guessing at its layout, e.g. by
+    /// following frame pointers, rather than by unwind information. The
+    /// caller of such a frame may be wrong.
+    pub fn is_heuristic(&self) -> bool {
+        self.strategy() == Some(UnwindStrategy::FramePointer)
+    }
+
+    /// The bias of the module whose unwind information covers the frame's
+    /// pc, which identifies the module among the loaded ones.
+    pub fn module_bias(&self) -> Option<Bias> {
+        self.walked.and_then(|walked| walked.bias)
+    }
+
+    /// The frame's pc as a stated virtual memory address in its module, to
+    /// look up symbols with. Like unwind information, this is the address of
+    /// the call for all frames but the first; see `PcKind::lookup_address`.
+    pub fn svma(&self) -> Option<Svma> {
+        let bias = self.module_bias()?;
+        let pc = self.registers.ip().map_or(None, Some)?;
+        let pc = self.pc_kind.lookup_address(pc);
+        Some(Svma((pc as isize).wrapping_sub(bias.0) as usize as *const u8))
+    }
+
+    /// The `.eh_frame` or `.debug_frame` entry that covers the frame's pc,
+    /// when that is what walked it.
+    pub fn unwind_entry(&self) -> Option<&'u UnwindEntry<'u>> {
+        self.walked.and_then(|walked| walked.entry)
+    }
+
+    /// Whether this is a signal trampoline's frame, whose caller is the
+    /// frame that the signal interrupted rather than a frame that called
+    /// it. Like the first frame of a walk, that frame's pc is the
+    /// instruction it was about to execute.
+    pub fn is_signal_frame(&self) -> bool {
+        self.walked.map_or(false, |walked| walked.signal_frame)
+    }
+}
+
+impl<'u> ops::Deref for Frame<'u> {
+    type Target = FrameRegisters;
+
+    fn deref(&self) -> &FrameRegisters {
+        &self.registers
+    }
+}
+
 /// A configuration options builder for an `Walker`.
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug)]
 pub struct Options<'a> {
-    entries: Vec<UnwindEntry<'a>>,
+    entry_modules: Vec<EntryModule<'a>>,
+    eh_frame_hdrs: Vec<EhFrameHdrModule<'a>>,
+    compiled: Vec<CompiledTable>,
+    unwind_table_cache: Option<PathBuf>,
+    jit: Option<Arc<JitRegistry>>,
+    breakpad: Vec<BreakpadModule>,
+    manual: Vec<ManualEntry>,
+    strategies: Vec<UnwindStrategy>,
+    max_frames: Option<usize>,
+    skip_frames: usize,
+    stack_bounds: Option<Range<usize>>,
+    pointer_auth_mask: usize,
+    expression_fuel: usize,
+    debug_file_directories: Vec<PathBuf>,
+    #[cfg(feature = "debuginfod")]
+    debuginfod: Option<debuginfod::Client>,
+    /// The name of each module `find_eh_frame_entries` found, to log.
+    module_names: Vec<(Bias, String)>,
+    /// The modules whose `.eh_frame` `find_eh_frame_entries` could not
+    /// parse, to log.
+    skipped_modules: Vec<(Bias, Arc<Error>)>,
+}
+
+impl<'a> Default for Options<'a> {
+    fn default() -> Self {
+        Options {
+            entry_modules: vec![],
+            eh_frame_hdrs: vec![],
+            compiled: vec![],
+            unwind_table_cache: None,
+            jit: None,
+            breakpad: vec![],
+            manual: vec![],
+            strategies: UnwindStrategy::DEFAULT.to_vec(),
+            max_frames: None,
+            skip_frames: 0,
+            stack_bounds: None,
+            pointer_auth_mask: registers::POINTER_AUTH_MASK,
+            expression_fuel: expression::DEFAULT_FUEL,
+            debug_file_directories: vec![PathBuf::from("/usr/lib/debug")],
+            #[cfg(feature = "debuginfod")]
+            debuginfod: None,
+            module_names: vec![],
+            skipped_modules: vec![],
+        }
+    }
 }
 
 impl<'a> Options<'a> {
@@ -158,17 +805,209 @@ impl<'a> Options<'a> {
         Default::default()
     }
 
+    /// Construct a new `Options`, configured from environment variables so
+    /// that the unwinder can be tuned in a deployed binary without a rebuild.
+    ///
+    /// * `PANCAKES_MAX_FRAMES`: the maximum number of frames to walk. See
+    ///   `Options::max_frames`.
+    ///
+    /// * `PANCAKES_STRATEGIES`: a comma-separated list of unwind strategies
+    ///   to consult, in order: `manual`, `dwarf`, `breakpad`, and
+    ///   `frame-pointer`. See
+    ///   `Options::strategies`.
+    ///
+    /// * `PANCAKES_CACHE`: the directory to cache the compiled unwind tables
+    ///   of modules added with `add_module_from_file` in. See
+    ///   `Options::unwind_table_cache`.
+    ///
+    /// Unset variables leave the default configuration in place. To also
+    /// enable logging with `PANCAKES_LOG`, build the walker with a
+    /// `log::EnvLogger`.
+    ///
+    /// ```
+    /// use pancakes::Options;
+    ///
+    /// let options = Options::from_env().expect("environment should be valid");
+    /// # let _ = options;
+    /// ```
+    pub fn from_env() -> Result<Self> {
+        let mut options = Options::new();
+
+        if let Some(max_frames) = env::var_os("PANCAKES_MAX_FRAMES") {
+            let max_frames = max_frames
+                .to_str()
+                .and_then(|s| s.trim().parse().ok())
+                .ok_or(Error::InvalidEnvironmentVariable("PANCAKES_MAX_FRAMES"))?;
+            options.max_frames(max_frames);
+        }
+
+        if let Some(strategies) = env::var_os("PANCAKES_STRATEGIES") {
+            let strategies = strategies
+                .to_str()
+                .and_then(|s| {
+                    s.split(',')
+                        .map(|name| UnwindStrategy::from_name(name.trim()))
+                        .collect::<Option<Vec<_>>>()
+                })
+                .ok_or(Error::InvalidEnvironmentVariable("PANCAKES_STRATEGIES"))?;
+            options.strategies(strategies);
+        }
+
+        if let Some(cache) = env::var_os("PANCAKES_CACHE") {
+            if cache.is_empty() {
+                return Err(Error::InvalidEnvironmentVariable("PANCAKES_CACHE"));
+            }
+            options.unwind_table_cache(cache);
+        }
+
+        Ok(options)
+    }
+
+    /// Stop walking after this many frames, including the starting frame
+    /// but not the frames skipped with `Options::skip_frames`.
+    ///
+    /// By default, there is no limit.
+    pub fn max_frames(&mut self, max_frames: usize) -> &mut Self {
+        self.max_frames = Some(max_frames);
+        self
+    }
+
+    /// Walk this many frames, starting with the starting frame, without
+    /// reporting them, e.g. to hide the frames of a profiler's or crash
+    /// reporter's own stack capturing code.
+    ///
+    /// `Walker::walk_current` and `pancakes::trace` already skip the frames
+    /// pancakes adds to capture the current registers, and skip this many
+    /// more.
+    ///
+    /// By default, no frames are skipped.
+    pub fn skip_frames(&mut self, skip_frames: usize) -> &mut Self {
+        self.skip_frames = skip_frames;
+        self
+    }
+
+    /// Set the address range of the stack being walked, which frame pointer
+    /// unwinding checks every frame pointer against, so that a corrupt or
+    /// reused frame pointer register ends the walk instead of sending it off
+    /// into arbitrary memory.
+    ///
+    /// By default, frame pointers are only checked to be word aligned and to
+    /// increase from frame to frame.
+    pub fn stack_bounds(&mut self, stack: Range<usize>) -> &mut Self {
+        self.stack_bounds = Some(stack);
+        self
+    }
+
+    /// Clear these bits from every walked frame's return address, before its
+    /// unwind information is looked up.
+    ///
+    /// With ARMv8.3 pointer authentication, return addresses are signed before
+    /// they are saved on the stack, and the signature is stored in the
+    /// address's unused high bits. Stripping the signature yields the
+    /// canonical address. Stripping an unsigned address is a no-op, so it
+    /// does not matter whether a particular frame's return address was signed.
+    ///
+    /// By default, this is `0xffff_0000_0000_0000` on aarch64, where
+    /// user-space addresses have at most 48 significant bits, and `0`
+    /// elsewhere.
+    pub fn pointer_auth_mask(&mut self, mask: usize) -> &mut Self {
+        self.pointer_auth_mask = mask;
+        self
+    }
+
+    /// Set how many operations evaluating a single DWARF expression in call
+    /// frame information may execute, so that corrupt or malicious CFI
+    /// cannot loop forever. Defaults to 1000.
+    pub fn expression_fuel(&mut self, fuel: usize) -> &mut Self {
+        self.expression_fuel = fuel;
+        self
+    }
+
+    /// Set the directories that `add_module_from_file` searches for separate
+    /// debug files, both by build ID and through `.gnu_debuglink`, and for
+    /// `.dSYM` bundles.
+    ///
+    /// By default, this is just `/usr/lib/debug`.
+    pub fn debug_file_directories<I, P>(&mut self, directories: I) -> &mut Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        self.debug_file_directories = directories.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Walk frames in JIT code using the unwind information registered with
+    /// the given registry.
+    ///
+    /// The JIT keeps registering and deregistering code with the registry
+    /// while the walker is in use, from any thread.
+    pub fn jit_registry(&mut self, registry: Arc<JitRegistry>) -> &mut Self {
+        self.jit = Some(registry);
+        self
+    }
+
+    /// Cache the compiled unwind tables of the modules added with
+    /// `add_module_from_file` in the given directory, keyed by build ID.
+    ///
+    /// Short-lived processes that walk stacks pay for parsing each module's
+    /// unwind information on every run; with a cache, only the first run
+    /// does. Entries are invalidated when the module's file changes.
+    ///
+    /// By default, there is no cache.
+    pub fn unwind_table_cache<P>(&mut self, directory: P) -> &mut Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.unwind_table_cache = Some(directory.into());
+        self
+    }
+
+    /// Fetch the debug files that `add_module_from_file` cannot find locally
+    /// from the given debuginfod servers.
+    ///
+    /// ```no_run
+    /// use pancakes::Options;
+    /// use pancakes::debuginfod::Client;
+    ///
+    /// let mut options = Options::new();
+    /// if let Some(client) = Client::from_env() {
+    ///     options.debuginfod(client);
+    /// }
+    /// ```
+    #[cfg(feature = "debuginfod")]
+    pub fn debuginfod(&mut self, client: debuginfod::Client) -> &mut Self {
+        self.debuginfod = Some(client);
+        self
+    }
+
+    /// Set which unwind strategies are consulted for each frame, and in which
+    /// order. The first strategy that has unwind information covering a
+    /// frame's address is used to walk it.
+    ///
+    /// By default, this is `UnwindStrategy::DEFAULT`.
+    pub fn strategies<I>(&mut self, strategies: I) -> &mut Self
+    where
+        I: IntoIterator<Item = UnwindStrategy>,
+    {
+        self.strategies = strategies.into_iter().collect();
+        self
+    }
+
     /// Add a single entry.
     pub fn add_entry(&mut self, entry: UnwindEntry<'a>) -> &mut Self {
-        eprintln!(
-            "FITZGEN: add_entry {:#0p} .. {:#0p}",
-            entry.range.start.0 as *const (),
-            entry.range.end.0 as *const (),
-        );
-        self.entries.push(entry);
+        match self.entry_modules.iter_mut().rev().find(|module| module.bias == entry.bias) {
+            Some(module) => module.push(entry),
+            None => self.entry_modules.push(EntryModule::new(entry)),
+        }
         self
     }
 
+    /// Get the module of the entries with the given bias.
+    fn entry_module(&self, bias: Bias) -> Option<&EntryModule<'a>> {
+        self.entry_modules.iter().rev().find(|module| module.bias == bias)
+    }
+
     /// Add many entries.
     pub fn add_entries<I>(&mut self, entries: I) -> &mut Self
     where
@@ -182,13 +1021,43 @@ impl<'a> Options<'a> {
 
     /// Create entries from the information in the given `.eh_frame` section,
     /// and add them to the builder.
+    ///
+    /// The section may have either endianness, so the `.eh_frame` of a
+    /// big-endian target can be parsed on a little-endian host and vice
+    /// versa. Use a `reader::TargetEndian` to read that target's stack words.
+    ///
+    /// ```
+    /// extern crate gimli;
+    /// extern crate pancakes;
+    /// # fn main() {
+    /// # let data: &[u8] = &[];
+    /// let eh_frame = gimli::EhFrame::new(data, gimli::RunTimeEndian::Big);
+    /// # let _ = eh_frame;
+    /// # }
+    /// ```
     pub fn add_entries_from_eh_frame(
         &mut self,
-        bias: findshlibs::Bias,
+        bias: Bias,
         bases: gimli::BaseAddresses,
         eh_frame: TargetEhFrame<'a>,
     ) -> Result<&mut Self> {
-        let mut entries = eh_frame.entries(&bases);
+        let entries = eh_frame_entries(bias, &bases, eh_frame)?;
+        Ok(self.add_entries(entries))
+    }
+
+    /// Create entries from the information in the given `.debug_frame`
+    /// section, and add them to the builder.
+    ///
+    /// Stripped binaries usually keep their `.eh_frame` but move
+    /// `.debug_frame` into a separate debug file, and some toolchains only
+    /// emit `.debug_frame` for code that cannot throw.
+    pub fn add_entries_from_debug_frame(
+        &mut self,
+        bias: Bias,
+        bases: gimli::BaseAddresses,
+        debug_frame: TargetDebugFrame<'a>,
+    ) -> Result<&mut Self> {
+        let mut entries = debug_frame.entries(&bases);
         let mut cies = HashMap::new();
         while let Some(entry) = entries.next()? {
             match entry {
@@ -196,14 +1065,12 @@ impl<'a> Options<'a> {
                 gimli::CieOrFde::Fde(partial) => {
                     let fde = partial.parse(|offset| {
                         cies.entry(offset)
-                            .or_insert_with(|| eh_frame.cie_from_offset(&bases, offset))
+                            .or_insert_with(|| debug_frame.cie_from_offset(&bases, offset))
                             .clone()
                     })?;
-                    let start = fde.initial_address() as usize as *const u8;
-                    let range = Range {
-                        start: Avma(unsafe { start.offset(bias.0) }),
-                        end: Avma(unsafe { start.offset(fde.len() as isize + bias.0) }),
-                    };
+                    let start = (fde.initial_address() as isize).wrapping_add(bias.0) as usize;
+                    let range = start..start.wrapping_add(fde.len() as usize);
+                    let fde = Fde::DebugFrame(fde);
                     self.add_entry(UnwindEntry { bias, range, fde });
                 }
             }
@@ -211,58 +1078,495 @@ impl<'a> Options<'a> {
         Ok(self)
     }
 
-    /// TODO FITZGEN
-    pub fn find_eh_frame_entries(&mut self) -> Result<&mut Self> {
-        cfg_if! {
-            if #[cfg(target_os = "macos")] {
-                const EH_FRAME: &'static [u8] = b"__eh_frame";
-            } else {
-                const EH_FRAME: &'static [u8] = b".eh_frame";
-            }
-        }
-
-        findshlibs::TargetSharedLibrary::each(|shlib| {
-            eprintln!("FITZGEN: shlib = {}", shlib.name().to_string_lossy());
-
-            for section in shlib.sections() {
-                eprintln!("FITZGEN:     section = {:?}", section.name().to_string_lossy());
+    /// Add the given `.eh_frame` section, whose FDEs are looked up through the
+    /// binary search table in the given `.eh_frame_hdr` section as frames are
+    /// walked, instead of being parsed ahead of time.
+    ///
+    /// This makes adding large modules much cheaper, at the cost of parsing
+    /// an FDE for each frame walked. `eh_frame_hdr_address` is the stated
+    /// virtual memory address of the `.eh_frame_hdr` section, and `bases`
+    /// must give the stated virtual memory address of the `.eh_frame`
+    /// section.
+    pub fn add_eh_frame_hdr(
+        &mut self,
+        bias: Bias,
+        bases: gimli::BaseAddresses,
+        eh_frame_hdr_address: Svma,
+        eh_frame_hdr: &'a [u8],
+        eh_frame: TargetEhFrame<'a>,
+    ) -> Result<&mut Self> {
+        let hdr = EhFrameHdr::parse(eh_frame_hdr, eh_frame_hdr_address.0 as usize)?;
+        self.eh_frame_hdrs.push(EhFrameHdrModule {
+            bias,
+            bases,
+            hdr,
+            eh_frame,
+        });
+        Ok(self)
+    }
 
-                if section.name().to_bytes() == EH_FRAME {
-                    let bias = shlib.virtual_memory_bias();
+    /// Find and add the `.eh_frame` of every module loaded into this process,
+    /// and of the vDSO.
+    ///
+    /// Modules with an `.eh_frame_hdr` are added with `add_eh_frame_hdr`, and
+    /// their FDEs are only parsed as they are needed. The others have every
+    /// FDE parsed up front, which with the `parallel-setup` feature is spread
+    /// across a thread per CPU.
+    #[cfg(feature = "live")]
+    pub fn find_eh_frame_entries(&mut self) -> Result<&mut Self> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("find_eh_frame_entries").entered();
 
-                    let ptr = section.actual_virtual_memory_address(shlib);
-                    let ptr = ptr.0 as *const u8;
-                    let len = section.len();
-                    let eh_frame = unsafe {
-                        slice::from_raw_parts(ptr, len)
-                    };
-                    let eh_frame = TargetEhFrame::new(eh_frame, gimli::NativeEndian);
+        // The modules without an `.eh_frame_hdr`, whose FDEs are all parsed
+        // at once afterwards.
+        let mut unparsed = vec![];
+        findshlibs::TargetSharedLibrary::each(|shlib| {
+            let bias = shlib.virtual_memory_bias();
+            let name = shlib.name().to_string_lossy().into_owned();
+            #[cfg(feature = "tracing")]
+            let _span = tracing::debug_span!("module", name = %name, bias = bias.0).entered();
+            #[cfg(feature = "tracing")]
+            let started = Instant::now();
 
-                    // TODO: create base addresses properly.
-                    let bases = gimli::BaseAddresses::default()
-                        .set_cfi(section.stated_virtual_memory_address().0 as u64);
+            let sections = ModuleSections::find(shlib);
+            if let (Some((_, eh_frame)), Some(bases)) = (sections.eh_frame, sections.bases()) {
+                let eh_frame = TargetEhFrame::new(eh_frame, gimli::RunTimeEndian::default());
 
-                    if let Err(e) = self.add_entries_from_eh_frame(bias, bases, eh_frame) {
-                        // error = Some(e);
-                        // return findshlibs::IterationControl::Break;
+                // Prefer looking FDEs up lazily through the `.eh_frame_hdr`
+                // search table, and only parse every FDE up front if there
+                // isn't one.
+                let added_hdr = sections.eh_frame_hdr.map_or(false, |(hdr_svma, hdr)| {
+                    self.add_eh_frame_hdr(bias, bases.clone(), hdr_svma, hdr, eh_frame)
+                        .is_ok()
+                });
 
-                        // TODO FITZGEN: warn or something...
-                        let _ = e;
-                    }
+                #[cfg(feature = "tracing")]
+                tracing::debug!(
+                    eh_frame_hdr = added_hdr,
+                    elapsed_us = started.elapsed().as_micros() as u64,
+                    "found .eh_frame"
+                );
 
-                    return findshlibs::IterationControl::Continue;
+                if !added_hdr {
+                    unparsed.push((bias, bases, eh_frame));
                 }
             }
+            self.module_names.push((bias, name));
 
             findshlibs::IterationControl::Continue
         });
 
+        let biases: Vec<_> = unparsed.iter().map(|&(bias, _, _)| bias).collect();
+        for (bias, entries) in biases.into_iter().zip(parse_eh_frames(unparsed)) {
+            match entries {
+                Ok(entries) => {
+                    self.add_entries(entries);
+                }
+                // Walk without this module rather than fail to walk at all,
+                // and tell the logger when a `Walker` is built.
+                Err(e) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(error = %e, "failed to parse .eh_frame");
+                    self.skipped_modules.push((bias, Arc::new(e)));
+                }
+            }
+        }
+
+        // The vDSO has no file for `findshlibs` to find sections in.
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let _ = self.add_vdso();
+
+        Ok(self)
+    }
+
+    /// Add the unwind information of the vDSO, the shared object the kernel
+    /// maps into every Linux process to implement syscalls like
+    /// `clock_gettime` without entering the kernel. Without it, stacks cannot
+    /// be walked through frames in the vDSO.
+    ///
+    /// `find_eh_frame_entries` calls this itself.
+    #[cfg(all(feature = "live", any(target_os = "linux", target_os = "android")))]
+    pub fn add_vdso(&mut self) -> Result<&mut Self> {
+        let image = match vdso::image() {
+            Some(image) => image,
+            None => return Ok(self),
+        };
+        let file = object::File::parse(image).map_err(|_| Error::InvalidObjectFile)?;
+        let endian = if file.is_little_endian() {
+            gimli::RunTimeEndian::Little
+        } else {
+            gimli::RunTimeEndian::Big
+        };
+
+        // The whole image is mapped at once, so its bias is where it was
+        // mapped less where its first segment says it should be.
+        let svma = file.segments().map(|segment| segment.address()).min().unwrap_or(0);
+        let bias = Bias(image.as_ptr() as isize - svma as isize);
+
+        self.add_eh_frame_from_object(&file, endian, bias)?;
+        Ok(self)
+    }
+
+    /// Add the unwind information in the ELF, Mach-O, or PE object file at the
+    /// given path, for a module loaded with the given bias.
+    ///
+    /// Unlike `find_eh_frame_entries`, this does not need the module to be
+    /// mapped into this process, so it works for walking the stacks of other
+    /// processes and core dumps. The file's unwind information is compiled
+    /// into unwind tables up front, as by `compile_unwind_tables`, and its
+    /// contents are not kept, so it can be added to an `Options<'static>`.
+    /// Rows that cannot be compiled, those whose rules use DWARF expressions
+    /// and those of signal trampolines, are left out.
+    ///
+    /// Both `.eh_frame` and `.debug_frame` are used. If the file has no
+    /// `.debug_frame`, its separate debug file is looked for, and the debug
+    /// file's `.debug_frame` is used instead:
+    ///
+    /// * For a Mach-O image, the `.dSYM` bundle with its UUID.
+    ///
+    /// * The debug file with the build ID in its `.note.gnu.build-id`, and
+    ///   then, if configured, the same from debuginfod.
+    ///
+    /// * The debug file named by its `.gnu_debuglink`.
+    ///
+    /// See `Options::debug_file_directories` and `Options::debuginfod`.
+    ///
+    /// If an unwind table cache is configured with
+    /// `Options::unwind_table_cache`, the module's compiled unwind tables are
+    /// cached, and the next process to add it skips all of the above.
+    pub fn add_module_from_file<P>(&mut self, path: P, load_bias: Bias) -> Result<&mut Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!(
+            "add_module_from_file",
+            path = %path.display(),
+            bias = load_bias.0
+        ).entered();
+
+        let data = fs::read(path)?;
+        let file = object::File::parse(&*data).map_err(|_| Error::InvalidObjectFile)?;
+        let endian = if file.is_little_endian() {
+            gimli::RunTimeEndian::Little
+        } else {
+            gimli::RunTimeEndian::Big
+        };
+
+        let cache = match self.unwind_table_cache {
+            Some(ref directory) => unwind_cache::Key::new(path, &file)
+                .map(|key| (directory.clone(), key)),
+            None => None,
+        };
+        if let Some((ref directory, ref key)) = cache {
+            if let Some(table) = unwind_cache::load(directory, key, load_bias) {
+                self.compiled.push(table);
+                return Ok(self);
+            }
+        }
+
+        let mut compiler = Compiler::default();
+        self.compile_object(&mut compiler, path, &file, endian, load_bias)?;
+        for table in compiler.finish() {
+            if let Some((ref directory, ref key)) = cache {
+                // Failing to cache only costs the next process some time.
+                let _ = unwind_cache::store(directory, key, &table);
+            }
+            self.compiled.push(table);
+        }
+
+        Ok(self)
+    }
+
+    /// Add the unwind information in the ELF, Mach-O, or PE object file with
+    /// the given contents, for a module loaded with the given bias.
+    ///
+    /// This is `add_module_from_file` for object files that are not on disk,
+    /// e.g. ones fetched from another machine. Separate debug files are only
+    /// looked for by build ID and UUID, since there is no path to look
+    /// relative to, and nothing is cached.
+    pub fn add_module_from_memory(&mut self, data: Vec<u8>, load_bias: Bias) -> Result<&mut Self> {
+        let file = object::File::parse(&*data).map_err(|_| Error::InvalidObjectFile)?;
+        let endian = if file.is_little_endian() {
+            gimli::RunTimeEndian::Little
+        } else {
+            gimli::RunTimeEndian::Big
+        };
+
+        let mut compiler = Compiler::default();
+        self.compile_object(&mut compiler, Path::new(""), &file, endian, load_bias)?;
+        self.compiled.extend(compiler.finish());
+        Ok(self)
+    }
+
+    /// Compile the unwind information in the given object file, and in its
+    /// separate debug file if it needs one.
+    fn compile_object(
+        &self,
+        compiler: &mut Compiler,
+        path: &Path,
+        file: &object::File,
+        endian: gimli::RunTimeEndian,
+        bias: Bias,
+    ) -> Result<()> {
+        compile_eh_frame_from_object(compiler, file, endian, bias)?;
+        if compile_debug_frame_from_object(compiler, file, endian, bias)? {
+            return Ok(());
+        }
+
+        if let Ok(Some(uuid)) = file.mach_uuid() {
+            if let Some(debug) = dsym::find(path, &self.debug_file_directories, uuid) {
+                if let Ok(debug) = object::File::parse(&*debug) {
+                    if compile_debug_frame_from_object(compiler, &debug, endian, bias)? {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+
+        if let Ok(Some(build_id)) = file.build_id() {
+            if let Some(debug) = build_id::find(&self.debug_file_directories, build_id) {
+                if let Ok(debug) = object::File::parse(&*debug) {
+                    // A stale debug file left behind by an upgrade might not
+                    // match any more.
+                    if debug.build_id().ok() == Some(Some(build_id)) &&
+                        compile_debug_frame_from_object(compiler, &debug, endian, bias)?
+                    {
+                        return Ok(());
+                    }
+                }
+            }
+
+            #[cfg(feature = "debuginfod")]
+            {
+                // The server being unreachable, or sending something else,
+                // is no reason to give up on the module.
+                let fetched = match self.debuginfod {
+                    Some(ref client) => client.debuginfo(build_id).unwrap_or(None),
+                    None => None,
+                };
+                if let Some(debug) = fetched {
+                    if let Ok(debug) = object::File::parse(&*debug) {
+                        if debug.build_id().ok() == Some(Some(build_id)) &&
+                            compile_debug_frame_from_object(compiler, &debug, endian, bias)?
+                        {
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+        }
+
+        let debuglink = file.section_by_name(".gnu_debuglink")
+            .and_then(|section| section.data().ok())
+            .and_then(debuglink::parse);
+        if let Some((name, crc)) = debuglink {
+            let roots = &self.debug_file_directories;
+            if let Some(debug) = debuglink::find(path, roots, name, crc) {
+                if let Ok(debug) = object::File::parse(&*debug) {
+                    compile_debug_frame_from_object(compiler, &debug, endian, bias)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add the `.eh_frame` of the given object file, if it has one.
+    #[cfg(all(feature = "live", any(target_os = "linux", target_os = "android")))]
+    fn add_eh_frame_from_object(
+        &mut self,
+        file: &object::File<'a>,
+        endian: gimli::RunTimeEndian,
+        bias: Bias,
+    ) -> Result<()> {
+        // `object` also finds Mach-O's `__eh_frame` by this name.
+        let section = match file.section_by_name(".eh_frame") {
+            Some(section) => section,
+            None => return Ok(()),
+        };
+        let eh_frame = section.data().map_err(|_| Error::InvalidObjectFile)?;
+        let eh_frame = TargetEhFrame::new(eh_frame, endian);
+        let bases = object_base_addresses(file, section.address());
+
+        if let Some(hdr) = file.section_by_name(".eh_frame_hdr") {
+            let hdr_address = Svma(hdr.address() as usize as *const u8);
+            if let Ok(hdr) = hdr.data() {
+                if self.add_eh_frame_hdr(bias, bases.clone(), hdr_address, hdr, eh_frame)
+                    .is_ok()
+                {
+                    return Ok(());
+                }
+            }
+        }
+
+        self.add_entries_from_eh_frame(bias, bases, eh_frame)?;
+        Ok(())
+    }
+
+    /// Compile the entries added so far into compact unwind tables, which are
+    /// much faster to walk than evaluating each frame's DWARF call frame
+    /// instructions.
+    ///
+    /// Rows whose rules use DWARF expressions are still walked by evaluating
+    /// their FDE. Modules added with `add_eh_frame_hdr` are not compiled,
+    /// since their FDEs are only parsed as they are needed, and neither are
+    /// entries added after this is called, until it is called again. Modules
+    /// that already have a compiled table, e.g. from the unwind table cache,
+    /// are left alone.
+    pub fn compile_unwind_tables(&mut self) -> Result<&mut Self> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("compile_unwind_tables").entered();
+
+        let mut compiler = Compiler::default();
+        for module in &self.entry_modules {
+            if self.compiled.iter().any(|table| table.bias == module.bias) {
+                continue;
+            }
+            for entry in &module.entries {
+                match entry.fde {
+                    Fde::EhFrame(ref fde) => compiler.add_fde(entry.bias, fde)?,
+                    Fde::DebugFrame(ref fde) => compiler.add_fde(entry.bias, fde)?,
+                }
+            }
+        }
+        self.compiled.extend(compiler.finish());
         Ok(self)
     }
 
+    /// Write the compiled unwind tables, from `compile_unwind_tables` and the
+    /// unwind table cache, in a stable binary format for other unwinders,
+    /// e.g. BPF programs that walk stacks in the kernel.
+    ///
+    /// All integers are little-endian. The output starts with a 32-byte
+    /// header:
+    ///
+    /// | Offset | Type       | Field                                            |
+    /// |--------|------------|--------------------------------------------------|
+    /// | 0      | `[u8; 8]`  | the magic bytes `PCUNWIND`                       |
+    /// | 8      | `u32`      | format version, currently 1                      |
+    /// | 12     | `u32`      | number of modules                                |
+    /// | 16     | `[u8; 16]` | architecture as named by `target_arch`, NUL-padded |
+    ///
+    /// Then each module follows, as a 16-byte module header and then its
+    /// rows, sorted by address and not overlapping:
+    ///
+    /// | Offset | Type  | Field                                           |
+    /// |--------|-------|-------------------------------------------------|
+    /// | 0      | `i64` | bias: actual minus stated virtual memory address |
+    /// | 8      | `u64` | number of rows                                  |
+    ///
+    /// Each row is 32 bytes, and covers the stated virtual memory addresses
+    /// `start .. end`:
+    ///
+    /// | Offset | Type  | Field                                      |
+    /// |--------|-------|--------------------------------------------|
+    /// | 0      | `u64` | `start`                                    |
+    /// | 8      | `u64` | `end`                                      |
+    /// | 16     | `i32` | CFA offset                                 |
+    /// | 20     | `i32` | frame base pointer rule's operand          |
+    /// | 24     | `i32` | return address rule's operand              |
+    /// | 28     | `u8`  | CFA register's DWARF register number       |
+    /// | 29     | `u8`  | frame base pointer rule's kind             |
+    /// | 30     | `u8`  | return address rule's kind                 |
+    /// | 31     | `u8`  | reserved, zero                             |
+    ///
+    /// The CFA is the CFA register's value plus the CFA offset, and is the
+    /// caller's stack pointer. A rule's kind is:
+    ///
+    /// * 0: the register is undefined in the caller.
+    /// * 1: the caller's value is the callee's value.
+    /// * 2: the caller's value is saved at CFA + operand.
+    /// * 3: the caller's value is CFA + operand.
+    /// * 4: the caller's value is the callee's value of the register whose
+    ///   DWARF register number is the operand.
+    ///
+    /// Addresses without a row have no compiled unwind information, either
+    /// because none was added or because their rules use DWARF expressions.
+    pub fn export_unwind_tables<W>(&self, out: W) -> Result<()>
+    where
+        W: io::Write,
+    {
+        compiled::export(&self.compiled, out)?;
+        Ok(())
+    }
+
+    /// Add the `STACK CFI` records from a Breakpad symbol file for a module
+    /// loaded with the given bias.
+    ///
+    /// These are consulted for addresses that are not covered by any DWARF
+    /// unwind entry.
+    pub fn add_breakpad_symbols(
+        &mut self,
+        bias: Bias,
+        symbols: breakpad::SymbolFile,
+    ) -> &mut Self {
+        self.breakpad.push(BreakpadModule { bias, symbols });
+        self
+    }
+
+    /// Add a hand-written unwind rule for the given address range.
+    ///
+    /// Manual rules take precedence over any DWARF or Breakpad unwind
+    /// information covering the same addresses, so they can both fill gaps
+    /// and override broken CFI.
+    pub fn add_manual_rule(&mut self, range: Range<Avma>, rule: ManualRule) -> &mut Self {
+        self.manual.push(ManualEntry {
+            range: address_range(&range),
+            rule,
+        });
+        self
+    }
+
+    /// Remove the unwind information for the addresses in the given range,
+    /// e.g. after the module loaded there is unloaded, so that a module later
+    /// loaded at the same addresses is not walked with stale unwind
+    /// information.
+    ///
+    /// This removes the entries, compiled rows, and manual rules that overlap
+    /// the range, and the modules added with `add_eh_frame_hdr` whose first
+    /// FDE is in it. Breakpad symbols are kept.
+    pub fn remove_entries_in_range(&mut self, range: Range<Avma>) -> &mut Self {
+        let range = address_range(&range);
+        let to_address = |svma: usize, bias: Bias| (svma as isize).wrapping_add(bias.0) as usize;
+
+        for module in &mut self.entry_modules {
+            if overlaps(&module.range, &range) {
+                module.entries.retain(|entry| !overlaps(&entry.range, &range));
+                module.update_range();
+            }
+        }
+        self.entry_modules.retain(|module| !module.entries.is_empty());
+        self.manual.retain(|entry| !overlaps(&entry.range, &range));
+        for table in &mut self.compiled {
+            let bias = table.bias;
+            table.retain(|row| {
+                let row_range =
+                    to_address(row.start as usize, bias)..to_address(row.end as usize, bias);
+                !overlaps(&row_range, &range)
+            });
+        }
+        self.compiled.retain(|table| !table.is_empty());
+        self.eh_frame_hdrs.retain(|module| match module.hdr.first_address() {
+            Some(first) => {
+                let first = to_address(first, module.bias);
+                !(range.start <= first && first < range.end)
+            }
+            None => true,
+        });
+        self
+    }
+
     /// Clear all entries.
     pub fn clear_entries(&mut self) -> &mut Self {
-        self.entries.clear();
+        self.entry_modules.clear();
+        self.eh_frame_hdrs.clear();
+        self.compiled.clear();
+        self.breakpad.clear();
+        self.manual.clear();
+        self.module_names.clear();
+        self.skipped_modules.clear();
         self
     }
 
@@ -274,225 +1578,2589 @@ impl<'a> Options<'a> {
 
     /// Finish configuring unwinding and create the `Walker` object with the
     /// configured options and the given logger.
+    ///
+    /// The logger is told about each module with DWARF call frame
+    /// information now, and about what each walk does later.
     pub fn build_with_reader_logger<Reader, Logger>(
-        mut self,
+        self,
         reader: Reader,
-        logger: Logger,
+        mut logger: Logger,
     ) -> Walker<'a, Reader, Logger>
     where
         Reader: MemoryReader,
         Logger: log::UnwindLogger,
     {
-        self.entries.sort();
-        let opts = self;
-        let ctx = Some(TargetUninitializedUnwindContext::new());
+        let unwinder = self.build_unwinder();
+        unwinder.opts.log_modules(&mut logger);
+
         Walker {
-            opts,
+            unwinder,
+            cx: UnwindContext::new(),
             reader,
             logger,
-            ctx,
+            observer: observer::IgnoreEvents,
+        }
+    }
+
+    /// Tell the logger about each module with DWARF call frame information,
+    /// and each module whose call frame information could not be parsed.
+    fn log_modules<Logger>(&self, logger: &mut Logger)
+    where
+        Logger: log::UnwindLogger,
+    {
+        let name = |bias: Bias| {
+            self.module_names
+                .iter()
+                .find(|&&(b, _)| b == bias)
+                .map(|&(_, ref name)| name.as_str())
+        };
+        if log::Level::Warn.enabled() {
+            for &(bias, ref error) in &self.skipped_modules {
+                logger.log(&log::LogEvent::ModuleSkipped {
+                    bias,
+                    name: name(bias),
+                    error: &**error,
+                });
+            }
+        }
+        if log::Level::Info.enabled() {
+            for module in &self.entry_modules {
+                logger.log(&log::LogEvent::ModuleScanned {
+                    bias: module.bias,
+                    name: name(module.bias),
+                    fdes: Some(module.entries.len()),
+                });
+            }
+            for module in &self.eh_frame_hdrs {
+                logger.log(&log::LogEvent::ModuleScanned {
+                    bias: module.bias,
+                    name: name(module.bias),
+                    fdes: None,
+                });
+            }
+        }
+    }
+
+    /// Finish configuring unwinding and create an `Unwinder` with the
+    /// configured options, to share between threads that each walk stacks
+    /// with their own `UnwindContext`.
+    ///
+    /// Entries that overlap other entries of the same module are dropped, and
+    /// reported by `Unwinder::entry_conflicts`.
+    pub fn build_unwinder(mut self) -> Unwinder<'a> {
+        let mut conflicts = vec![];
+        for module in &mut self.entry_modules {
+            module.build(&mut conflicts);
+        }
+        self.entry_modules.sort_by(|a, b| a.range.start.cmp(&b.range.start));
+        let mut reach = None;
+        for module in &mut self.entry_modules {
+            let end = cmp::max(reach.unwrap_or(module.range.end), module.range.end);
+            module.reach = end;
+            reach = Some(end);
+        }
+        self.manual.sort();
+
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            modules = self.entry_modules.len(),
+            compiled_modules = self.compiled.len(),
+            entry_conflicts = conflicts.len(),
+            "built unwinder"
+        );
+        Unwinder {
+            opts: self,
+            conflicts,
+            modules: LiveModules::new(),
+            #[cfg(feature = "live")]
+            loaded: Mutex::new(rescan::Loaded::default()),
+            stats: Counters::default(),
         }
     }
 }
 
-/// A `Walker` traverses frames that make up a native stack.
+/// The per-thread state for walking stacks with an `Unwinder`: the scratch
+/// space for evaluating DWARF call frame information, and a cache of the
+/// unwind table rows it found for recently walked addresses.
+///
+/// Creating a context allocates, so create it up front, e.g. before
+/// installing a signal handler that walks stacks, and reuse it for every
+/// walk. Walks never grow it: use `prepare` to size it for the expressions
+/// they must evaluate.
+#[derive(Debug)]
+pub struct UnwindContext<'a> {
+    ctx: Option<TargetUninitializedUnwindContext<'a>>,
+    debug_frame_ctx: Option<TargetDebugFrameUnwindContext<'a>>,
+    /// The stack DWARF expressions are evaluated on.
+    expression_stack: Vec<usize>,
+    /// The unwind table rows found for recently walked addresses.
+    row_cache: RowCache,
+    /// The most frames a walk with this context yields, if `prepare` set it.
+    max_frames: Option<usize>,
+}
+
+impl<'a> UnwindContext<'a> {
+    /// Construct a new context.
+    pub fn new() -> UnwindContext<'a> {
+        UnwindContext {
+            ctx: Some(TargetUninitializedUnwindContext::new()),
+            debug_frame_ctx: Some(TargetDebugFrameUnwindContext::new()),
+            expression_stack: vec![0; expression::DEFAULT_STACK_DEPTH],
+            row_cache: RowCache::new(),
+            max_frames: None,
+        }
+    }
+
+    /// Get how often walks with this context found the unwind table row for
+    /// an address in its cache, rather than running the FDE's call frame
+    /// instructions to find it.
+    pub fn row_cache_stats(&self) -> RowCacheStats {
+        self.row_cache.stats()
+    }
+
+    /// Reset the counts returned by `row_cache_stats`.
+    pub fn reset_row_cache_stats(&mut self) {
+        self.row_cache.reset_stats()
+    }
+
+    /// Allocate the scratch space for walks of up to `max_frames` frames,
+    /// that evaluate DWARF expressions up to `max_expression_depth` words
+    /// deep, or fail with `Error::OutOfMemory`.
+    ///
+    /// Walks with this context then stop after `max_frames` frames, even if
+    /// `Options::max_frames` allows more, so that a buffer sized for them,
+    /// e.g. for each frame's instruction pointer, never overflows. Expressions
+    /// that grow deeper fail to evaluate. Without `prepare`, expressions may
+    /// be 64 words deep.
+    pub fn prepare(&mut self, max_frames: usize, max_expression_depth: usize) -> Result<()> {
+        let additional = max_expression_depth.saturating_sub(self.expression_stack.len());
+        self.expression_stack
+            .try_reserve_exact(additional)
+            .map_err(|_| Error::OutOfMemory)?;
+        self.expression_stack.resize(max_expression_depth, 0);
+        self.max_frames = Some(max_frames);
+        Ok(())
+    }
+}
+
+impl<'a> Default for UnwindContext<'a> {
+    fn default() -> UnwindContext<'a> {
+        UnwindContext::new()
+    }
+}
+
+/// The unwind information of a set of modules, and the configuration for
+/// walking stacks with it.
 ///
-/// TODO FITZGEN: cache policy generic parameter?
+/// An `Unwinder` is never mutated by walking, so a single one, perhaps in an
+/// `Arc`, can be shared by every thread that walks stacks. Each thread passes
+/// its own `UnwindContext` and `MemoryReader` to the walking methods.
 ///
-/// TODO FITZGEN: make memory dereferencing operations a generic parameter
+/// Modules loaded after the `Unwinder` is built can still be added to it, and
+/// removed again, with `add_eh_frame` and `remove_module`, while other
+/// threads keep walking with it.
 ///
-/// THIS WILL NOT MALLOC OR ACQUIRE LOCKS!! IT MUST BE SIGNAL SAFE!!
+/// ```
+/// use pancakes::{reader, Options, UnwindContext};
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// let unwinder = Arc::new(Options::new().build_unwinder());
+///
+/// let threads: Vec<_> = (0..4).map(|_| {
+///     let unwinder = unwinder.clone();
+///     thread::spawn(move || {
+///         let mut cx = UnwindContext::new();
+///         let reader = reader::ThisProcessMemory;
+///         # let get_frame_regs = || -> pancakes::FrameRegisters { unimplemented!() };
+///         # if false {
+///         let result = unwinder.walk(&mut cx, &reader, &get_frame_regs(), |frame| {
+///             println!("Traversed frame {:?}", frame);
+///         });
+///         # let _ = result;
+///         # }
+///     })
+/// }).collect();
+///
+/// for thread in threads {
+///     thread.join().unwrap();
+/// }
+/// ```
 #[derive(Debug)]
-pub struct Walker<'a, Reader = reader::ThisProcessMemory, Logger = log::IgnoreLogs>
-where
-    Reader: MemoryReader,
-    Logger: log::UnwindLogger,
-{
+pub struct Unwinder<'a> {
     opts: Options<'a>,
-    reader: Reader,
-    logger: Logger,
-    ctx: Option<TargetUninitializedUnwindContext<'a>>,
+    /// The entries dropped while building, for overlapping others.
+    conflicts: Vec<EntryConflict>,
+    modules: LiveModules,
+    #[cfg(feature = "live")]
+    loaded: Mutex<rescan::Loaded>,
+    /// What walking with this `Unwinder` has cost and found.
+    stats: Counters,
 }
 
-impl<'a, Reader, Logger> Walker<'a, Reader, Logger>
-where
-    Reader: MemoryReader,
-    Logger: log::UnwindLogger,
-{
-    /// Reconfigure this `Walker`.
+impl<'a> Unwinder<'a> {
+    /// Turn this `Unwinder` back into the `Options` it was built from.
     ///
-    /// Turn this `Walker` back into an `Options` and perform new
-    /// configuration, after which `Options::build` may be invoked to
-    /// reconstruct an `Walker` with the new configuration.
+    /// Modules added with `add_eh_frame` are kept, as compiled unwind tables,
+    /// and the unwind information for ranges passed to
+    /// `remove_entries_in_range` is removed.
+    pub fn reconfigure(mut self) -> Options<'a> {
+        for range in self.modules.unloaded() {
+            self.opts.remove_entries_in_range(range);
+        }
+        self.opts.compiled.extend(self.modules.tables());
+        self.opts
+    }
+
+    /// Get the entries that were dropped while building this `Unwinder`,
+    /// because they overlapped other entries of the same module, along with
+    /// the entries that were kept instead.
     ///
     /// ```
-    /// use pancakes::Options;
-    ///
-    /// // We have some unwinder.
-    /// let unwinder = Options::new().build();
-    ///
-    /// // Oh, we learned about new stuff to configure. Turn the unwinder back
-    /// // into a builder.
-    /// let (builder, _, _) = unwinder.reconfigure();
-    /// // ... set new configuration parameters on the builder ...
-    ///
-    /// // Now that we are done reconfiguring, get the unwinder back again!
-    /// let unwinder = builder.build();
-    /// # let _ = unwinder;
+    /// let unwinder = pancakes::Options::new().build_unwinder();
+    /// for conflict in unwinder.entry_conflicts() {
+    ///     eprintln!("warning: {}", conflict);
+    /// }
     /// ```
-    pub fn reconfigure(self) -> (Options<'a>, Reader, Logger) {
-        (self.opts, self.reader, self.logger)
+    pub fn entry_conflicts(&self) -> &[EntryConflict] {
+        &self.conflicts
     }
 
-    /// Walk a single physical frame.
-    unsafe fn walk_one(&mut self, start_regs: &FrameRegisters) -> Result<FrameRegisters> {
-        let ip: Result<_> = start_regs.ip().into();
-        let ip = ip?;
-
-        let idx = self.opts
-            .entries
-            .binary_search_by(|e| {
-                let ip_avma = Avma(ip as *const u8);
-                eprintln!(
-                    "FITZGEN: {} within {} .. {} ? {}",
-                    ip_avma,
-                    e.range.start,
-                    e.range.end,
-                    e.fde.contains(ip_avma.0.offset(-e.bias.0) as _)
-                );
-
-                if ip_avma < e.range.start {
-                    eprintln!("FITZGEN:     greater");
-                    Ordering::Greater
-                } else if ip_avma > e.range.end {
-                    eprintln!("FITZGEN:     less");
-                    Ordering::Less
-                } else {
-                    eprintln!("FITZGEN:     equal");
-                    // TODO FITZGEN: this needs to adjust for bias
-                    //debug_assert!(e.fde.contains(ip_avma.0.offset(-e.bias.0) as u64));
-                    Ordering::Equal
-                }
-            })
-            .map_err(|_| Error::NoUnwindInfoForAddress(ip))?;
-
-        let result = {
-            let entry = &self.opts.entries[idx];
-            eprintln!("FITZGEN: entry = {:#?}", entry);
-
-            //let ip = (ip as *const u8).offset(-entry.bias.0);
-            let ip = Avma(ip as *const u8);
-            eprintln!("FITZGEN: adjusted ip = {}", ip);
-
-            self.ctx
-                .take()
-                .expect("should always have Some(ctx) at the beginning of Self::walk_one")
-                .initialize(entry.fde.cie())
-                .map_err(|(e, ctx)| (e.into(), ctx))
-                .and_then(|mut ctx| {
-                    let registers = {
-                        let mut table = gimli::UnwindTable::new(&mut ctx, &entry.fde);
-                        loop {
-                            match table.next_row() {
-                                Err(e) => break Err(e.into()),
-                                Ok(None) => break Ok(None),
-                                Ok(Some(row)) => {
-                                    let start = Svma(row.start_address() as *const u8);
-                                    let end = Svma(row.end_address() as *const u8);
-
-                                    eprintln!("FITZGEN:     row {} .. {}", start, end);
-
-                                    let start = Avma(start.0.offset(entry.bias.0));
-                                    let end = Avma(end.0.offset(entry.bias.0));
-
-                                    if start.0 <= ip.0 && ip.0 < end.0 {
-                                        eprintln!("FITZGEN:         contains!");
-                                        break FrameRegisters::from_unwind_table_row(
-                                            row,
-                                            &start_regs,
-                                            &self.reader,
-                                        ).map(Some);
-                                    } else {
-                                        eprintln!("FITZGEN:         not contained");
-                                        continue;
-                                    }
-                                }
-                            }
-                        }
-                    };
+    /// Get a snapshot of what walking with this `Unwinder` has cost and
+    /// found, on every thread, since it was built or `reset_stats` was last
+    /// called.
+    ///
+    /// ```
+    /// let unwinder = pancakes::Options::new().build_unwinder();
+    /// // ... walk some stacks ...
+    /// let stats = unwinder.stats();
+    /// println!(
+    ///     "{} walks, {} frames, {:?} per walk",
+    ///     stats.walks,
+    ///     stats.frames,
+    ///     stats.mean_walk_time()
+    /// );
+    /// ```
+    pub fn stats(&self) -> UnwindStats {
+        self.stats.snapshot()
+    }
 
-                    let ctx = ctx.reset();
-                    match registers {
-                        Ok(r) => Ok((r, ctx)),
-                        Err(e) => Err((e, ctx)),
-                    }
-                })
-        };
+    /// Zero the counts returned by `stats`.
+    pub fn reset_stats(&self) {
+        self.stats.reset()
+    }
 
-        match result {
-            Ok((Some(registers), ctx)) => {
-                self.ctx = Some(ctx);
-                Ok(registers)
-            }
-            Ok((None, ctx)) => {
-                self.ctx = Some(ctx);
-                Err(Error::NoUnwindInfoForAddress(ip))
-            }
-            Err((e, ctx)) => {
-                self.ctx = Some(ctx);
-                Err(e.into())
-            }
-        }
+    /// Get how much memory each module's compiled unwind table takes, and how
+    /// much delta-encoding its rows saved, including the modules added with
+    /// `add_eh_frame`.
+    ///
+    /// ```
+    /// let unwinder = pancakes::Options::new().build_unwinder();
+    /// for table in unwinder.compiled_table_memory() {
+    ///     println!("{:?}: {} rows in {} bytes", table.bias, table.rows, table.bytes);
+    /// }
+    /// ```
+    pub fn compiled_table_memory(&self) -> Vec<CompiledTableMemory> {
+        let mut memory: Vec<_> = self.opts.compiled.iter().map(CompiledTable::memory).collect();
+        memory.extend(self.modules.memory());
+        memory
     }
 
-    /// Keep walking until we've walked the whole stack, or `f` asks us to
-    /// halt walking.
+    /// Add the `.eh_frame` section of a module loaded with the given bias,
+    /// while other threads may be walking with this `Unwinder`. The base
+    /// addresses are the stated addresses of the module's sections that the
+    /// section's pointers may be relative to.
     ///
-    /// The return value is either the `T` returned in the last invokation of
-    /// `f`, on the oldest stack frame found, or the `T` where
-    /// `AsStackWalkControl::as_stack_walk_control` returns
-    /// `StackWalkControl::Break`.
+    /// This is for modules loaded after the `Unwinder` was built, e.g. with
+    /// `dlopen`. The section is compiled before this returns, and is not read
+    /// afterwards. Each frame is walked with either all or none of the
+    /// module's unwind information.
     ///
     /// ```
-    /// # fn f() {
-    /// use pancakes;
-    ///
-    /// let mut walker = pancakes::Options::new().build();
+    /// extern crate gimli;
+    /// extern crate pancakes;
+    /// # fn main() {
+    /// use pancakes::{Bias, Options};
     ///
-    /// # let get_frame_regs = || unimplemented!();
-    /// let result = walker.walk(get_frame_regs(), |frame| {
-    ///     println!("Traversed frame {:?}", frame);
+    /// let unwinder = Options::new().build_unwinder();
     ///
-    ///     // There is an `AsStackWalkControl` implementation for `()` that
-    ///     // always continues walking, so we don't need any explicit return.
-    /// });
+    /// // Later, after another module is loaded...
+    /// # let eh_frame: &[u8] = &[];
+    /// let bases = gimli::BaseAddresses::default().set_cfi(0x2000).set_text(0x3000);
+    /// let module = unwinder.add_eh_frame(Bias(0x1000), bases, eh_frame).unwrap();
     ///
-    /// // Handle walking errors however you'd like. Here, we ignore them.
-    /// let _ = result;
+    /// // ...and before it is unloaded.
+    /// unwinder.remove_module(module);
     /// # }
     /// ```
-    pub fn walk<F, T>(&mut self, start_registers: &FrameRegisters, mut f: F) -> Result<T>
-    where
-        F: FnMut(&FrameRegisters) -> T,
-        T: AsStackWalkControl,
-    {
-        let mut result = f(start_registers);
-        if result.as_stack_walk_control() == StackWalkControl::Break {
-            return Ok(result);
-        }
+    pub fn add_eh_frame(
+        &self,
+        bias: Bias,
+        bases: gimli::BaseAddresses,
+        eh_frame: &[u8],
+    ) -> Result<ModuleId> {
+        self.modules.add(bias, &bases, eh_frame)
+    }
 
-        let mut registers = unsafe { self.walk_one(start_registers)? };
-        result = f(&registers);
-        if result.as_stack_walk_control() == StackWalkControl::Break {
-            return Ok(result);
+    /// Remove a module added with `add_eh_frame`, so that it can be unloaded.
+    /// No frame is walked with its unwind information once this returns.
+    ///
+    /// Returns `false` if it was already removed.
+    pub fn remove_module(&self, module: ModuleId) -> bool {
+        self.modules.remove(module)
+    }
+
+    /// Find the modules loaded into this process, or unloaded, since the
+    /// last rescan, and add or remove their unwind information, while other
+    /// threads may be walking with this `Unwinder`. Returns whether any
+    /// were.
+    ///
+    /// Modules whose unwind information this `Unwinder` was built with, e.g.
+    /// by `Options::find_eh_frame_entries`, are not added again. New modules'
+    /// `.eh_frame` sections are added with `add_eh_frame`, and unloaded
+    /// modules' unwind information is removed with `remove_module` or
+    /// `remove_entries_in_range`.
+    ///
+    /// On Linux, when no module has been loaded or unloaded since the last
+    /// rescan, this returns without looking at any module, so it is cheap to
+    /// call before every walk that is not in a signal handler. It takes the
+    /// dynamic linker's lock, so it must not be called from a signal handler.
+    /// `trace` and `threads::walk_all` call it themselves.
+    #[cfg(feature = "live")]
+    pub fn rescan_modules(&self) -> bool {
+        rescan::rescan(self)
+    }
+
+    /// Does the unwind information this `Unwinder` was built with, and not
+    /// since removed, cover the start of the given range?
+    #[cfg(feature = "live")]
+    fn has_unwind_info_for(&self, range: &Range<usize>) -> bool {
+        if self.modules.is_unloaded(range.start) {
+            return false;
+        }
+        let to_address = |svma: usize, bias: Bias| (svma as isize).wrapping_add(bias.0) as usize;
+        self.opts.entry_modules.iter().any(|module| {
+            overlaps(&module.range, range)
+                && module.entries.iter().any(|entry| overlaps(&entry.range, range))
+        })
+            || self.opts.compiled.iter().any(|table| {
+                table.rows().any(|row| {
+                    let row_range = to_address(row.start as usize, table.bias)
+                        ..to_address(row.end as usize, table.bias);
+                    overlaps(&row_range, range)
+                })
+            })
+            || self.opts.eh_frame_hdrs.iter().any(|module| {
+                module.hdr.first_address().map_or(false, |first| {
+                    let first = to_address(first, module.bias);
+                    range.start <= first && first < range.end
+                })
+            })
+    }
+
+    /// Stop using any unwind information for the addresses in the given
+    /// range, while other threads may be walking with this `Unwinder`, e.g.
+    /// after the module loaded there is unloaded with `dlclose`.
+    ///
+    /// Modules added with `add_eh_frame` that overlap the range are removed.
+    /// The unwind information this `Unwinder` was built with is kept, but
+    /// ignored for addresses in the range, until `reconfigure` removes it
+    /// with `Options::remove_entries_in_range`. A module added with
+    /// `add_eh_frame` later, at the same addresses, is walked as usual.
+    ///
+    /// No frame is walked with the removed unwind information once this
+    /// returns.
+    pub fn remove_entries_in_range(&self, range: Range<Avma>) {
+        self.modules.remove_range(range)
+    }
+
+    /// Walk a single physical frame.
+    unsafe fn walk_one<'u, Reader, Observer>(
+        &'u self,
+        cx: &mut UnwindContext<'a>,
+        reader: &Reader,
+        observer: &Observer,
+        start_regs: &FrameRegisters,
+        pc_kind: PcKind,
+    ) -> Result<(FrameRegisters, Walked<'u>)>
+    where
+        Reader: MemoryReader,
+        Observer: UnwindObserver,
+    {
+        let ip: Result<_> = start_regs.ip().into();
+        let ip = ip?;
+        // The strategies look unwind information up by this address, but
+        // recover the caller's registers from the frame's actual ones.
+        let lookup_ip = pc_kind.lookup_address(ip);
+        let reader = &ObservedReader { reader, observer };
+
+        for i in 0..self.opts.strategies.len() {
+            let strategy = self.opts.strategies[i];
+            let result = match strategy {
+                UnwindStrategy::Manual => self.walk_one_manual(reader, lookup_ip, start_regs)
+                    .map(|registers| (registers, Walked::by(strategy))),
+                UnwindStrategy::Dwarf => {
+                    self.walk_one_dwarf(cx, reader, observer, lookup_ip, start_regs)
+                }
+                UnwindStrategy::Breakpad => self.walk_one_breakpad(reader, lookup_ip, start_regs)
+                    .map(|(registers, bias)| {
+                        (registers, Walked { bias: Some(bias), ..Walked::by(strategy) })
+                    }),
+                UnwindStrategy::FramePointer => {
+                    self.walk_one_frame_pointer(reader, lookup_ip, start_regs)
+                        .map(|registers| (registers, Walked::by(strategy)))
+                }
+            };
+            match result {
+                Err(Error::NoUnwindInfoForAddress(_)) => continue,
+                Ok((mut registers, walked)) => {
+                    let mask = self.opts.pointer_auth_mask;
+                    let caller_ip = registers.ip().map(|ip| ip & !mask);
+                    registers.set_register(registers::IP, caller_ip)?;
+                    check_progress(ip, start_regs, &registers, walked.signal_frame)?;
+                    if i > 0 {
+                        observer.fallback_used(lookup_ip, strategy);
+                    }
+                    return Ok((registers, walked));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(Error::NoUnwindInfoForAddress(ip))
+    }
+
+    /// Walk a single physical frame using a manual unwind rule.
+    unsafe fn walk_one_manual<Reader>(
+        &self,
+        reader: &Reader,
+        ip: usize,
+        start_regs: &FrameRegisters,
+    ) -> Result<FrameRegisters>
+    where
+        Reader: MemoryReader,
+    {
+        if self.modules.is_unloaded(ip) {
+            return Err(Error::NoUnwindInfoForAddress(ip));
+        }
+
+        match self.opts
+            .manual
+            .binary_search_by(|e| e.cmp_address(ip))
+        {
+            Ok(idx) => self.opts.manual[idx].rule.unwind(start_regs, reader),
+            Err(_) => Err(Error::NoUnwindInfoForAddress(ip)),
+        }
+    }
+
+    /// Find the entry covering the given address: binary search for the last
+    /// module starting at or before it, then search the modules from there
+    /// back to the first one that cannot reach it.
+    fn lookup_entry(&self, ip: usize) -> Option<&UnwindEntry<'a>> {
+        let modules = &self.opts.entry_modules;
+        let idx = modules.partition_point(|module| module.range.start <= ip);
+        modules[..idx]
+            .iter()
+            .rev()
+            .take_while(|module| ip <= module.reach)
+            .filter(|module| ip <= module.range.end)
+            .find_map(|module| module.lookup(ip))
+    }
+
+    /// Walk a single physical frame using DWARF call frame information.
+    unsafe fn walk_one_dwarf<'u, Reader, Observer>(
+        &'u self,
+        cx: &mut UnwindContext<'a>,
+        reader: &Reader,
+        observer: &Observer,
+        ip: usize,
+        start_regs: &FrameRegisters,
+    ) -> Result<(FrameRegisters, Walked<'u>)>
+    where
+        Reader: MemoryReader,
+        Observer: UnwindObserver,
+    {
+        if let Some(ref jit) = self.opts.jit {
+            if let Some(result) = jit.unwind(ip, start_regs, reader) {
+                return result.map(|registers| (registers, Walked::by(UnwindStrategy::Dwarf)));
+            }
+        }
+
+        if let Some(result) = self.modules.unwind(ip, start_regs, reader) {
+            return result.map(|(registers, bias)| {
+                let walked = Walked {
+                    bias: Some(bias),
+                    ..Walked::by(UnwindStrategy::Dwarf)
+                };
+                (registers, walked)
+            });
+        }
+
+        if self.modules.is_unloaded(ip) {
+            return Err(Error::NoUnwindInfoForAddress(ip));
+        }
+
+        for table in &self.opts.compiled {
+            if let Some(row) = table.lookup(ip) {
+                let walked = Walked {
+                    bias: Some(table.bias),
+                    ..Walked::by(UnwindStrategy::Dwarf)
+                };
+                return row.unwind(start_regs, reader).map(|registers| (registers, walked));
+            }
+        }
+
+        let mut state = FdeState {
+            row_cache: &mut cx.row_cache,
+            scratch: expression::Scratch {
+                stack: &mut cx.expression_stack,
+                fuel: self.opts.expression_fuel,
+            },
+            observer,
+        };
+        if let Some(entry) = self.lookup_entry(ip) {
+            observer.fde_found(ip, entry.range());
+            let registers = match entry.fde {
+                Fde::EhFrame(ref fde) => {
+                    eval_fde(&mut cx.ctx, &mut state, fde, entry.bias, ip, start_regs, reader)
+                }
+                Fde::DebugFrame(ref fde) => eval_fde(
+                    &mut cx.debug_frame_ctx,
+                    &mut state,
+                    fde,
+                    entry.bias,
+                    ip,
+                    start_regs,
+                    reader,
+                ),
+            };
+            return registers.map(|(registers, signal_frame)| {
+                let walked = Walked {
+                    bias: Some(entry.bias),
+                    entry: Some(entry),
+                    signal_frame,
+                    ..Walked::by(UnwindStrategy::Dwarf)
+                };
+                (registers, walked)
+            });
+        }
+
+        for module in &self.opts.eh_frame_hdrs {
+            let svma = (ip as isize).wrapping_sub(module.bias.0) as usize;
+            let offset = match module.hdr.lookup(svma) {
+                Some(offset) => offset,
+                None => continue,
+            };
+
+            let eh_frame = &module.eh_frame;
+            let bases = &module.bases;
+            let fde = eh_frame.fde_from_offset(bases, gimli::EhFrameOffset(offset), |offset| {
+                eh_frame.cie_from_offset(bases, offset)
+            })?;
+            if !fde.contains(svma as u64) {
+                continue;
+            }
+
+            let start = (fde.initial_address() as isize).wrapping_add(module.bias.0);
+            let end = start.wrapping_add(fde.len() as isize);
+            observer.fde_found(ip, Avma(start as *const u8)..Avma(end as *const u8));
+            return eval_fde(
+                &mut cx.ctx,
+                &mut state,
+                &fde,
+                module.bias,
+                ip,
+                start_regs,
+                reader,
+            ).map(|(registers, signal_frame)| {
+                let walked = Walked {
+                    bias: Some(module.bias),
+                    signal_frame,
+                    ..Walked::by(UnwindStrategy::Dwarf)
+                };
+                (registers, walked)
+            });
+        }
+
+        Err(Error::NoUnwindInfoForAddress(ip))
+    }
+
+    /// Walk a single physical frame using Breakpad call frame information,
+    /// returning the bias of the module it is in.
+    unsafe fn walk_one_breakpad<Reader>(
+        &self,
+        reader: &Reader,
+        ip: usize,
+        start_regs: &FrameRegisters,
+    ) -> Result<(FrameRegisters, Bias)>
+    where
+        Reader: MemoryReader,
+    {
+        if self.modules.is_unloaded(ip) {
+            return Err(Error::NoUnwindInfoForAddress(ip));
+        }
+
+        for module in &self.opts.breakpad {
+            let address = (ip as isize).wrapping_sub(module.bias.0) as usize as u64;
+            if let Some(cfi) = module.symbols.find_stack_cfi(address) {
+                return cfi.unwind(address, start_regs, reader)
+                    .map(|registers| (registers, module.bias));
+            }
+        }
+        Err(Error::NoUnwindInfoForAddress(ip))
+    }
+
+    /// Walk a single physical frame by following its saved frame pointer.
+    ///
+    /// The frame pointer points at the frame record that the function's
+    /// prologue pushed: the caller's frame pointer, then the return address.
+    /// On x86 and x86_64 the caller's stack pointer is just past the record,
+    /// but on aarch64 the record is at the bottom of the frame, so the
+    /// caller's stack pointer is unknown.
+    unsafe fn walk_one_frame_pointer<Reader>(
+        &self,
+        reader: &Reader,
+        ip: usize,
+        start_regs: &FrameRegisters,
+    ) -> Result<FrameRegisters>
+    where
+        Reader: MemoryReader,
+    {
+        let has_frame_records = cfg!(any(
+            target_arch = "x86_64",
+            target_arch = "x86",
+            target_arch = "aarch64"
+        ));
+        let bp = match start_regs.bp() {
+            TaggedWord::Valid(bp) if has_frame_records && start_regs.bp().is_word_aligned() => bp,
+            _ => return Err(Error::NoUnwindInfoForAddress(ip)),
+        };
+
+        let word_size = mem::size_of::<usize>();
+        let record_end = bp.checked_add(2 * word_size);
+        let in_bounds = match (self.opts.stack_bounds.as_ref(), record_end) {
+            (_, None) => false,
+            (Some(stack), Some(end)) => stack.start <= bp && end <= stack.end,
+            (None, Some(_)) => true,
+        };
+        // The frame record is at or above the stack pointer, since it was
+        // pushed before the rest of the frame was allocated.
+        let above_sp = start_regs.sp().map_or(true, |sp| sp <= bp);
+        if !in_bounds || !above_sp {
+            return Err(Error::NoUnwindInfoForAddress(ip));
+        }
+
+        let caller_ip = reader.read_offset(bp, word_size as isize)?;
+
+        // The stack grows down, so the caller's frame record must be above
+        // this one. The outermost frame's saved frame pointer is usually
+        // zero, which is treated as the end of the chain.
+        let caller_bp = TaggedWord::from(reader.read(bp))
+            .and_then(|caller_bp| if caller_bp > bp {
+                TaggedWord::valid(caller_bp)
+            } else {
+                TaggedWord::invalid()
+            });
+        let caller_bp = if caller_bp.is_word_aligned() {
+            caller_bp
+        } else {
+            TaggedWord::invalid()
+        };
+
+        let caller_sp = if cfg!(any(target_arch = "x86_64", target_arch = "x86")) {
+            TaggedWord::valid(bp + 2 * word_size)
+        } else {
+            TaggedWord::invalid()
+        };
+
+        Ok(FrameRegisters::from_parts(caller_bp, caller_sp, TaggedWord::valid(caller_ip)))
+    }
+
+    /// Keep walking until we've walked the whole stack, `f` asks us to halt
+    /// walking, or we've walked `Options::max_frames` frames. See
+    /// `Walker::walk`.
+    pub fn walk<Reader, F, T>(
+        &self,
+        cx: &mut UnwindContext<'a>,
+        reader: &Reader,
+        start_registers: &FrameRegisters,
+        f: F,
+    ) -> WalkOutcome<T>
+    where
+        Reader: MemoryReader,
+        F: FnMut(&Frame) -> T,
+        T: AsStackWalkControl,
+    {
+        self.walk_observed(cx, reader, &observer::IgnoreEvents, start_registers, f)
+    }
+
+    /// Like `walk`, telling the given observer what the walk does.
+    fn walk_observed<Reader, Observer, F, T>(
+        &self,
+        cx: &mut UnwindContext<'a>,
+        reader: &Reader,
+        observer: &Observer,
+        start_registers: &FrameRegisters,
+        mut f: F,
+    ) -> WalkOutcome<T>
+    where
+        Reader: MemoryReader,
+        Observer: UnwindObserver,
+        F: FnMut(&Frame) -> T,
+        T: AsStackWalkControl,
+    {
+        let mut frames = self.frames_observed(cx, reader, observer, start_registers.clone());
+        // `f` is always called at least once, unless the walk fails first, to
+        // have a result to return.
+        frames.max_frames = frames.max_frames.max(1);
+
+        let mut outcome = WalkOutcome {
+            frames_walked: 0,
+            last: None,
+            stopped_because: WalkStop::MaxFrames,
+        };
+        while let Some(frame) = frames.next() {
+            let frame = match frame {
+                Ok(frame) => frame,
+                Err(e) => {
+                    outcome.stopped_because = WalkStop::Error(e);
+                    return outcome;
+                }
+            };
+            let result = f(&frame);
+            outcome.frames_walked += 1;
+            let stop = result.as_stack_walk_control() == StackWalkControl::Break;
+            outcome.last = Some(result);
+            if stop {
+                outcome.stopped_because = WalkStop::Break;
+                return outcome;
+            }
+        }
+        if frames.ended {
+            outcome.stopped_because = WalkStop::End;
+        }
+        outcome
+    }
+
+    /// Iterate over the frames of the stack, starting with the frame with
+    /// the given registers. See `Walker::frames`.
+    pub fn frames<'w, Reader>(
+        &'w self,
+        cx: &'w mut UnwindContext<'a>,
+        reader: &'w Reader,
+        start: FrameRegisters,
+    ) -> Frames<'w, 'a, Reader>
+    where
+        Reader: MemoryReader,
+    {
+        self.frames_observed(cx, reader, observer::IgnoreEvents, start)
+    }
+
+    /// Like `frames`, telling the given observer what the walk does.
+    fn frames_observed<'w, Reader, Observer>(
+        &'w self,
+        cx: &'w mut UnwindContext<'a>,
+        reader: &'w Reader,
+        observer: Observer,
+        start: FrameRegisters,
+    ) -> Frames<'w, 'a, Reader, Observer>
+    where
+        Reader: MemoryReader,
+        Observer: 'w + UnwindObserver,
+    {
+        Frames {
+            unwinder: self,
+            reader,
+            observer,
+            start: Some(start),
+            next: None,
+            error: None,
+            chain_end: false,
+            ended: false,
+            pc_kind: PcKind::Interrupted,
+            found_by: None,
+            skip: self.opts.skip_frames,
+            frames: 0,
+            max_frames: self.max_frames(cx),
+            started: Instant::now(),
+            row_cache_at_start: cx.row_cache.stats(),
+            cx,
+        }
+    }
+
+    /// Get the most frames a walk with the given context may yield.
+    fn max_frames(&self, cx: &UnwindContext<'a>) -> usize {
+        cmp::min(
+            self.opts.max_frames.unwrap_or(usize::MAX),
+            cx.max_frames.unwrap_or(usize::MAX),
+        )
+    }
+
+    /// Walk the current thread's stack, skipping the frames of pancakes'
+    /// public entry point and everything it called to capture the
+    /// registers, which are the frames with stack pointers at or below
+    /// `marker`: the address of a local in the entry point's frame.
+    #[cfg(feature = "live")]
+    fn walk_current<Reader, Observer, F, T>(
+        &self,
+        cx: &mut UnwindContext<'a>,
+        reader: &Reader,
+        observer: &Observer,
+        marker: usize,
+        mut f: F,
+    ) -> WalkOutcome<T>
+    where
+        Reader: MemoryReader,
+        Observer: UnwindObserver,
+        F: FnMut(&Frame) -> T,
+        T: AsStackWalkControl,
+    {
+        let max_frames = self.max_frames(cx);
+        let walked = FrameRegisters::with_current(|registers| {
+            let mut frames = self.frames_observed(cx, reader, observer, registers.clone());
+            // Only skip and count the frames after our own.
+            frames.skip = 0;
+            frames.max_frames = usize::MAX;
+
+            let mut skip = self.opts.skip_frames;
+            let mut outcome = WalkOutcome {
+                frames_walked: 0,
+                last: None,
+                stopped_because: WalkStop::MaxFrames,
+            };
+            let ours = frames.by_ref().skip_while(|frame| match *frame {
+                Ok(ref frame) => frame.sp().map_or(false, |sp| sp <= marker),
+                Err(_) => false,
+            });
+            for frame in ours {
+                let mut frame = match frame {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        outcome.stopped_because = WalkStop::Error(e);
+                        return Ok(outcome);
+                    }
+                };
+                if skip > 0 {
+                    skip -= 1;
+                    continue;
+                }
+
+                frame.index = outcome.frames_walked;
+                let result = f(&frame);
+                outcome.frames_walked += 1;
+                let stop = result.as_stack_walk_control() == StackWalkControl::Break;
+                outcome.last = Some(result);
+                if stop {
+                    outcome.stopped_because = WalkStop::Break;
+                    return Ok(outcome);
+                }
+                if outcome.frames_walked >= max_frames {
+                    return Ok(outcome);
+                }
+            }
+            if frames.ended {
+                outcome.stopped_because = WalkStop::End;
+            }
+            Ok(outcome)
+        });
+        walked.unwrap_or_else(WalkOutcome::failed)
+    }
+}
+
+/// A `Walker` traverses frames that make up a native stack.
+///
+/// A `Walker` bundles an `Unwinder` with the `UnwindContext`, memory reader,
+/// logger, and observer to walk with. To walk stacks on many threads without
+/// duplicating the unwind information, share an `Unwinder` instead.
+///
+/// THIS WILL NOT MALLOC OR ACQUIRE LOCKS!! IT MUST BE SIGNAL SAFE!!
+#[derive(Debug)]
+pub struct Walker<
+    'a,
+    Reader = reader::ThisProcessMemory,
+    Logger = log::IgnoreLogs,
+    Observer = observer::IgnoreEvents,
+> where
+    Reader: MemoryReader,
+    Logger: log::UnwindLogger,
+    Observer: UnwindObserver,
+{
+    unwinder: Unwinder<'a>,
+    cx: UnwindContext<'a>,
+    reader: Reader,
+    logger: Logger,
+    observer: Observer,
+}
+
+impl<'a, Reader, Logger, Observer> Walker<'a, Reader, Logger, Observer>
+where
+    Reader: MemoryReader,
+    Logger: log::UnwindLogger,
+    Observer: UnwindObserver,
+{
+    /// Walk with the given observer, which is told what each walk does,
+    /// instead of this walker's current one.
+    ///
+    /// See `observer::UnwindObserver`.
+    pub fn with_observer<O>(self, observer: O) -> Walker<'a, Reader, Logger, O>
+    where
+        O: UnwindObserver,
+    {
+        Walker {
+            unwinder: self.unwinder,
+            cx: self.cx,
+            reader: self.reader,
+            logger: self.logger,
+            observer,
+        }
+    }
+
+    /// Get the observer this walker tells what each walk does.
+    pub fn observer(&self) -> &Observer {
+        &self.observer
+    }
+
+    /// Reconfigure this `Walker`.
+    ///
+    /// Turn this `Walker` back into an `Options` and perform new
+    /// configuration, after which `Options::build` may be invoked to
+    /// reconstruct an `Walker` with the new configuration.
+    ///
+    /// ```
+    /// use pancakes::Options;
+    ///
+    /// // We have some unwinder.
+    /// let unwinder = Options::new().build();
+    ///
+    /// // Oh, we learned about new stuff to configure. Turn the unwinder back
+    /// // into a builder.
+    /// let (builder, _, _) = unwinder.reconfigure();
+    /// // ... set new configuration parameters on the builder ...
+    ///
+    /// // Now that we are done reconfiguring, get the unwinder back again!
+    /// let unwinder = builder.build();
+    /// # let _ = unwinder;
+    /// ```
+    pub fn reconfigure(self) -> (Options<'a>, Reader, Logger) {
+        (self.unwinder.reconfigure(), self.reader, self.logger)
+    }
+
+    /// Get the `Unwinder` this walker walks with.
+    pub fn unwinder(&self) -> &Unwinder<'a> {
+        &self.unwinder
+    }
+
+    /// Add the `.eh_frame` section of a module loaded after this `Walker` was
+    /// built.
+    ///
+    /// See `Unwinder::add_eh_frame`.
+    pub fn add_eh_frame(
+        &self,
+        bias: Bias,
+        bases: gimli::BaseAddresses,
+        eh_frame: &[u8],
+    ) -> Result<ModuleId> {
+        self.unwinder.add_eh_frame(bias, bases, eh_frame)
+    }
+
+    /// Remove a module added with `add_eh_frame`.
+    ///
+    /// See `Unwinder::remove_module`.
+    pub fn remove_module(&self, module: ModuleId) -> bool {
+        self.unwinder.remove_module(module)
+    }
+
+    /// Add and remove the unwind information of the modules loaded and
+    /// unloaded since the last rescan.
+    ///
+    /// See `Unwinder::rescan_modules`.
+    #[cfg(feature = "live")]
+    pub fn rescan_modules(&self) -> bool {
+        self.unwinder.rescan_modules()
+    }
+
+    /// Stop using any unwind information for the addresses in the given
+    /// range.
+    ///
+    /// See `Unwinder::remove_entries_in_range`.
+    pub fn remove_entries_in_range(&self, range: Range<Avma>) {
+        self.unwinder.remove_entries_in_range(range)
+    }
+
+    /// Get a snapshot of what walking with this walker's `Unwinder` has cost
+    /// and found.
+    ///
+    /// See `Unwinder::stats`.
+    pub fn stats(&self) -> UnwindStats {
+        self.unwinder.stats()
+    }
+
+    /// Zero the counts returned by `stats`.
+    ///
+    /// See `Unwinder::reset_stats`.
+    pub fn reset_stats(&self) {
+        self.unwinder.reset_stats()
+    }
+
+    /// Allocate the scratch space for walks of up to `max_frames` frames,
+    /// that evaluate DWARF expressions up to `max_expression_depth` words
+    /// deep, or fail with `Error::OutOfMemory`.
+    ///
+    /// Call this before installing a signal handler that walks stacks, to
+    /// fail there rather than in the handler. See `UnwindContext::prepare`.
+    ///
+    /// ```
+    /// let mut walker = pancakes::Options::new().build();
+    /// walker.prepare(128, 64).expect("should allocate scratch space OK");
+    /// ```
+    pub fn prepare(&mut self, max_frames: usize, max_expression_depth: usize) -> Result<()> {
+        self.cx.prepare(max_frames, max_expression_depth)
+    }
+
+    /// Keep walking until we've walked the whole stack, `f` asks us to halt
+    /// walking, or we've walked `Options::max_frames` frames.
+    ///
+    /// The returned `WalkOutcome` holds the `T` returned in the last
+    /// invokation of `f`, either on the oldest stack frame found or where
+    /// `AsStackWalkControl::as_stack_walk_control` returns
+    /// `StackWalkControl::Break`, along with how many frames were walked and
+    /// why walking stopped. When a frame cannot be walked part way up the
+    /// stack, `f` has still been called on every frame before it.
+    ///
+    /// ```
+    /// # fn f() {
+    /// use pancakes;
+    ///
+    /// let mut walker = pancakes::Options::new().build();
+    ///
+    /// # let get_frame_regs = || unimplemented!();
+    /// let result = walker.walk(get_frame_regs(), |frame| {
+    ///     println!("Traversed frame {:?}", frame);
+    ///
+    ///     // There is an `AsStackWalkControl` implementation for `()` that
+    ///     // always continues walking, so we don't need any explicit return.
+    /// });
+    ///
+    /// // Handle walking errors however you'd like. Here, we report them
+    /// // along with how far we got.
+    /// if let Some(e) = result.error() {
+    ///     println!("Stopped after {} frames: {}", result.frames_walked, e);
+    /// }
+    /// # }
+    /// ```
+    pub fn walk<F, T>(&mut self, start_registers: &FrameRegisters, f: F) -> WalkOutcome<T>
+    where
+        F: FnMut(&Frame) -> T,
+        T: AsStackWalkControl,
+    {
+        let observer = log::LogEvents::new(&self.observer, &mut self.logger);
+        self.unwinder
+            .walk_observed(&mut self.cx, &self.reader, &observer, start_registers, f)
+    }
+
+    /// Iterate over the frames of the stack, starting with the frame with
+    /// the given registers.
+    ///
+    /// The iterator yields `Options::max_frames` frames at most, and stops
+    /// after the outermost frame. If a frame before the outermost cannot be
+    /// walked to its caller, it yields the error after that frame and then
+    /// stops.
+    ///
+    /// ```
+    /// # fn f() {
+    /// use pancakes::Registers;
+    ///
+    /// let mut walker = pancakes::Options::new().build();
+    ///
+    /// # let get_frame_regs = || unimplemented!();
+    /// let ips: Vec<_> = walker
+    ///     .frames(get_frame_regs())
+    ///     .take(10)
+    ///     .filter_map(|frame| frame.ok())
+    ///     .map(|frame| frame.ip())
+    ///     .collect();
+    /// # let _ = ips;
+    /// # }
+    /// ```
+    pub fn frames<'w>(
+        &'w mut self,
+        start: FrameRegisters,
+    ) -> Frames<'w, 'a, Reader, log::LogEvents<'w, Observer, Logger>> {
+        let observer = log::LogEvents::new(&self.observer, &mut self.logger);
+        self.unwinder
+            .frames_observed(&mut self.cx, &self.reader, observer, start)
+    }
+
+    /// Walk the stack from the given registers, like `walk`, recording just
+    /// the instruction pointer of each frame in `ips`, outermost last, until
+    /// it is full. Returns how many were recorded.
+    ///
+    /// This never allocates, so it may be called from signal handlers. See
+    /// `StackCapture` for a buffer that also records whether the stack had
+    /// more frames than fit.
+    ///
+    /// ```
+    /// # fn f() {
+    /// let mut walker = pancakes::Options::new().build();
+    /// let mut ips = [0; 64];
+    ///
+    /// # let get_frame_regs = || unimplemented!();
+    /// let len = walker.capture_into(get_frame_regs(), &mut ips);
+    /// # let _ = &ips[..len];
+    /// # }
+    /// ```
+    pub fn capture_into(&mut self, start_registers: &FrameRegisters, ips: &mut [usize]) -> usize {
+        self.capture_ips(start_registers, ips).0
+    }
+
+    /// Walk the stack from the given registers, like `walk`, recording just
+    /// the instruction pointer of each frame in `capture`.
+    ///
+    /// This never allocates, so it may be called from signal handlers.
+    pub fn capture<const N: usize>(
+        &mut self,
+        start_registers: &FrameRegisters,
+        capture: &mut StackCapture<N>,
+    ) {
+        let (len, truncated) = self.capture_ips(start_registers, capture.start());
+        capture.finish(len, truncated);
+    }
+
+    /// Record the instruction pointers of the stack's frames in `ips`.
+    /// Returns how many were recorded, and whether there were more frames
+    /// than fit.
+    fn capture_ips(
+        &mut self,
+        start_registers: &FrameRegisters,
+        ips: &mut [usize],
+    ) -> (usize, bool) {
+        let mut len = 0;
+        let mut truncated = false;
+        self.walk(start_registers, |frame| {
+            let ip = match frame.ip() {
+                TaggedWord::Valid(ip) => ip,
+                TaggedWord::Invalid => return StackWalkControl::Break,
+            };
+            if len == ips.len() {
+                truncated = true;
+                return StackWalkControl::Break;
+            }
+            ips[len] = ip;
+            len += 1;
+            StackWalkControl::Continue
+        });
+        (len, truncated)
+    }
+
+    /// Walk the current thread's stack, starting with the caller of
+    /// `walk_current`, like `walk` does from the registers given to it.
+    ///
+    /// This saves wrapping `walk` in `FrameRegisters::with_current`, and
+    /// skips the frames that capturing the registers adds.
+    ///
+    /// ```
+    /// # fn f() {
+    /// let mut walker = pancakes::Options::new().build();
+    ///
+    /// let result = walker.walk_current(|frame| {
+    ///     println!("Traversed frame {:?}", frame);
+    /// });
+    /// # let _ = result;
+    /// # }
+    /// ```
+    #[cfg(feature = "live")]
+    #[inline(never)]
+    pub fn walk_current<F, T>(&mut self, f: F) -> WalkOutcome<T>
+    where
+        F: FnMut(&Frame) -> T,
+        T: AsStackWalkControl,
+    {
+        let marker = 0u8;
+        let marker = hint::black_box(&marker) as *const u8 as usize;
+        let observer = log::LogEvents::new(&self.observer, &mut self.logger);
+        self.unwinder
+            .walk_current(&mut self.cx, &self.reader, &observer, marker, f)
+    }
+
+    /// Walk the stack of the thread that the given `ucontext_t` was captured
+    /// from, starting with the frame it was captured in.
+    ///
+    /// This is the entry point for walking stacks from a signal handler,
+    /// e.g. a `SIGPROF` handler in a sampling profiler or a `SIGSEGV` handler
+    /// in a crash reporter: the kernel passes the interrupted thread's
+    /// context to an `SA_SIGINFO` handler as its third argument, and walking
+    /// from it skips the handler's own frames and the kernel's signal
+    /// trampoline.
+    ///
+    /// This is unsafe because `ctx` must point to a valid `ucontext_t`.
+    ///
+    /// ```no_run
+    /// extern crate libc;
+    /// extern crate pancakes;
+    ///
+    /// extern "C" fn on_sigprof(_: libc::c_int, _: *mut libc::siginfo_t, ctx: *mut libc::c_void) {
+    ///     # let walker: &mut pancakes::Walker = unimplemented!();
+    ///     let _ = unsafe {
+    ///         walker.walk_from_ucontext(ctx as *const pancakes::ucontext_t, |frame| {
+    ///             // Record the frame...
+    ///             # let _ = frame;
+    ///         })
+    ///     };
+    /// }
+    /// # fn main() { let _ = on_sigprof; }
+    /// ```
+    #[cfg(feature = "live")]
+    pub unsafe fn walk_from_ucontext<F, T>(
+        &mut self,
+        ctx: *const ucontext_t,
+        f: F,
+    ) -> WalkOutcome<T>
+    where
+        F: FnMut(&Frame) -> T,
+        T: AsStackWalkControl,
+    {
+        let registers = FrameRegisters::from_ucontext(ctx);
+        self.walk(&registers, f)
+    }
+}
+
+/// An iterator over the frames of a stack, created by `Walker::frames` or
+/// `Unwinder::frames`.
+#[derive(Debug)]
+pub struct Frames<
+    'w,
+    'a: 'w,
+    Reader = reader::ThisProcessMemory,
+    Observer = observer::IgnoreEvents,
+> where
+    Reader: 'w + MemoryReader,
+    Observer: 'w + UnwindObserver,
+{
+    unwinder: &'w Unwinder<'a>,
+    cx: &'w mut UnwindContext<'a>,
+    reader: &'w Reader,
+    observer: Observer,
+    start: Option<FrameRegisters>,
+    /// The registers of the next frame to yield, whose callee was walked to
+    /// find them.
+    next: Option<FrameRegisters>,
+    /// The error walking the most recently yielded frame, to yield next.
+    error: Option<Error>,
+    /// Whether the next frame was found through a frame record that ends the
+    /// frame pointer chain, so that nothing else knowing its caller makes it
+    /// the outermost frame.
+    chain_end: bool,
+    /// Whether the most recently yielded frame is the outermost frame.
+    ended: bool,
+    /// What the instruction pointer of the next frame points to.
+    pc_kind: PcKind,
+    /// The strategy that found the next frame.
+    found_by: Option<UnwindStrategy>,
+    skip: usize,
+    frames: usize,
+    max_frames: usize,
+    /// When the walk started, and the row cache's counts then, to count in
+    /// the `Unwinder`'s stats once it ends.
+    started: Instant,
+    row_cache_at_start: RowCacheStats,
+}
+
+impl<'w, 'a, Reader, Observer> Frames<'w, 'a, Reader, Observer>
+where
+    Reader: MemoryReader,
+    Observer: UnwindObserver,
+{
+    /// Count how walking a frame went in the `Unwinder`'s stats, and tell
+    /// the observer if it failed.
+    fn count(&self, walked: &Result<(FrameRegisters, Walked)>) {
+        let stats = &self.unwinder.stats;
+        match *walked {
+            Ok((_, ref walked)) => {
+                let first = self.unwinder.opts.strategies.first();
+                stats.walked(walked.strategy, first != Some(&walked.strategy));
+            }
+            Err(ref e) => {
+                stats.failed(e);
+                self.observer.walk_aborted(e);
+            }
+        }
+    }
+}
+
+impl<'w, 'a: 'w, Reader, Observer> Drop for Frames<'w, 'a, Reader, Observer>
+where
+    Reader: 'w + MemoryReader,
+    Observer: 'w + UnwindObserver,
+{
+    fn drop(&mut self) {
+        let now = self.cx.row_cache.stats();
+        let row_cache = RowCacheStats {
+            hits: now.hits.saturating_sub(self.row_cache_at_start.hits),
+            misses: now.misses.saturating_sub(self.row_cache_at_start.misses),
+        };
+        self.unwinder
+            .stats
+            .finished(self.frames, self.started.elapsed(), row_cache);
+    }
+}
+
+impl<'w, 'a, Reader, Observer> Iterator for Frames<'w, 'a, Reader, Observer>
+where
+    Reader: MemoryReader,
+    Observer: UnwindObserver,
+{
+    type Item = Result<Frame<'w>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.frames >= self.max_frames {
+            return None;
+        }
+        if let Some(e) = self.error.take() {
+            return Some(Err(e));
+        }
+
+        if let Some(mut start) = self.start.take() {
+            for _ in 0..self.skip {
+                let caller = alloc_guard::forbid_allocations(|| unsafe {
+                    self.unwinder
+                        .walk_one(self.cx, self.reader, &self.observer, &start, self.pc_kind)
+                });
+                self.count(&caller);
+                start = match caller {
+                    Ok((caller, walked)) => {
+                        self.pc_kind = walked.caller_pc_kind();
+                        self.found_by = Some(walked.strategy);
+                        caller
+                    }
+                    Err(e) => return Some(Err(e)),
+                };
+            }
+            self.next = Some(start);
+        }
+
+        // Walk each frame before yielding it, so that it can carry what
+        // walking it found. The walk already ended, or failed, if there is
+        // no next frame.
+        let registers = self.next.take()?;
+        let pc_kind = self.pc_kind;
+        let found_by = self.found_by;
+        let caller = alloc_guard::forbid_allocations(|| unsafe {
+            self.unwinder
+                .walk_one(self.cx, self.reader, &self.observer, &registers, pc_kind)
+        });
+        match caller {
+            Err(Error::NoUnwindInfoForAddress(_)) if self.chain_end => self.ended = true,
+            _ => self.count(&caller),
+        }
+        let (cfa, walked) = match caller {
+            Ok((caller, walked)) => {
+                let cfa = caller.sp().map_or(None, Some);
+                self.pc_kind = walked.caller_pc_kind();
+                self.found_by = Some(walked.strategy);
+                self.chain_end =
+                    walked.strategy == UnwindStrategy::FramePointer && caller.bp().is_invalid();
+                // An undefined or null return address marks the outermost
+                // frame.
+                if caller.ip().map_or(false, |ip| ip != 0) {
+                    self.next = Some(caller);
+                } else {
+                    self.ended = true;
+                }
+                (cfa, Some(walked))
+            }
+            Err(e) => {
+                if !self.ended {
+                    self.error = Some(e);
+                }
+                (None, None)
+            }
+        };
+
+        let frame = Frame {
+            index: self.frames,
+            registers,
+            pc_kind,
+            cfa,
+            found_by,
+            walked,
+        };
+        self.frames += 1;
+        Some(Ok(frame))
+    }
+}
+
+/// Walk the current thread's stack, calling `f` on each frame, like
+/// `backtrace::trace`, starting with the caller of `trace`.
+///
+/// This is the quickest way to get a backtrace: the first call builds a
+/// process-wide `Unwinder` from the `.eh_frame` sections of every loaded
+/// shared library, and every call walks with it. Libraries loaded after the
+/// first call are not known to it; build an `Unwinder` or `Walker` with
+/// `Options::find_eh_frame_entries` to control when they are found.
+///
+/// Unlike walking with a prepared `Walker`, this allocates, so it must not be
+/// called from a signal handler.
+///
+/// ```
+/// # fn f() {
+/// pancakes::trace(|frame| {
+///     println!("Traversed frame {:?}", frame);
+/// });
+/// # }
+/// ```
+#[cfg(feature = "live")]
+#[inline(never)]
+pub fn trace<F, T>(f: F) -> WalkOutcome<T>
+where
+    F: FnMut(&Frame) -> T,
+    T: AsStackWalkControl,
+{
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!("trace").entered();
+
+    let marker = 0u8;
+    let marker = hint::black_box(&marker) as *const u8 as usize;
+    let mut cx = UnwindContext::new();
+    let outcome = process_unwinder().walk_current(
+        &mut cx,
+        &reader::ThisProcessMemory,
+        &observer::IgnoreEvents,
+        marker,
+        f,
+    );
+
+    #[cfg(feature = "tracing")]
+    tracing::debug!(
+        frames = outcome.frames_walked,
+        stopped_because = ?outcome.stopped_because,
+        "walked stack"
+    );
+    outcome
+}
+
+/// Get the process-wide `Unwinder` that `trace` and `threads::walk_all` walk
+/// with. It is built on first use, and the loaded modules are rescanned on
+/// every later use.
+#[cfg(feature = "live")]
+fn process_unwinder() -> &'static Unwinder<'static> {
+    static UNWINDER: OnceLock<Unwinder<'static>> = OnceLock::new();
+    let unwinder = UNWINDER.get_or_init(|| {
+        let mut options = Options::new();
+        // Whichever libraries' unwind information could be found is better
+        // than none.
+        let _ = options.find_eh_frame_entries();
+        options.build_unwinder()
+    });
+    // Pick up the modules loaded and unloaded since.
+    unwinder.rescan_modules();
+    unwinder
+}
+
+/// Get the base addresses that the pointers in a call frame information
+/// section at the stated address `cfi` may be relative to: the section
+/// itself, and the stated addresses of the module's `.text` and of its
+/// `.got`, which `DW_EH_PE_datarel` pointers are relative to.
+fn base_addresses(cfi: u64, text: Option<u64>, got: Option<u64>) -> gimli::BaseAddresses {
+    let mut bases = gimli::BaseAddresses::default().set_cfi(cfi);
+    if let Some(text) = text {
+        bases = bases.set_text(text);
+    }
+    if let Some(got) = got {
+        bases = bases.set_data(got);
+    }
+    bases
+}
+
+/// Get the base addresses for the call frame information section at the
+/// stated address `cfi` in the given object file.
+fn object_base_addresses(file: &object::File, cfi: u64) -> gimli::BaseAddresses {
+    // `object` also finds Mach-O's `__text` and `__got` by these names.
+    let address = |name| file.section_by_name(name).map(|section| section.address());
+    base_addresses(cfi, address(".text"), address(".got"))
+}
+
+cfg_if! {
+    if #[cfg(all(feature = "live", any(target_os = "macos", target_os = "ios")))] {
+        const EH_FRAME: &'static [u8] = b"__eh_frame";
+        // Mach-O has no `.eh_frame_hdr` equivalent.
+        const EH_FRAME_HDR: &'static [u8] = b"";
+        const TEXT: &'static [u8] = b"__text";
+        const GOT: &'static [u8] = b"__got";
+    } else if #[cfg(feature = "live")] {
+        const EH_FRAME: &'static [u8] = b".eh_frame";
+        const EH_FRAME_HDR: &'static [u8] = b".eh_frame_hdr";
+        const TEXT: &'static [u8] = b".text";
+        const GOT: &'static [u8] = b".got";
+    }
+}
+
+/// The sections of a module loaded into this process that walking with its
+/// unwind information needs.
+#[cfg(feature = "live")]
+struct ModuleSections<'a> {
+    /// From the start of the module's first section to the end of its last,
+    /// if it has any.
+    range: Option<Range<Avma>>,
+    eh_frame: Option<(Svma, &'a [u8])>,
+    eh_frame_hdr: Option<(Svma, &'a [u8])>,
+    text: Option<Svma>,
+    got: Option<Svma>,
+}
+
+#[cfg(feature = "live")]
+impl<'a> ModuleSections<'a> {
+    /// Find the sections of the given loaded module. The sections' contents
+    /// are only valid until the module is unloaded.
+    fn find<S>(shlib: &S) -> ModuleSections<'a>
+    where
+        S: SharedLibrary,
+    {
+        let mut sections = ModuleSections {
+            range: None,
+            eh_frame: None,
+            eh_frame_hdr: None,
+            text: None,
+            got: None,
+        };
+        let (mut start, mut end) = (usize::MAX, 0);
+        for section in shlib.sections() {
+            let avma = section.actual_virtual_memory_address(shlib).0 as usize;
+            start = cmp::min(start, avma);
+            end = cmp::max(end, avma + section.len());
+
+            let svma = section.stated_virtual_memory_address();
+            let data = || unsafe { slice::from_raw_parts(avma as *const u8, section.len()) };
+            match section.name().to_bytes() {
+                name if name == EH_FRAME => sections.eh_frame = Some((svma, data())),
+                name if name == EH_FRAME_HDR => sections.eh_frame_hdr = Some((svma, data())),
+                name if name == TEXT => sections.text = Some(svma),
+                name if name == GOT => sections.got = Some(svma),
+                _ => {}
+            }
+        }
+        if start < end {
+            sections.range = Some(Avma(start as *const u8)..Avma(end as *const u8));
+        }
+        sections
+    }
+
+    /// Get the base addresses for the module's `.eh_frame`, if it has one.
+    fn bases(&self) -> Option<gimli::BaseAddresses> {
+        self.eh_frame.map(|(svma, _)| {
+            base_addresses(
+                svma.0 as u64,
+                self.text.map(|svma| svma.0 as u64),
+                self.got.map(|svma| svma.0 as u64),
+            )
+        })
+    }
+}
+
+/// Parse an entry for every FDE in the given `.eh_frame` section.
+fn eh_frame_entries<'a>(
+    bias: Bias,
+    bases: &gimli::BaseAddresses,
+    eh_frame: TargetEhFrame<'a>,
+) -> Result<Vec<UnwindEntry<'a>>> {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!("parse_eh_frame", bias = bias.0).entered();
+    #[cfg(feature = "tracing")]
+    let started = Instant::now();
+
+    let mut parsed = vec![];
+    let mut entries = eh_frame.entries(bases);
+    let mut cies = HashMap::new();
+    while let Some(entry) = entries.next()? {
+        match entry {
+            gimli::CieOrFde::Cie(_) => continue,
+            gimli::CieOrFde::Fde(partial) => {
+                let fde = partial.parse(|offset| {
+                    cies.entry(offset)
+                        .or_insert_with(|| eh_frame.cie_from_offset(bases, offset))
+                        .clone()
+                })?;
+                let start = (fde.initial_address() as isize).wrapping_add(bias.0) as usize;
+                let range = start..start.wrapping_add(fde.len() as usize);
+                let fde = Fde::EhFrame(fde);
+                parsed.push(UnwindEntry { bias, range, fde });
+            }
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    tracing::debug!(
+        entries = parsed.len(),
+        elapsed_us = started.elapsed().as_micros() as u64,
+        "parsed .eh_frame"
+    );
+    Ok(parsed)
+}
+
+/// Parse the entries of each of the given `.eh_frame` sections, spread across
+/// a thread per CPU.
+#[cfg(all(feature = "live", feature = "parallel-setup"))]
+fn parse_eh_frames<'a>(
+    sections: Vec<(Bias, gimli::BaseAddresses, TargetEhFrame<'a>)>,
+) -> Vec<Result<Vec<UnwindEntry<'a>>>> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = cmp::max(1, (sections.len() + threads - 1) / threads);
+    #[cfg(feature = "tracing")]
+    let span = tracing::Span::current();
+    thread::scope(|scope| {
+        let handles: Vec<_> = sections
+            .chunks(chunk_size)
+            .map(|chunk| {
+                // Nest each thread's spans in the caller's.
+                #[cfg(feature = "tracing")]
+                let span = span.clone();
+                scope.spawn(move || {
+                    #[cfg(feature = "tracing")]
+                    let _entered = span.enter();
+                    chunk
+                        .iter()
+                        .map(|&(bias, ref bases, eh_frame)| {
+                            eh_frame_entries(bias, bases, eh_frame)
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+/// Parse the entries of each of the given `.eh_frame` sections.
+#[cfg(all(feature = "live", not(feature = "parallel-setup")))]
+fn parse_eh_frames<'a>(
+    sections: Vec<(Bias, gimli::BaseAddresses, TargetEhFrame<'a>)>,
+) -> Vec<Result<Vec<UnwindEntry<'a>>>> {
+    sections
+        .into_iter()
+        .map(|(bias, bases, eh_frame)| eh_frame_entries(bias, &bases, eh_frame))
+        .collect()
+}
+
+/// Check that walking from `callee` to `caller` made progress up the stack,
+/// so that corrupt frame pointers or bad unwind information end the walk
+/// instead of looping forever.
+///
+/// The stack grows down on every supported architecture, so the caller's
+/// stack pointer must be above the callee's, or, on architectures with a
+/// link register, the same with a different pc, for leaf functions that
+/// never touch the stack. If either is unknown, the caller's registers must
+/// at least differ from the callee's. The same goes for a signal frame,
+/// since the handler may have run on an alternate signal stack, anywhere
+/// relative to the interrupted frame's.
+fn check_progress(
+    ip: usize,
+    callee: &FrameRegisters,
+    caller: &FrameRegisters,
+    signal_frame: bool,
+) -> Result<()> {
+    let progressed = match (callee.sp(), caller.sp()) {
+        (TaggedWord::Valid(callee_sp), TaggedWord::Valid(caller_sp)) if !signal_frame => {
+            let leaf = cfg!(not(any(target_arch = "x86", target_arch = "x86_64"))) &&
+                caller_sp == callee_sp &&
+                callee.ip() != caller.ip();
+            caller_sp > callee_sp || leaf
+        }
+        _ => {
+            callee.ip() != caller.ip() || callee.sp() != caller.sp() ||
+                callee.bp() != caller.bp()
+        }
+    };
+    if progressed {
+        Ok(())
+    } else {
+        Err(Error::NonProgressingUnwind(ip))
+    }
+}
+
+/// The parts of a walk's state that `eval_fde` uses, besides the gimli
+/// context.
+struct FdeState<'s, Observer>
+where
+    Observer: 's + UnwindObserver,
+{
+    row_cache: &'s mut RowCache,
+    scratch: expression::Scratch<'s>,
+    observer: &'s Observer,
+}
+
+/// Find the row of the given FDE's unwind table that covers `ip`, in the row
+/// cache or by running the FDE's instructions, and use it to recover the
+/// caller's registers. Also returns whether the FDE is a signal trampoline's.
+unsafe fn eval_fde<'a, Section, Reader, Observer>(
+    ctx_slot: &mut Option<gimli::UninitializedUnwindContext<Section, TargetEndianBuf<'a>>>,
+    state: &mut FdeState<Observer>,
+    fde: &gimli::FrameDescriptionEntry<Section, TargetEndianBuf<'a>>,
+    bias: Bias,
+    ip: usize,
+    start_regs: &FrameRegisters,
+    reader: &Reader,
+) -> Result<(FrameRegisters, bool)>
+where
+    Section: UnwindSection<TargetEndianBuf<'a>>,
+    Reader: MemoryReader,
+    Observer: UnwindObserver,
+{
+    // A signal trampoline's caller is the frame the signal interrupted, whose
+    // registers the kernel saved in the signal frame on the stack. The
+    // trampoline's CFI, when it has any, often describes only some of them.
+    let signal_frame = fde.is_signal_trampoline();
+    let return_address_register = fde.cie().return_address_register() as u8;
+    if signal_frame {
+        if let Some(registers) = FrameRegisters::from_sigframe(start_regs, reader) {
+            return registers.map(|registers| (registers, true));
+        }
+    }
+
+    // Signal trampolines' rows are not cached, like they are not compiled.
+    let fde_address = fde.initial_address();
+    if !signal_frame {
+        if let Some(row) = state.row_cache.lookup(ip, bias, fde_address) {
+            state.observer.row_resolved(ip, true);
+            return row.unwind(start_regs, reader).map(|registers| (registers, false));
+        }
+    }
+
+    let result = {
+        //let ip = (ip as *const u8).offset(-bias.0);
+        let ip = Avma(ip as *const u8);
+
+        ctx_slot
+            .take()
+            .expect("should always have Some(ctx) at the beginning of Walker::walk_one")
+            .initialize(fde.cie())
+            .map_err(|(e, ctx)| (e.into(), ctx))
+            .and_then(|mut ctx| {
+                let registers = {
+                    let mut table = gimli::UnwindTable::new(&mut ctx, fde);
+                    loop {
+                        match table.next_row() {
+                            Err(e) => break Err(e.into()),
+                            Ok(None) => break Ok(None),
+                            Ok(Some(row)) => {
+                                let start = Svma(row.start_address() as *const u8);
+                                let end = Svma(row.end_address() as *const u8);
+
+                                let start = Avma(start.0.offset(bias.0));
+                                let end = Avma(end.0.offset(bias.0));
+
+                                if start.0 <= ip.0 && ip.0 < end.0 {
+                                    state.observer.row_resolved(ip.0 as usize, false);
+                                    if !signal_frame {
+                                        let compiled = CompiledRow::compile(
+                                            row,
+                                            return_address_register,
+                                        );
+                                        if let Some(compiled) = compiled {
+                                            state.row_cache.insert(
+                                                ip.0 as usize,
+                                                bias,
+                                                fde_address,
+                                                compiled,
+                                            );
+                                        }
+                                    }
+                                    break FrameRegisters::from_unwind_table_row(
+                                        row,
+                                        return_address_register,
+                                        &mut state.scratch,
+                                        start_regs,
+                                        reader,
+                                    ).map(Some);
+                                } else {
+                                    continue;
+                                }
+                            }
+                        }
+                    }
+                };
+
+                let ctx = ctx.reset();
+                match registers {
+                    Ok(r) => Ok((r, ctx)),
+                    Err(e) => Err((e, ctx)),
+                }
+            })
+    };
+
+    match result {
+        Ok((Some(registers), ctx)) => {
+            *ctx_slot = Some(ctx);
+            Ok((registers, signal_frame))
+        }
+        Ok((None, ctx)) => {
+            *ctx_slot = Some(ctx);
+            Err(Error::NoUnwindInfoForAddress(ip))
+        }
+        Err((e, ctx)) => {
+            *ctx_slot = Some(ctx);
+            Err(e.into())
+        }
+    }
+}
+
+/// Compile the `.eh_frame` of the given object file, if it has one.
+fn compile_eh_frame_from_object(
+    compiler: &mut Compiler,
+    file: &object::File,
+    endian: gimli::RunTimeEndian,
+    bias: Bias,
+) -> Result<()> {
+    // `object` also finds Mach-O's `__eh_frame` by this name.
+    let section = match file.section_by_name(".eh_frame") {
+        Some(section) => section,
+        None => return Ok(()),
+    };
+    let eh_frame = section.data().map_err(|_| Error::InvalidObjectFile)?;
+    let eh_frame = TargetEhFrame::new(eh_frame, endian);
+    let bases = object_base_addresses(file, section.address());
+    compiler.add_section(bias, &bases, &eh_frame)
+}
+
+/// Compile the `.debug_frame` of the given object file, and return whether
+/// it had a non-empty one.
+fn compile_debug_frame_from_object(
+    compiler: &mut Compiler,
+    file: &object::File,
+    endian: gimli::RunTimeEndian,
+    bias: Bias,
+) -> Result<bool> {
+    let section = match file.section_by_name(".debug_frame") {
+        Some(section) => section,
+        None => return Ok(false),
+    };
+    let debug_frame = section.data().map_err(|_| Error::InvalidObjectFile)?;
+    if debug_frame.is_empty() {
+        return Ok(false);
+    }
+
+    let debug_frame = TargetDebugFrame::new(debug_frame, endian);
+    let bases = object_base_addresses(file, section.address());
+    compiler.add_section(bias, &bases, &debug_frame)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reader::SliceMemory;
+
+    pub(crate) fn stack(words: &[usize]) -> Vec<u8> {
+        words.iter().flat_map(|word| word.to_ne_bytes().to_vec()).collect()
+    }
+
+    /// Append a CIE, or an FDE of the CIE at offset 0, padded with
+    /// `DW_CFA_nop`s to a multiple of 8 bytes, to an `.eh_frame`.
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    fn eh_frame_entry(eh_frame: &mut Vec<u8>, is_cie: bool, contents: &[u8]) {
+        let id = if is_cie { 0 } else { eh_frame.len() as u32 + 4 };
+        let mut body = id.to_ne_bytes().to_vec();
+        body.extend_from_slice(contents);
+        while (body.len() + 4) % 8 != 0 {
+            body.push(0);
+        }
+        eh_frame.extend_from_slice(&(body.len() as u32).to_ne_bytes());
+        eh_frame.extend_from_slice(&body);
+    }
+
+    /// Append an FDE covering 0x100 bytes from `start` to an `.eh_frame`.
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    fn eh_frame_fde(eh_frame: &mut Vec<u8>, start: u64, instructions: &[u8]) {
+        let mut contents = start.to_ne_bytes().to_vec();
+        contents.extend_from_slice(&0x100u64.to_ne_bytes());
+        // No augmentation data.
+        contents.push(0);
+        contents.extend_from_slice(instructions);
+        eh_frame_entry(eh_frame, false, &contents);
+    }
+
+    /// An `.eh_frame` with one FDE, for 0x1000..0x1100, whose code has pushed
+    /// one word below the return address.
+    #[cfg(target_arch = "x86_64")]
+    pub(crate) fn eh_frame_pushing_one_word() -> Vec<u8> {
+        // Version 1, with a "zR" augmentation, code alignment 1, data
+        // alignment -8, the return address in register 16, and absolute
+        // pointers. DW_CFA_def_cfa: rsp + 8, DW_CFA_offset: rip at cfa - 8
+        let cie = [1, b'z', b'R', 0, 1, 0x78, 16, 1, 0, 0x0c, 7, 8, 0x90, 1];
+        let mut eh_frame = vec![];
+        eh_frame_entry(&mut eh_frame, true, &cie);
+        // DW_CFA_def_cfa_offset: 16
+        eh_frame_fde(&mut eh_frame, 0x1000, &[0x0e, 16]);
+        eh_frame
+    }
+
+    fn walk_one<Reader>(
+        walker: &mut Walker<Reader>,
+        regs: &FrameRegisters,
+    ) -> Result<FrameRegisters>
+    where
+        Reader: MemoryReader,
+    {
+        unsafe {
+            walker
+                .unwinder
+                .walk_one(
+                    &mut walker.cx,
+                    &walker.reader,
+                    &walker.observer,
+                    regs,
+                    PcKind::Interrupted,
+                )
+                .map(|(registers, _)| registers)
+        }
+    }
+
+    #[test]
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86", target_arch = "aarch64"))]
+    fn frame_pointer() {
+        let w = mem::size_of::<usize>();
+        let base = 0x1000;
+        // Two frame records, at `base + 2w` and `base + 6w`.
+        let bytes = stack(&[0, 0, base + 6 * w, 0x4000, 0, 0, 0, 0x5000]);
+
+        let mut options = Options::new();
+        options.strategies(vec![UnwindStrategy::FramePointer]);
+        let mut walker =
+            options.build_with_reader_logger(SliceMemory::new(base, &bytes), log::IgnoreLogs);
+
+        let regs = FrameRegisters::from_parts(
+            TaggedWord::valid(base + 2 * w),
+            TaggedWord::valid(base),
+            TaggedWord::valid(0x3000),
+        );
+        let caller = walk_one(&mut walker, &regs).unwrap();
+        assert_eq!(caller.ip(), TaggedWord::valid(0x4000));
+        if cfg!(target_arch = "aarch64") {
+            assert_eq!(caller.sp(), TaggedWord::invalid());
+        } else {
+            assert_eq!(caller.sp(), TaggedWord::valid(base + 4 * w));
+        }
+        assert_eq!(caller.bp(), TaggedWord::valid(base + 6 * w));
+
+        // The outermost frame saved a null frame pointer.
+        let outermost = walk_one(&mut walker, &caller).unwrap();
+        assert_eq!(outermost.ip(), TaggedWord::valid(0x5000));
+        assert_eq!(outermost.bp(), TaggedWord::invalid());
+        match walk_one(&mut walker, &outermost) {
+            Err(Error::NoUnwindInfoForAddress(0x5000)) => {}
+            otherwise => panic!("unexpected result: {:?}", otherwise),
+        }
+
+        // A frame pointer outside the stack is not followed.
+        let (mut options, reader, logger) = walker.reconfigure();
+        options.stack_bounds(base..base + 4 * w);
+        let mut walker = options.build_with_reader_logger(reader, logger);
+        assert!(walk_one(&mut walker, &regs).is_ok());
+        assert!(walk_one(&mut walker, &caller).is_err());
+
+        // Nor is a misaligned one.
+        let misaligned = FrameRegisters::from_parts(
+            TaggedWord::valid(base + 2 * w + 1),
+            TaggedWord::valid(base),
+            TaggedWord::valid(0x3000),
+        );
+        assert!(walk_one(&mut walker, &misaligned).is_err());
+    }
+
+    #[test]
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86", target_arch = "aarch64"))]
+    fn observer_sees_fallbacks_and_failed_reads() {
+        use std::cell::Cell;
+
+        #[derive(Debug, Default)]
+        struct Counts {
+            fallbacks: Cell<usize>,
+            failed_reads: Cell<usize>,
+        }
+
+        impl UnwindObserver for Counts {
+            fn fallback_used(&self, _ip: usize, strategy: UnwindStrategy) {
+                assert_eq!(strategy, UnwindStrategy::FramePointer);
+                self.fallbacks.set(self.fallbacks.get() + 1);
+            }
+
+            fn read_failed(&self, _address: usize) {
+                self.failed_reads.set(self.failed_reads.get() + 1);
+            }
+        }
+
+        let w = mem::size_of::<usize>();
+        let base = 0x1000;
+        // One frame record, whose caller's frame pointer is outside the
+        // stack.
+        let bytes = stack(&[0x9000, 0x4000]);
+
+        let mut options = Options::new();
+        options.strategies(vec![UnwindStrategy::Manual, UnwindStrategy::FramePointer]);
+        let mut walker = options
+            .build_with_reader_logger(SliceMemory::new(base, &bytes), log::IgnoreLogs)
+            .with_observer(Counts::default());
+
+        let regs = FrameRegisters::from_parts(
+            TaggedWord::valid(base),
+            TaggedWord::valid(base - 2 * w),
+            TaggedWord::valid(0x3000),
+        );
+        let outcome = walker.walk(&regs, |_| ());
+        assert!(outcome.error().is_some());
+        assert_eq!(walker.observer().fallbacks.get(), 1);
+        assert!(walker.observer().failed_reads.get() > 0);
+    }
+
+    #[test]
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86", target_arch = "aarch64"))]
+    fn logger_hears_aborted_walks() {
+        #[derive(Debug, Default)]
+        struct Aborted(Vec<String>);
+
+        impl log::UnwindLogger for Aborted {
+            fn log(&mut self, event: &log::LogEvent) {
+                if let log::LogEvent::WalkAborted { error } = *event {
+                    self.0.push(error.to_string());
+                }
+            }
+        }
+
+        let w = mem::size_of::<usize>();
+        let base = 0x1000;
+        let mut options = Options::new();
+        options.strategies(vec![UnwindStrategy::FramePointer]);
+        let regs = FrameRegisters::from_parts(
+            TaggedWord::valid(base),
+            TaggedWord::valid(base - 2 * w),
+            TaggedWord::valid(0x3000),
+        );
+
+        // Reaching the outermost frame is not an aborted walk.
+        let bytes = stack(&[0, 0x4000]);
+        let mut walker = options
+            .clone()
+            .build_with_reader_logger(SliceMemory::new(base, &bytes), Aborted::default());
+        assert_eq!(walker.walk(&regs, |_| ()).frames_walked, 2);
+        assert_eq!(walker.frames(regs.clone()).count(), 2);
+        let (_, _, logger) = walker.reconfigure();
+        assert!(logger.0.is_empty());
+
+        // The caller's frame pointer is outside the stack.
+        let bytes = stack(&[0x9000, 0x4000]);
+        let mut walker =
+            options.build_with_reader_logger(SliceMemory::new(base, &bytes), Aborted::default());
+        assert_eq!(walker.walk(&regs, |_| ()).frames_walked, 2);
+        assert_eq!(walker.frames(regs).count(), 3);
+
+        let (_, _, logger) = walker.reconfigure();
+        let aborted = Error::InvalidAddress(0x9000 + w).to_string();
+        if log::Level::Debug.enabled() {
+            assert_eq!(logger.0, vec![aborted.clone(), aborted]);
+        } else {
+            assert!(logger.0.is_empty());
+        }
+    }
+
+    #[test]
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86", target_arch = "aarch64"))]
+    fn walk_carries_registers_forward() {
+        let w = mem::size_of::<usize>();
+        let base = 0x1000;
+        // A 3-deep call chain: each frame record holds its caller's frame
+        // pointer and the return address into its caller.
+        let bytes = stack(&[base + 2 * w, 0x4000, base + 4 * w, 0x5000, 0, 0x6000]);
+
+        let mut options = Options::new();
+        options.strategies(vec![UnwindStrategy::FramePointer]).max_frames(3);
+        let mut walker =
+            options.build_with_reader_logger(SliceMemory::new(base, &bytes), log::IgnoreLogs);
+
+        let regs = FrameRegisters::from_parts(
+            TaggedWord::valid(base),
+            TaggedWord::valid(base),
+            TaggedWord::valid(0x3000),
+        );
+        let mut ips = vec![];
+        walker
+            .walk(&regs, |frame| ips.push(frame.ip()))
+            .into_result()
+            .unwrap();
+        assert_eq!(
+            ips,
+            vec![
+                TaggedWord::valid(0x3000),
+                TaggedWord::valid(0x4000),
+                TaggedWord::valid(0x5000),
+            ]
+        );
+        let stats = walker.stats();
+        assert_eq!((stats.walks, stats.frames), (1, 3));
+        assert_eq!(stats.strategies.frame_pointer, 3);
+
+        let mut buffer = [0; 2];
+        assert_eq!(walker.capture_into(&regs, &mut buffer), 2);
+        assert_eq!(buffer, [0x3000, 0x4000]);
+        let mut capture = StackCapture::<4>::new();
+        walker.capture(&regs, &mut capture);
+        assert_eq!(capture.ips(), &[0x3000, 0x4000, 0x5000]);
+        assert!(!capture.is_truncated());
+        let mut capture = StackCapture::<2>::new();
+        walker.capture(&regs, &mut capture);
+        assert_eq!(capture.ips(), &[0x3000, 0x4000]);
+        assert!(capture.is_truncated());
+
+        // Preparing for fewer frames stops walks sooner.
+        walker.prepare(2, 16).unwrap();
+        ips.clear();
+        walker
+            .walk(&regs, |frame| ips.push(frame.ip()))
+            .into_result()
+            .unwrap();
+        assert_eq!(ips.len(), 2);
+    }
+
+    #[test]
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86", target_arch = "aarch64"))]
+    fn frames() {
+        let w = mem::size_of::<usize>();
+        let base = 0x1000;
+        let bytes = stack(&[base + 2 * w, 0x4000, 0, 0x5000]);
+
+        let mut options = Options::new();
+        options.strategies(vec![UnwindStrategy::FramePointer]);
+        let mut walker =
+            options.build_with_reader_logger(SliceMemory::new(base, &bytes), log::IgnoreLogs);
+
+        let regs = FrameRegisters::from_parts(
+            TaggedWord::valid(base),
+            TaggedWord::valid(base),
+            TaggedWord::valid(0x3000),
+        );
+        let frames: Vec<_> = walker.frames(regs.clone()).collect();
+        assert_eq!(frames.len(), 3);
+        let ips: Vec<_> = frames
+            .iter()
+            .map(|frame| frame.as_ref().unwrap().ip())
+            .collect();
+        assert_eq!(
+            ips,
+            vec![
+                TaggedWord::valid(0x3000),
+                TaggedWord::valid(0x4000),
+                TaggedWord::valid(0x5000),
+            ]
+        );
+
+        // Each frame's CFA is just past its frame record.
+        let first = frames[0].as_ref().unwrap();
+        assert_eq!(first.index(), 0);
+        assert_eq!(first.cfa(), Some(base + 2 * w));
+        assert_eq!(first.found_by(), None);
+        assert_eq!(first.strategy(), Some(UnwindStrategy::FramePointer));
+        assert!(first.is_heuristic());
+        assert!(first.module_bias().is_none());
+        assert!(first.unwind_entry().is_none());
+        assert_eq!(breakpad::FrameTrust::from(first), breakpad::FrameTrust::Context);
+        // The outermost frame saved a null frame pointer, so the walk ends
+        // there without an error, and nothing is known about its caller.
+        let last = frames[2].as_ref().unwrap();
+        assert_eq!(last.index(), 2);
+        assert_eq!(last.cfa(), None);
+        assert_eq!(last.found_by(), Some(UnwindStrategy::FramePointer));
+        assert_eq!(last.strategy(), None);
+        assert_eq!(breakpad::FrameTrust::from(last), breakpad::FrameTrust::FramePointer);
+
+        assert_eq!(walker.frames(regs).take(2).count(), 2);
+    }
+
+    #[test]
+    fn unwinder_is_send_and_sync() {
+        fn shareable<T: Send + Sync>() {}
+        shareable::<Unwinder<'static>>();
+        shareable::<JitRegistry>();
+    }
+
+    #[test]
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn add_module_from_file() {
+        // The file is only needed while it is added.
+        let mut options: Options<'static> = Options::new();
+        options
+            .add_module_from_file(::std::env::current_exe().unwrap(), Bias(0))
+            .unwrap();
+        assert!(options.entry_modules.is_empty());
+        assert_eq!(options.compiled.len(), 1);
+        assert!(!options.compiled[0].is_empty());
+    }
+
+    #[test]
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86", target_arch = "aarch64"))]
+    fn skip_frames() {
+        let w = mem::size_of::<usize>();
+        let base = 0x1000;
+        let bytes = stack(&[base + 2 * w, 0x4000, base + 4 * w, 0x5000, 0, 0x6000]);
+
+        let mut options = Options::new();
+        options
+            .strategies(vec![UnwindStrategy::FramePointer])
+            .skip_frames(1)
+            .max_frames(2);
+        let mut walker =
+            options.build_with_reader_logger(SliceMemory::new(base, &bytes), log::IgnoreLogs);
+
+        let regs = FrameRegisters::from_parts(
+            TaggedWord::valid(base),
+            TaggedWord::valid(base),
+            TaggedWord::valid(0x3000),
+        );
+        let expected = vec![TaggedWord::valid(0x4000), TaggedWord::valid(0x5000)];
+
+        let mut ips = vec![];
+        walker
+            .walk(&regs, |frame| ips.push(frame.ip()))
+            .into_result()
+            .unwrap();
+        assert_eq!(ips, expected);
+
+        let ips: Vec<_> = walker
+            .frames(regs)
+            .map(|frame| frame.unwrap().ip())
+            .collect();
+        assert_eq!(ips, expected);
+    }
+
+    #[test]
+    fn non_progressing_unwind() {
+        let w = mem::size_of::<usize>();
+        let base = 0x1000;
+        // A rule that recovers the frame it was given: the CFA is the stack
+        // pointer, where the frame's own address is saved.
+        let bytes = stack(&[0x3000, 0]);
+
+        let mut options = Options::new();
+        options
+            .strategies(vec![UnwindStrategy::Manual])
+            .add_manual_rule(
+                Avma(0x3000 as *const u8)..Avma(0x3100 as *const u8),
+                ManualRule::sp_offset(0, 0),
+            );
+        let mut walker =
+            options.build_with_reader_logger(SliceMemory::new(base, &bytes), log::IgnoreLogs);
+
+        let regs = FrameRegisters::from_parts(
+            TaggedWord::valid(base + w),
+            TaggedWord::valid(base),
+            TaggedWord::valid(0x3000),
+        );
+        let outcome = walker.walk(&regs, |_| ());
+        assert_eq!(outcome.frames_walked, 1);
+        match outcome.into_result() {
+            Err(Error::NonProgressingUnwind(0x3000)) => {}
+            otherwise => panic!("unexpected result: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    #[cfg(target_arch = "aarch64")]
+    fn leaf_function_returns_through_the_link_register() {
+        // Version 1, with a "zR" augmentation, code alignment 4, data
+        // alignment -8, the return address in x30, and absolute pointers.
+        let mut cie = vec![1, b'z', b'R', 0, 4, 0x78, 30, 1, 0];
+        // DW_CFA_def_cfa: sp + 0
+        cie.extend_from_slice(&[0x0c, 31, 0]);
+        let mut eh_frame = vec![];
+        eh_frame_entry(&mut eh_frame, true, &cie);
+        // A leaf function, with no rules for x29 or x30.
+        eh_frame_fde(&mut eh_frame, 0x1000, &[]);
+        // DW_CFA_def_cfa: x29 + 16, DW_CFA_offset: x29 at cfa - 16,
+        // DW_CFA_offset: x30 at cfa - 8
+        eh_frame_fde(&mut eh_frame, 0x2000, &[0x0c, 29, 16, 0x9d, 2, 0x9e, 1]);
+
+        let base = 0x1000;
+        // The leaf's caller's frame record.
+        let bytes = stack(&[0, 0x4000]);
+        let mut regs = FrameRegisters::from_parts(
+            TaggedWord::valid(base),
+            TaggedWord::valid(base),
+            TaggedWord::valid(0x1010),
+        );
+        regs.set_lr(TaggedWord::valid(0x2010));
+
+        let mut options = Options::new();
+        options
+            .strategies(vec![UnwindStrategy::Dwarf])
+            .add_entries_from_eh_frame(
+                Bias(0),
+                gimli::BaseAddresses::default(),
+                TargetEhFrame::new(&eh_frame, gimli::RunTimeEndian::default()),
+            )
+            .unwrap();
+        for &compiled in &[false, true] {
+            if compiled {
+                options.compile_unwind_tables().unwrap();
+            }
+            let mut walker = options
+                .clone()
+                .build_with_reader_logger(SliceMemory::new(base, &bytes), log::IgnoreLogs);
+            let ips: Vec<_> = walker
+                .frames(regs.clone())
+                .take_while(|frame| frame.is_ok())
+                .map(|frame| frame.unwrap().ip())
+                .collect();
+            assert_eq!(
+                ips,
+                [0x1010, 0x2010, 0x4000]
+                    .iter()
+                    .map(|&ip| TaggedWord::valid(ip))
+                    .collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(any(target_arch = "x86_64", target_arch = "x86", target_arch = "aarch64"))]
+    fn walk_outcome() {
+        let w = mem::size_of::<usize>();
+        let base = 0x1000;
+        let bytes = stack(&[base + 2 * w, 0x4000, 0, 0x5000]);
+
+        let mut options = Options::new();
+        options.strategies(vec![UnwindStrategy::FramePointer]);
+        let mut walker =
+            options.build_with_reader_logger(SliceMemory::new(base, &bytes), log::IgnoreLogs);
+
+        let regs = FrameRegisters::from_parts(
+            TaggedWord::valid(base),
+            TaggedWord::valid(base),
+            TaggedWord::valid(0x3000),
+        );
+
+        let outcome = walker.walk(&regs, |frame| Some(frame.ip()));
+        assert_eq!(outcome.frames_walked, 3);
+        assert_eq!(outcome.last, Some(Some(TaggedWord::valid(0x5000))));
+        match outcome.stopped_because {
+            WalkStop::End => {}
+            otherwise => panic!("unexpected stop: {:?}", otherwise),
+        }
+
+        let outcome = walker.walk(&regs, |frame| {
+            if frame.index() == 1 {
+                StackWalkControl::Break
+            } else {
+                StackWalkControl::Continue
+            }
+        });
+        assert_eq!(outcome.frames_walked, 2);
+        match outcome.stopped_because {
+            WalkStop::Break => {}
+            otherwise => panic!("unexpected stop: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    #[cfg(all(target_arch = "x86_64", any(target_os = "linux", target_os = "android")))]
+    fn sigframe() {
+        let base = 0x1000;
+        // The `struct ucontext` at the trampoline's stack pointer, with the
+        // interrupted frame's rbp, rsp, and rip in its `uc_mcontext`.
+        let mut words = vec![0; 5 + 17];
+        words[5 + 10] = 0x9010;
+        words[5 + 15] = 0x9000;
+        words[5 + 16] = 0x3000;
+        let bytes = stack(&words);
+        let reader = SliceMemory::new(base, &bytes);
+
+        let trampoline = FrameRegisters::from_parts(
+            TaggedWord::invalid(),
+            TaggedWord::valid(base),
+            TaggedWord::valid(0x2000),
+        );
+        let interrupted = unsafe { FrameRegisters::from_sigframe(&trampoline, &reader) }
+            .unwrap()
+            .unwrap();
+        assert_eq!(interrupted.bp(), TaggedWord::valid(0x9010));
+        assert_eq!(interrupted.sp(), TaggedWord::valid(0x9000));
+        assert_eq!(interrupted.ip(), TaggedWord::valid(0x3000));
+
+        // The handler may have run on an alternate signal stack above the
+        // interrupted frame's.
+        let below = FrameRegisters::from_parts(
+            TaggedWord::invalid(),
+            TaggedWord::valid(0x100),
+            TaggedWord::valid(0x3000),
+        );
+        assert!(check_progress(0x2000, &trampoline, &below, false).is_err());
+        assert!(check_progress(0x2000, &trampoline, &below, true).is_ok());
+        assert!(check_progress(0x2000, &trampoline, &trampoline, true).is_err());
+    }
+
+    #[test]
+    fn remove_entries_in_range() {
+        let w = mem::size_of::<usize>();
+        let base = 0x1000;
+        let bytes = stack(&[0x5000]);
+        let avma = |addr: usize| Avma(addr as *const u8);
+        let rule = ManualRule::sp_offset(w as isize, -(w as isize));
+        let regs = |ip| {
+            FrameRegisters::from_parts(
+                TaggedWord::invalid(),
+                TaggedWord::valid(base),
+                TaggedWord::valid(ip),
+            )
+        };
+
+        let mut options = Options::new();
+        options
+            .strategies(vec![UnwindStrategy::Manual])
+            .add_manual_rule(avma(0x2000)..avma(0x2100), rule.clone())
+            .add_manual_rule(avma(0x3000)..avma(0x3100), rule);
+        let mut walker =
+            options.build_with_reader_logger(SliceMemory::new(base, &bytes), log::IgnoreLogs);
+        assert!(walk_one(&mut walker, &regs(0x2000)).is_ok());
+
+        walker.remove_entries_in_range(avma(0x2000)..avma(0x3000));
+        match walk_one(&mut walker, &regs(0x2000)) {
+            Err(Error::NoUnwindInfoForAddress(0x2000)) => {}
+            otherwise => panic!("unexpected result: {:?}", otherwise),
+        }
+        assert!(walk_one(&mut walker, &regs(0x3000)).is_ok());
+
+        let (mut options, _, _) = walker.reconfigure();
+        assert_eq!(options.manual.len(), 1);
+        options.remove_entries_in_range(avma(0x3080)..avma(0x4000));
+        assert!(options.manual.is_empty());
+    }
+
+    #[test]
+    fn return_addresses_are_looked_up_before_the_call() {
+        let w = mem::size_of::<usize>();
+        let base = 0x1000;
+        let bytes = stack(&[0x3100, 0x5000]);
+        let avma = |addr: usize| Avma(addr as *const u8);
+        // The return address pops off the top of the stack.
+        let rule = ManualRule::sp_offset(w as isize, -(w as isize));
+
+        let mut options = Options::new();
+        options
+            .strategies(vec![UnwindStrategy::Manual])
+            .max_frames(3)
+            .add_manual_rule(avma(0x2000)..avma(0x2100), rule.clone())
+            // The call returning to 0x3100 is this function's last
+            // instruction.
+            .add_manual_rule(avma(0x3000)..avma(0x3100), rule);
+        let mut walker =
+            options.build_with_reader_logger(SliceMemory::new(base, &bytes), log::IgnoreLogs);
+
+        let regs = FrameRegisters::from_parts(
+            TaggedWord::invalid(),
+            TaggedWord::valid(base),
+            TaggedWord::valid(0x2000),
+        );
+        let mut frames = walker.frames(regs);
+        let frame = frames.next().unwrap().unwrap();
+        assert_eq!(frame.ip(), TaggedWord::valid(0x2000));
+        assert_eq!(frame.pc_kind(), PcKind::Interrupted);
+        let frame = frames.next().unwrap().unwrap();
+        assert_eq!(frame.ip(), TaggedWord::valid(0x3100));
+        assert_eq!(frame.pc_kind(), PcKind::ReturnAddress);
+        assert_eq!(frames.next().unwrap().unwrap().ip(), TaggedWord::valid(0x5000));
+        assert!(frames.next().is_none());
+    }
+
+    #[test]
+    fn walks_end_at_a_null_return_address() {
+        let w = mem::size_of::<usize>();
+        let base = 0x1000;
+        let bytes = stack(&[0x3100, 0]);
+        let avma = |addr: usize| Avma(addr as *const u8);
+        let rule = ManualRule::sp_offset(w as isize, -(w as isize));
+
+        let mut options = Options::new();
+        options
+            .strategies(vec![UnwindStrategy::Manual])
+            .add_manual_rule(avma(0x2000)..avma(0x2100), rule.clone())
+            .add_manual_rule(avma(0x3000)..avma(0x3100), rule);
+        let mut walker =
+            options.build_with_reader_logger(SliceMemory::new(base, &bytes), log::IgnoreLogs);
+
+        let regs = FrameRegisters::from_parts(
+            TaggedWord::invalid(),
+            TaggedWord::valid(base),
+            TaggedWord::valid(0x2000),
+        );
+        let outcome = walker.walk(&regs, |frame| Some(frame.ip()));
+        assert_eq!(outcome.frames_walked, 2);
+        assert_eq!(outcome.last, Some(Some(TaggedWord::valid(0x3100))));
+        match outcome.stopped_because {
+            WalkStop::End => {}
+            otherwise => panic!("unexpected stop: {:?}", otherwise),
         }
 
-        loop {
-            registers = unsafe { self.walk_one(start_registers)? };
-            result = f(&registers);
-            if result.as_stack_walk_control() == StackWalkControl::Break {
-                return Ok(result);
+        // The outermost frame was walked, so it knows its unwind entry.
+        let frames: Vec<_> = walker.frames(regs).collect();
+        assert_eq!(frames.len(), 2);
+        let outermost = frames[1].as_ref().unwrap();
+        assert_eq!(outermost.strategy(), Some(UnwindStrategy::Manual));
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn frameless_function_between_frame_pointer_frames() {
+        // Version 1, with a "zR" augmentation, code alignment 1, data
+        // alignment -8, the return address in register 16, and absolute
+        // pointers.
+        let mut cie = vec![1, b'z', b'R', 0, 1, 0x78, 16, 1, 0];
+        // DW_CFA_def_cfa: rsp + 8, DW_CFA_offset: rip at cfa - 8
+        cie.extend_from_slice(&[0x0c, 7, 8, 0x90, 1]);
+        let mut eh_frame = vec![];
+        eh_frame_entry(&mut eh_frame, true, &cie);
+        // DW_CFA_def_cfa: rbp + 16, DW_CFA_offset: rbp at cfa - 16
+        let frame_pointer = [0x0c, 6, 16, 0x86, 2];
+        eh_frame_fde(&mut eh_frame, 0x1000, &frame_pointer);
+        // DW_CFA_def_cfa_offset: 16, leaving rbp alone.
+        eh_frame_fde(&mut eh_frame, 0x2000, &[0x0e, 16]);
+        eh_frame_fde(&mut eh_frame, 0x3000, &frame_pointer);
+
+        let base = 0x1000;
+        // The frameless function's frame is the two words between the frame
+        // records of the functions at 0x1000 and 0x3000.
+        let bytes = stack(&[base + 32, 0x2010, 0xdead, 0x3010, 0, 0x4000]);
+        let regs = FrameRegisters::from_parts(
+            TaggedWord::valid(base),
+            TaggedWord::valid(base),
+            TaggedWord::valid(0x1010),
+        );
+
+        let mut options = Options::new();
+        options
+            .strategies(vec![UnwindStrategy::Dwarf])
+            .add_entries_from_eh_frame(
+                Bias(0),
+                gimli::BaseAddresses::default(),
+                TargetEhFrame::new(&eh_frame, gimli::RunTimeEndian::default()),
+            )
+            .unwrap();
+        for &compiled in &[false, true] {
+            if compiled {
+                options.compile_unwind_tables().unwrap();
             }
+            let mut walker = options
+                .clone()
+                .build_with_reader_logger(SliceMemory::new(base, &bytes), log::IgnoreLogs);
+            let ips: Vec<_> = walker
+                .frames(regs.clone())
+                .take_while(|frame| frame.is_ok())
+                .map(|frame| frame.unwrap().ip())
+                .collect();
+            assert_eq!(
+                ips,
+                [0x1010, 0x2010, 0x3010, 0x4000]
+                    .iter()
+                    .map(|&ip| TaggedWord::valid(ip))
+                    .collect::<Vec<_>>()
+            );
         }
     }
 }
@@ -1,7 +1,27 @@
 //! The definition and implementations of `UnwindLogger`.
+//!
+//! Loggers are told what happens as `LogEvent`s, to filter and aggregate as
+//! they like. Every `Write`r is also a logger, which writes each event as a
+//! line of text.
+//!
+//! Events less severe than `MAX_LEVEL`, which only the `log-level-*`
+//! features set, are compiled out, in debug and release builds alike. With
+//! `log-level-off`, walks have no logging branches at all; without any of
+//! the features, everything is logged.
 
-use std::fmt::Debug;
+use super::{Avma, Bias, UnwindStrategy};
+use error::Error;
+#[cfg(feature = "log")]
+use log_crate;
+use observer::UnwindObserver;
+use ring::Ring;
+use std::cell::RefCell;
+use std::cmp;
+use std::env;
+use std::ffi::OsStr;
+use std::fmt::{self, Debug};
 use std::io::{self, Write};
+use std::ops::Range;
 
 #[allow(unused_macros)]
 macro_rules! log {
@@ -15,10 +35,236 @@ macro_rules! log {
     };
 }
 
-/// TODO FITZGEN
-pub trait UnwindLogger: Debug + Write + Sized {}
+/// Something that happened while building a `Walker` or walking with it.
+#[derive(Clone, Debug)]
+pub enum LogEvent<'e> {
+    /// The `Walker` was built with DWARF call frame information for the
+    /// module with the given bias, with `fdes` FDEs, or `None` if they are
+    /// looked up lazily through its `.eh_frame_hdr`.
+    ModuleScanned {
+        /// The module's bias.
+        bias: Bias,
+        /// The module's name, if it was found by
+        /// `Options::find_eh_frame_entries`.
+        name: Option<&'e str>,
+        /// How many FDEs the module has, if they were all parsed.
+        fdes: Option<usize>,
+    },
 
-/// TODO FITZGEN
+    /// The `.eh_frame` of the module with the given bias, found by
+    /// `Options::find_eh_frame_entries`, could not be parsed, so the `Walker`
+    /// was built without it.
+    ModuleSkipped {
+        /// The module's bias.
+        bias: Bias,
+        /// The module's name.
+        name: Option<&'e str>,
+        /// Why its `.eh_frame` could not be parsed.
+        error: &'e Error,
+    },
+
+    /// The FDE covering `range` was found for the frame at `ip`.
+    FdeMatched {
+        /// The frame's instruction pointer.
+        ip: usize,
+        /// The addresses the FDE covers.
+        range: Range<Avma>,
+    },
+
+    /// The rules for walking the frame at `ip` were found, in the unwind
+    /// context's row cache if `cached`, or by running its FDE's call frame
+    /// instructions.
+    RuleEvaluated {
+        /// The frame's instruction pointer.
+        ip: usize,
+        /// Whether the rules were cached.
+        cached: bool,
+    },
+
+    /// A frame could not be walked to its caller, which ended the walk
+    /// before the outermost frame.
+    WalkAborted {
+        /// Why not.
+        error: &'e Error,
+    },
+}
+
+impl<'e> fmt::Display for LogEvent<'e> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LogEvent::ModuleScanned { bias, name, fdes } => {
+                write!(f, "Scanned module ")?;
+                if let Some(name) = name {
+                    write!(f, "{} ", name)?;
+                }
+                match fdes {
+                    Some(fdes) => write!(f, "with bias {}: {} FDEs", bias, fdes),
+                    None => write!(f, "with bias {}: FDEs looked up in .eh_frame_hdr", bias),
+                }
+            }
+            LogEvent::ModuleSkipped { bias, name, error } => {
+                write!(f, "Skipped module ")?;
+                if let Some(name) = name {
+                    write!(f, "{} ", name)?;
+                }
+                write!(f, "with bias {}: {}", bias, error)
+            }
+            LogEvent::FdeMatched { ip, ref range } => write!(
+                f,
+                "{:#x}: matched FDE for {}..{}",
+                ip, range.start, range.end
+            ),
+            LogEvent::RuleEvaluated { ip, cached: true } => {
+                write!(f, "{:#x}: found unwind rules in the row cache", ip)
+            }
+            LogEvent::RuleEvaluated { ip, cached: false } => {
+                write!(f, "{:#x}: evaluated unwind rules", ip)
+            }
+            LogEvent::WalkAborted { error } => write!(f, "Walk aborted: {}", error),
+        }
+    }
+}
+
+impl<'e> LogEvent<'e> {
+    /// Get how severe this event is.
+    pub fn level(&self) -> Level {
+        match *self {
+            LogEvent::ModuleSkipped { .. } => Level::Warn,
+            LogEvent::ModuleScanned { .. } => Level::Info,
+            LogEvent::WalkAborted { .. } => Level::Debug,
+            LogEvent::FdeMatched { .. } | LogEvent::RuleEvaluated { .. } => Level::Trace,
+        }
+    }
+}
+
+/// How severe a `LogEvent` is, from the most severe to the most verbose.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    /// Something failed.
+    Error,
+    /// Something may be wrong.
+    Warn,
+    /// Setup progress.
+    Info,
+    /// What each walk did as a whole.
+    Debug,
+    /// What each frame of each walk did.
+    Trace,
+}
+
+impl Level {
+    /// Is logging events of this level compiled in, given `MAX_LEVEL`?
+    pub const fn enabled(self) -> bool {
+        match MAX_LEVEL {
+            Some(max) => self as u8 <= max as u8,
+            None => false,
+        }
+    }
+}
+
+/// The most verbose level of events that are logged, or `None` if none are.
+///
+/// It is `Level::Trace` unless one of the `log-level-off`,
+/// `log-level-error`, or `log-level-debug` features is enabled. When several
+/// are, the least verbose wins.
+pub const MAX_LEVEL: Option<Level> = if cfg!(feature = "log-level-off") {
+    None
+} else if cfg!(feature = "log-level-error") {
+    Some(Level::Error)
+} else if cfg!(feature = "log-level-debug") {
+    Some(Level::Debug)
+} else {
+    Some(Level::Trace)
+};
+
+/// A logger for what building a `Walker` and walking with it does.
+///
+/// Walks may happen in signal handlers, so loggers that walks log to must not
+/// allocate or take locks, like `RingBufferLogger`.
+pub trait UnwindLogger: Debug {
+    /// Log the given event.
+    fn log(&mut self, event: &LogEvent);
+}
+
+/// Every `Write`r logs each event as a line of text.
+impl<T> UnwindLogger for T
+where
+    T: Debug + Write,
+{
+    fn log(&mut self, event: &LogEvent) {
+        let _ = writeln!(self, "{}", event);
+    }
+}
+
+/// An `UnwindObserver` that logs what a walk does, and tells another
+/// observer as well. `Walker`s walk with one, so that their logger hears
+/// about every walk.
+#[derive(Debug)]
+pub struct LogEvents<'l, Observer, Logger>
+where
+    Observer: 'l + UnwindObserver,
+    Logger: 'l + UnwindLogger,
+{
+    observer: &'l Observer,
+    logger: RefCell<&'l mut Logger>,
+}
+
+impl<'l, Observer, Logger> LogEvents<'l, Observer, Logger>
+where
+    Observer: UnwindObserver,
+    Logger: UnwindLogger,
+{
+    pub(crate) fn new(observer: &'l Observer, logger: &'l mut Logger) -> Self {
+        LogEvents {
+            observer,
+            logger: RefCell::new(logger),
+        }
+    }
+
+    fn log(&self, event: &LogEvent) {
+        self.logger.borrow_mut().log(event);
+    }
+}
+
+impl<'l, Observer, Logger> UnwindObserver for LogEvents<'l, Observer, Logger>
+where
+    Observer: UnwindObserver,
+    Logger: UnwindLogger,
+{
+    fn fde_found(&self, ip: usize, fde: Range<Avma>) {
+        if Level::Trace.enabled() {
+            self.log(&LogEvent::FdeMatched {
+                ip,
+                range: fde.clone(),
+            });
+        }
+        self.observer.fde_found(ip, fde);
+    }
+
+    fn row_resolved(&self, ip: usize, cached: bool) {
+        if Level::Trace.enabled() {
+            self.log(&LogEvent::RuleEvaluated { ip, cached });
+        }
+        self.observer.row_resolved(ip, cached);
+    }
+
+    fn fallback_used(&self, ip: usize, strategy: UnwindStrategy) {
+        self.observer.fallback_used(ip, strategy);
+    }
+
+    fn read_failed(&self, address: usize) {
+        self.observer.read_failed(address);
+    }
+
+    fn walk_aborted(&self, error: &Error) {
+        if Level::Debug.enabled() {
+            self.log(&LogEvent::WalkAborted { error });
+        }
+        self.observer.walk_aborted(error);
+    }
+}
+
+/// An `UnwindLogger` that ignores every event.
 #[derive(Debug)]
 pub struct IgnoreLogs;
 
@@ -34,10 +280,251 @@ impl Write for IgnoreLogs {
     }
 }
 
-impl<T> UnwindLogger for T
-where
-    T: Debug + Write + Sized,
-{
+/// An `UnwindLogger` that writes to stderr when the `PANCAKES_LOG`
+/// environment variable is set to anything other than `0` or the empty
+/// string, and ignores logs otherwise.
+///
+/// On Unix it writes straight to the file descriptor, without locking, so
+/// walks from signal handlers may log to it, though lines written by
+/// different threads may interleave.
+#[derive(Debug)]
+pub struct EnvLogger {
+    enabled: bool,
+}
+
+impl EnvLogger {
+    /// Construct a new `EnvLogger`, checking `PANCAKES_LOG` once now.
+    pub fn from_env() -> EnvLogger {
+        EnvLogger {
+            enabled: is_enabled(env::var_os("PANCAKES_LOG").as_ref().map(|v| v.as_os_str())),
+        }
+    }
+
+    /// Is this logger writing logs?
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+fn is_enabled(value: Option<&OsStr>) -> bool {
+    match value {
+        None => false,
+        Some(v) => !v.is_empty() && v != "0",
+    }
+}
+
+impl Write for EnvLogger {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.enabled {
+            write_stderr(buf)
+        } else {
+            Ok(buf.len())
+        }
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        // Nothing is buffered.
+        Ok(())
+    }
+}
+
+/// Write to stderr without taking `io::stderr()`'s lock.
+#[cfg(unix)]
+fn write_stderr(buf: &[u8]) -> io::Result<usize> {
+    let n = unsafe {
+        ::libc::write(
+            ::libc::STDERR_FILENO,
+            buf.as_ptr() as *const ::libc::c_void,
+            buf.len(),
+        )
+    };
+    if n < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(n as usize)
+    }
+}
+
+/// Elsewhere, `io::stderr()` is used, so walks from signal handlers must not
+/// log with an `EnvLogger`.
+#[cfg(not(unix))]
+fn write_stderr(buf: &[u8]) -> io::Result<usize> {
+    io::stderr().write(buf)
+}
+
+/// An `UnwindLogger` that logs each event with the `log` crate, with the
+/// target `pancakes`, so that pancakes' logs go wherever the application's
+/// other logs do, e.g. through `env_logger` or `fern`.
+///
+/// Each event is logged at the `log` level of the same name as its own. `log`
+/// implementations may allocate or take locks, so walks from signal handlers
+/// must not log with this logger. Use a `RingBufferLogger` there.
+#[cfg(feature = "log")]
+#[derive(Debug, Default)]
+pub struct LogCrateLogger;
+
+#[cfg(feature = "log")]
+impl UnwindLogger for LogCrateLogger {
+    fn log(&mut self, event: &LogEvent) {
+        let level = match event.level() {
+            Level::Error => log_crate::Level::Error,
+            Level::Warn => log_crate::Level::Warn,
+            Level::Info => log_crate::Level::Info,
+            Level::Debug => log_crate::Level::Debug,
+            Level::Trace => log_crate::Level::Trace,
+        };
+        log_crate::log!(target: "pancakes", level, "{}", event);
+    }
+}
+
+/// The most bytes of each line a `RingBufferLogger` keeps. The rest of a
+/// longer line is dropped.
+pub const MAX_LINE_BYTES: usize = 240;
+
+/// A line in a `LogRing`.
+#[derive(Clone, Copy)]
+struct Line {
+    len: usize,
+    bytes: [u8; MAX_LINE_BYTES],
+}
+
+/// A fixed number of lines logged by `RingBufferLogger`s, to drain on a
+/// normal thread.
+///
+/// The ring is a lock-free bounded queue, so lines may be written from signal
+/// handlers, even ones interrupting another write or a drain, while a normal
+/// thread drains it. Its slots are allocated up front, in `new`. When every
+/// slot is full, new lines are dropped and counted by `dropped`.
+///
+/// ```
+/// use pancakes::log::{LogRing, RingBufferLogger};
+/// use std::io::Write;
+///
+/// let ring = LogRing::new(64);
+/// let mut logger = RingBufferLogger::new(&ring);
+/// writeln!(logger, "Walked {} frames", 3).unwrap();
+///
+/// ring.drain(|line| println!("{}", String::from_utf8_lossy(line)));
+/// ```
+pub struct LogRing {
+    lines: Ring<Line>,
+}
+
+impl LogRing {
+    /// Construct a new ring with room for the given number of lines, which
+    /// must be at least one.
+    pub fn new(lines: usize) -> LogRing {
+        let empty = Line {
+            len: 0,
+            bytes: [0; MAX_LINE_BYTES],
+        };
+        LogRing {
+            lines: Ring::new(lines, empty),
+        }
+    }
+
+    /// Add a line, or drop it if the ring is full.
+    fn push(&self, line: &[u8]) {
+        let mut copy = Line {
+            len: cmp::min(line.len(), MAX_LINE_BYTES),
+            bytes: [0; MAX_LINE_BYTES],
+        };
+        copy.bytes[..copy.len].copy_from_slice(&line[..copy.len]);
+        self.lines.push(&copy);
+    }
+
+    /// Call `f` with each finished line, oldest first, without its newline,
+    /// and remove it from the ring. Returns how many lines there were.
+    ///
+    /// Lines that are still being written when the drain reaches them are
+    /// left for the next drain, along with every line after them.
+    pub fn drain<F>(&self, mut f: F) -> usize
+    where
+        F: FnMut(&[u8]),
+    {
+        let mut drained = 0;
+        while let Some(line) = self.lines.pop() {
+            f(&line.bytes[..line.len]);
+            drained += 1;
+        }
+        drained
+    }
+
+    /// Get how many lines were dropped because the ring was full.
+    pub fn dropped(&self) -> usize {
+        self.lines.dropped()
+    }
+}
+
+impl Debug for LogRing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LogRing")
+            .field("lines", &self.lines.capacity())
+            .field("dropped", &self.dropped())
+            .finish()
+    }
+}
+
+/// An `UnwindLogger` that can be written from signal handlers: it collects
+/// each line in a fixed buffer of its own, then adds it to a `LogRing`,
+/// without allocating or taking locks.
+///
+/// Lines are added to the ring when they end, or on `flush`. Only the first
+/// `MAX_LINE_BYTES` of each are kept.
+pub struct RingBufferLogger<'r> {
+    ring: &'r LogRing,
+    line: [u8; MAX_LINE_BYTES],
+    len: usize,
+}
+
+impl<'r> RingBufferLogger<'r> {
+    /// Construct a new `RingBufferLogger` that adds its lines to the given
+    /// ring.
+    pub fn new(ring: &'r LogRing) -> RingBufferLogger<'r> {
+        RingBufferLogger {
+            ring,
+            line: [0; MAX_LINE_BYTES],
+            len: 0,
+        }
+    }
+
+    /// Add the buffered line to the ring, if there is one.
+    fn emit(&mut self) {
+        if self.len > 0 {
+            self.ring.push(&self.line[..self.len]);
+            self.len = 0;
+        }
+    }
+}
+
+impl<'r> Write for RingBufferLogger<'r> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &b in buf {
+            if b == b'\n' {
+                self.emit();
+            } else if self.len < MAX_LINE_BYTES {
+                self.line[self.len] = b;
+                self.len += 1;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.emit();
+        Ok(())
+    }
+}
+
+impl<'r> Debug for RingBufferLogger<'r> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RingBufferLogger")
+            .field("ring", self.ring)
+            .field("pending", &String::from_utf8_lossy(&self.line[..self.len]))
+            .finish()
+    }
 }
 
 #[cfg(test)]
@@ -49,4 +536,139 @@ mod tests {
         let mut logger = IgnoreLogs;
         log!(&mut logger, "Wow! {}", 42);
     }
+
+    #[test]
+    fn env_logger_enabled() {
+        assert!(!is_enabled(None));
+        assert!(!is_enabled(Some(OsStr::new(""))));
+        assert!(!is_enabled(Some(OsStr::new("0"))));
+        assert!(is_enabled(Some(OsStr::new("1"))));
+        assert!(is_enabled(Some(OsStr::new("trace"))));
+    }
+
+    fn drain(ring: &LogRing) -> Vec<String> {
+        let mut lines = vec![];
+        ring.drain(|line| lines.push(String::from_utf8_lossy(line).into_owned()));
+        lines
+    }
+
+    #[test]
+    fn ring_buffer_logger() {
+        let ring = LogRing::new(2);
+        let mut logger = RingBufferLogger::new(&ring);
+        log!(&mut logger, "Wow! {}", 42);
+        write!(&mut logger, "one\ntw").unwrap();
+        assert_eq!(drain(&ring), vec!["Wow! 42", "one"]);
+
+        // The ring wraps around, and drops lines when it is full.
+        log!(&mut logger, "o");
+        log!(&mut logger, "three");
+        log!(&mut logger, "four");
+        assert_eq!(ring.dropped(), 1);
+        assert_eq!(drain(&ring), vec!["two", "three"]);
+        assert!(drain(&ring).is_empty());
+
+        // Long lines are truncated.
+        log!(&mut logger, "{:1$}", "", MAX_LINE_BYTES + 10);
+        logger.flush().unwrap();
+        assert_eq!(drain(&ring), vec![" ".repeat(MAX_LINE_BYTES)]);
+    }
+
+    #[test]
+    fn levels() {
+        assert!(Level::Error < Level::Trace);
+        match MAX_LEVEL {
+            Some(max) => {
+                assert!(max.enabled());
+                assert!(Level::Error.enabled());
+            }
+            None => assert!(!Level::Error.enabled()),
+        }
+        if cfg!(not(any(
+            feature = "log-level-off",
+            feature = "log-level-error",
+            feature = "log-level-debug"
+        ))) {
+            assert!(Level::Trace.enabled());
+        }
+    }
+
+    #[test]
+    fn writers_log_lines() {
+        let mut logged = vec![];
+        logged.log(&LogEvent::RuleEvaluated {
+            ip: 0x1234,
+            cached: false,
+        });
+        logged.log(&LogEvent::FdeMatched {
+            ip: 0x1234,
+            range: Avma(0x1000 as *const u8)..Avma(0x2000 as *const u8),
+        });
+        assert_eq!(
+            String::from_utf8(logged).unwrap(),
+            "0x1234: evaluated unwind rules\n\
+             0x1234: matched FDE for 0x1000..0x2000\n"
+        );
+    }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn log_crate_logger() {
+        use log_crate::{Level, LevelFilter, Log, Metadata, Record};
+        use std::sync::Mutex;
+
+        struct Capture(Mutex<Vec<(Level, String)>>);
+
+        impl Log for Capture {
+            fn enabled(&self, metadata: &Metadata) -> bool {
+                metadata.target() == "pancakes"
+            }
+
+            fn log(&self, record: &Record) {
+                let line = record.args().to_string();
+                self.0.lock().unwrap().push((record.level(), line));
+            }
+
+            fn flush(&self) {}
+        }
+
+        static CAPTURE: Capture = Capture(Mutex::new(Vec::new()));
+        log_crate::set_logger(&CAPTURE).unwrap();
+        log_crate::set_max_level(LevelFilter::Debug);
+
+        let mut logger = LogCrateLogger;
+        let error = Error::NoUnwindInfoForAddress(0x1234);
+        logger.log(&LogEvent::ModuleScanned {
+            bias: Bias(0x1000),
+            name: None,
+            fdes: Some(3),
+        });
+        logger.log(&LogEvent::ModuleSkipped {
+            bias: Bias(0x2000),
+            name: Some("libfoo.so"),
+            error: &error,
+        });
+        logger.log(&LogEvent::WalkAborted { error: &error });
+        // Filtered out by the maximum level.
+        logger.log(&LogEvent::RuleEvaluated {
+            ip: 0x1234,
+            cached: true,
+        });
+
+        let captured = CAPTURE.0.lock().unwrap();
+        assert_eq!(
+            *captured,
+            vec![
+                (
+                    Level::Info,
+                    "Scanned module with bias 0x1000: 3 FDEs".to_string(),
+                ),
+                (
+                    Level::Warn,
+                    format!("Skipped module libfoo.so with bias 0x2000: {}", error),
+                ),
+                (Level::Debug, format!("Walk aborted: {}", error)),
+            ]
+        );
+    }
 }
@@ -0,0 +1,225 @@
+//! Architecture specific concerns for LoongArch64 registers.
+
+use super::{Error, MemoryReader, Registers, Result, TaggedWord, TargetEndianBuf};
+use expression;
+#[cfg(feature = "live")]
+use ffi;
+use gimli;
+#[cfg(feature = "live")]
+use std::io;
+#[cfg(feature = "live")]
+use std::mem;
+
+// From the LoongArch ELF psABI, the DWARF register numbers are the general
+// purpose register numbers:
+//
+// > ...
+// > 1        $ra      (return address)
+// > 3        $sp      (stack pointer)
+// > ...
+// > 22       $fp      (frame pointer, also known as $s9)
+// > ...
+//
+// The CIE's return address column is `$ra`, so the value recovered for it is
+// the caller's program counter.
+pub(crate) const BP: u8 = 22;
+pub(crate) const LR: u8 = 1;
+pub(crate) const SP: u8 = 3;
+pub(crate) const IP: u8 = LR;
+
+/// LoongArch has no pointer authentication, so return addresses are never
+/// signed.
+pub(crate) const POINTER_AUTH_MASK: usize = 0;
+
+/// The registers needed to unwind a frame on LoongArch64.
+#[derive(Clone, Debug)]
+pub struct FrameRegisters {
+    /// The `$fp` frame pointer register for this frame.
+    fp: TaggedWord,
+
+    /// The `$sp` stack pointer register for this frame.
+    sp: TaggedWord,
+
+    /// The `$ra` return address register for this frame. This is only known
+    /// for the youngest frame, and is needed to unwind out of leaf functions
+    /// that never spill it to the stack.
+    lr: TaggedWord,
+
+    /// The program counter for this frame.
+    pc: TaggedWord,
+}
+
+impl FrameRegisters {
+    /// The name Breakpad symbol files use for the frame base register.
+    pub(crate) const BREAKPAD_BP: &'static str = "$fp";
+
+    /// Construct a register set from its individual registers.
+    pub(crate) fn from_parts(bp: TaggedWord, sp: TaggedWord, ip: TaggedWord) -> FrameRegisters {
+        FrameRegisters {
+            fp: bp,
+            sp,
+            lr: TaggedWord::invalid(),
+            pc: ip,
+        }
+    }
+
+    /// Get the register with the given Breakpad name, e.g. `$sp`.
+    pub(crate) fn breakpad_register(&self, name: &str) -> TaggedWord {
+        match name {
+            "$fp" | "$s9" => self.fp,
+            "$ra" => self.lr,
+            "$sp" => self.sp,
+            "$pc" => self.pc,
+            _ => TaggedWord::invalid(),
+        }
+    }
+
+    /// Construct a register set from a `ucontext_t`, such as the one the
+    /// kernel passes to an `SA_SIGINFO` signal handler.
+    ///
+    /// This is unsafe because `ctx` must point to a valid `ucontext_t`.
+    #[cfg(feature = "live")]
+    pub unsafe fn from_ucontext(ctx: *const ffi::ucontext_t) -> FrameRegisters {
+        let mcontext = &(*ctx).uc_mcontext;
+        FrameRegisters {
+            fp: TaggedWord::valid(mcontext.__gregs[BP as usize] as usize),
+            sp: TaggedWord::valid(mcontext.__gregs[SP as usize] as usize),
+            lr: TaggedWord::valid(mcontext.__gregs[LR as usize] as usize),
+            pc: TaggedWord::valid(mcontext.__pc as usize),
+        }
+    }
+
+    pub(crate) fn get_register(&self, register_num: u8) -> Result<TaggedWord> {
+        match register_num {
+            r if r == BP => Ok(self.fp),
+            r if r == LR => Ok(self.lr),
+            r if r == SP => Ok(self.sp),
+            otherwise => Err(Error::UnknownRegister(otherwise)),
+        }
+    }
+
+    /// Set a register in a caller's register set. The value recovered for
+    /// `$ra` is the caller's return address, so it becomes the caller's
+    /// program counter.
+    pub(crate) fn set_register(&mut self, register_num: u8, value: TaggedWord) -> Result<()> {
+        match register_num {
+            r if r == BP => self.fp = value,
+            r if r == LR => self.pc = value,
+            r if r == SP => self.sp = value,
+            otherwise => return Err(Error::UnknownRegister(otherwise)),
+        }
+        Ok(())
+    }
+
+    unsafe fn eval_register_rule<R>(
+        &self,
+        register: u8,
+        rule: gimli::RegisterRule<TargetEndianBuf>,
+        cfa: usize,
+        scratch: &mut expression::Scratch,
+        reader: &R,
+    ) -> TaggedWord
+    where
+        R: MemoryReader,
+    {
+        match rule {
+            gimli::RegisterRule::Undefined |
+            gimli::RegisterRule::Architectural => TaggedWord::invalid(),
+
+            gimli::RegisterRule::SameValue => self.get_register(register).unwrap_or_default(),
+
+            gimli::RegisterRule::Offset(offset) => reader.read_offset(cfa, offset as isize).into(),
+
+            gimli::RegisterRule::ValOffset(offset) => {
+                TaggedWord::valid((cfa as isize).wrapping_add(offset as isize) as usize)
+            }
+
+            gimli::RegisterRule::Register(r) => self.get_register(r).unwrap_or_default(),
+
+            gimli::RegisterRule::Expression(expr) => {
+                expression::evaluate(expr.buf(), self, Some(cfa), scratch, reader)
+                    .and_then(|addr| reader.read(addr))
+                    .into()
+            }
+            gimli::RegisterRule::ValExpression(expr) => {
+                expression::evaluate(expr.buf(), self, Some(cfa), scratch, reader).into()
+            }
+        }
+    }
+}
+
+impl Registers for FrameRegisters {
+    unsafe fn from_unwind_table_row<R>(
+        row: &gimli::UnwindTableRow<TargetEndianBuf>,
+        return_address_register: u8,
+        scratch: &mut expression::Scratch,
+        old_registers: &FrameRegisters,
+        reader: &R,
+    ) -> Result<Self>
+    where
+        R: MemoryReader,
+    {
+        let cfa = match *row.cfa() {
+            gimli::CfaRule::RegisterAndOffset { register, offset } => {
+                let tagged_word = old_registers.get_register(register)?;
+                let word: Result<_> = tagged_word.into();
+                (word? as isize).wrapping_add(offset as isize) as usize
+            }
+            gimli::CfaRule::Expression(ref expr) => {
+                expression::evaluate(expr.buf(), old_registers, None, scratch, reader)?
+            }
+        };
+
+        // Functions that never touch the frame pointer have no rule for it,
+        // and it is callee-saved, so it still holds the caller's value.
+        let fp = match row.register(BP) {
+            gimli::RegisterRule::Undefined => old_registers.fp,
+            rule => old_registers.eval_register_rule(BP, rule, cfa, scratch, reader),
+        };
+        // Leaf functions have no rule for the return address, which is still
+        // in the `$ra`.
+        let pc = match row.register(return_address_register) {
+            gimli::RegisterRule::Undefined => old_registers
+                .get_register(return_address_register)
+                .unwrap_or_default(),
+            rule => old_registers.eval_register_rule(
+                return_address_register,
+                rule,
+                cfa,
+                scratch,
+                reader,
+            ),
+        };
+
+        Ok(FrameRegisters {
+            fp,
+            // The CFA is the value of the stack pointer at the call site.
+            sp: TaggedWord::valid(cfa),
+            // The caller's `$ra` was clobbered by the call, and cannot be
+            // recovered.
+            lr: TaggedWord::invalid(),
+            pc,
+        })
+    }
+
+    #[cfg(feature = "live")]
+    fn with_current<F, T>(mut f: F) -> Result<T>
+    where
+        F: FnMut(&Self) -> Result<T>,
+    {
+        unsafe {
+            let mut context: ffi::ucontext_t = mem::zeroed();
+
+            let r = ffi::getcontext(&mut context);
+            if r != 0 {
+                return Err(Error::Io(io::Error::last_os_error()));
+            }
+
+            f(&FrameRegisters::from_ucontext(&context))
+        }
+    }
+
+    fn bp(&self) -> TaggedWord { self.fp }
+    fn sp(&self) -> TaggedWord { self.sp }
+    fn ip(&self) -> TaggedWord { self.pc }
+}
@@ -0,0 +1,179 @@
+//! Hand-written unwind rules for code without usable DWARF CFI.
+
+use super::{Error, FrameRegisters, MemoryReader, Registers, Result, TaggedWord};
+use registers;
+use std::cmp::Ordering;
+use std::ops::Range;
+
+/// A hand-written unwind rule, for assembly trampolines and `#[naked]`
+/// functions that have no (or incorrect) DWARF call frame information.
+///
+/// The rule says how to find the canonical frame address (CFA) from one of
+/// the callee's registers, where the return address is saved relative to the
+/// CFA, and where any callee-saved registers are saved relative to the CFA.
+/// The caller's stack pointer is the CFA.
+///
+/// Registers are identified by their DWARF register numbers.
+///
+/// ```
+/// use pancakes::ManualRule;
+///
+/// // A trampoline that pushed the frame pointer and then 16 more bytes:
+/// //
+/// //     push %rbp
+/// //     sub $16, %rsp
+/// let rule = ManualRule::sp_offset(32, -8).with_saved_register(6, -16);
+/// # let _ = rule;
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ManualRule {
+    cfa_register: u8,
+    cfa_offset: isize,
+    ra_offset: isize,
+    saved_registers: Vec<(u8, isize)>,
+}
+
+impl ManualRule {
+    /// Construct a rule where CFA = `cfa_register` + `cfa_offset`, and the
+    /// return address is saved at CFA + `ra_offset`.
+    pub fn new(cfa_register: u8, cfa_offset: isize, ra_offset: isize) -> ManualRule {
+        ManualRule {
+            cfa_register,
+            cfa_offset,
+            ra_offset,
+            saved_registers: vec![],
+        }
+    }
+
+    /// Construct a rule where CFA = stack pointer + `cfa_offset`, and the
+    /// return address is saved at CFA + `ra_offset`.
+    pub fn sp_offset(cfa_offset: isize, ra_offset: isize) -> ManualRule {
+        ManualRule::new(registers::SP, cfa_offset, ra_offset)
+    }
+
+    /// Construct a rule where CFA = frame base pointer + `cfa_offset`, and the
+    /// return address is saved at CFA + `ra_offset`.
+    pub fn bp_offset(cfa_offset: isize, ra_offset: isize) -> ManualRule {
+        ManualRule::new(registers::BP, cfa_offset, ra_offset)
+    }
+
+    /// The callee saved the caller's value of `register` at CFA + `offset`.
+    pub fn with_saved_register(mut self, register: u8, offset: isize) -> ManualRule {
+        self.saved_registers.push((register, offset));
+        self
+    }
+
+    /// Recover the caller's registers from the callee's registers.
+    pub(crate) unsafe fn unwind<Reader>(
+        &self,
+        regs: &FrameRegisters,
+        reader: &Reader,
+    ) -> Result<FrameRegisters>
+    where
+        Reader: MemoryReader,
+    {
+        let base: Result<_> = regs.get_register(self.cfa_register)?.into();
+        let cfa = offset(base?, self.cfa_offset);
+
+        let ip = TaggedWord::valid(reader.read(offset(cfa, self.ra_offset))?);
+        let mut caller = FrameRegisters::from_parts(regs.bp(), TaggedWord::valid(cfa), ip);
+        for &(register, saved_at) in &self.saved_registers {
+            let value = reader.read(offset(cfa, saved_at)).into();
+            caller.set_register(register, value)?;
+        }
+        Ok(caller)
+    }
+}
+
+fn offset(addr: usize, offset: isize) -> usize {
+    (addr as isize).wrapping_add(offset) as usize
+}
+
+/// A manual unwind rule and the address range it covers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ManualEntry {
+    pub(crate) range: Range<usize>,
+    pub(crate) rule: ManualRule,
+}
+
+impl ManualEntry {
+    /// Compare this entry's range against an address, for binary searching.
+    pub(crate) fn cmp_address(&self, addr: usize) -> Ordering {
+        if addr < self.range.start {
+            Ordering::Greater
+        } else if addr >= self.range.end {
+            Ordering::Less
+        } else {
+            Ordering::Equal
+        }
+    }
+}
+
+impl PartialOrd for ManualEntry {
+    fn partial_cmp(&self, rhs: &Self) -> Option<Ordering> {
+        self.range.start.partial_cmp(&rhs.range.start)
+    }
+}
+
+impl Ord for ManualEntry {
+    fn cmp(&self, rhs: &Self) -> Ordering {
+        self.range.start.cmp(&rhs.range.start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(Debug)]
+    struct MapMemory(HashMap<usize, usize>);
+
+    impl MemoryReader for MapMemory {
+        unsafe fn read(&self, addr: usize) -> Result<usize> {
+            self.0
+                .get(&addr)
+                .cloned()
+                .ok_or(Error::NoUnwindInfoForAddress(addr))
+        }
+    }
+
+    #[test]
+    fn unwind_sp_offset_rule() {
+        let mut memory = HashMap::new();
+        memory.insert(0x1018, 0xdead);
+        memory.insert(0x1010, 0x2000);
+        let reader = MapMemory(memory);
+
+        let regs = FrameRegisters::from_parts(
+            TaggedWord::valid(0x1234),
+            TaggedWord::valid(0x1000),
+            TaggedWord::valid(0x4000),
+        );
+
+        let rule = ManualRule::sp_offset(0x20, -8).with_saved_register(registers::BP, -16);
+        let caller = unsafe { rule.unwind(&regs, &reader).unwrap() };
+        assert_eq!(caller.sp(), TaggedWord::valid(0x1020));
+        assert_eq!(caller.ip(), TaggedWord::valid(0xdead));
+        assert_eq!(caller.bp(), TaggedWord::valid(0x2000));
+
+        let rule = ManualRule::sp_offset(0x20, -8);
+        let caller = unsafe { rule.unwind(&regs, &reader).unwrap() };
+        assert_eq!(caller.bp(), TaggedWord::valid(0x1234));
+    }
+
+    #[test]
+    fn unknown_register() {
+        let regs = FrameRegisters::from_parts(
+            TaggedWord::valid(0x1234),
+            TaggedWord::valid(0x1000),
+            TaggedWord::valid(0x4000),
+        );
+        let rule = ManualRule::new(200, 0, -8);
+        let reader = MapMemory(HashMap::new());
+        match unsafe { rule.unwind(&regs, &reader) } {
+            Err(Error::UnknownRegister(200)) => {}
+            otherwise => panic!("unexpected result: {:?}", otherwise),
+        }
+    }
+}
@@ -0,0 +1,373 @@
+//! Modules added to an `Unwinder` while it is walking.
+//!
+//! A built `Unwinder` is shared between walking threads, so modules loaded
+//! and unloaded later cannot be added to its sorted entries. Instead, each
+//! module added with `Unwinder::add_eh_frame` has its unwind information
+//! compiled up front, and the set of such modules is a `CompiledModules`: a
+//! `Published` list, sorted by address, that adding and removing replace
+//! wholesale. `JitRegistry` keeps registered JIT code in one too. Each frame
+//! is walked with a consistent snapshot of the list, and a removed module's
+//! memory is not read after removing it returns.
+//!
+//! The unwind information the `Unwinder` was built with cannot be removed
+//! while it is shared either, so `Unwinder::remove_entries_in_range` records
+//! the unloaded address ranges, and walking ignores that unwind information
+//! for addresses in them.
+
+use super::{address_range, avma_range};
+use super::{Avma, Bias, FrameRegisters, MemoryReader, Result, TargetEhFrame};
+use compiled::{CompiledTable, CompiledTableMemory, Compiler};
+use gimli;
+use published::Published;
+use std::fmt;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A handle for removing a module added with `Unwinder::add_eh_frame`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct ModuleId(u64);
+
+/// A module and its compiled unwind table.
+#[derive(Clone, Debug)]
+struct CompiledModule {
+    id: u64,
+    range: Range<usize>,
+    table: CompiledTable,
+}
+
+/// A list of modules with compiled unwind tables, which can be added to and
+/// removed from while other threads walk with it. `LiveModules` and
+/// `JitRegistry` are both built on it.
+pub(crate) struct CompiledModules {
+    /// The modules, sorted by the start of their range.
+    modules: Published<Vec<CompiledModule>>,
+    /// The next module's id.
+    next_id: AtomicU64,
+}
+
+impl CompiledModules {
+    pub(crate) fn new() -> CompiledModules {
+        CompiledModules {
+            modules: Published::new(vec![]),
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    /// Compile the given `.eh_frame` section of a module loaded with the
+    /// given bias, and add it for the addresses in `range`, or for the
+    /// addresses its rows cover if `range` is `None`. Returns the module's
+    /// id.
+    pub(crate) fn add(
+        &self,
+        bias: Bias,
+        bases: &gimli::BaseAddresses,
+        eh_frame: &[u8],
+        range: Option<Range<usize>>,
+    ) -> Result<u64> {
+        let section = TargetEhFrame::new(eh_frame, gimli::RunTimeEndian::default());
+        let mut compiler = Compiler::default();
+        compiler.add_section(bias, bases, &section)?;
+        let table = compiler
+            .finish()
+            .pop()
+            .unwrap_or_else(|| CompiledTable::new(bias, &[]));
+
+        let range = range.unwrap_or_else(|| {
+            let to_address = |svma: u64| (svma as isize).wrapping_add(bias.0) as usize;
+            match table.svma_range() {
+                Some(range) => to_address(range.start)..to_address(range.end),
+                None => to_address(0)..to_address(0),
+            }
+        });
+
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        self.modules.update(|modules| {
+            let idx = modules.partition_point(|module| module.range.start <= range.start);
+            modules.insert(idx, CompiledModule { id, range, table });
+        });
+        Ok(id)
+    }
+
+    /// Remove the module with the given id. Returns `false` if it was already
+    /// removed.
+    pub(crate) fn remove(&self, id: u64) -> bool {
+        self.modules.update(|modules| {
+            let len = modules.len();
+            modules.retain(|module| module.id != id);
+            modules.len() != len
+        })
+    }
+
+    /// Remove every module overlapping the given range.
+    pub(crate) fn remove_range(&self, range: &Range<usize>) {
+        self.modules.update(|modules| modules.retain(|module| !overlaps(&module.range, range)));
+    }
+
+    /// Copy out the address range of every module.
+    pub(crate) fn ranges(&self) -> Vec<Range<Avma>> {
+        self.modules.read(|modules| {
+            modules.iter().map(|module| avma_range(&module.range)).collect()
+        })
+    }
+
+    /// Copy out the compiled tables of every module.
+    pub(crate) fn tables(&self) -> Vec<CompiledTable> {
+        self.modules.read(|modules| modules.iter().map(|module| module.table.clone()).collect())
+    }
+
+    /// Get how much memory the compiled table of every module takes.
+    pub(crate) fn memory(&self) -> Vec<CompiledTableMemory> {
+        self.modules.read(|modules| modules.iter().map(|module| module.table.memory()).collect())
+    }
+
+    /// Walk a frame in one of the modules, or return `None` if `ip` is not
+    /// in any, or its module has no row for it.
+    ///
+    /// This does not block or allocate.
+    pub(crate) unsafe fn unwind<Reader>(
+        &self,
+        ip: usize,
+        regs: &FrameRegisters,
+        reader: &Reader,
+    ) -> Option<Result<(FrameRegisters, Bias)>>
+    where
+        Reader: MemoryReader,
+    {
+        self.modules.read(|modules| {
+            let idx = modules.partition_point(|module| module.range.start <= ip);
+            modules[..idx]
+                .iter()
+                .rev()
+                .find(|module| ip < module.range.end)
+                .and_then(|module| {
+                    module.table.lookup(ip).map(|row| {
+                        row.unwind(regs, reader).map(|registers| (registers, module.table.bias))
+                    })
+                })
+        })
+    }
+}
+
+/// The modules added to an `Unwinder` after it was built.
+pub(crate) struct LiveModules {
+    modules: CompiledModules,
+    /// The address ranges whose unwind information from before the
+    /// `Unwinder` was built is removed, without overlaps.
+    unloaded: Published<Vec<Range<usize>>>,
+}
+
+impl LiveModules {
+    pub(crate) fn new() -> LiveModules {
+        LiveModules {
+            modules: CompiledModules::new(),
+            unloaded: Published::new(vec![]),
+        }
+    }
+
+    /// Compile and add the given `.eh_frame` section of a module loaded with
+    /// the given bias.
+    pub(crate) fn add(
+        &self,
+        bias: Bias,
+        bases: &gimli::BaseAddresses,
+        eh_frame: &[u8],
+    ) -> Result<ModuleId> {
+        self.modules.add(bias, bases, eh_frame, None).map(ModuleId)
+    }
+
+    /// Remove a module added with `add`. Returns `false` if it was already
+    /// removed.
+    pub(crate) fn remove(&self, id: ModuleId) -> bool {
+        self.modules.remove(id.0)
+    }
+
+    /// Remove every module overlapping the given range, and ignore the
+    /// `Unwinder`'s own unwind information for the addresses in it.
+    pub(crate) fn remove_range(&self, range: Range<Avma>) {
+        let range = address_range(&range);
+        self.modules.remove_range(&range);
+        self.unloaded.update(|ranges| {
+            let mut merged = range;
+            ranges.retain(|r| {
+                if overlaps(r, &merged) || r.end == merged.start || merged.end == r.start {
+                    if r.start < merged.start {
+                        merged.start = r.start;
+                    }
+                    if merged.end < r.end {
+                        merged.end = r.end;
+                    }
+                    false
+                } else {
+                    true
+                }
+            });
+            ranges.push(merged);
+        });
+    }
+
+    /// Is the `Unwinder`'s own unwind information for `ip` removed?
+    ///
+    /// This does not block or allocate.
+    pub(crate) fn is_unloaded(&self, ip: usize) -> bool {
+        self.unloaded.read(|ranges| ranges.iter().any(|range| range.start <= ip && ip < range.end))
+    }
+
+    /// Copy out the unloaded address ranges.
+    pub(crate) fn unloaded(&self) -> Vec<Range<Avma>> {
+        self.unloaded.read(|ranges| ranges.iter().map(avma_range).collect())
+    }
+
+    /// Copy out the compiled tables of every module.
+    pub(crate) fn tables(&self) -> Vec<CompiledTable> {
+        self.modules.tables()
+    }
+
+    /// Get how much memory the compiled table of every module takes.
+    pub(crate) fn memory(&self) -> Vec<CompiledTableMemory> {
+        self.modules.memory()
+    }
+
+    /// Walk a frame in one of the modules, or return `None` if `ip` is not
+    /// in any.
+    ///
+    /// This does not block or allocate.
+    pub(crate) unsafe fn unwind<Reader>(
+        &self,
+        ip: usize,
+        regs: &FrameRegisters,
+        reader: &Reader,
+    ) -> Option<Result<(FrameRegisters, Bias)>>
+    where
+        Reader: MemoryReader,
+    {
+        self.modules.unwind(ip, regs, reader)
+    }
+}
+
+/// Do the given address ranges overlap?
+pub(crate) fn overlaps<T: PartialOrd>(a: &Range<T>, b: &Range<T>) -> bool {
+    a.start < b.end && b.start < a.end
+}
+
+impl fmt::Debug for LiveModules {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LiveModules")
+            .field("modules", &self.modules.ranges())
+            .field("unloaded", &self.unloaded())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(target_arch = "x86_64")]
+    use reader::SliceMemory;
+    #[cfg(target_arch = "x86_64")]
+    use tests::eh_frame_pushing_one_word;
+    #[cfg(target_arch = "x86_64")]
+    use Registers;
+    #[cfg(target_arch = "x86_64")]
+    use TaggedWord;
+
+    #[test]
+    fn add_and_remove() {
+        let modules = LiveModules::new();
+        let first = modules.add(Bias(0x1000), &Default::default(), &[]).unwrap();
+        let second = modules.add(Bias(0x2000), &Default::default(), &[]).unwrap();
+        assert!(first != second);
+
+        assert!(modules.remove(first));
+        assert!(!modules.remove(first));
+        assert!(modules.remove(second));
+        assert!(modules.tables().is_empty());
+    }
+
+    #[test]
+    fn remove_range() {
+        let avma = |addr: usize| Avma(addr as *const u8);
+        let modules = LiveModules::new();
+        modules.remove_range(avma(0x1000)..avma(0x2000));
+        modules.remove_range(avma(0x3000)..avma(0x4000));
+        modules.remove_range(avma(0x2000)..avma(0x3000));
+        assert_eq!(modules.unloaded(), vec![avma(0x1000)..avma(0x4000)]);
+
+        assert!(!modules.is_unloaded(0xfff));
+        assert!(modules.is_unloaded(0x1000));
+        assert!(modules.is_unloaded(0x3fff));
+        assert!(!modules.is_unloaded(0x4000));
+    }
+
+    /// The registers of a frame at `ip`, and its stack at 0x8000, which
+    /// holds the return address 0x4000 above one pushed word.
+    #[cfg(target_arch = "x86_64")]
+    fn frame_at(ip: usize) -> (FrameRegisters, Vec<u8>) {
+        let regs = FrameRegisters::from_parts(
+            TaggedWord::valid(0),
+            TaggedWord::valid(0x8000),
+            TaggedWord::valid(ip),
+        );
+        (regs, ::tests::stack(&[0xdead, 0x4000]))
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn unwind_added_module() {
+        let modules = LiveModules::new();
+        let eh_frame = eh_frame_pushing_one_word();
+        let id = modules.add(Bias(0x10000), &Default::default(), &eh_frame).unwrap();
+
+        // The module covers the addresses its FDE does, once biased.
+        let (regs, stack) = frame_at(0x11010);
+        let reader = SliceMemory::new(0x8000, &stack);
+        unsafe {
+            let (caller, bias) = modules.unwind(0x11010, &regs, &reader).unwrap().unwrap();
+            assert_eq!(bias, Bias(0x10000));
+            assert_eq!(caller.ip(), TaggedWord::valid(0x4000));
+            assert_eq!(caller.sp(), TaggedWord::valid(0x8010));
+            assert!(modules.unwind(0x1010, &regs, &reader).is_none());
+            assert!(modules.unwind(0x11100, &regs, &reader).is_none());
+        }
+
+        assert!(modules.remove(id));
+        unsafe {
+            assert!(modules.unwind(0x11010, &regs, &reader).is_none());
+        }
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn remove_while_walking() {
+        use std::sync::atomic::AtomicBool;
+        use std::sync::Arc;
+        use std::thread;
+
+        let modules = Arc::new(LiveModules::new());
+        let done = Arc::new(AtomicBool::new(false));
+        let walker = {
+            let modules = modules.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                let (regs, stack) = frame_at(0x11010);
+                let reader = SliceMemory::new(0x8000, &stack);
+                while !done.load(Ordering::SeqCst) {
+                    // Each walk sees the module either added or removed, and
+                    // never a table that was freed.
+                    if let Some(caller) = unsafe { modules.unwind(0x11010, &regs, &reader) } {
+                        assert_eq!(caller.unwrap().0.ip(), TaggedWord::valid(0x4000));
+                    }
+                }
+            })
+        };
+
+        let eh_frame = eh_frame_pushing_one_word();
+        for _ in 0..1000 {
+            let id = modules.add(Bias(0x10000), &Default::default(), &eh_frame).unwrap();
+            thread::yield_now();
+            assert!(modules.remove(id));
+        }
+        done.store(true, Ordering::SeqCst);
+        walker.join().unwrap();
+        assert!(modules.tables().is_empty());
+    }
+}
@@ -0,0 +1,174 @@
+//! The definition and implementations of `UnwindObserver`.
+//!
+//! An observer is told what a walk does as it does it: which FDE covers a
+//! frame, whether the frame's unwind table row came from the row cache, when
+//! a strategy other than the first walks a frame, and which memory reads
+//! fail, and which error ends it. Give a `Walker` one with
+//! `Walker::with_observer` to build dashboards and debugging tools on top of
+//! walking.
+//!
+//! Observers are called in the middle of walks, which may be in signal
+//! handlers, so they must not allocate or take locks either. They are only
+//! given shared references, so record events with atomics or `Cell`s.
+
+use super::{Avma, Error, MemoryReader, Result, UnwindStrategy};
+use std::fmt::Debug;
+use std::ops::Range;
+
+/// Callbacks for what walking a stack does. Every callback does nothing by
+/// default.
+///
+/// ```
+/// use pancakes::observer::UnwindObserver;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// #[derive(Debug, Default)]
+/// struct CountFailedReads(AtomicUsize);
+///
+/// impl UnwindObserver for CountFailedReads {
+///     fn read_failed(&self, _address: usize) {
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+///
+/// let walker = pancakes::Options::new()
+///     .build()
+///     .with_observer(CountFailedReads::default());
+/// # let _ = walker;
+/// ```
+pub trait UnwindObserver: Debug {
+    /// An FDE covering the addresses in `fde` was found for the frame at
+    /// `ip`.
+    fn fde_found(&self, ip: usize, fde: Range<Avma>) {
+        let _ = (ip, fde);
+    }
+
+    /// The unwind table row covering `ip` was found, in the row cache if
+    /// `cached`, or by running its FDE's call frame instructions.
+    fn row_resolved(&self, ip: usize, cached: bool) {
+        let _ = (ip, cached);
+    }
+
+    /// The frame at `ip` was walked with `strategy`, because the strategies
+    /// before it in `Options::strategies` could not walk it.
+    fn fallback_used(&self, ip: usize, strategy: UnwindStrategy) {
+        let _ = (ip, strategy);
+    }
+
+    /// Reading the memory at `address` failed.
+    fn read_failed(&self, address: usize) {
+        let _ = address;
+    }
+
+    /// A frame could not be walked to its caller, which ends the walk early.
+    /// Walks that reach the outermost frame end without calling this.
+    fn walk_aborted(&self, error: &Error) {
+        let _ = error;
+    }
+}
+
+impl<'o, Observer> UnwindObserver for &'o Observer
+where
+    Observer: UnwindObserver,
+{
+    fn fde_found(&self, ip: usize, fde: Range<Avma>) {
+        (**self).fde_found(ip, fde)
+    }
+
+    fn row_resolved(&self, ip: usize, cached: bool) {
+        (**self).row_resolved(ip, cached)
+    }
+
+    fn fallback_used(&self, ip: usize, strategy: UnwindStrategy) {
+        (**self).fallback_used(ip, strategy)
+    }
+
+    fn read_failed(&self, address: usize) {
+        (**self).read_failed(address)
+    }
+
+    fn walk_aborted(&self, error: &Error) {
+        (**self).walk_aborted(error)
+    }
+}
+
+/// An `UnwindObserver` that ignores every event.
+#[derive(Debug, Default)]
+pub struct IgnoreEvents;
+
+impl UnwindObserver for IgnoreEvents {}
+
+/// A `MemoryReader` that tells an observer about the reads that fail.
+#[derive(Debug)]
+pub(crate) struct ObservedReader<'r, Reader, Observer>
+where
+    Reader: 'r + MemoryReader,
+    Observer: 'r + UnwindObserver,
+{
+    pub(crate) reader: &'r Reader,
+    pub(crate) observer: &'r Observer,
+}
+
+impl<'r, Reader, Observer> ObservedReader<'r, Reader, Observer>
+where
+    Reader: MemoryReader,
+    Observer: UnwindObserver,
+{
+    fn observe<T>(&self, addr: usize, result: Result<T>) -> Result<T> {
+        if result.is_err() {
+            self.observer.read_failed(addr);
+        }
+        result
+    }
+}
+
+impl<'r, Reader, Observer> MemoryReader for ObservedReader<'r, Reader, Observer>
+where
+    Reader: MemoryReader,
+    Observer: UnwindObserver,
+{
+    unsafe fn read(&self, addr: usize) -> Result<usize> {
+        self.observe(addr, self.reader.read(addr))
+    }
+
+    unsafe fn read_words(&self, addr: usize, words: &mut [usize]) -> Result<()> {
+        self.observe(addr, self.reader.read_words(addr, words))
+    }
+
+    unsafe fn read_bytes(&self, addr: usize, bytes: &mut [u8]) -> Result<()> {
+        self.observe(addr, self.reader.read_bytes(addr, bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reader::SliceMemory;
+    use std::cell::Cell;
+
+    #[derive(Debug, Default)]
+    struct FailedReads(Cell<usize>);
+
+    impl UnwindObserver for FailedReads {
+        fn read_failed(&self, _address: usize) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn observed_reader() {
+        let bytes = 0x1234usize.to_ne_bytes();
+        let reader = SliceMemory::new(0x1000, &bytes);
+        let observer = FailedReads::default();
+        let observed = ObservedReader {
+            reader: &reader,
+            observer: &observer,
+        };
+        unsafe {
+            assert_eq!(observed.read(0x1000).unwrap(), 0x1234);
+            assert!(observed.read(0x2000).is_err());
+            assert!(observed.read_bytes(0x3000, &mut [0; 3]).is_err());
+        }
+        assert_eq!(observer.0.get(), 2);
+    }
+}
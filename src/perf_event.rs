@@ -0,0 +1,522 @@
+//! Sampling the current process's threads with `perf_event_open`, and
+//! walking the samples offline.
+//!
+//! The kernel takes each sample from its own timer or hardware counter
+//! interrupt, copying the thread's user registers and the top of its user
+//! stack into a ring buffer shared with the process. No signals are sent, so
+//! sampling neither interrupts system calls nor runs code on the sampled
+//! threads, and each sample can be walked later with an `Unwinder`, using
+//! `PerfSample::stack` as the reader.
+//!
+//! An event is opened on each thread, since the kernel only copies a
+//! sample's stack for events that follow a single thread.
+//! `PerfSampler::rescan` opens events on threads started since.
+//!
+//! Opening events may be denied by the `kernel.perf_event_paranoid` sysctl,
+//! or by seccomp filters in containers. Only the frame pointer, stack
+//! pointer, and instruction pointer are copied, and on AArch64 the link
+//! register, so that frames that have not saved it yet can be walked.
+//!
+//! ```no_run
+//! use pancakes::perf_event::PerfOptions;
+//! use pancakes::UnwindContext;
+//!
+//! let mut options = pancakes::Options::new();
+//! options.find_eh_frame_entries().unwrap();
+//! let unwinder = options.build_unwinder();
+//! let mut cx = UnwindContext::new();
+//!
+//! let mut sampler = PerfOptions::new().frequency(999).open().unwrap();
+//! // ... do some work ...
+//! sampler.read(|sample| {
+//!     let outcome = unwinder.walk(&mut cx, sample.stack(), sample.registers(), |frame| {
+//!         println!("Traversed frame {:?}", frame);
+//!     });
+//!     println!("Thread {} walked {} frames", sample.tid(), outcome.frames_walked);
+//! });
+//! ```
+
+use super::{reader, FrameRegisters, Result, TaggedWord};
+use error::Error;
+use libc;
+use std::collections::HashMap;
+use std::io;
+use std::mem;
+use std::os::unix::io::RawFd;
+use std::ptr;
+use std::sync::atomic::{self, Ordering};
+use threads;
+
+const PERF_TYPE_HARDWARE: u32 = 0;
+const PERF_TYPE_SOFTWARE: u32 = 1;
+const PERF_COUNT_HW_CPU_CYCLES: u64 = 0;
+const PERF_COUNT_SW_CPU_CLOCK: u64 = 0;
+
+const PERF_SAMPLE_TID: u64 = 1 << 1;
+const PERF_SAMPLE_TIME: u64 = 1 << 2;
+const PERF_SAMPLE_REGS_USER: u64 = 1 << 12;
+const PERF_SAMPLE_STACK_USER: u64 = 1 << 13;
+
+// Bits of `perf_event_attr`'s flags bitfield.
+const ATTR_EXCLUDE_KERNEL: u64 = 1 << 5;
+const ATTR_EXCLUDE_HV: u64 = 1 << 6;
+const ATTR_FREQ: u64 = 1 << 10;
+const ATTR_USE_CLOCKID: u64 = 1 << 25;
+
+const PERF_FLAG_FD_CLOEXEC: libc::c_ulong = 1 << 3;
+
+const PERF_RECORD_LOST: u32 = 2;
+const PERF_RECORD_SAMPLE: u32 = 9;
+
+/// The offsets of `data_head` and `data_tail` in `perf_event_mmap_page`.
+const DATA_HEAD: usize = 1024;
+const DATA_TAIL: usize = 1032;
+
+// The `perf_regs.h` indices of the frame pointer, stack pointer, and
+// instruction pointer, and on aarch64 the link register. Registers are copied
+// in order of their indices.
+#[cfg(target_arch = "x86_64")]
+const REGS: &'static [u32] = &[6, 7, 8];
+#[cfg(target_arch = "aarch64")]
+const REGS: &'static [u32] = &[29, 30, 31, 32];
+
+/// `struct perf_event_attr`, as of `PERF_ATTR_SIZE_VER5`.
+#[repr(C)]
+#[derive(Default)]
+struct PerfEventAttr {
+    type_: u32,
+    size: u32,
+    config: u64,
+    sample_freq: u64,
+    sample_type: u64,
+    read_format: u64,
+    flags: u64,
+    wakeup_events: u32,
+    bp_type: u32,
+    config1: u64,
+    config2: u64,
+    branch_sample_type: u64,
+    sample_regs_user: u64,
+    sample_stack_user: u32,
+    clockid: i32,
+    sample_regs_intr: u64,
+    aux_watermark: u32,
+    sample_max_stack: u16,
+    reserved: u16,
+}
+
+/// What drives sampling.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PerfEvent {
+    /// The kernel's high-resolution CPU clock, which is available even
+    /// without a hardware performance monitoring unit, e.g. in most virtual
+    /// machines.
+    CpuClock,
+    /// The CPU's cycle counter.
+    CpuCycles,
+}
+
+/// Configuration for a `PerfSampler`.
+#[derive(Clone, Debug)]
+pub struct PerfOptions {
+    event: PerfEvent,
+    frequency: u32,
+    stack_bytes: u32,
+    pages: usize,
+}
+
+impl Default for PerfOptions {
+    fn default() -> Self {
+        PerfOptions {
+            event: PerfEvent::CpuClock,
+            frequency: 99,
+            stack_bytes: 16 * 1024,
+            pages: 64,
+        }
+    }
+}
+
+impl PerfOptions {
+    /// Construct a new `PerfOptions` with the default configuration: 99
+    /// samples per second of each thread's CPU clock, copying 16 KiB of
+    /// each stack, into 64 pages of ring buffer per thread.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sample on this event.
+    pub fn event(&mut self, event: PerfEvent) -> &mut Self {
+        self.event = event;
+        self
+    }
+
+    /// Take this many samples per second. The kernel lowers the frequency
+    /// if sampling takes too long, and refuses frequencies over the
+    /// `kernel.perf_event_max_sample_rate` sysctl.
+    pub fn frequency(&mut self, hz: u32) -> &mut Self {
+        self.frequency = hz.max(1);
+        self
+    }
+
+    /// Copy this many bytes of the top of each sampled stack, rounded down
+    /// to a multiple of eight, and at most 65528. Frames whose stack lies
+    /// beyond the copy cannot be walked.
+    pub fn stack_bytes(&mut self, bytes: u32) -> &mut Self {
+        self.stack_bytes = bytes.min(65528) & !7;
+        self
+    }
+
+    /// Give each thread's ring buffer this many pages, rounded up to a
+    /// power of two. Samples that arrive while a ring buffer is full are
+    /// lost; see `PerfSampler::lost`.
+    pub fn pages(&mut self, pages: usize) -> &mut Self {
+        self.pages = pages.max(1).next_power_of_two();
+        self
+    }
+
+    /// Open events on every thread of the current process and start
+    /// sampling them.
+    pub fn open(&self) -> Result<PerfSampler> {
+        let mut sampler = PerfSampler {
+            options: self.clone(),
+            page_size: unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize },
+            buffers: HashMap::new(),
+            record: vec![],
+            lost: 0,
+        };
+        sampler.rescan()?;
+        Ok(sampler)
+    }
+}
+
+/// Events sampling the current process's threads, opened with
+/// `PerfOptions::open`. Dropping it stops sampling.
+#[derive(Debug)]
+pub struct PerfSampler {
+    options: PerfOptions,
+    page_size: usize,
+    buffers: HashMap<usize, Buffer>,
+    record: Vec<u8>,
+    lost: u64,
+}
+
+impl PerfSampler {
+    /// Open events on the threads started since the events were opened or
+    /// last rescanned. Threads that exited are forgotten, along with their
+    /// samples that were not read yet.
+    pub fn rescan(&mut self) -> Result<()> {
+        let tids = threads::thread_ids()?;
+        self.buffers.retain(|tid, _| tids.contains(tid));
+        for tid in tids {
+            if self.buffers.contains_key(&tid) {
+                continue;
+            }
+            match Buffer::open(tid, &self.options, self.page_size) {
+                Ok(buffer) => {
+                    self.buffers.insert(tid, buffer);
+                }
+                // The thread exited since it was listed.
+                Err(ref e) if e.raw_os_error() == Some(libc::ESRCH) => {}
+                Err(e) => return Err(Error::Io(e)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Call `f` with each sample taken since the last read, returning how
+    /// many there were.
+    pub fn read<F>(&mut self, mut f: F) -> usize
+    where
+        F: FnMut(&PerfSample),
+    {
+        let PerfSampler {
+            ref buffers,
+            ref mut record,
+            ref mut lost,
+            ..
+        } = *self;
+        let mut samples = 0;
+        for buffer in buffers.values() {
+            unsafe {
+                buffer.drain(record, |record| match record_type(record) {
+                    Some(PERF_RECORD_SAMPLE) => {
+                        if let Some(sample) = parse_sample(record) {
+                            f(&sample);
+                            samples += 1;
+                        }
+                    }
+                    Some(PERF_RECORD_LOST) => {
+                        *lost += read_u64(record, 16).unwrap_or(0);
+                    }
+                    _ => {}
+                });
+            }
+        }
+        samples
+    }
+
+    /// Get how many samples were lost because a ring buffer was full.
+    pub fn lost(&self) -> u64 {
+        self.lost
+    }
+}
+
+/// A sample taken by a `PerfSampler`.
+#[derive(Clone, Debug)]
+pub struct PerfSample<'a> {
+    tid: usize,
+    time: u64,
+    registers: FrameRegisters,
+    stack: reader::SliceMemory<'a>,
+}
+
+impl<'a> PerfSample<'a> {
+    /// Get the id of the sampled thread.
+    pub fn tid(&self) -> usize {
+        self.tid
+    }
+
+    /// Get when the sample was taken, in nanoseconds of `CLOCK_MONOTONIC`,
+    /// the clock `std::time::Instant` uses.
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    /// Get the registers the thread was interrupted with.
+    pub fn registers(&self) -> &FrameRegisters {
+        &self.registers
+    }
+
+    /// Get a reader for the copy of the top of the thread's stack, starting
+    /// at its stack pointer, to walk the sample with.
+    pub fn stack(&self) -> &reader::SliceMemory<'a> {
+        &self.stack
+    }
+}
+
+/// An event opened on one thread, and its ring buffer.
+#[derive(Debug)]
+struct Buffer {
+    fd: RawFd,
+    base: *mut u8,
+    page_size: usize,
+    pages: usize,
+}
+
+impl Buffer {
+    fn open(tid: usize, options: &PerfOptions, page_size: usize) -> io::Result<Buffer> {
+        let (type_, config) = match options.event {
+            PerfEvent::CpuClock => (PERF_TYPE_SOFTWARE, PERF_COUNT_SW_CPU_CLOCK),
+            PerfEvent::CpuCycles => (PERF_TYPE_HARDWARE, PERF_COUNT_HW_CPU_CYCLES),
+        };
+        let attr = PerfEventAttr {
+            type_,
+            size: mem::size_of::<PerfEventAttr>() as u32,
+            config,
+            sample_freq: options.frequency as u64,
+            sample_type: PERF_SAMPLE_TID
+                | PERF_SAMPLE_TIME
+                | PERF_SAMPLE_REGS_USER
+                | PERF_SAMPLE_STACK_USER,
+            flags: ATTR_EXCLUDE_KERNEL | ATTR_EXCLUDE_HV | ATTR_FREQ | ATTR_USE_CLOCKID,
+            sample_regs_user: REGS.iter().fold(0, |mask, &reg| mask | 1 << reg),
+            sample_stack_user: options.stack_bytes,
+            clockid: libc::CLOCK_MONOTONIC,
+            ..Default::default()
+        };
+
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_perf_event_open,
+                &attr as *const PerfEventAttr,
+                tid as libc::pid_t,
+                -1 as libc::c_int,
+                -1 as libc::c_int,
+                PERF_FLAG_FD_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = fd as RawFd;
+
+        let len = (options.pages + 1) * page_size;
+        let base = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd,
+                0,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            let e = io::Error::last_os_error();
+            unsafe {
+                libc::close(fd);
+            }
+            return Err(e);
+        }
+
+        Ok(Buffer {
+            fd,
+            base: base as *mut u8,
+            page_size,
+            pages: options.pages,
+        })
+    }
+
+    /// Call `f` with each record written since the last drain, copied into
+    /// `record`, and free their space for the kernel.
+    unsafe fn drain<F>(&self, record: &mut Vec<u8>, mut f: F)
+    where
+        F: FnMut(&[u8]),
+    {
+        let head_ptr = self.base.add(DATA_HEAD) as *const u64;
+        let tail_ptr = self.base.add(DATA_TAIL) as *mut u64;
+        let head = ptr::read_volatile(head_ptr);
+        atomic::fence(Ordering::Acquire);
+
+        let data = self.base.add(self.page_size);
+        let size = self.pages * self.page_size;
+        let mut tail = ptr::read_volatile(tail_ptr);
+        while tail < head {
+            let start = (tail % size as u64) as usize;
+            let mut header = [0; 8];
+            copy_wrapped(data, size, start, &mut header);
+            let len = u16::from_ne_bytes([header[6], header[7]]) as usize;
+            if len < header.len() {
+                // A corrupt header: skip everything written.
+                tail = head;
+                break;
+            }
+            record.resize(len, 0);
+            copy_wrapped(data, size, start, record);
+            f(record);
+            tail += len as u64;
+        }
+
+        atomic::fence(Ordering::Release);
+        ptr::write_volatile(tail_ptr, tail);
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(
+                self.base as *mut libc::c_void,
+                (self.pages + 1) * self.page_size,
+            );
+            libc::close(self.fd);
+        }
+    }
+}
+
+// The ring buffer is only accessed through `&mut PerfSampler`.
+unsafe impl Send for Buffer {}
+
+/// Copy `out.len()` bytes of the ring buffer of `size` bytes at `data`,
+/// starting at `start` and wrapping around its end.
+unsafe fn copy_wrapped(data: *const u8, size: usize, start: usize, out: &mut [u8]) {
+    let first = out.len().min(size - start);
+    ptr::copy_nonoverlapping(data.add(start), out.as_mut_ptr(), first);
+    ptr::copy_nonoverlapping(data, out[first..].as_mut_ptr(), out.len() - first);
+}
+
+fn record_type(record: &[u8]) -> Option<u32> {
+    read_u32(record, 0)
+}
+
+/// Parse a `PERF_RECORD_SAMPLE` with our sample type, returning `None` for
+/// samples without user registers, e.g. of kernel threads.
+fn parse_sample(record: &[u8]) -> Option<PerfSample> {
+    // The header, then `u32 pid, tid`, then `u64 time`.
+    let tid = read_u32(record, 12)? as usize;
+    let time = read_u64(record, 16)?;
+
+    // `u64 abi`, then a `u64` for each register in `REGS` unless there are
+    // none.
+    let abi = read_u64(record, 24)?;
+    if abi == 0 {
+        return None;
+    }
+    let reg = |i: usize| read_u64(record, 32 + i * 8).map(|r| TaggedWord::valid(r as usize));
+    #[cfg(target_arch = "x86_64")]
+    let registers = FrameRegisters::from_parts(reg(0)?, reg(1)?, reg(2)?);
+    #[cfg(target_arch = "aarch64")]
+    let registers = {
+        let mut registers = FrameRegisters::from_parts(reg(0)?, reg(2)?, reg(3)?);
+        // A sample in a leaf function or a prologue has its return address
+        // only in the link register.
+        registers.set_lr(reg(1)?);
+        registers
+    };
+
+    // `u64 size`, `size` bytes of stack, and `u64 dyn_size`, how many of
+    // them were copied.
+    let at = 32 + REGS.len() * 8;
+    let size = read_u64(record, at)? as usize;
+    let stack = record.get(at + 8..at + 8 + size)?;
+    let copied = if size == 0 {
+        0
+    } else {
+        read_u64(record, at + 8 + size)? as usize
+    };
+
+    let sp = registers.sp().unwrap_or(0);
+    Some(PerfSample {
+        tid,
+        time,
+        stack: reader::SliceMemory::new(sp, &stack[..copied.min(size)]),
+        registers,
+    })
+}
+
+fn read_u32(record: &[u8], at: usize) -> Option<u32> {
+    let bytes = record.get(at..at + 4)?;
+    Some(u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_u64(record: &[u8], at: usize) -> Option<u64> {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(record.get(at..at + 8)?);
+    Some(u64::from_ne_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+    use {Options, Registers, UnwindContext};
+
+    #[test]
+    #[ignore = "perf_event_open is often unavailable in containers and CI"]
+    fn sample_busy_loop() {
+        let mut sampler = PerfOptions::new().frequency(1000).open().unwrap();
+
+        let started = Instant::now();
+        let mut x = 0u64;
+        while started.elapsed() < Duration::from_millis(200) {
+            x = x.wrapping_mul(31).wrapping_add(1);
+        }
+        let _ = ::std::hint::black_box(x);
+
+        let mut options = Options::new();
+        options.find_eh_frame_entries().unwrap();
+        let unwinder = options.build_unwinder();
+        let mut cx = UnwindContext::new();
+        let current = threads::current_tid();
+        let mut walked = 0;
+        let samples = sampler.read(|sample| {
+            assert!(sample.registers().ip().is_valid());
+            if sample.tid() == current {
+                let outcome = unwinder.walk(&mut cx, sample.stack(), sample.registers(), |_| ());
+                walked += outcome.frames_walked;
+            }
+        });
+        assert!(samples > 0);
+        assert!(walked > 0);
+    }
+}
@@ -0,0 +1,196 @@
+//! Names for JIT code from the `/tmp/perf-<pid>.map` files that JITs write for
+//! `perf`.
+//!
+//! Each line of a perf map describes one function: its start address and size
+//! in hex, and its name, which may contain spaces:
+//!
+//! ```text
+//! 7f3c1c00a000 1c0 LazyCompile:~main /srv/app.js:1
+//! ```
+//!
+//! The JIT appends lines as it compiles, so a `PerfMap` reloads the file,
+//! reading only what was appended since, when asked for an address it doesn't
+//! know. Where the code of a newer line overlaps an older one, e.g. because
+//! the older code was freed and its memory reused, the newer line wins.
+//!
+//! Perf maps have no unwind information; see `jitdump` for the format that
+//! does.
+
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// A function described by a perf map.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PerfMapEntry {
+    /// The function's start address.
+    pub start: u64,
+    /// The function's size, in bytes.
+    pub size: u64,
+    /// The function's name.
+    pub name: String,
+}
+
+impl PerfMapEntry {
+    fn contains(&self, addr: u64) -> bool {
+        self.start <= addr && addr - self.start < self.size
+    }
+}
+
+/// The functions in a perf map file.
+#[derive(Clone, Debug)]
+pub struct PerfMap {
+    path: PathBuf,
+    entries: Vec<PerfMapEntry>,
+    /// How much of the file has been parsed, which always ends at the end of
+    /// a line.
+    offset: u64,
+    last_reload: Option<Instant>,
+    reload_interval: Duration,
+}
+
+impl PerfMap {
+    /// The perf map of the process with the given pid, at
+    /// `/tmp/perf-<pid>.map`.
+    pub fn for_pid(pid: u32) -> PerfMap {
+        PerfMap::new(format!("/tmp/perf-{}.map", pid))
+    }
+
+    /// The perf map at the given path. It is not read until the first
+    /// `lookup` or `reload`, so the file need not exist yet.
+    pub fn new<P: Into<PathBuf>>(path: P) -> PerfMap {
+        PerfMap {
+            path: path.into(),
+            entries: vec![],
+            offset: 0,
+            last_reload: None,
+            reload_interval: Duration::from_secs(1),
+        }
+    }
+
+    /// The path of the perf map file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Set how often, at most, `lookup` reloads the file. Defaults to once a
+    /// second.
+    pub fn reload_interval(&mut self, interval: Duration) -> &mut Self {
+        self.reload_interval = interval;
+        self
+    }
+
+    /// The functions read so far, oldest first.
+    pub fn entries(&self) -> &[PerfMapEntry] {
+        &self.entries
+    }
+
+    /// Find the function containing the given address, reloading the file
+    /// first if the address is unknown and the reload interval has passed.
+    pub fn lookup(&mut self, addr: u64) -> Option<&PerfMapEntry> {
+        let due = self.last_reload
+            .map_or(true, |last| last.elapsed() >= self.reload_interval);
+        if due && self.find(addr).is_none() {
+            // A missing or unreadable file just means no names.
+            let _ = self.reload();
+        }
+        self.find(addr)
+    }
+
+    /// Find the function containing the given address, without reloading.
+    pub fn find(&self, addr: u64) -> Option<&PerfMapEntry> {
+        self.entries.iter().rev().find(|entry| entry.contains(addr))
+    }
+
+    /// Read the lines appended to the file since it was last read. If the
+    /// file was truncated or replaced by a shorter one, it is read again from
+    /// the start.
+    pub fn reload(&mut self) -> io::Result<()> {
+        self.last_reload = Some(Instant::now());
+
+        let mut file = fs::File::open(&self.path)?;
+        if file.metadata()?.len() < self.offset {
+            self.entries.clear();
+            self.offset = 0;
+        }
+
+        let mut data = vec![];
+        file.seek(SeekFrom::Start(self.offset))?;
+        file.read_to_end(&mut data)?;
+
+        // Leave a partially written last line for the next reload.
+        let complete = match data.iter().rposition(|&b| b == b'\n') {
+            Some(newline) => newline + 1,
+            None => return Ok(()),
+        };
+        let text = String::from_utf8_lossy(&data[..complete]);
+        self.entries.extend(text.lines().filter_map(parse_line));
+        self.offset += complete as u64;
+        Ok(())
+    }
+}
+
+/// Parse a perf map line, skipping malformed lines.
+fn parse_line(line: &str) -> Option<PerfMapEntry> {
+    let mut fields = line.trim().splitn(3, char::is_whitespace);
+    let start = parse_hex(fields.next()?)?;
+    let size = parse_hex(fields.next()?)?;
+    let name = fields.next().unwrap_or("").trim().to_string();
+    Some(PerfMapEntry { start, size, name })
+}
+
+fn parse_hex(field: &str) -> Option<u64> {
+    let digits = if field.starts_with("0x") { &field[2..] } else { field };
+    u64::from_str_radix(digits, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::io::Write;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            parse_line("7f00a000 1c0 LazyCompile:~main /srv/app.js:1"),
+            Some(PerfMapEntry {
+                start: 0x7f00a000,
+                size: 0x1c0,
+                name: "LazyCompile:~main /srv/app.js:1".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_line("0x10 0x20 foo"),
+            Some(PerfMapEntry {
+                start: 0x10,
+                size: 0x20,
+                name: "foo".to_string(),
+            })
+        );
+        assert_eq!(parse_line("garbage"), None);
+        assert_eq!(parse_line(""), None);
+    }
+
+    #[test]
+    fn reload() {
+        let path = env::temp_dir().join(format!("pancakes-perf-map-{}.map", ::std::process::id()));
+        let mut file = fs::File::create(&path).unwrap();
+        file.write_all(b"1000 100 foo\n2000 10").unwrap();
+
+        let mut map = PerfMap::new(path.clone());
+        map.reload_interval(Duration::from_secs(0));
+        assert_eq!(map.lookup(0x1080).map(|e| &e.name[..]), Some("foo"));
+        assert_eq!(map.lookup(0x2000), None);
+
+        // Finish the partial line, and reuse `foo`'s memory.
+        file.write_all(b"0 bar\n1000 80 baz\n").unwrap();
+        assert_eq!(map.lookup(0x2000).map(|e| &e.name[..]), Some("bar"));
+        assert_eq!(map.lookup(0x1000).map(|e| &e.name[..]), Some("baz"));
+        assert_eq!(map.lookup(0x10c0).map(|e| &e.name[..]), Some("foo"));
+        assert_eq!(map.entries().len(), 3);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,677 @@
+//! A sampling CPU profiler for the current process.
+//!
+//! By default, the profiler arms `ITIMER_PROF`, which sends `SIGPROF` to the
+//! process each time it has used another interval of CPU time. The kernel
+//! delivers that signal to whichever thread it likes, which is usually, but
+//! not always, the one using the CPU. `Clock::ThreadCpuTime` instead arms a
+//! timer on each thread's own CPU-time clock that signals just that thread,
+//! so each thread's samples are proportional to its CPU usage.
+//!
+//! The handler copies the top of the interrupted thread's stack, walks the copy with the
+//! `Unwinder` the profiler was started with, and queues the instruction
+//! pointers it found. Walking the copy rather than the live stack means bad
+//! unwind information can only make the walk fail, never fault.
+//!
+//! Everything the handler uses is allocated when the profiler starts: one
+//! `UnwindContext` and stack buffer for each of the handlers that may run at
+//! once, and the queue of samples. Signals that arrive while every context
+//! is busy, or while the queue is full, are dropped and counted. A worker
+//! thread drains the queue every few milliseconds and aggregates identical
+//! stacks.
+//!
+//! Only one profiler runs at a time, and it replaces any other `SIGPROF`
+//! handler while it runs.
+//!
+//! ```no_run
+//! use pancakes::profiler::ProfilerOptions;
+//!
+//! let mut options = pancakes::Options::new();
+//! options.find_eh_frame_entries().unwrap();
+//!
+//! let profiler = ProfilerOptions::new()
+//!     .frequency(99)
+//!     .start(options.build_unwinder())
+//!     .unwrap();
+//! // ... do some work ...
+//! let profile = profiler.stop();
+//!
+//! for (stack, count) in profile.stacks() {
+//!     println!("{} samples: {:x?}", count, stack);
+//! }
+//! ```
+
+use super::{reader, FrameRegisters, Registers, StackWalkControl, TaggedWord, UnwindContext,
+            Unwinder};
+use error::{Error, Result};
+use ffi;
+use libc;
+use ring::Ring;
+use std::cell::UnsafeCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use threads;
+
+/// The most frames recorded for each sample. Deeper stacks lose their
+/// outermost frames.
+pub const MAX_DEPTH: usize = 128;
+
+/// How many samples the queue between the handler and the worker thread
+/// holds.
+const QUEUE_SAMPLES: usize = 1024;
+
+/// How often the worker thread drains the queue.
+const DRAIN_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The running profiler's state, or null.
+static SHARED: AtomicPtr<Shared> = AtomicPtr::new(0 as *mut Shared);
+
+/// How many handlers are using `SHARED`, which must not be freed until there
+/// are none.
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// Which CPU time a `Profiler` samples by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Clock {
+    /// One `ITIMER_PROF` timer for the whole process, whose signals are
+    /// delivered to an arbitrary thread.
+    ProcessCpuTime,
+    /// A timer on each thread's `CLOCK_THREAD_CPUTIME_ID` clock, whose
+    /// signals are delivered to that thread. Threads started while the
+    /// profiler runs get their timers within a few milliseconds.
+    ThreadCpuTime,
+}
+
+/// Configuration for a `Profiler`.
+#[derive(Clone, Debug)]
+pub struct ProfilerOptions {
+    clock: Clock,
+    frequency: u32,
+    stack_bytes: usize,
+    contexts: usize,
+}
+
+impl Default for ProfilerOptions {
+    fn default() -> Self {
+        ProfilerOptions {
+            clock: Clock::ProcessCpuTime,
+            frequency: 99,
+            stack_bytes: 32 * 1024,
+            contexts: thread::available_parallelism().map_or(4, |n| n.get()),
+        }
+    }
+}
+
+impl ProfilerOptions {
+    /// Construct a new `ProfilerOptions` with the default configuration: 99
+    /// samples per second of process CPU time, copying 32 KiB of each stack,
+    /// with a context for each CPU.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sample by this clock.
+    pub fn clock(&mut self, clock: Clock) -> &mut Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Take this many samples per second of CPU time the process, or with
+    /// `Clock::ThreadCpuTime` each thread, uses. The kernel rounds the
+    /// interval up to its timer resolution.
+    pub fn frequency(&mut self, hz: u32) -> &mut Self {
+        self.frequency = hz.max(1);
+        self
+    }
+
+    /// Copy this many bytes of the top of each sampled stack to walk. Frames
+    /// whose stack lies beyond the copy are not recorded.
+    pub fn stack_bytes(&mut self, bytes: usize) -> &mut Self {
+        self.stack_bytes = bytes;
+        self
+    }
+
+    /// Allocate this many unwind contexts, which bounds how many threads can
+    /// be sampled at once. Signals that arrive while every context is busy
+    /// are dropped.
+    pub fn contexts(&mut self, contexts: usize) -> &mut Self {
+        self.contexts = contexts.max(1);
+        self
+    }
+
+    /// Start profiling the current process, walking stacks with the given
+    /// unwinder, or fail with `Error::ProfilerAlreadyRunning` if a profiler
+    /// is already running.
+    pub fn start(&self, unwinder: Unwinder<'static>) -> Result<Profiler> {
+        let contexts = (0..self.contexts)
+            .map(|_| Context {
+                busy: AtomicBool::new(false),
+                cx: UnsafeCell::new(UnwindContext::new()),
+                stack: UnsafeCell::new(vec![0; self.stack_bytes].into_boxed_slice()),
+            })
+            .collect();
+        let empty = RawSample {
+            tid: 0,
+            len: 0,
+            ips: [0; MAX_DEPTH],
+        };
+        let shared = Arc::new(Shared {
+            unwinder,
+            contexts,
+            samples: Ring::new(QUEUE_SAMPLES, empty),
+            busy: AtomicU64::new(0),
+        });
+
+        let raw = Arc::as_ptr(&shared) as *mut Shared;
+        if SHARED
+            .compare_exchange(ptr::null_mut(), raw, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            return Err(Error::ProfilerAlreadyRunning);
+        }
+
+        let previous = match unsafe { install() } {
+            Ok(previous) => previous,
+            Err(e) => {
+                release();
+                return Err(e);
+            }
+        };
+        let timers = match self.clock {
+            Clock::ProcessCpuTime => unsafe { arm_process_timer(self.frequency) }.map(|()| None),
+            Clock::ThreadCpuTime => {
+                let mut timers = ThreadTimers::new(self.frequency);
+                timers.rescan().map(|()| Some(timers))
+            }
+        };
+        let mut timers = match timers {
+            Ok(timers) => timers,
+            Err(e) => {
+                unsafe {
+                    restore(&previous);
+                }
+                release();
+                return Err(e);
+            }
+        };
+
+        let aggregate = Arc::new(Mutex::new(Profile::default()));
+        let stopping = Arc::new(AtomicBool::new(false));
+        let worker = {
+            let shared = shared.clone();
+            let aggregate = aggregate.clone();
+            let stopping = stopping.clone();
+            thread::spawn(move || {
+                while !stopping.load(Ordering::SeqCst) {
+                    thread::sleep(DRAIN_INTERVAL);
+                    if let Some(ref mut timers) = timers {
+                        // Threads that exit mid-scan are skipped, and any
+                        // other failure is retried on the next scan.
+                        let _ = timers.rescan();
+                    }
+                    drain(&shared, &aggregate);
+                }
+                timers
+            })
+        };
+
+        Ok(Profiler {
+            shared,
+            aggregate,
+            stopping,
+            worker: Some(worker),
+            previous,
+            started: Instant::now(),
+        })
+    }
+}
+
+/// A running profiler, started with `ProfilerOptions::start`. Dropping it
+/// stops profiling.
+pub struct Profiler {
+    shared: Arc<Shared>,
+    aggregate: Arc<Mutex<Profile>>,
+    stopping: Arc<AtomicBool>,
+    worker: Option<JoinHandle<Option<ThreadTimers>>>,
+    previous: libc::sigaction,
+    started: Instant,
+}
+
+impl Profiler {
+    /// Get the samples aggregated so far, without stopping.
+    pub fn profile(&self) -> Profile {
+        drain(&self.shared, &self.aggregate);
+        self.snapshot()
+    }
+
+    /// Stop profiling, restore the previous `SIGPROF` handler, and get every
+    /// sample taken. If there was no previous handler, `SIGPROF` is left
+    /// ignored.
+    pub fn stop(mut self) -> Profile {
+        self.shutdown();
+        self.snapshot()
+    }
+
+    fn snapshot(&self) -> Profile {
+        let mut profile = self.aggregate.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        profile.dropped = self.shared.dropped();
+        profile.duration = self.started.elapsed();
+        profile
+    }
+
+    fn shutdown(&mut self) {
+        let worker = match self.worker.take() {
+            Some(worker) => worker,
+            None => return,
+        };
+
+        // Stop the worker first, so it stops arming timers for new threads,
+        // and delete the timers it armed.
+        self.stopping.store(true, Ordering::SeqCst);
+        drop(worker.join());
+        unsafe {
+            disarm_process_timer();
+            restore(&self.previous);
+        }
+        release();
+        drain(&self.shared, &self.aggregate);
+    }
+}
+
+impl Drop for Profiler {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+impl fmt::Debug for Profiler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Profiler")
+            .field("running", &self.worker.is_some())
+            .field("started", &self.started)
+            .finish()
+    }
+}
+
+/// The stacks sampled by a `Profiler`.
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    stacks: HashMap<Vec<usize>, u64>,
+    threads: HashMap<usize, u64>,
+    samples: u64,
+    dropped: u64,
+    duration: Duration,
+}
+
+impl Profile {
+    /// Iterate over each distinct stack sampled, as instruction pointers,
+    /// outermost last, and how many samples found it.
+    ///
+    /// The first instruction pointer is where the thread was interrupted.
+    /// The rest are return addresses, which point just after their calls.
+    pub fn stacks(&self) -> impl Iterator<Item = (&[usize], u64)> {
+        self.stacks.iter().map(|(stack, &count)| (&stack[..], count))
+    }
+
+    /// Iterate over the id of each thread sampled, and how many samples
+    /// were taken on it.
+    pub fn threads(&self) -> impl Iterator<Item = (usize, u64)> + '_ {
+        self.threads.iter().map(|(&tid, &count)| (tid, count))
+    }
+
+    /// Get how many samples were taken.
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    /// Get how many samples were dropped, because every unwind context was
+    /// busy or the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Get how long the profiler had been running.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+}
+
+/// What the handler shares with the `Profiler` and its worker thread.
+struct Shared {
+    unwinder: Unwinder<'static>,
+    contexts: Box<[Context]>,
+    samples: Ring<RawSample>,
+    /// Signals dropped because every context was busy.
+    busy: AtomicU64,
+}
+
+// Each context is only used by the handler that claimed it.
+unsafe impl Send for Shared {}
+unsafe impl Sync for Shared {}
+
+impl Shared {
+    fn dropped(&self) -> u64 {
+        self.busy.load(Ordering::Relaxed) + self.samples.dropped() as u64
+    }
+}
+
+/// The scratch space for one handler.
+struct Context {
+    busy: AtomicBool,
+    cx: UnsafeCell<UnwindContext<'static>>,
+    stack: UnsafeCell<Box<[u8]>>,
+}
+
+/// A sample, as queued by the handler.
+#[derive(Clone, Copy)]
+struct RawSample {
+    tid: usize,
+    len: usize,
+    ips: [usize; MAX_DEPTH],
+}
+
+/// Aggregate the queued samples into `aggregate`.
+fn drain(shared: &Shared, aggregate: &Mutex<Profile>) {
+    let mut aggregate = aggregate.lock().unwrap_or_else(|e| e.into_inner());
+    while let Some(sample) = shared.samples.pop() {
+        *aggregate.stacks.entry(sample.ips[..sample.len].to_vec()).or_insert(0) += 1;
+        *aggregate.threads.entry(sample.tid).or_insert(0) += 1;
+        aggregate.samples += 1;
+    }
+}
+
+/// Stop handlers from using the running profiler's state, and wait for those
+/// already using it to finish.
+fn release() {
+    SHARED.store(ptr::null_mut(), Ordering::SeqCst);
+    while ACTIVE.load(Ordering::SeqCst) != 0 {
+        thread::yield_now();
+    }
+}
+
+/// Install the handler, returning the previous handler.
+unsafe fn install() -> Result<libc::sigaction> {
+    let mut action: libc::sigaction = mem::zeroed();
+    action.sa_sigaction = handle_sigprof as usize;
+    action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART | libc::SA_ONSTACK;
+    libc::sigemptyset(&mut action.sa_mask);
+    let mut previous: libc::sigaction = mem::zeroed();
+    if libc::sigaction(libc::SIGPROF, &action, &mut previous) != 0 {
+        return Err(Error::Io(io::Error::last_os_error()));
+    }
+    Ok(previous)
+}
+
+/// Arm `ITIMER_PROF` at the given frequency.
+unsafe fn arm_process_timer(frequency: u32) -> Result<()> {
+    let micros = (1_000_000 / frequency as libc::suseconds_t).max(1);
+    let interval = libc::timeval {
+        tv_sec: 0,
+        tv_usec: micros,
+    };
+    let timer = libc::itimerval {
+        it_interval: interval,
+        it_value: interval,
+    };
+    if libc::setitimer(libc::ITIMER_PROF, &timer, ptr::null_mut()) != 0 {
+        return Err(Error::Io(io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+/// Disarm `ITIMER_PROF`, if it was armed.
+unsafe fn disarm_process_timer() {
+    let timer: libc::itimerval = mem::zeroed();
+    libc::setitimer(libc::ITIMER_PROF, &timer, ptr::null_mut());
+}
+
+/// A timer on each thread's CPU-time clock, that sends `SIGPROF` to that
+/// thread. The timers are deleted on drop.
+struct ThreadTimers {
+    interval: libc::timespec,
+    timers: HashMap<usize, libc::timer_t>,
+}
+
+// Timers are process-wide, and can be deleted from any thread.
+unsafe impl Send for ThreadTimers {}
+
+impl ThreadTimers {
+    fn new(frequency: u32) -> ThreadTimers {
+        let nanos = (1_000_000_000 / frequency as libc::c_long).max(1);
+        ThreadTimers {
+            interval: libc::timespec {
+                tv_sec: 0,
+                tv_nsec: nanos,
+            },
+            timers: HashMap::new(),
+        }
+    }
+
+    /// Arm timers for the threads started since the last scan, and delete
+    /// those of the threads that exited.
+    fn rescan(&mut self) -> Result<()> {
+        let tids = threads::thread_ids()?;
+        self.timers.retain(|tid, timer| {
+            if tids.contains(tid) {
+                return true;
+            }
+            unsafe {
+                libc::timer_delete(*timer);
+            }
+            false
+        });
+        for tid in tids {
+            if self.timers.contains_key(&tid) {
+                continue;
+            }
+            match unsafe { arm_thread_timer(tid, self.interval) } {
+                Ok(timer) => {
+                    self.timers.insert(tid, timer);
+                }
+                // The thread exited since it was listed.
+                Err(ref e) if e.raw_os_error() == Some(libc::EINVAL) => {}
+                Err(e) => return Err(Error::Io(e)),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ThreadTimers {
+    fn drop(&mut self) {
+        for (_, timer) in self.timers.drain() {
+            unsafe {
+                libc::timer_delete(timer);
+            }
+        }
+    }
+}
+
+/// Arm a timer on the CPU-time clock of the thread `tid`, that sends
+/// `SIGPROF` to it every `interval`.
+unsafe fn arm_thread_timer(tid: usize, interval: libc::timespec) -> io::Result<libc::timer_t> {
+    // `CLOCK_THREAD_CPUTIME_ID` is the calling thread's clock. Other threads'
+    // clocks are named the way the kernel's `MAKE_THREAD_CPUCLOCK` does:
+    // the complement of the tid, then `CPUCLOCK_PERTHREAD_MASK` and
+    // `CPUCLOCK_SCHED` in the low three bits.
+    let clock = ((!(tid as libc::clockid_t)) << 3) | 6;
+
+    let mut event: libc::sigevent = mem::zeroed();
+    event.sigev_notify = libc::SIGEV_THREAD_ID;
+    event.sigev_signo = libc::SIGPROF;
+    event.sigev_notify_thread_id = tid as libc::c_int;
+    let mut timer: libc::timer_t = mem::zeroed();
+    if libc::timer_create(clock, &mut event, &mut timer) != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let spec = libc::itimerspec {
+        it_interval: interval,
+        it_value: interval,
+    };
+    if libc::timer_settime(timer, 0, &spec, ptr::null_mut()) != 0 {
+        let e = io::Error::last_os_error();
+        libc::timer_delete(timer);
+        return Err(e);
+    }
+    Ok(timer)
+}
+
+/// Restore the handler that was installed before the profiler's.
+///
+/// A `SIGPROF` may still be pending after disarming the timer, and its
+/// default action is to terminate the process, so it is ignored instead of
+/// restoring the default.
+unsafe fn restore(previous: &libc::sigaction) {
+    if previous.sa_sigaction == libc::SIG_DFL {
+        let mut ignore: libc::sigaction = mem::zeroed();
+        ignore.sa_sigaction = libc::SIG_IGN;
+        libc::sigaction(libc::SIGPROF, &ignore, ptr::null_mut());
+    } else {
+        libc::sigaction(libc::SIGPROF, previous, ptr::null_mut());
+    }
+}
+
+extern "C" fn handle_sigprof(
+    _signal: libc::c_int,
+    _info: *mut libc::siginfo_t,
+    context: *mut libc::c_void,
+) {
+    unsafe {
+        // Walking may clobber `errno` for the interrupted code.
+        let errno = *errno_location();
+        ACTIVE.fetch_add(1, Ordering::SeqCst);
+        let shared = SHARED.load(Ordering::SeqCst);
+        if !shared.is_null() {
+            sample(&*shared, context as *const ffi::ucontext_t);
+        }
+        ACTIVE.fetch_sub(1, Ordering::SeqCst);
+        *errno_location() = errno;
+    }
+}
+
+/// Walk the interrupted thread's stack and queue its instruction pointers.
+unsafe fn sample(shared: &Shared, context: *const ffi::ucontext_t) {
+    let slot = match shared
+        .contexts
+        .iter()
+        .find(|slot| !slot.busy.swap(true, Ordering::Acquire))
+    {
+        Some(slot) => slot,
+        None => {
+            shared.busy.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+    };
+    let cx = &mut *slot.cx.get();
+    let stack = &mut *slot.stack.get();
+
+    let registers = FrameRegisters::from_ucontext(context);
+    let len = threads::copy_stack(&registers, stack);
+    let reader = reader::SliceMemory::new(registers.sp().unwrap_or(0), &stack[..len]);
+
+    let mut sample = RawSample {
+        tid: threads::current_tid(),
+        len: 0,
+        ips: [0; MAX_DEPTH],
+    };
+    shared.unwinder.walk(cx, &reader, &registers, |frame| match frame.registers().ip() {
+        TaggedWord::Valid(ip) if sample.len < MAX_DEPTH => {
+            sample.ips[sample.len] = ip;
+            sample.len += 1;
+            StackWalkControl::Continue
+        }
+        _ => StackWalkControl::Break,
+    });
+    slot.busy.store(false, Ordering::Release);
+
+    if sample.len > 0 {
+        shared.samples.push(&sample);
+    }
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn errno_location() -> *mut libc::c_int {
+    libc::__errno_location()
+}
+
+#[cfg(target_os = "android")]
+unsafe fn errno_location() -> *mut libc::c_int {
+    libc::__errno()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Options;
+
+    /// Only one profiler runs at a time, so the tests take turns.
+    static RUNNING: Mutex<()> = Mutex::new(());
+
+    fn unwinder() -> Unwinder<'static> {
+        let mut options = Options::new();
+        options.find_eh_frame_entries().unwrap();
+        options.build_unwinder()
+    }
+
+    fn burn(duration: Duration) {
+        let started = Instant::now();
+        let mut x = 0u64;
+        while started.elapsed() < duration {
+            x = x.wrapping_mul(31).wrapping_add(1);
+        }
+        let _ = ::std::hint::black_box(x);
+    }
+
+    #[test]
+    fn profile_busy_loop() {
+        let _running = RUNNING.lock().unwrap_or_else(|e| e.into_inner());
+        let profiler = ProfilerOptions::new().frequency(1000).start(unwinder()).unwrap();
+
+        match ProfilerOptions::new().start(Options::new().build_unwinder()) {
+            Err(Error::ProfilerAlreadyRunning) => {}
+            otherwise => panic!("unexpected result: {:?}", otherwise),
+        }
+
+        burn(Duration::from_millis(200));
+
+        let profile = profiler.stop();
+        assert!(profile.samples() > 0);
+        assert!(profile.stacks().all(|(stack, count)| !stack.is_empty() && count > 0));
+        assert_eq!(
+            profile.threads().map(|(_, count)| count).sum::<u64>(),
+            profile.samples()
+        );
+    }
+
+    #[test]
+    fn profile_each_thread() {
+        let _running = RUNNING.lock().unwrap_or_else(|e| e.into_inner());
+        let profiler = ProfilerOptions::new()
+            .clock(Clock::ThreadCpuTime)
+            .frequency(1000)
+            .start(unwinder())
+            .unwrap();
+
+        let burners: Vec<_> = (0..2)
+            .map(|_| {
+                thread::spawn(|| {
+                    burn(Duration::from_millis(200));
+                    threads::current_tid()
+                })
+            })
+            .collect();
+        let tids: Vec<_> = burners.into_iter().map(|b| b.join().unwrap()).collect();
+
+        let profile = profiler.stop();
+        for tid in tids {
+            assert!(profile.threads().any(|(sampled, count)| sampled == tid && count > 0));
+        }
+    }
+}
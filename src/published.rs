@@ -0,0 +1,90 @@
+//! A value that writers replace wholesale, and that walkers read without
+//! blocking, possibly from signal handlers.
+//!
+//! Readers count themselves in and out of the current epoch while they read
+//! the current value. Replacing the value starts a new epoch, and the old
+//! value is freed once the readers of the old epoch are done with it, so
+//! that readers that come and go continuously cannot hold a writer up.
+
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+pub(crate) struct Published<T> {
+    /// The current value, from `Box::into_raw`.
+    current: AtomicPtr<T>,
+    /// The index of the current epoch in `readers`.
+    epoch: AtomicUsize,
+    /// How many readers are reading in each epoch.
+    readers: [AtomicUsize; 2],
+    /// Serializes writers.
+    writer: Mutex<()>,
+}
+
+// Values are shared by readers on any thread, and freed on whichever thread
+// replaces or drops them.
+unsafe impl<T: Send + Sync> Send for Published<T> {}
+unsafe impl<T: Send + Sync> Sync for Published<T> {}
+
+impl<T> Published<T> {
+    pub(crate) fn new(value: T) -> Published<T> {
+        Published {
+            current: AtomicPtr::new(Box::into_raw(Box::new(value))),
+            epoch: AtomicUsize::new(0),
+            readers: [AtomicUsize::new(0), AtomicUsize::new(0)],
+            writer: Mutex::new(()),
+        }
+    }
+
+    /// Call `f` with the current value.
+    ///
+    /// This does not block or allocate.
+    pub(crate) fn read<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        // Register as a reader of the current epoch, making sure `update`
+        // did not start another before it could see the registration.
+        let epoch = loop {
+            let epoch = self.epoch.load(Ordering::SeqCst);
+            self.readers[epoch].fetch_add(1, Ordering::SeqCst);
+            if self.epoch.load(Ordering::SeqCst) == epoch {
+                break epoch;
+            }
+            self.readers[epoch].fetch_sub(1, Ordering::SeqCst);
+        };
+        let result = f(unsafe { &*self.current.load(Ordering::SeqCst) });
+        self.readers[epoch].fetch_sub(1, Ordering::SeqCst);
+        result
+    }
+
+    /// Replace the current value with a copy of it that `f` modified, and
+    /// free the old value once no reader is reading it.
+    pub(crate) fn update<F, R>(&self, f: F) -> R
+    where
+        T: Clone,
+        F: FnOnce(&mut T) -> R,
+    {
+        let _writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut value = unsafe { (*self.current.load(Ordering::SeqCst)).clone() };
+        let result = f(&mut value);
+
+        // Readers that register after the new epoch starts read the new
+        // value, so only the old epoch's readers can be reading the old one.
+        let old = self.current.swap(Box::into_raw(Box::new(value)), Ordering::SeqCst);
+        let epoch = self.epoch.load(Ordering::SeqCst);
+        self.epoch.store(1 - epoch, Ordering::SeqCst);
+        while self.readers[epoch].load(Ordering::SeqCst) != 0 {
+            thread::yield_now();
+        }
+        drop(unsafe { Box::from_raw(old) });
+        result
+    }
+}
+
+impl<T> Drop for Published<T> {
+    fn drop(&mut self) {
+        drop(unsafe { Box::from_raw(*self.current.get_mut()) });
+    }
+}
@@ -1,8 +1,22 @@
-//! TODO FITZGEN
+//! Implementations of `MemoryReader`, for reading the memory of this
+//! process, other processes, core dumps, and captured stacks.
 
-use super::{MemoryReader, Result};
+pub use core_dump::CoreDump;
+use super::{Error, MemoryReader, Result};
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+use super::{FrameRegisters, TaggedWord};
+use gimli::{self, Endianity};
+use std::cell::{Cell, RefCell};
+#[cfg(target_os = "linux")]
+use libc;
+#[cfg(target_os = "linux")]
+use std::io;
+use std::mem;
+use std::ops::Range;
+#[cfg(target_os = "linux")]
+use std::ptr;
 
-/// TODO FITZGEN
+/// A `MemoryReader` that reads the memory of the current process directly.
 #[derive(Debug)]
 pub struct ThisProcessMemory;
 
@@ -12,3 +26,823 @@ impl MemoryReader for ThisProcessMemory {
         Ok(addr.as_ref().cloned().unwrap())
     }
 }
+
+/// A `MemoryReader` that serves reads from a copy of a region of memory, such
+/// as the stack snapshot in a `perf` sample.
+///
+/// Reads of words that are not entirely within the region fail with
+/// `Error::InvalidAddress`.
+///
+/// ```
+/// use pancakes::MemoryReader;
+/// use pancakes::reader::SliceMemory;
+///
+/// let stack = [0x11, 0, 0, 0, 0, 0, 0, 0];
+/// let memory = SliceMemory::new(0x7000, &stack);
+/// # if cfg!(all(target_pointer_width = "64", target_endian = "little")) {
+/// assert_eq!(unsafe { memory.read(0x7000).unwrap() }, 0x11);
+/// # }
+/// assert!(unsafe { memory.read(0x7008).is_err() });
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct SliceMemory<'a> {
+    base: usize,
+    bytes: &'a [u8],
+}
+
+impl<'a> SliceMemory<'a> {
+    /// Construct a reader for a copy of the memory starting at address
+    /// `base`.
+    pub fn new(base: usize, bytes: &'a [u8]) -> SliceMemory<'a> {
+        SliceMemory { base, bytes }
+    }
+}
+
+impl<'a> MemoryReader for SliceMemory<'a> {
+    unsafe fn read(&self, addr: usize) -> Result<usize> {
+        let start = addr.checked_sub(self.base).ok_or(Error::InvalidAddress(addr))?;
+        let bytes = start
+            .checked_add(mem::size_of::<usize>())
+            .and_then(|end| self.bytes.get(start..end))
+            .ok_or(Error::InvalidAddress(addr))?;
+        let mut word = [0; mem::size_of::<usize>()];
+        word.copy_from_slice(bytes);
+        Ok(usize::from_ne_bytes(word))
+    }
+
+    unsafe fn read_bytes(&self, addr: usize, bytes: &mut [u8]) -> Result<()> {
+        let start = addr.checked_sub(self.base).ok_or(Error::InvalidAddress(addr))?;
+        let slice = start
+            .checked_add(bytes.len())
+            .and_then(|end| self.bytes.get(start..end))
+            .ok_or(Error::InvalidAddress(addr))?;
+        bytes.copy_from_slice(slice);
+        Ok(())
+    }
+}
+
+/// A `MemoryReader` that routes each read to the reader responsible for the
+/// address range containing it.
+///
+/// This combines several sources of memory into one reader for a single
+/// walk: for example, a `SliceMemory` snapshot of the stack, and a
+/// `CoreDump` for everything else.
+///
+/// Ranges should not overlap. Reads outside every range fail with
+/// `Error::InvalidAddress`.
+#[derive(Debug, Default)]
+pub struct Composite<'a> {
+    readers: Vec<(Range<usize>, Box<MemoryReader + 'a>)>,
+}
+
+impl<'a> Composite<'a> {
+    /// Construct a new, empty `Composite` reader.
+    pub fn new() -> Composite<'a> {
+        Default::default()
+    }
+
+    /// Route reads of addresses within `range` to `reader`.
+    pub fn add<R>(&mut self, range: Range<usize>, reader: R) -> &mut Self
+    where
+        R: MemoryReader + 'a,
+    {
+        let idx = match self.readers
+            .binary_search_by_key(&range.start, |&(ref r, _)| r.start)
+        {
+            Ok(idx) | Err(idx) => idx,
+        };
+        self.readers.insert(idx, (range, Box::new(reader)));
+        self
+    }
+}
+
+impl<'a> MemoryReader for Composite<'a> {
+    unsafe fn read(&self, addr: usize) -> Result<usize> {
+        let idx = match self.readers.binary_search_by_key(&addr, |&(ref r, _)| r.start) {
+            Ok(idx) => idx,
+            Err(0) => return Err(Error::InvalidAddress(addr)),
+            Err(idx) => idx - 1,
+        };
+        let (ref range, ref reader) = self.readers[idx];
+        if addr >= range.end {
+            return Err(Error::InvalidAddress(addr));
+        }
+        reader.read(addr)
+    }
+}
+
+/// A `MemoryReader` wrapper that caches whole pages of the inner reader's
+/// memory, for targets where each read is expensive, like another process.
+///
+/// On a miss, the aligned page containing the address is read with
+/// `MemoryReader::read_words` and cached. At most `max_pages` pages are
+/// cached, and the oldest page is evicted to make room for a new one. If a
+/// page cannot be read in its entirety, the word is read on its own and is not
+/// cached.
+///
+/// The target's memory may change between walks, so call `Cached::clear`
+/// before each walk when sampling a running process.
+#[derive(Debug)]
+pub struct Cached<R> {
+    inner: R,
+    page_words: usize,
+    max_pages: usize,
+    pages: RefCell<Vec<CachedPage>>,
+    next_victim: Cell<usize>,
+}
+
+#[derive(Debug)]
+struct CachedPage {
+    base: usize,
+    words: Vec<usize>,
+}
+
+impl<R> Cached<R>
+where
+    R: MemoryReader,
+{
+    /// The default page size, in bytes.
+    pub const DEFAULT_PAGE_SIZE: usize = 4096;
+
+    /// The default maximum number of cached pages.
+    pub const DEFAULT_MAX_PAGES: usize = 16;
+
+    /// Wrap the given reader with the default page size and cache size.
+    pub fn new(inner: R) -> Cached<R> {
+        Cached::with_capacity(inner, Self::DEFAULT_PAGE_SIZE, Self::DEFAULT_MAX_PAGES)
+    }
+
+    /// Wrap the given reader, caching at most `max_pages` pages of
+    /// `page_size` bytes each. The page size must be a power of two, and at
+    /// least a word.
+    pub fn with_capacity(inner: R, page_size: usize, max_pages: usize) -> Cached<R> {
+        assert!(page_size.is_power_of_two());
+        assert!(page_size >= mem::size_of::<usize>());
+        Cached {
+            inner,
+            page_words: page_size / mem::size_of::<usize>(),
+            max_pages,
+            pages: RefCell::new(Vec::with_capacity(max_pages)),
+            next_victim: Cell::new(0),
+        }
+    }
+
+    /// Forget every cached page.
+    pub fn clear(&mut self) {
+        self.pages.borrow_mut().clear();
+        self.next_victim.set(0);
+    }
+
+    /// Get the inner reader back.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R> MemoryReader for Cached<R>
+where
+    R: MemoryReader,
+{
+    unsafe fn read(&self, addr: usize) -> Result<usize> {
+        let word_size = mem::size_of::<usize>();
+        if addr % word_size != 0 || self.max_pages == 0 {
+            return self.inner.read(addr);
+        }
+
+        let page_size = self.page_words * word_size;
+        let base = addr & !(page_size - 1);
+        let idx = (addr - base) / word_size;
+
+        let mut pages = self.pages.borrow_mut();
+        if let Some(page) = pages.iter().find(|p| p.base == base) {
+            return Ok(page.words[idx]);
+        }
+
+        let mut words = vec![0; self.page_words];
+        if self.inner.read_words(base, &mut words).is_err() {
+            return self.inner.read(addr);
+        }
+        let word = words[idx];
+
+        let page = CachedPage { base, words };
+        if pages.len() < self.max_pages {
+            pages.push(page);
+        } else {
+            let victim = self.next_victim.get();
+            pages[victim] = page;
+            self.next_victim.set((victim + 1) % self.max_pages);
+        }
+        Ok(word)
+    }
+}
+
+/// A `MemoryReader` wrapper that only permits reads inside the given stack
+/// range, and any other ranges explicitly allowed.
+///
+/// Reads of words that are not entirely inside an allowed range fail with
+/// `Error::InvalidAddress`, instead of being passed to the inner reader. This
+/// turns the `MemoryReader` contract, that every address read is valid, into a
+/// runtime check, which is what a crash handler walking a possibly corrupt
+/// stack needs.
+///
+/// ```
+/// use pancakes::MemoryReader;
+/// use pancakes::reader::{Restricted, ThisProcessMemory};
+///
+/// let stack = [0usize; 4];
+/// let start = stack.as_ptr() as usize;
+/// let end = start + std::mem::size_of_val(&stack);
+/// let memory = Restricted::new(ThisProcessMemory, start..end);
+/// assert!(unsafe { memory.read(start) }.is_ok());
+/// assert!(unsafe { memory.read(end) }.is_err());
+/// ```
+#[derive(Debug)]
+pub struct Restricted<R> {
+    inner: R,
+    ranges: Vec<Range<usize>>,
+}
+
+impl<R> Restricted<R>
+where
+    R: MemoryReader,
+{
+    /// Wrap the given reader, permitting reads inside `stack`.
+    pub fn new(inner: R, stack: Range<usize>) -> Restricted<R> {
+        Restricted {
+            inner,
+            ranges: vec![stack],
+        }
+    }
+
+    /// Also permit reads inside `range`.
+    pub fn allow(&mut self, range: Range<usize>) -> &mut Self {
+        self.ranges.push(range);
+        self
+    }
+
+    /// Get the inner reader back.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R> MemoryReader for Restricted<R>
+where
+    R: MemoryReader,
+{
+    unsafe fn read(&self, addr: usize) -> Result<usize> {
+        let end = addr.checked_add(mem::size_of::<usize>());
+        let allowed = end.map_or(false, |end| {
+            self.ranges
+                .iter()
+                .any(|range| range.start <= addr && end <= range.end)
+        });
+        if !allowed {
+            return Err(Error::InvalidAddress(addr));
+        }
+        self.inner.read(addr)
+    }
+}
+
+/// A `MemoryReader` for a target whose endianness may differ from this
+/// host's, such as a big-endian core dump being analyzed on a little-endian
+/// machine.
+///
+/// The inner reader reads words in this host's byte order, and they are byte
+/// swapped if the target's endianness differs.
+#[derive(Debug)]
+pub struct TargetEndian<R> {
+    inner: R,
+    endian: gimli::RunTimeEndian,
+}
+
+impl<R> TargetEndian<R>
+where
+    R: MemoryReader,
+{
+    /// Construct a reader for a target with the given endianness.
+    pub fn new(inner: R, endian: gimli::RunTimeEndian) -> TargetEndian<R> {
+        TargetEndian { inner, endian }
+    }
+
+    /// Get the inner reader back.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R> MemoryReader for TargetEndian<R>
+where
+    R: MemoryReader,
+{
+    unsafe fn read(&self, addr: usize) -> Result<usize> {
+        let word = self.inner.read(addr)?;
+        if self.endian.is_big_endian() == cfg!(target_endian = "big") {
+            Ok(word)
+        } else {
+            Ok(word.swap_bytes())
+        }
+    }
+}
+
+/// A `MemoryReader` for another process on Linux, using `PTRACE_PEEKDATA`.
+///
+/// The process must already be attached to with `PTRACE_ATTACH` or
+/// `PTRACE_SEIZE`, and stopped, for as long as this reader is used.
+#[cfg(target_os = "linux")]
+#[derive(Debug)]
+pub struct PtraceMemory {
+    pid: libc::pid_t,
+}
+
+#[cfg(target_os = "linux")]
+impl PtraceMemory {
+    /// Construct a reader for the attached, stopped process with the given
+    /// pid.
+    pub fn new(pid: libc::pid_t) -> PtraceMemory {
+        PtraceMemory { pid }
+    }
+
+    /// Get the registers of the stopped process, to start walking its stack
+    /// from.
+    #[cfg(target_arch = "x86_64")]
+    pub fn registers(&self) -> Result<FrameRegisters> {
+        unsafe {
+            let mut regs: libc::user_regs_struct = mem::zeroed();
+            let r = libc::ptrace(
+                libc::PTRACE_GETREGS,
+                self.pid,
+                ptr::null_mut::<libc::c_void>(),
+                &mut regs as *mut libc::user_regs_struct as *mut libc::c_void,
+            );
+            if r == -1 {
+                return Err(Error::Io(io::Error::last_os_error()));
+            }
+
+            Ok(FrameRegisters::from_parts(
+                TaggedWord::valid(regs.rbp as usize),
+                TaggedWord::valid(regs.rsp as usize),
+                TaggedWord::valid(regs.rip as usize),
+            ))
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl MemoryReader for PtraceMemory {
+    unsafe fn read(&self, addr: usize) -> Result<usize> {
+        // `PTRACE_PEEKDATA` returns the word read, so the only way to tell a
+        // word of all ones apart from an error is to check `errno`.
+        *libc::__errno_location() = 0;
+        let word = libc::ptrace(
+            libc::PTRACE_PEEKDATA,
+            self.pid,
+            addr as *mut libc::c_void,
+            ptr::null_mut::<libc::c_void>(),
+        );
+        if word == -1 && *libc::__errno_location() != 0 {
+            return Err(Error::Io(io::Error::last_os_error()));
+        }
+        Ok(word as usize)
+    }
+}
+
+#[cfg(windows)]
+pub use self::windows::{SuspendedThread, WindowsProcessMemory};
+
+#[cfg(windows)]
+pub(crate) mod windows {
+    use super::super::{Error, MemoryReader, Result};
+    #[cfg(target_arch = "x86_64")]
+    use super::super::{Frame, FrameRegisters, TaggedWord, UnwindContext, Unwinder};
+    use std::io;
+    use std::mem;
+    use std::os::raw::{c_int, c_long, c_ulong, c_void};
+
+    pub(crate) type Handle = *mut c_void;
+    pub(crate) type Bool = c_int;
+    pub(crate) type Dword = c_ulong;
+
+    const INVALID_HANDLE_VALUE: Handle = !0 as Handle;
+    const PROCESS_VM_READ: Dword = 0x0010;
+    const TH32CS_SNAPTHREAD: Dword = 0x0004;
+    const THREAD_SUSPEND_RESUME: Dword = 0x0002;
+    const THREAD_GET_CONTEXT: Dword = 0x0008;
+
+    /// The most frames `WindowsProcessMemory::walk_threads` walks on each
+    /// thread, unless the unwinder's `Options::max_frames` says otherwise.
+    #[cfg(target_arch = "x86_64")]
+    const MAX_FRAMES: usize = 256;
+
+    /// `THREADENTRY32`, from `tlhelp32.h`.
+    #[repr(C)]
+    struct ThreadEntry32 {
+        size: Dword,
+        usage: Dword,
+        thread_id: Dword,
+        owner_process_id: Dword,
+        base_priority: c_long,
+        delta_priority: c_long,
+        flags: Dword,
+    }
+
+    extern "system" {
+        pub(crate) fn OpenProcess(access: Dword, inherit: Bool, process_id: Dword) -> Handle;
+        fn OpenThread(access: Dword, inherit: Bool, thread_id: Dword) -> Handle;
+        fn CloseHandle(handle: Handle) -> Bool;
+        fn GetCurrentProcessId() -> Dword;
+        fn GetCurrentThreadId() -> Dword;
+        fn CreateToolhelp32Snapshot(flags: Dword, process_id: Dword) -> Handle;
+        fn Thread32First(snapshot: Handle, entry: *mut ThreadEntry32) -> Bool;
+        fn Thread32Next(snapshot: Handle, entry: *mut ThreadEntry32) -> Bool;
+        fn ReadProcessMemory(
+            process: Handle,
+            base: *const c_void,
+            buf: *mut c_void,
+            size: usize,
+            read: *mut usize,
+        ) -> Bool;
+        fn SuspendThread(thread: Handle) -> Dword;
+        fn ResumeThread(thread: Handle) -> Dword;
+        #[cfg(target_arch = "x86_64")]
+        fn GetThreadContext(thread: Handle, context: *mut Context) -> Bool;
+    }
+
+    /// An owned Windows handle, closed on drop.
+    #[derive(Debug)]
+    pub(crate) struct OwnedHandle(pub(crate) Handle);
+
+    impl OwnedHandle {
+        pub(crate) fn new(handle: Handle) -> Result<OwnedHandle> {
+            if handle.is_null() {
+                Err(Error::Io(io::Error::last_os_error()))
+            } else {
+                Ok(OwnedHandle(handle))
+            }
+        }
+    }
+
+    impl Drop for OwnedHandle {
+        fn drop(&mut self) {
+            unsafe {
+                CloseHandle(self.0);
+            }
+        }
+    }
+
+    // The x64 `CONTEXT` structure, from `winnt.h`. We only need a handful of
+    // its fields, so it is treated as opaque bytes with known offsets.
+    #[cfg(target_arch = "x86_64")]
+    #[repr(C, align(16))]
+    struct Context([u8; 1232]);
+
+    #[cfg(target_arch = "x86_64")]
+    impl Context {
+        const CONTEXT_FLAGS: usize = 0x30;
+        const RSP: usize = 0x98;
+        const RBP: usize = 0xa0;
+        const RIP: usize = 0xf8;
+
+        const CONTEXT_CONTROL: u32 = 0x0010_0001;
+        const CONTEXT_INTEGER: u32 = 0x0010_0002;
+
+        fn u64_at(&self, offset: usize) -> u64 {
+            let mut word = [0; 8];
+            word.copy_from_slice(&self.0[offset..offset + 8]);
+            u64::from_ne_bytes(word)
+        }
+    }
+
+    /// A `MemoryReader` for another process on Windows, using
+    /// `ReadProcessMemory`.
+    ///
+    /// The process is opened with `PROCESS_VM_READ` access, which usually
+    /// requires that the process belongs to the same user, or that this
+    /// process has `SeDebugPrivilege`.
+    #[derive(Debug)]
+    pub struct WindowsProcessMemory {
+        process: OwnedHandle,
+        process_id: u32,
+    }
+
+    impl WindowsProcessMemory {
+        /// Open the process with the given id for reading.
+        pub fn open(process_id: u32) -> Result<WindowsProcessMemory> {
+            let process = unsafe { OpenProcess(PROCESS_VM_READ, 0, process_id as Dword) };
+            Ok(WindowsProcessMemory {
+                process: OwnedHandle::new(process)?,
+                process_id,
+            })
+        }
+
+        /// Get the ids of the process's threads, from a Toolhelp32 snapshot.
+        pub fn thread_ids(&self) -> Result<Vec<u32>> {
+            unsafe {
+                let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0);
+                if snapshot == INVALID_HANDLE_VALUE {
+                    return Err(Error::Io(io::Error::last_os_error()));
+                }
+                let snapshot = OwnedHandle::new(snapshot)?;
+
+                // The snapshot holds every thread in the system.
+                let mut thread_ids = vec![];
+                let mut entry: ThreadEntry32 = mem::zeroed();
+                entry.size = mem::size_of::<ThreadEntry32>() as Dword;
+                let mut more = Thread32First(snapshot.0, &mut entry);
+                while more != 0 {
+                    if entry.owner_process_id as u32 == self.process_id {
+                        thread_ids.push(entry.thread_id as u32);
+                    }
+                    more = Thread32Next(snapshot.0, &mut entry);
+                }
+                Ok(thread_ids)
+            }
+        }
+
+        /// Suspend one of the process's threads, until the returned
+        /// `SuspendedThread` is dropped.
+        ///
+        /// Suspending a thread of the current process that holds a lock, such
+        /// as the heap's, blocks every other thread that takes it until the
+        /// thread is resumed: avoid allocating while it is suspended.
+        pub fn suspend_thread(&self, thread_id: u32) -> Result<SuspendedThread> {
+            unsafe {
+                let thread = OpenThread(
+                    THREAD_SUSPEND_RESUME | THREAD_GET_CONTEXT,
+                    0,
+                    thread_id as Dword,
+                );
+                let thread = OwnedHandle::new(thread)?;
+
+                if SuspendThread(thread.0) == Dword::max_value() {
+                    return Err(Error::Io(io::Error::last_os_error()));
+                }
+                Ok(SuspendedThread { thread })
+            }
+        }
+
+        /// Capture the registers of one of the process's threads, to start
+        /// walking its stack from.
+        ///
+        /// The thread is suspended while its context is captured, and then
+        /// resumed. It keeps running afterwards, so the stack may have changed
+        /// by the time it is walked. Walk with `suspend_thread` or
+        /// `walk_threads` for consistent results.
+        #[cfg(target_arch = "x86_64")]
+        pub fn thread_registers(&self, thread_id: u32) -> Result<FrameRegisters> {
+            self.suspend_thread(thread_id)?.registers()
+        }
+
+        /// Walk the stack of every thread in the process, calling `f` with
+        /// each thread's id and its frames, outermost last.
+        ///
+        /// Each thread is suspended while its stack is walked, into a buffer
+        /// that was allocated beforehand, and resumed before `f` is called.
+        /// Threads that exit, or cannot be suspended, are skipped, and so is
+        /// the calling thread when walking the current process.
+        #[cfg(target_arch = "x86_64")]
+        pub fn walk_threads<F>(&self, unwinder: &Unwinder, mut f: F) -> Result<()>
+        where
+            F: FnMut(u32, &[Frame]),
+        {
+            let capacity = unwinder.opts.max_frames.unwrap_or(MAX_FRAMES);
+            let current = unsafe {
+                if GetCurrentProcessId() as u32 == self.process_id {
+                    Some(GetCurrentThreadId() as u32)
+                } else {
+                    None
+                }
+            };
+
+            // Allocate up front: a suspended thread may hold the heap lock.
+            let mut cx = UnwindContext::new();
+            let mut frames = Vec::with_capacity(capacity);
+            for thread_id in self.thread_ids()? {
+                if Some(thread_id) == current {
+                    continue;
+                }
+
+                frames.clear();
+                {
+                    let suspended = match self.suspend_thread(thread_id) {
+                        Ok(suspended) => suspended,
+                        Err(_) => continue,
+                    };
+                    let registers = match suspended.registers() {
+                        Ok(registers) => registers,
+                        Err(_) => continue,
+                    };
+                    for frame in unwinder.frames(&mut cx, self, registers) {
+                        match frame {
+                            Ok(frame) if frames.len() < frames.capacity() => frames.push(frame),
+                            _ => break,
+                        }
+                    }
+                }
+                f(thread_id, &frames);
+            }
+
+            Ok(())
+        }
+    }
+
+    /// A thread suspended by `WindowsProcessMemory::suspend_thread`, which is
+    /// resumed on drop.
+    #[derive(Debug)]
+    pub struct SuspendedThread {
+        thread: OwnedHandle,
+    }
+
+    impl SuspendedThread {
+        /// Capture the suspended thread's registers with `GetThreadContext`,
+        /// to start walking its stack from.
+        #[cfg(target_arch = "x86_64")]
+        pub fn registers(&self) -> Result<FrameRegisters> {
+            unsafe {
+                let mut context: Context = mem::zeroed();
+                let flags = Context::CONTEXT_CONTROL | Context::CONTEXT_INTEGER;
+                context.0[Context::CONTEXT_FLAGS..Context::CONTEXT_FLAGS + 4]
+                    .copy_from_slice(&flags.to_ne_bytes());
+                if GetThreadContext(self.thread.0, &mut context) == 0 {
+                    return Err(Error::Io(io::Error::last_os_error()));
+                }
+
+                Ok(FrameRegisters::from_parts(
+                    TaggedWord::valid(context.u64_at(Context::RBP) as usize),
+                    TaggedWord::valid(context.u64_at(Context::RSP) as usize),
+                    TaggedWord::valid(context.u64_at(Context::RIP) as usize),
+                ))
+            }
+        }
+    }
+
+    impl Drop for SuspendedThread {
+        fn drop(&mut self) {
+            unsafe {
+                ResumeThread(self.thread.0);
+            }
+        }
+    }
+
+    impl MemoryReader for WindowsProcessMemory {
+        unsafe fn read(&self, addr: usize) -> Result<usize> {
+            let mut word: usize = 0;
+            let mut read = 0;
+            let ok = ReadProcessMemory(
+                self.process.0,
+                addr as *const c_void,
+                &mut word as *mut usize as *mut c_void,
+                mem::size_of::<usize>(),
+                &mut read,
+            );
+            if ok == 0 || read != mem::size_of::<usize>() {
+                return Err(Error::Io(io::Error::last_os_error()));
+            }
+            Ok(word)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slice_memory() {
+        let size = mem::size_of::<usize>();
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&0x1234usize.to_ne_bytes());
+        bytes.extend_from_slice(&0x5678usize.to_ne_bytes());
+        let memory = SliceMemory::new(0x1000, &bytes);
+
+        unsafe {
+            assert_eq!(memory.read(0x1000).unwrap(), 0x1234);
+            assert_eq!(memory.read(0x1000 + size).unwrap(), 0x5678);
+            assert_eq!(memory.read_offset(0x1000 + size, -(size as isize)).unwrap(), 0x1234);
+            match memory.read(0x1000 + 2 * size) {
+                Err(Error::InvalidAddress(addr)) => assert_eq!(addr, 0x1000 + 2 * size),
+                otherwise => panic!("unexpected result: {:?}", otherwise),
+            }
+            assert!(memory.read(0x1001 + size).is_err());
+            assert!(memory.read(0xfff).is_err());
+        }
+    }
+
+    #[derive(Debug)]
+    struct WordMemory<'a>(SliceMemory<'a>);
+
+    impl<'a> MemoryReader for WordMemory<'a> {
+        unsafe fn read(&self, addr: usize) -> Result<usize> {
+            self.0.read(addr)
+        }
+    }
+
+    #[test]
+    fn typed_reads() {
+        let bytes: Vec<u8> = (0..16).collect();
+        let slice = SliceMemory::new(0x1000, &bytes);
+        // Use the default, word-at-a-time `read_bytes`, too.
+        let words = WordMemory(slice);
+
+        let big = gimli::RunTimeEndian::Big;
+        let little = gimli::RunTimeEndian::Little;
+        unsafe {
+            for memory in &[&slice as &MemoryReader, &words] {
+                assert_eq!(memory.read_u32(0x1000, big).unwrap(), 0x00010203);
+                assert_eq!(memory.read_u32(0x1006, little).unwrap(), 0x09080706);
+                assert_eq!(memory.read_u64(0x1003, big).unwrap(), 0x030405060708090a);
+                assert_eq!(memory.read_ptr(0x1004, 4, big).unwrap(), 0x04050607);
+                assert_eq!(memory.read_ptr(0x1008, 8, little).unwrap(), 0x0f0e0d0c0b0a0908);
+                assert!(memory.read_u32(0x100e, big).is_err());
+                match memory.read_ptr(0x1000, 2, big) {
+                    Err(Error::UnsupportedPointerWidth(2)) => {}
+                    otherwise => panic!("unexpected result: {:?}", otherwise),
+                }
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    struct CountingMemory<'a> {
+        memory: SliceMemory<'a>,
+        reads: Cell<usize>,
+    }
+
+    impl<'a> MemoryReader for CountingMemory<'a> {
+        unsafe fn read(&self, addr: usize) -> Result<usize> {
+            self.reads.set(self.reads.get() + 1);
+            self.memory.read(addr)
+        }
+    }
+
+    #[test]
+    fn cached() {
+        let size = mem::size_of::<usize>();
+        let mut bytes = vec![];
+        for i in 0..8usize {
+            bytes.extend_from_slice(&i.to_ne_bytes());
+        }
+        let inner = CountingMemory {
+            memory: SliceMemory::new(0x1000, &bytes),
+            reads: Cell::new(0),
+        };
+        let memory = Cached::with_capacity(inner, 4 * size, 1);
+
+        unsafe {
+            assert_eq!(memory.read(0x1000 + size).unwrap(), 1);
+            assert_eq!(memory.inner.reads.get(), 4);
+            assert_eq!(memory.read(0x1000 + 3 * size).unwrap(), 3);
+            assert_eq!(memory.inner.reads.get(), 4);
+
+            // Evicts the first page.
+            assert_eq!(memory.read(0x1000 + 4 * size).unwrap(), 4);
+            assert_eq!(memory.inner.reads.get(), 8);
+            assert_eq!(memory.read(0x1000).unwrap(), 0);
+            assert_eq!(memory.inner.reads.get(), 12);
+
+            assert!(memory.read(0x1000 + 8 * size).is_err());
+        }
+    }
+
+    #[test]
+    fn restricted() {
+        let size = mem::size_of::<usize>();
+        let mut bytes = vec![];
+        for i in 0..4usize {
+            bytes.extend_from_slice(&i.to_ne_bytes());
+        }
+
+        let mut memory = Restricted::new(SliceMemory::new(0x1000, &bytes), 0x1000..0x1000 + size);
+        unsafe {
+            assert_eq!(memory.read(0x1000).unwrap(), 0);
+            assert!(memory.read(0x1000 + 2 * size).is_err());
+            assert!(memory.read(0x1001).is_err());
+        }
+
+        memory.allow(0x1000 + 2 * size..0x1000 + 3 * size);
+        unsafe {
+            assert_eq!(memory.read(0x1000 + 2 * size).unwrap(), 2);
+            assert!(memory.read(0x1000 + size).is_err());
+        }
+    }
+
+    #[test]
+    fn composite() {
+        let size = mem::size_of::<usize>();
+        let stack = 1usize.to_ne_bytes();
+        let heap = 2usize.to_ne_bytes();
+
+        let mut memory = Composite::new();
+        memory
+            .add(0x2000..0x2000 + size, SliceMemory::new(0x2000, &heap))
+            .add(0x1000..0x1000 + size, SliceMemory::new(0x1000, &stack));
+
+        unsafe {
+            assert_eq!(memory.read(0x1000).unwrap(), 1);
+            assert_eq!(memory.read(0x2000).unwrap(), 2);
+            assert!(memory.read(0x1800).is_err());
+            assert!(memory.read(0x3000).is_err());
+            assert!(memory.read(0).is_err());
+        }
+    }
+}
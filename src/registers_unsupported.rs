@@ -0,0 +1,99 @@
+//! A stand-in register set for architectures that `pancakes` does not support
+//! yet.
+//!
+//! This lets crates that optionally depend on `pancakes` build everywhere.
+//! Every attempt to capture or unwind registers fails at runtime with
+//! `Error::UnsupportedArchitecture`.
+
+use super::{Error, MemoryReader, Registers, Result, TaggedWord, TargetEndianBuf};
+use expression;
+#[cfg(feature = "live")]
+use ffi;
+use gimli;
+
+// There is no DWARF register numbering for an unknown architecture. These are
+// only here so that `ManualRule` has something to refer to; looking up any
+// register fails.
+pub(crate) const BP: u8 = 0;
+pub(crate) const SP: u8 = 1;
+pub(crate) const IP: u8 = 2;
+
+/// Unknown architectures are assumed to have no pointer authentication.
+pub(crate) const POINTER_AUTH_MASK: usize = 0;
+
+/// The registers needed to unwind a frame on an unsupported architecture.
+#[derive(Clone, Debug)]
+pub struct FrameRegisters {
+    /// The frame base register for this frame.
+    bp: TaggedWord,
+
+    /// The stack pointer register for this frame.
+    sp: TaggedWord,
+
+    /// The instruction pointer register for this frame.
+    ip: TaggedWord,
+}
+
+impl FrameRegisters {
+    /// The name Breakpad symbol files use for the frame base register. No
+    /// Breakpad rule is ever named this.
+    pub(crate) const BREAKPAD_BP: &'static str = "";
+
+    /// Construct a register set from its individual registers.
+    pub(crate) fn from_parts(bp: TaggedWord, sp: TaggedWord, ip: TaggedWord) -> FrameRegisters {
+        FrameRegisters { bp, sp, ip }
+    }
+
+    /// Get the register with the given Breakpad name. There are none.
+    pub(crate) fn breakpad_register(&self, _name: &str) -> TaggedWord {
+        TaggedWord::invalid()
+    }
+
+    /// Construct a register set from a `ucontext_t`. Its layout is unknown,
+    /// so every register is invalid.
+    ///
+    /// This is unsafe because `ctx` must point to a valid `ucontext_t`.
+    #[cfg(feature = "live")]
+    pub unsafe fn from_ucontext(_ctx: *const ffi::ucontext_t) -> FrameRegisters {
+        FrameRegisters {
+            bp: TaggedWord::invalid(),
+            sp: TaggedWord::invalid(),
+            ip: TaggedWord::invalid(),
+        }
+    }
+
+    pub(crate) fn get_register(&self, _register_num: u8) -> Result<TaggedWord> {
+        Err(Error::UnsupportedArchitecture)
+    }
+
+    pub(crate) fn set_register(&mut self, _register_num: u8, _value: TaggedWord) -> Result<()> {
+        Err(Error::UnsupportedArchitecture)
+    }
+}
+
+impl Registers for FrameRegisters {
+    unsafe fn from_unwind_table_row<R>(
+        _row: &gimli::UnwindTableRow<TargetEndianBuf>,
+        _return_address_register: u8,
+        _scratch: &mut expression::Scratch,
+        _old_registers: &FrameRegisters,
+        _reader: &R,
+    ) -> Result<Self>
+    where
+        R: MemoryReader,
+    {
+        Err(Error::UnsupportedArchitecture)
+    }
+
+    #[cfg(feature = "live")]
+    fn with_current<F, T>(_f: F) -> Result<T>
+    where
+        F: FnMut(&Self) -> Result<T>,
+    {
+        Err(Error::UnsupportedArchitecture)
+    }
+
+    fn bp(&self) -> TaggedWord { self.bp }
+    fn sp(&self) -> TaggedWord { self.sp }
+    fn ip(&self) -> TaggedWord { self.ip }
+}
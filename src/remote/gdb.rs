@@ -0,0 +1,284 @@
+//! A client for the GDB remote serial protocol.
+//!
+//! This lets `pancakes` read the memory and registers of targets behind
+//! `gdbserver`, QEMU's gdbstub, or an embedded debug probe. Only the `m`
+//! (read memory) and `g` (read registers) packets are used. The target must
+//! already be stopped, and have the same pointer width and endianness as this
+//! host.
+//!
+//! ```no_run
+//! use pancakes::remote::gdb::GdbRemote;
+//!
+//! let target = GdbRemote::connect("localhost:1234").expect("should connect OK");
+//! # let _ = target;
+//! ```
+
+use super::super::{Error, MemoryReader, Result};
+#[cfg(target_arch = "x86_64")]
+use super::super::{FrameRegisters, TaggedWord};
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{Read, Write};
+use std::mem;
+use std::net::{TcpStream, ToSocketAddrs};
+
+/// The most bytes requested in a single `m` packet. Stubs are allowed to
+/// limit their packet size, and 4096 bytes of memory is 8192 bytes of hex.
+const MAX_READ: usize = 4096;
+
+/// A connection to a GDB remote stub, which implements `MemoryReader`.
+#[derive(Debug)]
+pub struct GdbRemote<S> {
+    stream: RefCell<S>,
+}
+
+impl GdbRemote<TcpStream> {
+    /// Connect to a stub listening on the given TCP address.
+    pub fn connect<A>(addr: A) -> Result<GdbRemote<TcpStream>>
+    where
+        A: ToSocketAddrs,
+    {
+        let stream = TcpStream::connect(addr).map_err(Error::Io)?;
+        stream.set_nodelay(true).map_err(Error::Io)?;
+        Ok(GdbRemote::new(stream))
+    }
+}
+
+impl<S> GdbRemote<S>
+where
+    S: Read + Write,
+{
+    /// Speak the protocol over the given stream, such as a serial port.
+    pub fn new(stream: S) -> GdbRemote<S> {
+        GdbRemote {
+            stream: RefCell::new(stream),
+        }
+    }
+
+    /// Send a command packet, and return the stub's response packet.
+    fn command(&self, command: &str) -> Result<Vec<u8>> {
+        let mut stream = self.stream.borrow_mut();
+
+        let checksum = command.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        write!(stream, "${}#{:02x}", command, checksum).map_err(Error::Io)?;
+        stream.flush().map_err(Error::Io)?;
+
+        let response = read_packet(&mut *stream)?;
+        stream.write_all(b"+").map_err(Error::Io)?;
+        stream.flush().map_err(Error::Io)?;
+        Ok(response)
+    }
+
+    /// Read target memory into `bytes`, starting at the given address.
+    pub fn read_memory(&self, addr: usize, bytes: &mut [u8]) -> Result<()> {
+        let mut done = 0;
+        while done < bytes.len() {
+            let at = addr.wrapping_add(done);
+            let n = (bytes.len() - done).min(MAX_READ);
+            let response = self.command(&format!("m{:x},{:x}", at, n))?;
+            if response.first() == Some(&b'E') {
+                return Err(Error::InvalidAddress(at));
+            }
+
+            let chunk = decode_hex(&response)?;
+            if chunk.is_empty() {
+                return Err(Error::InvalidAddress(at));
+            }
+            // Stubs may return fewer bytes than requested.
+            let n = chunk.len().min(n);
+            bytes[done..done + n].copy_from_slice(&chunk[..n]);
+            done += n;
+        }
+        Ok(())
+    }
+
+    /// Get the registers of the stopped target's current thread, to start
+    /// walking its stack from.
+    #[cfg(target_arch = "x86_64")]
+    pub fn registers(&self) -> Result<FrameRegisters> {
+        // The `g` packet's register order for x86_64 starts with rax, rbx,
+        // rcx, rdx, rsi, rdi, rbp, rsp, r8-r15, and then rip.
+        const RBP: usize = 6;
+        const RSP: usize = 7;
+        const RIP: usize = 16;
+
+        let registers = decode_hex(&self.command("g")?)?;
+        let reg = |i: usize| -> Result<TaggedWord> {
+            let bytes = registers
+                .get(i * 8..i * 8 + 8)
+                .ok_or(Error::InvalidRemoteResponse)?;
+            let mut word = [0; 8];
+            word.copy_from_slice(bytes);
+            Ok(TaggedWord::valid(u64::from_le_bytes(word) as usize))
+        };
+        Ok(FrameRegisters::from_parts(reg(RBP)?, reg(RSP)?, reg(RIP)?))
+    }
+}
+
+impl<S> MemoryReader for GdbRemote<S>
+where
+    S: Read + Write + fmt::Debug,
+{
+    unsafe fn read(&self, addr: usize) -> Result<usize> {
+        let mut word = [0; mem::size_of::<usize>()];
+        self.read_memory(addr, &mut word)?;
+        Ok(usize::from_ne_bytes(word))
+    }
+
+    unsafe fn read_bytes(&self, addr: usize, bytes: &mut [u8]) -> Result<()> {
+        self.read_memory(addr, bytes)
+    }
+}
+
+fn read_byte<S: Read>(stream: &mut S) -> Result<u8> {
+    let mut byte = [0];
+    stream.read_exact(&mut byte).map_err(Error::Io)?;
+    Ok(byte[0])
+}
+
+/// Read one packet, skipping any acknowledgements before it, and return its
+/// run-length decoded contents.
+fn read_packet<S: Read>(stream: &mut S) -> Result<Vec<u8>> {
+    loop {
+        match read_byte(stream)? {
+            b'$' => break,
+            b'+' => continue,
+            _ => return Err(Error::InvalidRemoteResponse),
+        }
+    }
+
+    let mut data = vec![];
+    let mut checksum = 0u8;
+    loop {
+        match read_byte(stream)? {
+            b'#' => break,
+            byte => {
+                checksum = checksum.wrapping_add(byte);
+                data.push(byte);
+            }
+        }
+    }
+
+    let expected = [read_byte(stream)?, read_byte(stream)?];
+    if decode_hex(&expected)? != [checksum] {
+        return Err(Error::InvalidRemoteResponse);
+    }
+
+    decode_run_length(&data)
+}
+
+/// Expand the protocol's run-length encoding, where `x*n` means `x` followed by
+/// `n - 29` more copies of it.
+fn decode_run_length(data: &[u8]) -> Result<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        if data[i] == b'*' {
+            let last = *decoded.last().ok_or(Error::InvalidRemoteResponse)?;
+            let count = data.get(i + 1).ok_or(Error::InvalidRemoteResponse)?;
+            let count = count.checked_sub(29).ok_or(Error::InvalidRemoteResponse)?;
+            for _ in 0..count {
+                decoded.push(last);
+            }
+            i += 2;
+        } else {
+            decoded.push(data[i]);
+            i += 1;
+        }
+    }
+    Ok(decoded)
+}
+
+fn decode_hex(hex: &[u8]) -> Result<Vec<u8>> {
+    fn nibble(c: u8) -> Result<u8> {
+        match c {
+            b'0'..=b'9' => Ok(c - b'0'),
+            b'a'..=b'f' => Ok(c - b'a' + 10),
+            b'A'..=b'F' => Ok(c - b'A' + 10),
+            _ => Err(Error::InvalidRemoteResponse),
+        }
+    }
+
+    if hex.len() % 2 != 0 {
+        return Err(Error::InvalidRemoteResponse);
+    }
+    hex.chunks(2)
+        .map(|pair| Ok(nibble(pair[0])? << 4 | nibble(pair[1])?))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{self, Cursor};
+
+    /// A stream that replays a canned response, and records what was sent.
+    #[derive(Debug)]
+    struct MockStream {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn packet(data: &str) -> Vec<u8> {
+        let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        format!("+${}#{:02x}", data, checksum).into_bytes()
+    }
+
+    fn remote(responses: &[&str]) -> GdbRemote<MockStream> {
+        let mut input = vec![];
+        for response in responses {
+            input.extend(packet(response));
+        }
+        GdbRemote::new(MockStream {
+            input: Cursor::new(input),
+            output: vec![],
+        })
+    }
+
+    #[test]
+    fn read_memory() {
+        let target = remote(&["0102030405"]);
+        let mut bytes = [0; 5];
+        target.read_memory(0x1000, &mut bytes).unwrap();
+        assert_eq!(bytes, [1, 2, 3, 4, 5]);
+        assert_eq!(target.stream.borrow().output, b"$m1000,5#8f+".to_vec());
+    }
+
+    #[test]
+    fn read_memory_error() {
+        let target = remote(&["E14"]);
+        let mut bytes = [0; 4];
+        match target.read_memory(0x1000, &mut bytes) {
+            Err(Error::InvalidAddress(0x1000)) => {}
+            otherwise => panic!("unexpected result: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn run_length() {
+        assert_eq!(decode_run_length(b"0* ").unwrap(), b"0000".to_vec());
+        assert!(decode_run_length(b"*!").is_err());
+    }
+
+    #[test]
+    fn bad_checksum() {
+        let mut input = Cursor::new(b"$00#ff".to_vec());
+        assert!(read_packet(&mut input).is_err());
+    }
+}
@@ -0,0 +1,4 @@
+//! Walking the stacks of targets that are only reachable through a debugging
+//! protocol.
+
+pub mod gdb;
@@ -0,0 +1,150 @@
+//! Keeping an `Unwinder` up to date with the modules loaded into this
+//! process.
+//!
+//! `Options::find_eh_frame_entries` only finds the modules loaded when it is
+//! called. `Unwinder::rescan_modules` finds the modules loaded and unloaded
+//! since, adds the `.eh_frame` of each new one with `Unwinder::add_eh_frame`,
+//! and removes the unwind information of each unloaded one. On Linux, the
+//! dynamic linker counts the modules it has loaded and unloaded, and a rescan
+//! that finds the counts unchanged returns without looking at any module.
+
+use super::{address_range, avma_range, ModuleId, ModuleSections, Unwinder};
+use findshlibs::{self, SharedLibrary};
+#[cfg(target_os = "linux")]
+use libc;
+#[cfg(target_os = "linux")]
+use std::mem;
+use std::ops::Range;
+#[cfg(target_os = "linux")]
+use std::ptr;
+
+/// What the last rescan found loaded.
+#[derive(Debug, Default)]
+pub(crate) struct Loaded {
+    /// The dynamic linker's count of loads and unloads, if it keeps one.
+    generation: Option<u64>,
+    modules: Vec<LoadedModule>,
+}
+
+/// A loaded module.
+#[derive(Debug)]
+struct LoadedModule {
+    range: Range<usize>,
+    /// The module, if it was added by a rescan rather than being in the
+    /// unwind information the `Unwinder` was built with.
+    added: Option<ModuleId>,
+}
+
+/// Rescan the loaded modules, and update `unwinder` with the ones loaded and
+/// unloaded since the last rescan. Returns whether any were.
+pub(crate) fn rescan(unwinder: &Unwinder) -> bool {
+    let mut loaded = unwinder.loaded.lock().unwrap_or_else(|e| e.into_inner());
+    let generation = generation();
+    if generation.is_some() && generation == loaded.generation {
+        return false;
+    }
+
+    let mut changed = false;
+    let mut found = vec![];
+    findshlibs::TargetSharedLibrary::each(|shlib| {
+        let bias = shlib.virtual_memory_bias();
+        let sections = ModuleSections::find(shlib);
+        let range = match sections.range {
+            Some(ref range) => address_range(range),
+            None => return findshlibs::IterationControl::Continue,
+        };
+        found.push(range.clone());
+        if loaded.modules.iter().any(|module| module.range == range) {
+            return findshlibs::IterationControl::Continue;
+        }
+
+        // The module's unwind information is only read while it is added,
+        // which is while the dynamic linker cannot unload it.
+        let added = if unwinder.has_unwind_info_for(&range) {
+            None
+        } else {
+            match (sections.eh_frame, sections.bases()) {
+                (Some((_, eh_frame)), Some(bases)) => {
+                    unwinder.add_eh_frame(bias, bases, eh_frame).ok()
+                }
+                _ => None,
+            }
+        };
+        loaded.modules.push(LoadedModule { range, added });
+        changed = true;
+
+        findshlibs::IterationControl::Continue
+    });
+
+    loaded.modules.retain(|module| {
+        if found.contains(&module.range) {
+            return true;
+        }
+        match module.added {
+            Some(id) => {
+                unwinder.remove_module(id);
+            }
+            None => unwinder.remove_entries_in_range(avma_range(&module.range)),
+        }
+        changed = true;
+        false
+    });
+
+    loaded.generation = generation;
+    changed
+}
+
+/// Get the dynamic linker's count of loads and unloads.
+#[cfg(target_os = "linux")]
+fn generation() -> Option<u64> {
+    extern "C" fn callback(
+        info: *mut libc::dl_phdr_info,
+        size: libc::size_t,
+        data: *mut libc::c_void,
+    ) -> libc::c_int {
+        // Older dynamic linkers pass a shorter `dl_phdr_info` without the
+        // counts.
+        if size < offset_of_subs() + mem::size_of::<u64>() {
+            return 1;
+        }
+        unsafe {
+            let info = &*info;
+            *(data as *mut Option<u64>) = Some(info.dlpi_adds + info.dlpi_subs);
+        }
+        // The counts are the same for every module, so stop at the first.
+        1
+    }
+
+    let mut generation: Option<u64> = None;
+    unsafe {
+        libc::dl_iterate_phdr(Some(callback), &mut generation as *mut _ as *mut libc::c_void);
+    }
+    generation
+}
+
+/// Get the offset of `dlpi_subs` within `dl_phdr_info`, which follows
+/// `dlpi_adds`, which follows the four fields every dynamic linker passes.
+#[cfg(target_os = "linux")]
+fn offset_of_subs() -> usize {
+    let info = mem::MaybeUninit::<libc::dl_phdr_info>::uninit();
+    let base = info.as_ptr();
+    let subs = unsafe { ptr::addr_of!((*base).dlpi_subs) };
+    subs as usize - base as usize
+}
+
+#[cfg(not(target_os = "linux"))]
+fn generation() -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use Options;
+
+    #[test]
+    fn rescan_finds_loaded_modules_once() {
+        let unwinder = Options::new().build_unwinder();
+        assert!(unwinder.rescan_modules());
+        assert!(!unwinder.rescan_modules());
+    }
+}
@@ -0,0 +1,144 @@
+//! A lock-free bounded queue of fixed-size values, that signal handlers push
+//! to and normal threads pop from.
+//!
+//! The queue is Dmitry Vyukov's bounded MPMC queue: each slot has a sequence
+//! number saying which lap of the queue it is ready for, and pushers and
+//! poppers claim positions with compare-and-swap. A signal handler that
+//! interrupts another push or a pop on its own thread claims a different
+//! position instead of waiting on it, so nothing ever blocks.
+
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A slot in a `Ring`.
+struct Slot<T> {
+    /// The slot's position in the queue: equal to the position of the next
+    /// value pushed to it while it is free, and one more than the position
+    /// of the value in it once that value has been written.
+    seq: AtomicUsize,
+    value: UnsafeCell<T>,
+}
+
+/// The queue. Its slots are all allocated in `new`.
+pub(crate) struct Ring<T> {
+    slots: Box<[Slot<T>]>,
+    /// The position of the next value to push.
+    enqueue: AtomicUsize,
+    /// The position of the next value to pop.
+    dequeue: AtomicUsize,
+    dropped: AtomicUsize,
+}
+
+// Each slot's value is only accessed by the one pusher or popper that claimed
+// its position.
+unsafe impl<T: Send> Sync for Ring<T> {}
+
+impl<T: Copy> Ring<T> {
+    /// Construct a new queue with room for `capacity` values, which must be
+    /// at least one. Every slot starts out as a copy of `empty`.
+    pub(crate) fn new(capacity: usize, empty: T) -> Ring<T> {
+        assert!(capacity > 0, "a Ring needs room for at least one value");
+        let slots = (0..capacity)
+            .map(|i| Slot {
+                seq: AtomicUsize::new(i),
+                value: UnsafeCell::new(empty),
+            })
+            .collect();
+        Ring {
+            slots,
+            enqueue: AtomicUsize::new(0),
+            dequeue: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    /// Add a value, or drop it if the queue is full. Returns whether it was
+    /// added.
+    pub(crate) fn push(&self, value: &T) -> bool {
+        let mut pos = self.enqueue.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos % self.slots.len()];
+            let seq = slot.seq.load(Ordering::Acquire);
+            if seq == pos {
+                if self.enqueue
+                    .compare_exchange_weak(pos, pos + 1, Ordering::Relaxed, Ordering::Relaxed)
+                    .is_err()
+                {
+                    pos = self.enqueue.load(Ordering::Relaxed);
+                    continue;
+                }
+                unsafe {
+                    *slot.value.get() = *value;
+                }
+                slot.seq.store(pos + 1, Ordering::Release);
+                return true;
+            } else if seq < pos {
+                // The slot still holds the value from a lap ago.
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                return false;
+            } else {
+                pos = self.enqueue.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Remove the oldest value, or return `None` if there are no finished
+    /// values. A value that is still being pushed is left for the next pop,
+    /// along with every value after it.
+    pub(crate) fn pop(&self) -> Option<T> {
+        let mut pos = self.dequeue.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos % self.slots.len()];
+            let seq = slot.seq.load(Ordering::Acquire);
+            if seq == pos + 1 {
+                if self.dequeue
+                    .compare_exchange_weak(pos, pos + 1, Ordering::Relaxed, Ordering::Relaxed)
+                    .is_err()
+                {
+                    pos = self.dequeue.load(Ordering::Relaxed);
+                    continue;
+                }
+                let value = unsafe { *slot.value.get() };
+                slot.seq.store(pos + self.slots.len(), Ordering::Release);
+                return Some(value);
+            } else if seq <= pos {
+                // Empty, or the value there is still being pushed.
+                return None;
+            } else {
+                pos = self.dequeue.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Get how many values the queue has room for.
+    pub(crate) fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Get how many values were dropped because the queue was full.
+    pub(crate) fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_and_pop() {
+        let ring = Ring::new(2, 0);
+        assert_eq!(ring.pop(), None);
+        assert!(ring.push(&1));
+        assert!(ring.push(&2));
+        assert!(!ring.push(&3));
+        assert_eq!(ring.dropped(), 1);
+
+        // Wrap around.
+        assert_eq!(ring.pop(), Some(1));
+        assert!(ring.push(&4));
+        assert_eq!(ring.pop(), Some(2));
+        assert_eq!(ring.pop(), Some(4));
+        assert_eq!(ring.pop(), None);
+    }
+}
@@ -0,0 +1,215 @@
+//! A per-thread cache of the unwind table rows resolved for each address.
+//!
+//! Sampling profilers walk through the same return addresses over and over,
+//! and finding the row covering an address runs its FDE's call frame
+//! instructions from the start. The cache remembers the row found for each
+//! address, compiled to the rules for the CFA, the frame base pointer and the
+//! return address, in a fixed number of slots allocated with the
+//! `UnwindContext`. Rows whose rules use DWARF expressions are not cached.
+//!
+//! The cache is open addressed: an address hashes to a slot, and is stored in
+//! one of the `PROBES` slots from there. When they are all full, the least
+//! recently used is replaced.
+
+use super::Bias;
+use compiled::CompiledRow;
+use std::fmt;
+
+/// The number of slots.
+const SLOTS: usize = 1024;
+
+/// The number of slots an address may be stored in.
+const PROBES: usize = 4;
+
+/// How often walks found the row for an address in an `UnwindContext`'s row
+/// cache.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RowCacheStats {
+    /// How many rows were found in the cache.
+    pub hits: u64,
+    /// How many rows were not, and were found by running their FDE's
+    /// instructions.
+    pub misses: u64,
+}
+
+impl RowCacheStats {
+    /// Get the fraction of lookups that hit, or zero if there were none.
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+/// A cached row.
+#[derive(Clone, Copy)]
+struct Slot {
+    ip: usize,
+    /// The bias of the module and the first address of the FDE the row was
+    /// found in, which must match for the row to be used, in case the
+    /// context is used with a different `Unwinder`.
+    bias: Bias,
+    fde_address: u64,
+    row: CompiledRow,
+    last_used: u64,
+}
+
+/// The row cache.
+pub(crate) struct RowCache {
+    slots: Box<[Option<Slot>]>,
+    /// Incremented on each use of a slot, to find the least recently used.
+    clock: u64,
+    stats: RowCacheStats,
+}
+
+impl RowCache {
+    /// Construct an empty cache.
+    pub(crate) fn new() -> RowCache {
+        RowCache {
+            slots: vec![None; SLOTS].into_boxed_slice(),
+            clock: 0,
+            stats: RowCacheStats::default(),
+        }
+    }
+
+    /// Get the index of the first slot the given address may be stored in,
+    /// with Fibonacci hashing.
+    fn home(ip: usize) -> usize {
+        let hash = (ip as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        (hash >> (64 - SLOTS.trailing_zeros())) as usize
+    }
+
+    /// Get the row cached for the given address in the given FDE.
+    pub(crate) fn lookup(
+        &mut self,
+        ip: usize,
+        bias: Bias,
+        fde_address: u64,
+    ) -> Option<CompiledRow> {
+        self.clock += 1;
+        let home = RowCache::home(ip);
+        for i in 0..PROBES {
+            if let Some(ref mut slot) = self.slots[(home + i) % SLOTS] {
+                if slot.ip == ip && slot.bias == bias && slot.fde_address == fde_address {
+                    slot.last_used = self.clock;
+                    self.stats.hits += 1;
+                    return Some(slot.row);
+                }
+            }
+        }
+        self.stats.misses += 1;
+        None
+    }
+
+    /// Cache the row found for the given address in the given FDE.
+    pub(crate) fn insert(&mut self, ip: usize, bias: Bias, fde_address: u64, row: CompiledRow) {
+        let home = RowCache::home(ip);
+        let mut victim = home;
+        for i in 0..PROBES {
+            let idx = (home + i) % SLOTS;
+            match self.slots[idx] {
+                None => {
+                    victim = idx;
+                    break;
+                }
+                Some(ref slot) if slot.ip == ip => {
+                    victim = idx;
+                    break;
+                }
+                Some(ref slot) => {
+                    let oldest = self.slots[victim].as_ref().map_or(0, |s| s.last_used);
+                    if slot.last_used < oldest {
+                        victim = idx;
+                    }
+                }
+            }
+        }
+        self.slots[victim] = Some(Slot {
+            ip,
+            bias,
+            fde_address,
+            row,
+            last_used: self.clock,
+        });
+    }
+
+    /// Get the hit and miss counts.
+    pub(crate) fn stats(&self) -> RowCacheStats {
+        self.stats
+    }
+
+    /// Reset the hit and miss counts.
+    pub(crate) fn reset_stats(&mut self) {
+        self.stats = RowCacheStats::default();
+    }
+}
+
+impl fmt::Debug for RowCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RowCache")
+            .field("cached", &self.slots.iter().filter(|slot| slot.is_some()).count())
+            .field("stats", &self.stats)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use compiled::CompiledRule;
+    use registers;
+
+    fn row(cfa_offset: i32) -> CompiledRow {
+        CompiledRow {
+            start: 0,
+            end: 0,
+            cfa_register: registers::SP,
+            cfa_offset,
+            bp: CompiledRule::SameValue,
+            ra: CompiledRule::Offset(-cfa_offset),
+        }
+    }
+
+    #[test]
+    fn hits_and_misses() {
+        let mut cache = RowCache::new();
+        assert_eq!(cache.lookup(0x1000, Bias(0), 0x1000), None);
+        cache.insert(0x1000, Bias(0), 0x1000, row(16));
+        assert_eq!(cache.lookup(0x1000, Bias(0), 0x1000), Some(row(16)));
+        // A different FDE, or module, at the same address.
+        assert_eq!(cache.lookup(0x1000, Bias(0), 0x800), None);
+        assert_eq!(cache.lookup(0x1000, Bias(0x10), 0x1000), None);
+
+        assert_eq!(cache.stats(), RowCacheStats { hits: 1, misses: 3 });
+        assert_eq!(cache.stats().hit_rate(), 0.25);
+        cache.reset_stats();
+        assert_eq!(cache.stats(), RowCacheStats::default());
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = RowCache::new();
+        // Find more addresses than probes that share a home slot.
+        let home = RowCache::home(0x1000);
+        let colliding: Vec<_> = (0x1000..)
+            .step_by(8)
+            .filter(|&ip| RowCache::home(ip) == home)
+            .take(PROBES + 1)
+            .collect();
+
+        for (i, &ip) in colliding[..PROBES].iter().enumerate() {
+            cache.insert(ip, Bias(0), 0, row(i as i32));
+            cache.lookup(ip, Bias(0), 0);
+        }
+        // Use the first again, so that the second is least recently used.
+        cache.lookup(colliding[0], Bias(0), 0);
+        cache.insert(colliding[PROBES], Bias(0), 0, row(PROBES as i32));
+
+        assert!(cache.lookup(colliding[0], Bias(0), 0).is_some());
+        assert!(cache.lookup(colliding[1], Bias(0), 0).is_none());
+        assert!(cache.lookup(colliding[PROBES], Bias(0), 0).is_some());
+    }
+}
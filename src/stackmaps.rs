@@ -0,0 +1,485 @@
+//! Parsing the `.llvm_stackmaps` section that LLVM emits for `gc.statepoint`,
+//! `stackmap`, and `patchpoint` intrinsics.
+//!
+//! Precise garbage collectors need to find every live GC reference held in
+//! each frame on the stack. LLVM records where those references live at each
+//! call site, relative to the frame's registers. After walking a frame, look
+//! up the stack map record for its instruction pointer with
+//! `StackMaps::record_for_frame`, and resolve each `Location` against the
+//! frame's recovered registers.
+//!
+//! Only version 3 of the format is supported. See
+//! <https://llvm.org/docs/StackMaps.html#stack-map-format> for details.
+
+use super::{Error, FrameRegisters, MemoryReader, Registers, Result};
+#[cfg(feature = "live")]
+use findshlibs::{self, NamedMemoryRange, SectionIterable, SharedLibrary};
+use std::mem;
+#[cfg(feature = "live")]
+use std::slice;
+
+const SUPPORTED_VERSION: u8 = 3;
+
+/// The parsed contents of a `.llvm_stackmaps` section.
+#[derive(Clone, Debug, Default)]
+pub struct StackMaps {
+    records: Vec<StackMapRecord>,
+}
+
+/// A single stack map record, describing the locations of live values at one
+/// call site.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StackMapRecord {
+    id: u64,
+    address: usize,
+    locations: Vec<Location>,
+    live_outs: Vec<LiveOut>,
+}
+
+/// Where a live value is found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Location {
+    /// The value is in the given DWARF register.
+    Register {
+        /// The DWARF register number.
+        register: u16,
+        /// The size of the value, in bytes.
+        size: u16,
+    },
+
+    /// The value is the address `register + offset`.
+    Direct {
+        /// The DWARF register number.
+        register: u16,
+        /// The offset from the register's value.
+        offset: i32,
+    },
+
+    /// The value is in memory, at `register + offset`.
+    Indirect {
+        /// The DWARF register number.
+        register: u16,
+        /// The offset from the register's value.
+        offset: i32,
+        /// The size of the value, in bytes.
+        size: u16,
+    },
+
+    /// The value is the given constant.
+    Constant(u64),
+}
+
+/// A register that is live across a patch point.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LiveOut {
+    /// The DWARF register number.
+    pub register: u16,
+    /// The size of the live value, in bytes.
+    pub size: u8,
+}
+
+impl StackMaps {
+    /// Parse the contents of a `.llvm_stackmaps` section, in native
+    /// endianness.
+    ///
+    /// Function addresses are used as they appear in `data`: if the section
+    /// has been relocated by the dynamic loader, they are actual virtual
+    /// memory addresses.
+    pub fn parse(data: &[u8]) -> Result<StackMaps> {
+        let mut input = Input { data, offset: 0 };
+
+        let version = input.read_u8()?;
+        if version != SUPPORTED_VERSION {
+            return Err(Error::InvalidStackMaps);
+        }
+        input.skip(3)?;
+
+        let num_functions = input.read_u32()? as usize;
+        let num_constants = input.read_u32()? as usize;
+        let num_records = input.read_u32()? as usize;
+
+        let mut functions = Vec::with_capacity(num_functions);
+        for _ in 0..num_functions {
+            let address = input.read_u64()?;
+            let _stack_size = input.read_u64()?;
+            let record_count = input.read_u64()?;
+            functions.push((address, record_count));
+        }
+
+        let mut constants = Vec::with_capacity(num_constants);
+        for _ in 0..num_constants {
+            constants.push(input.read_u64()?);
+        }
+
+        let mut records = Vec::with_capacity(num_records);
+        for (function_address, record_count) in functions {
+            for _ in 0..record_count {
+                records.push(StackMapRecord::parse(&mut input, function_address, &constants)?);
+            }
+        }
+        if records.len() != num_records {
+            return Err(Error::InvalidStackMaps);
+        }
+
+        records.sort_by_key(|r| r.address);
+        Ok(StackMaps { records })
+    }
+
+    /// Find and parse the stack maps of every module loaded in this process.
+    #[cfg(feature = "live")]
+    pub fn find_in_this_process() -> Result<StackMaps> {
+        cfg_if! {
+            if #[cfg(any(target_os = "macos", target_os = "ios"))] {
+                const LLVM_STACKMAPS: &'static [u8] = b"__llvm_stackmaps";
+            } else {
+                const LLVM_STACKMAPS: &'static [u8] = b".llvm_stackmaps";
+            }
+        }
+
+        let mut stack_maps = StackMaps::default();
+        let mut result = Ok(());
+        findshlibs::TargetSharedLibrary::each(|shlib| {
+            for section in shlib.sections() {
+                if section.name().to_bytes() == LLVM_STACKMAPS {
+                    let ptr = section.actual_virtual_memory_address(shlib).0 as *const u8;
+                    let data = unsafe { slice::from_raw_parts(ptr, section.len()) };
+                    match StackMaps::parse(data) {
+                        Ok(parsed) => stack_maps.records.extend(parsed.records),
+                        Err(e) => {
+                            result = Err(e);
+                            return findshlibs::IterationControl::Break;
+                        }
+                    }
+                }
+            }
+            findshlibs::IterationControl::Continue
+        });
+        result?;
+
+        stack_maps.records.sort_by_key(|r| r.address);
+        Ok(stack_maps)
+    }
+
+    /// Get all records, sorted by address.
+    pub fn records(&self) -> &[StackMapRecord] {
+        &self.records
+    }
+
+    /// Find the record for the call site whose return address is `address`.
+    pub fn record_for(&self, address: usize) -> Option<&StackMapRecord> {
+        self.records
+            .binary_search_by_key(&address, |r| r.address)
+            .ok()
+            .map(|idx| &self.records[idx])
+    }
+
+    /// Find the record for the call site at which the given walked frame is
+    /// suspended.
+    pub fn record_for_frame(&self, frame: &FrameRegisters) -> Option<&StackMapRecord> {
+        frame.ip().map_or(None, |ip| self.record_for(ip))
+    }
+}
+
+impl StackMapRecord {
+    fn parse(input: &mut Input, function_address: u64, constants: &[u64]) -> Result<Self> {
+        let id = input.read_u64()?;
+        let instruction_offset = input.read_u32()?;
+        let _flags = input.read_u16()?;
+
+        let num_locations = input.read_u16()? as usize;
+        let mut locations = Vec::with_capacity(num_locations);
+        for _ in 0..num_locations {
+            let kind = input.read_u8()?;
+            input.skip(1)?;
+            let size = input.read_u16()?;
+            let register = input.read_u16()?;
+            input.skip(2)?;
+            let offset = input.read_i32()?;
+
+            locations.push(match kind {
+                1 => Location::Register { register, size },
+                2 => Location::Direct { register, offset },
+                3 => Location::Indirect {
+                    register,
+                    offset,
+                    size,
+                },
+                4 => Location::Constant(offset as i64 as u64),
+                5 => {
+                    let constant = constants
+                        .get(offset as usize)
+                        .ok_or(Error::InvalidStackMaps)?;
+                    Location::Constant(*constant)
+                }
+                _ => return Err(Error::InvalidStackMaps),
+            });
+        }
+        input.align(8)?;
+
+        input.skip(2)?;
+        let num_live_outs = input.read_u16()? as usize;
+        let mut live_outs = Vec::with_capacity(num_live_outs);
+        for _ in 0..num_live_outs {
+            let register = input.read_u16()?;
+            input.skip(1)?;
+            let size = input.read_u8()?;
+            live_outs.push(LiveOut { register, size });
+        }
+        input.align(8)?;
+
+        Ok(StackMapRecord {
+            id,
+            address: function_address.wrapping_add(instruction_offset as u64) as usize,
+            locations,
+            live_outs,
+        })
+    }
+
+    /// The patch point or statepoint ID.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The address just after the call instruction this record describes.
+    pub fn address(&self) -> usize {
+        self.address
+    }
+
+    /// The locations of the live values.
+    pub fn locations(&self) -> &[Location] {
+        &self.locations
+    }
+
+    /// The registers that are live across the call.
+    pub fn live_outs(&self) -> &[LiveOut] {
+        &self.live_outs
+    }
+}
+
+impl Location {
+    /// If this value lives in memory, get its address given the frame's
+    /// recovered registers.
+    ///
+    /// The stack pointer recovered for a frame is the CFA of the frame it
+    /// called, which is the stack pointer that stack map offsets are
+    /// relative to.
+    pub fn address(&self, frame: &FrameRegisters) -> Result<Option<usize>> {
+        match *self {
+            Location::Indirect {
+                register, offset, ..
+            } => {
+                let base = register_value(frame, register)?;
+                Ok(Some((base as isize).wrapping_add(offset as isize) as usize))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Get this value, given the frame's recovered registers.
+    ///
+    /// Values larger than a word are not supported.
+    pub unsafe fn value<Reader>(&self, frame: &FrameRegisters, reader: &Reader) -> Result<usize>
+    where
+        Reader: MemoryReader,
+    {
+        match *self {
+            Location::Register { register, .. } => register_value(frame, register),
+            Location::Direct { register, offset } => {
+                let base = register_value(frame, register)?;
+                Ok((base as isize).wrapping_add(offset as isize) as usize)
+            }
+            Location::Indirect { size, .. } if size as usize > mem::size_of::<usize>() => {
+                Err(Error::InvalidStackMaps)
+            }
+            Location::Indirect { .. } => {
+                let address = self.address(frame)?.expect("indirect locations have addresses");
+                reader.read(address)
+            }
+            Location::Constant(c) => Ok(c as usize),
+        }
+    }
+}
+
+fn register_value(frame: &FrameRegisters, register: u16) -> Result<usize> {
+    if register > u8::max_value() as u16 {
+        return Err(Error::UnknownRegister(u8::max_value()));
+    }
+    frame.get_register(register as u8)?.into()
+}
+
+/// A cursor over the native-endian contents of a stack maps section.
+struct Input<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+macro_rules! read_int {
+    ( $name:ident , $ty:ty ) => {
+        fn $name(&mut self) -> Result<$ty> {
+            let size = mem::size_of::<$ty>();
+            let bytes = self.data
+                .get(self.offset..self.offset + size)
+                .ok_or(Error::InvalidStackMaps)?;
+            self.offset += size;
+            let mut value: $ty = 0;
+            unsafe {
+                ::std::ptr::copy_nonoverlapping(
+                    bytes.as_ptr(),
+                    &mut value as *mut $ty as *mut u8,
+                    size,
+                );
+            }
+            Ok(value)
+        }
+    }
+}
+
+impl<'a> Input<'a> {
+    read_int!(read_u8, u8);
+    read_int!(read_u16, u16);
+    read_int!(read_u32, u32);
+    read_int!(read_i32, i32);
+    read_int!(read_u64, u64);
+
+    fn skip(&mut self, n: usize) -> Result<()> {
+        if self.offset + n > self.data.len() {
+            return Err(Error::InvalidStackMaps);
+        }
+        self.offset += n;
+        Ok(())
+    }
+
+    fn align(&mut self, alignment: usize) -> Result<()> {
+        let padding = (alignment - self.offset % alignment) % alignment;
+        self.skip(padding)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use TaggedWord;
+    use std::collections::HashMap;
+
+    #[derive(Debug)]
+    struct MapMemory(HashMap<usize, usize>);
+
+    impl MemoryReader for MapMemory {
+        unsafe fn read(&self, addr: usize) -> Result<usize> {
+            self.0
+                .get(&addr)
+                .cloned()
+                .ok_or(Error::NoUnwindInfoForAddress(addr))
+        }
+    }
+
+    fn push<T: Copy>(data: &mut Vec<u8>, value: T) {
+        let bytes = unsafe {
+            ::std::slice::from_raw_parts(&value as *const T as *const u8, mem::size_of::<T>())
+        };
+        data.extend_from_slice(bytes);
+    }
+
+    fn push_location(data: &mut Vec<u8>, kind: u8, size: u16, register: u16, offset: i32) {
+        push(data, kind);
+        push(data, 0u8);
+        push(data, size);
+        push(data, register);
+        push(data, 0u16);
+        push(data, offset);
+    }
+
+    fn section() -> Vec<u8> {
+        let mut data = vec![];
+        push(&mut data, 3u8);
+        push(&mut data, 0u8);
+        push(&mut data, 0u16);
+        push(&mut data, 1u32); // functions
+        push(&mut data, 1u32); // constants
+        push(&mut data, 2u32); // records
+
+        push(&mut data, 0x1000u64);
+        push(&mut data, 32u64);
+        push(&mut data, 2u64);
+
+        push(&mut data, 0xdead_beef_u64);
+
+        // Record with one indirect location and one large constant.
+        push(&mut data, 42u64);
+        push(&mut data, 0x10u32);
+        push(&mut data, 0u16);
+        push(&mut data, 2u16);
+        push_location(&mut data, 3, 8, 7, 16);
+        push_location(&mut data, 5, 8, 0, 0);
+        push(&mut data, 0u16);
+        push(&mut data, 0u16);
+        push(&mut data, 0u32); // align to 8
+
+        // Record with no locations and one live out.
+        push(&mut data, 43u64);
+        push(&mut data, 0x20u32);
+        push(&mut data, 0u16);
+        push(&mut data, 0u16);
+        push(&mut data, 0u16);
+        push(&mut data, 1u16);
+        push(&mut data, 6u16);
+        push(&mut data, 0u8);
+        push(&mut data, 8u8);
+
+        data
+    }
+
+    #[test]
+    fn parse_stack_maps() {
+        let maps = StackMaps::parse(&section()).expect("should parse OK");
+        assert_eq!(maps.records().len(), 2);
+
+        let record = maps.record_for(0x1010).expect("should have a record");
+        assert_eq!(record.id(), 42);
+        assert_eq!(
+            record.locations(),
+            &[
+                Location::Indirect {
+                    register: 7,
+                    offset: 16,
+                    size: 8,
+                },
+                Location::Constant(0xdead_beef),
+            ]
+        );
+
+        let record = maps.record_for(0x1020).expect("should have a record");
+        assert_eq!(record.id(), 43);
+        assert_eq!(record.live_outs(), &[LiveOut { register: 6, size: 8 }]);
+
+        assert!(maps.record_for(0x1018).is_none());
+    }
+
+    #[test]
+    fn parse_rejects_bad_version() {
+        let mut data = section();
+        data[0] = 2;
+        assert!(StackMaps::parse(&data).is_err());
+        assert!(StackMaps::parse(&section()[..40]).is_err());
+    }
+
+    #[test]
+    fn resolve_locations() {
+        let maps = StackMaps::parse(&section()).expect("should parse OK");
+        let frame = FrameRegisters::from_parts(
+            TaggedWord::valid(0x2000),
+            TaggedWord::valid(0x7f00),
+            TaggedWord::valid(0x1010),
+        );
+        let record = maps.record_for_frame(&frame).expect("should have a record");
+
+        let mut memory = HashMap::new();
+        memory.insert(0x7f10, 0xcafe);
+        let reader = MapMemory(memory);
+
+        let locations = record.locations();
+        assert_eq!(locations[0].address(&frame).unwrap(), Some(0x7f10));
+        assert_eq!(unsafe { locations[0].value(&frame, &reader).unwrap() }, 0xcafe);
+        assert_eq!(unsafe { locations[1].value(&frame, &reader).unwrap() }, 0xdead_beef);
+    }
+}
@@ -0,0 +1,221 @@
+//! Counters of what walking stacks cost and found, for tuning how often a
+//! profiler samples.
+//!
+//! Every `Unwinder` counts its walks as they happen, with relaxed atomic
+//! additions, so that counting is cheap and safe in signal handlers and on
+//! every thread sharing the `Unwinder`. `Unwinder::stats` takes a snapshot of
+//! the counters as an `UnwindStats`, and `Unwinder::reset_stats` zeroes them.
+
+use super::{Error, RowCacheStats, UnwindStrategy};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// A snapshot of an `Unwinder`'s counters.
+///
+/// The counters are read one at a time, so a snapshot taken while other
+/// threads walk may count part of a walk.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UnwindStats {
+    /// How many walks there were: calls to `walk`, and `Frames` iterators
+    /// that were dropped.
+    pub walks: u64,
+    /// How many frames the walks yielded.
+    pub frames: u64,
+    /// How many frames were walked to their callers with each strategy.
+    pub strategies: StrategyCounts,
+    /// How many frames were walked with a strategy other than the first in
+    /// `Options::strategies`, because the ones before it had no unwind
+    /// information for the frame.
+    pub fallbacks: u64,
+    /// How many frames walked with DWARF call frame information found their
+    /// unwind table row in their `UnwindContext`'s row cache, and how many
+    /// did not.
+    pub row_cache: RowCacheStats,
+    /// How many frames could not be walked to their callers, by why not.
+    pub errors: ErrorCounts,
+    /// The total time spent walking, including in the `walk` callbacks.
+    pub walk_time: Duration,
+}
+
+impl UnwindStats {
+    /// Get the mean time per walk, or zero if there were none.
+    pub fn mean_walk_time(&self) -> Duration {
+        if self.walks == 0 {
+            return Duration::from_secs(0);
+        }
+        let nanos = self.walk_time.as_nanos() / self.walks as u128;
+        Duration::from_nanos(nanos as u64)
+    }
+}
+
+/// How many frames were walked with each `UnwindStrategy`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StrategyCounts {
+    /// `UnwindStrategy::Manual`.
+    pub manual: u64,
+    /// `UnwindStrategy::Dwarf`.
+    pub dwarf: u64,
+    /// `UnwindStrategy::Breakpad`.
+    pub breakpad: u64,
+    /// `UnwindStrategy::FramePointer`.
+    pub frame_pointer: u64,
+}
+
+/// How many frames could not be walked, by the kind of error.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ErrorCounts {
+    /// `Error::NoUnwindInfoForAddress`.
+    pub no_unwind_info: u64,
+    /// `Error::InvalidAddress`.
+    pub invalid_address: u64,
+    /// `Error::NonProgressingUnwind`.
+    pub non_progressing: u64,
+    /// `Error::InvalidCfiExpression` and `Error::CfiExpressionOutOfFuel`.
+    pub cfi_expression: u64,
+    /// Every other error.
+    pub other: u64,
+}
+
+/// The counters behind `UnwindStats`.
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    walks: AtomicU64,
+    frames: AtomicU64,
+    /// Indexed like `strategy_index`.
+    strategies: [AtomicU64; 4],
+    fallbacks: AtomicU64,
+    row_cache_hits: AtomicU64,
+    row_cache_misses: AtomicU64,
+    /// Indexed like `error_index`.
+    errors: [AtomicU64; 5],
+    walk_nanos: AtomicU64,
+}
+
+fn add(counter: &AtomicU64, n: u64) {
+    counter.fetch_add(n, Ordering::Relaxed);
+}
+
+fn strategy_index(strategy: UnwindStrategy) -> usize {
+    match strategy {
+        UnwindStrategy::Manual => 0,
+        UnwindStrategy::Dwarf => 1,
+        UnwindStrategy::Breakpad => 2,
+        UnwindStrategy::FramePointer => 3,
+    }
+}
+
+fn error_index(error: &Error) -> usize {
+    match *error {
+        Error::NoUnwindInfoForAddress(_) => 0,
+        Error::InvalidAddress(_) => 1,
+        Error::NonProgressingUnwind(_) => 2,
+        Error::InvalidCfiExpression | Error::CfiExpressionOutOfFuel => 3,
+        _ => 4,
+    }
+}
+
+impl Counters {
+    /// Count a frame walked to its caller with the given strategy.
+    pub(crate) fn walked(&self, strategy: UnwindStrategy, fallback: bool) {
+        add(&self.strategies[strategy_index(strategy)], 1);
+        if fallback {
+            add(&self.fallbacks, 1);
+        }
+    }
+
+    /// Count a frame that could not be walked.
+    pub(crate) fn failed(&self, error: &Error) {
+        add(&self.errors[error_index(error)], 1);
+    }
+
+    /// Count a finished walk.
+    pub(crate) fn finished(&self, frames: usize, time: Duration, row_cache: RowCacheStats) {
+        add(&self.walks, 1);
+        add(&self.frames, frames as u64);
+        add(&self.row_cache_hits, row_cache.hits);
+        add(&self.row_cache_misses, row_cache.misses);
+        add(&self.walk_nanos, time.as_nanos() as u64);
+    }
+
+    /// Take a snapshot of the counters.
+    pub(crate) fn snapshot(&self) -> UnwindStats {
+        let get = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        UnwindStats {
+            walks: get(&self.walks),
+            frames: get(&self.frames),
+            strategies: StrategyCounts {
+                manual: get(&self.strategies[0]),
+                dwarf: get(&self.strategies[1]),
+                breakpad: get(&self.strategies[2]),
+                frame_pointer: get(&self.strategies[3]),
+            },
+            fallbacks: get(&self.fallbacks),
+            row_cache: RowCacheStats {
+                hits: get(&self.row_cache_hits),
+                misses: get(&self.row_cache_misses),
+            },
+            errors: ErrorCounts {
+                no_unwind_info: get(&self.errors[0]),
+                invalid_address: get(&self.errors[1]),
+                non_progressing: get(&self.errors[2]),
+                cfi_expression: get(&self.errors[3]),
+                other: get(&self.errors[4]),
+            },
+            walk_time: Duration::from_nanos(get(&self.walk_nanos)),
+        }
+    }
+
+    /// Zero the counters.
+    pub(crate) fn reset(&self) {
+        let counters = [
+            &self.walks,
+            &self.frames,
+            &self.fallbacks,
+            &self.row_cache_hits,
+            &self.row_cache_misses,
+            &self.walk_nanos,
+        ];
+        for counter in counters
+            .iter()
+            .cloned()
+            .chain(self.strategies.iter())
+            .chain(self.errors.iter())
+        {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_and_reset() {
+        let counters = Counters::default();
+        counters.walked(UnwindStrategy::Dwarf, false);
+        counters.walked(UnwindStrategy::FramePointer, true);
+        counters.failed(&Error::NoUnwindInfoForAddress(0x1000));
+        counters.failed(&Error::CfiExpressionOutOfFuel);
+        counters.finished(
+            3,
+            Duration::from_micros(10),
+            RowCacheStats { hits: 1, misses: 0 },
+        );
+        counters.finished(1, Duration::from_micros(20), RowCacheStats::default());
+
+        let stats = counters.snapshot();
+        assert_eq!(stats.walks, 2);
+        assert_eq!(stats.frames, 4);
+        assert_eq!(stats.strategies.dwarf, 1);
+        assert_eq!(stats.strategies.frame_pointer, 1);
+        assert_eq!(stats.fallbacks, 1);
+        assert_eq!(stats.row_cache.hits, 1);
+        assert_eq!(stats.errors.no_unwind_info, 1);
+        assert_eq!(stats.errors.cfi_expression, 1);
+        assert_eq!(stats.mean_walk_time(), Duration::from_micros(15));
+
+        counters.reset();
+        assert_eq!(counters.snapshot(), UnwindStats::default());
+    }
+}
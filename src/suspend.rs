@@ -0,0 +1,373 @@
+//! Stopping every thread of another process, so that their stacks can be
+//! walked consistently.
+//!
+//! Walking the threads of a running process one at a time gives stacks from
+//! different moments, which may not make sense together. A `Suspender` stops
+//! the whole process first. `Suspender::suspend` returns a guard that resumes
+//! the process when it is dropped, so the process is resumed even if walking
+//! panics.
+//!
+//! ```no_run
+//! # #[cfg(target_os = "linux")]
+//! # fn f() -> pancakes::Result<()> {
+//! use pancakes::reader::PtraceMemory;
+//! use pancakes::suspend::{PtraceSuspender, Suspender};
+//!
+//! # let pid = 0;
+//! let mut suspender = PtraceSuspender::new(pid);
+//! let suspended = suspender.suspend()?;
+//! for &tid in suspended.thread_ids() {
+//!     let memory = PtraceMemory::new(tid);
+//!     // Walk the thread's stack with `memory`...
+//!     # let _ = memory;
+//! }
+//! // Every thread is resumed when `suspended` is dropped.
+//! # Ok(())
+//! # }
+//! ```
+
+#[cfg(any(target_os = "linux", all(feature = "live", target_os = "macos")))]
+use error::Error;
+use error::Result;
+#[cfg(all(feature = "live", target_os = "macos"))]
+use ffi;
+#[cfg(any(target_os = "linux", all(feature = "live", target_os = "macos")))]
+use libc;
+#[cfg(target_os = "linux")]
+use std::fs;
+#[cfg(any(target_os = "linux", all(feature = "live", target_os = "macos")))]
+use std::io;
+use std::ops::Deref;
+#[cfg(target_os = "linux")]
+use std::ptr;
+
+/// A way to stop every thread of a process, and resume them.
+pub trait Suspender {
+    /// Stop every thread of the process.
+    fn suspend_all(&mut self) -> Result<()>;
+
+    /// Resume the threads stopped by `suspend_all`.
+    fn resume_all(&mut self) -> Result<()>;
+
+    /// Stop every thread of the process, until the returned guard is
+    /// dropped.
+    fn suspend(&mut self) -> Result<Suspended<Self>>
+    where
+        Self: Sized,
+    {
+        self.suspend_all()?;
+        Ok(Suspended { suspender: self })
+    }
+}
+
+/// A process stopped by `Suspender::suspend`, which is resumed on drop.
+///
+/// This dereferences to the `Suspender`, for what it knows about the stopped
+/// threads.
+#[derive(Debug)]
+pub struct Suspended<'a, S>
+where
+    S: 'a + Suspender,
+{
+    suspender: &'a mut S,
+}
+
+impl<'a, S> Deref for Suspended<'a, S>
+where
+    S: 'a + Suspender,
+{
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        self.suspender
+    }
+}
+
+impl<'a, S> Drop for Suspended<'a, S>
+where
+    S: 'a + Suspender,
+{
+    fn drop(&mut self) {
+        let _ = self.suspender.resume_all();
+    }
+}
+
+/// A `Suspender` for a process on Linux, which attaches to each of its
+/// threads with `PTRACE_ATTACH` and waits for it to stop.
+///
+/// While the process is suspended, each thread can be read with
+/// `reader::PtraceMemory`. Attaching requires the same permissions as a
+/// debugger: usually, that the process is a child of this one, or that
+/// `/proc/sys/kernel/yama/ptrace_scope` is 0.
+#[cfg(target_os = "linux")]
+#[derive(Debug)]
+pub struct PtraceSuspender {
+    pid: libc::pid_t,
+    stopped: Vec<libc::pid_t>,
+}
+
+#[cfg(target_os = "linux")]
+impl PtraceSuspender {
+    /// Construct a suspender for the process with the given pid.
+    pub fn new(pid: libc::pid_t) -> PtraceSuspender {
+        PtraceSuspender {
+            pid,
+            stopped: vec![],
+        }
+    }
+
+    /// Get the ids of the stopped threads.
+    pub fn thread_ids(&self) -> &[libc::pid_t] {
+        &self.stopped
+    }
+
+    fn attach(&mut self, tid: libc::pid_t) -> Result<()> {
+        unsafe {
+            let r = libc::ptrace(
+                libc::PTRACE_ATTACH,
+                tid,
+                ptr::null_mut::<libc::c_void>(),
+                ptr::null_mut::<libc::c_void>(),
+            );
+            if r == -1 {
+                let error = io::Error::last_os_error();
+                // The thread exited since the task list was read.
+                if error.raw_os_error() == Some(libc::ESRCH) {
+                    return Ok(());
+                }
+                return Err(Error::Io(error));
+            }
+
+            let mut status = 0;
+            if libc::waitpid(tid, &mut status, libc::__WALL) == -1 {
+                let error = io::Error::last_os_error();
+                libc::ptrace(
+                    libc::PTRACE_DETACH,
+                    tid,
+                    ptr::null_mut::<libc::c_void>(),
+                    ptr::null_mut::<libc::c_void>(),
+                );
+                return Err(Error::Io(error));
+            }
+        }
+        self.stopped.push(tid);
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Suspender for PtraceSuspender {
+    fn suspend_all(&mut self) -> Result<()> {
+        // Threads may be spawned while we attach to the others, so keep
+        // reading the task list until it has no thread we have not stopped.
+        loop {
+            let mut attached_any = false;
+            for entry in fs::read_dir(format!("/proc/{}/task", self.pid))? {
+                let tid = match entry?.file_name().to_str().and_then(|name| name.parse().ok()) {
+                    Some(tid) => tid,
+                    None => continue,
+                };
+                if self.stopped.contains(&tid) {
+                    continue;
+                }
+                if let Err(e) = self.attach(tid) {
+                    let _ = self.resume_all();
+                    return Err(e);
+                }
+                attached_any = true;
+            }
+            if !attached_any {
+                return Ok(());
+            }
+        }
+    }
+
+    fn resume_all(&mut self) -> Result<()> {
+        let mut result = Ok(());
+        for tid in self.stopped.drain(..) {
+            let r = unsafe {
+                libc::ptrace(
+                    libc::PTRACE_DETACH,
+                    tid,
+                    ptr::null_mut::<libc::c_void>(),
+                    ptr::null_mut::<libc::c_void>(),
+                )
+            };
+            if r == -1 && result.is_ok() {
+                result = Err(Error::Io(io::Error::last_os_error()));
+            }
+        }
+        result
+    }
+}
+
+/// A `Suspender` for a process on macOS, which suspends its task with
+/// `task_suspend`.
+///
+/// Getting another process's task port with `task_for_pid` requires root, or
+/// the `com.apple.security.cs.debugger` entitlement.
+#[cfg(all(feature = "live", target_os = "macos"))]
+#[derive(Debug)]
+pub struct TaskSuspender {
+    task: ffi::mach_port_t,
+    suspended: bool,
+}
+
+#[cfg(all(feature = "live", target_os = "macos"))]
+impl TaskSuspender {
+    /// Construct a suspender for the process with the given pid.
+    pub fn for_pid(pid: libc::pid_t) -> Result<TaskSuspender> {
+        let mut task = 0;
+        let kr = unsafe { ffi::task_for_pid(ffi::mach_task_self_, pid, &mut task) };
+        if kr != ffi::KERN_SUCCESS {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "task_for_pid failed",
+            )));
+        }
+        Ok(TaskSuspender {
+            task,
+            suspended: false,
+        })
+    }
+
+    /// Get the process's task port.
+    pub fn task(&self) -> ffi::mach_port_t {
+        self.task
+    }
+}
+
+#[cfg(all(feature = "live", target_os = "macos"))]
+impl Suspender for TaskSuspender {
+    fn suspend_all(&mut self) -> Result<()> {
+        if unsafe { ffi::task_suspend(self.task) } != ffi::KERN_SUCCESS {
+            return Err(Error::Io(io::Error::new(io::ErrorKind::Other, "task_suspend failed")));
+        }
+        self.suspended = true;
+        Ok(())
+    }
+
+    fn resume_all(&mut self) -> Result<()> {
+        if !self.suspended {
+            return Ok(());
+        }
+        self.suspended = false;
+        if unsafe { ffi::task_resume(self.task) } != ffi::KERN_SUCCESS {
+            return Err(Error::Io(io::Error::new(io::ErrorKind::Other, "task_resume failed")));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "live", target_os = "macos"))]
+impl Drop for TaskSuspender {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::mach_port_deallocate(ffi::mach_task_self_, self.task);
+        }
+    }
+}
+
+#[cfg(windows)]
+pub use self::windows::WindowsProcessSuspender;
+
+#[cfg(windows)]
+mod windows {
+    use super::Suspender;
+    use error::{Error, Result};
+    use reader::windows::{Dword, Handle, OpenProcess, OwnedHandle};
+    use std::io;
+
+    const PROCESS_SUSPEND_RESUME: Dword = 0x0800;
+
+    #[link(name = "ntdll")]
+    extern "system" {
+        fn NtSuspendProcess(process: Handle) -> i32;
+        fn NtResumeProcess(process: Handle) -> i32;
+    }
+
+    /// A `Suspender` for a process on Windows, which suspends every one of
+    /// its threads at once with `NtSuspendProcess`.
+    ///
+    /// While the process is suspended, read it with
+    /// `reader::WindowsProcessMemory`.
+    #[derive(Debug)]
+    pub struct WindowsProcessSuspender {
+        process: OwnedHandle,
+        suspended: bool,
+    }
+
+    impl WindowsProcessSuspender {
+        /// Open the process with the given id for suspending.
+        pub fn open(process_id: u32) -> Result<WindowsProcessSuspender> {
+            let process = unsafe { OpenProcess(PROCESS_SUSPEND_RESUME, 0, process_id as Dword) };
+            Ok(WindowsProcessSuspender {
+                process: OwnedHandle::new(process)?,
+                suspended: false,
+            })
+        }
+    }
+
+    impl Suspender for WindowsProcessSuspender {
+        fn suspend_all(&mut self) -> Result<()> {
+            let status = unsafe { NtSuspendProcess(self.process.0) };
+            if status < 0 {
+                return Err(Error::Io(io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("NtSuspendProcess failed with {:#x}", status),
+                )));
+            }
+            self.suspended = true;
+            Ok(())
+        }
+
+        fn resume_all(&mut self) -> Result<()> {
+            if !self.suspended {
+                return Ok(());
+            }
+            self.suspended = false;
+            let status = unsafe { NtResumeProcess(self.process.0) };
+            if status < 0 {
+                return Err(Error::Io(io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("NtResumeProcess failed with {:#x}", status),
+                )));
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn ptrace_suspender() {
+        let child = unsafe { libc::fork() };
+        assert!(child >= 0);
+        if child == 0 {
+            loop {
+                unsafe {
+                    libc::pause();
+                }
+            }
+        }
+
+        let mut suspender = PtraceSuspender::new(child);
+        let result = suspender.suspend().map(|suspended| suspended.thread_ids().to_vec());
+        unsafe {
+            libc::kill(child, libc::SIGKILL);
+            libc::waitpid(child, ptr::null_mut(), 0);
+        }
+
+        match result {
+            Ok(tids) => assert_eq!(tids, vec![child]),
+            // Attaching is not allowed in every environment tests run in.
+            Err(Error::Io(ref e)) if e.raw_os_error() == Some(libc::EPERM) => {}
+            Err(e) => panic!("unexpected error: {:?}", e),
+        }
+        assert!(suspender.thread_ids().is_empty());
+    }
+}
@@ -113,7 +113,8 @@ impl TaggedWord {
         }
     }
 
-    /// TODO FITZGEN
+    /// Get the inner word if it is valid, or the given default value if it
+    /// is invalid.
     pub fn unwrap_or(self, default: usize) -> usize {
         match self {
             Valid(w) => w,
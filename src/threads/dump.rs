@@ -0,0 +1,353 @@
+//! Dumping every thread's stack when the process receives a signal, so that
+//! a live process can be inspected with `kill -QUIT`.
+//!
+//! Walking every thread allocates and takes locks, so it can't be done in
+//! the signal handler. The handler only writes a byte to a pipe; a dumper
+//! thread waiting on the other end walks every thread with `walk_all` and
+//! writes the stacks out.
+
+use super::super::breakpad::{FrameTrust, Module, TraceFormatter};
+use super::super::{Error, Registers, Result, TaggedWord};
+use super::{current_tid, walk_all};
+use libc;
+use std::fs::File;
+use std::io::{self, Write};
+use std::mem::{self, ManuallyDrop};
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::ptr;
+use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
+
+/// The write end of the installed dumper's pipe, or -1.
+static WAKE: AtomicI32 = AtomicI32::new(-1);
+
+/// How many handlers may be writing to `WAKE`'s pipe.
+static HANDLING: AtomicUsize = AtomicUsize::new(0);
+
+/// Configuration for a `StackDumper`.
+#[derive(Clone, Debug)]
+pub struct DumpOptions {
+    signal: libc::c_int,
+    fd: RawFd,
+    modules: Option<Vec<Module>>,
+}
+
+impl Default for DumpOptions {
+    fn default() -> Self {
+        DumpOptions {
+            signal: libc::SIGQUIT,
+            fd: libc::STDERR_FILENO,
+            modules: None,
+        }
+    }
+}
+
+impl DumpOptions {
+    /// Construct a new `DumpOptions` with the default configuration: dump
+    /// on `SIGQUIT` to stderr, naming the modules loaded at each dump.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Dump when this signal arrives instead of `SIGQUIT`. It must not be a
+    /// signal that something else in the process relies on, including the
+    /// signal that `walk_all` stops threads with on Linux and Android.
+    pub fn signal(&mut self, signal: libc::c_int) -> &mut Self {
+        self.signal = signal;
+        self
+    }
+
+    /// Write the stacks to `fd` instead of stderr. It must stay open for as
+    /// long as the dumper is installed.
+    pub fn fd(&mut self, fd: RawFd) -> &mut Self {
+        self.fd = fd;
+        self
+    }
+
+    /// Describe addresses in terms of these modules, e.g. with symbol files
+    /// attached to name functions, instead of the modules loaded at each
+    /// dump.
+    pub fn modules(&mut self, modules: Vec<Module>) -> &mut Self {
+        self.modules = Some(modules);
+        self
+    }
+
+    /// Install a handler for the configured signal that has a dumper thread
+    /// walk every other thread's stack, and write each thread's frames in
+    /// `minidump_stackwalk`'s human readable layout:
+    ///
+    /// ```text
+    /// Thread 4242
+    ///  0  libfoo.so!foo + 0x12
+    ///     Found by: given as instruction pointer in context
+    ///  1  libfoo.so + 0x3a4
+    ///     Found by: call frame info
+    ///
+    /// ```
+    ///
+    /// Walks are made with `walk_all`, so the threads that `walk_all` would
+    /// skip are skipped. Only one dumper can be installed at a time.
+    ///
+    /// ```
+    /// # fn f() {
+    /// use pancakes::threads::DumpOptions;
+    ///
+    /// let dumper = DumpOptions::new().install().unwrap();
+    /// // ... `kill -QUIT` the process to dump every thread's stack ...
+    /// dumper.uninstall();
+    /// # }
+    /// ```
+    pub fn install(&self) -> Result<StackDumper> {
+        let (read, write) = pipe()?;
+        if WAKE
+            .compare_exchange(-1, write, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            unsafe {
+                libc::close(read);
+                libc::close(write);
+            }
+            return Err(Error::DumpHandlerAlreadyInstalled);
+        }
+
+        let formatter = self.modules.clone().map(TraceFormatter::new);
+        let fd = self.fd;
+        let worker = thread::Builder::new()
+            .name("pancakes-dumper".into())
+            .spawn(move || wait_for_signals(read, fd, formatter));
+        let mut dumper = StackDumper {
+            signal: self.signal,
+            previous: None,
+            write,
+            worker: None,
+        };
+        match worker {
+            Ok(worker) => dumper.worker = Some(worker),
+            Err(e) => {
+                unsafe {
+                    libc::close(read);
+                }
+                return Err(Error::Io(e));
+            }
+        }
+
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = handle_dump_signal as usize;
+            action.sa_flags = libc::SA_RESTART | libc::SA_ONSTACK;
+            libc::sigemptyset(&mut action.sa_mask);
+
+            let mut previous: libc::sigaction = mem::zeroed();
+            if libc::sigaction(self.signal, &action, &mut previous) != 0 {
+                return Err(Error::Io(io::Error::last_os_error()));
+            }
+            dumper.previous = Some(previous);
+        }
+        Ok(dumper)
+    }
+}
+
+/// An installed signal handler that dumps every thread's stack, installed
+/// with `DumpOptions::install`. Dropping it uninstalls the handler and
+/// restores the previous one.
+pub struct StackDumper {
+    signal: libc::c_int,
+    previous: Option<libc::sigaction>,
+    write: RawFd,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl StackDumper {
+    /// Uninstall the handler, waiting for the dump in progress, if any, to
+    /// finish.
+    pub fn uninstall(self) {}
+}
+
+impl Drop for StackDumper {
+    fn drop(&mut self) {
+        if let Some(ref previous) = self.previous {
+            unsafe {
+                libc::sigaction(self.signal, previous, ptr::null_mut());
+            }
+        }
+
+        // Wait for the handlers that may still be using the pipe, then hang
+        // up, which wakes the dumper thread.
+        WAKE.store(-1, Ordering::SeqCst);
+        while HANDLING.load(Ordering::SeqCst) != 0 {
+            thread::yield_now();
+        }
+        unsafe {
+            libc::close(self.write);
+        }
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl ::std::fmt::Debug for StackDumper {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.debug_struct("StackDumper")
+            .field("signal", &self.signal)
+            .finish()
+    }
+}
+
+/// Create a pipe whose write end never blocks, so that the handler can't
+/// hang when dumps are requested faster than they are made.
+fn pipe() -> Result<(RawFd, RawFd)> {
+    let mut fds = [0; 2];
+    unsafe {
+        if libc::pipe(fds.as_mut_ptr()) != 0 {
+            return Err(Error::Io(io::Error::last_os_error()));
+        }
+        for &fd in &fds {
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+        let flags = libc::fcntl(fds[1], libc::F_GETFL);
+        libc::fcntl(fds[1], libc::F_SETFL, flags | libc::O_NONBLOCK);
+    }
+    Ok((fds[0], fds[1]))
+}
+
+extern "C" fn handle_dump_signal(_signal: libc::c_int) {
+    HANDLING.fetch_add(1, Ordering::SeqCst);
+    let fd = WAKE.load(Ordering::SeqCst);
+    if fd >= 0 {
+        unsafe {
+            // Writing may clobber `errno` for the interrupted code.
+            let errno = *errno_location();
+            let byte = 1u8;
+            libc::write(fd, &byte as *const u8 as *const libc::c_void, 1);
+            *errno_location() = errno;
+        }
+    }
+    HANDLING.fetch_sub(1, Ordering::SeqCst);
+}
+
+/// Dump every time a byte arrives on `read`, until the pipe is hung up.
+fn wait_for_signals(read: RawFd, fd: RawFd, formatter: Option<TraceFormatter>) {
+    let dumper = current_tid();
+    loop {
+        let mut byte = 0u8;
+        let n = unsafe { libc::read(read, &mut byte as *mut u8 as *mut libc::c_void, 1) };
+        match n {
+            1 => {}
+            0 => break,
+            _ if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => continue,
+            _ => break,
+        }
+
+        let modules;
+        let formatter = match formatter {
+            Some(ref formatter) => formatter,
+            None => {
+                modules = TraceFormatter::new(Module::this_process());
+                &modules
+            }
+        };
+        let _ = dump(fd, formatter, dumper);
+    }
+    unsafe {
+        libc::close(read);
+    }
+}
+
+/// Write every thread's stack, except the dumper's, to `fd`.
+fn dump(fd: RawFd, formatter: &TraceFormatter, dumper: usize) -> Result<()> {
+    // Format everything before writing, so that one dump's threads aren't
+    // interleaved with whatever else writes to `fd`.
+    let mut out = vec![];
+    walk_all(|tid, frames| {
+        if tid == dumper {
+            return;
+        }
+        let _ = writeln!(out, "Thread {}", tid);
+        for (index, frame) in frames.iter().enumerate() {
+            let ip = match frame.registers().ip() {
+                TaggedWord::Valid(ip) => ip,
+                TaggedWord::Invalid => break,
+            };
+            let _ = formatter.write_frame(&mut out, index, ip, FrameTrust::from(frame));
+        }
+        let _ = writeln!(out);
+    })?;
+
+    // The fd isn't ours to close.
+    let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+    file.write_all(&out).map_err(Error::Io)
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn errno_location() -> *mut libc::c_int {
+    libc::__errno_location()
+}
+
+#[cfg(target_os = "android")]
+unsafe fn errno_location() -> *mut libc::c_int {
+    libc::__errno()
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn errno_location() -> *mut libc::c_int {
+    libc::__error()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dump_on_signal() {
+        // A blocking pipe, unlike `pipe()`'s, so that the dump can't fill it.
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let (read, write) = (fds[0], fds[1]);
+        let dumper = DumpOptions::new()
+            .signal(libc::SIGUSR2)
+            .fd(write)
+            .install()
+            .unwrap();
+        match DumpOptions::new().signal(libc::SIGUSR2).install() {
+            Err(Error::DumpHandlerAlreadyInstalled) => {}
+            otherwise => panic!("expected DumpHandlerAlreadyInstalled, got {:?}", otherwise),
+        }
+
+        unsafe {
+            libc::raise(libc::SIGUSR2);
+        }
+
+        // Read until this thread's stack has been written in full.
+        let header = format!("Thread {}\n", current_tid());
+        let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(read) });
+        let mut out = String::new();
+        let stack = loop {
+            let mut buf = [0; 4096];
+            let n = io::Read::read(&mut *file, &mut buf).unwrap();
+            assert!(n > 0);
+            out.push_str(::std::str::from_utf8(&buf[..n]).unwrap());
+            if let Some(start) = out.find(&header) {
+                if let Some(len) = out[start..].find("\n\n") {
+                    break out[start..start + len].to_string();
+                }
+            }
+        };
+        dumper.uninstall();
+        unsafe {
+            libc::close(read);
+            libc::close(write);
+        }
+
+        let mut lines = stack.lines().skip(1);
+        assert!(lines.next().unwrap().starts_with(" 0  "));
+        assert_eq!(
+            lines.next().unwrap(),
+            "    Found by: given as instruction pointer in context"
+        );
+        assert!(stack.lines().count() > 3);
+
+        // Uninstalling made room for another dumper.
+        DumpOptions::new().signal(libc::SIGUSR2).install().unwrap();
+    }
+}
@@ -0,0 +1,166 @@
+//! Stopping threads on Linux and Android, with a signal handshake.
+//!
+//! Each thread is stopped by directing `signal()` at it: the handler saves the
+//! interrupted registers from its `ucontext`, then parks until the requesting
+//! thread is done with them.
+
+use super::super::{Error, FrameRegisters, Registers, Result, TaggedWord};
+use ffi;
+use libc;
+use std::cell::UnsafeCell;
+use std::fs;
+use std::io;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long to wait for a thread to respond to `signal()` before giving up on
+/// it. Threads that block the signal, or are stuck in the kernel, never do.
+const TIMEOUT: Duration = Duration::from_millis(100);
+
+// The states of the handshake with the thread being walked.
+const IDLE: usize = 0;
+const REQUESTED: usize = 1;
+const CAPTURING: usize = 2;
+const CAPTURED: usize = 3;
+const RESUME: usize = 4;
+
+/// The realtime signal used to stop threads. The first thread stopped by
+/// `threads` installs a handler for it, replacing any other.
+pub fn signal() -> libc::c_int {
+    libc::SIGRTMIN() + 4
+}
+
+static STATE: AtomicUsize = AtomicUsize::new(IDLE);
+static TARGET: AtomicUsize = AtomicUsize::new(0);
+static SLOT: Slot = Slot(UnsafeCell::new(None));
+
+/// The registers of the parked thread. Only the handler writes them, in the
+/// `CAPTURING` state, and only the requesting thread reads them, in the
+/// `CAPTURED` state.
+struct Slot(UnsafeCell<Option<FrameRegisters>>);
+
+unsafe impl Sync for Slot {}
+
+/// Stop the thread `tid` by directing `signal()` at it, call `f` with the
+/// registers its handler saved, and resume it.
+pub(crate) unsafe fn capture<F>(tid: usize, f: F) -> Result<()>
+where
+    F: FnOnce(&FrameRegisters),
+{
+    TARGET.store(tid, Ordering::SeqCst);
+    STATE.store(REQUESTED, Ordering::SeqCst);
+
+    let pid = libc::getpid();
+    if libc::syscall(libc::SYS_tgkill, pid, tid as libc::pid_t, signal()) != 0 {
+        STATE.store(IDLE, Ordering::SeqCst);
+        return Err(Error::Io(io::Error::last_os_error()));
+    }
+
+    let deadline = Instant::now() + TIMEOUT;
+    while STATE.load(Ordering::Acquire) != CAPTURED {
+        // If the handler has not started capturing yet, it never will.
+        if Instant::now() > deadline &&
+            STATE
+                .compare_exchange(REQUESTED, IDLE, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+        {
+            return Err(Error::ThreadNotResponding(tid));
+        }
+        thread::yield_now();
+    }
+
+    if let Some(ref registers) = *SLOT.0.get() {
+        f(registers);
+    }
+
+    STATE.store(RESUME, Ordering::Release);
+    // Wait for the handler to finish with the slot before the next thread.
+    while STATE.load(Ordering::Acquire) != IDLE {
+        thread::yield_now();
+    }
+    Ok(())
+}
+
+extern "C" fn handle_signal(
+    _signal: libc::c_int,
+    _info: *mut libc::siginfo_t,
+    context: *mut libc::c_void,
+) {
+    unsafe {
+        // Ignore signals that arrive late, after their handshake timed out.
+        if TARGET.load(Ordering::SeqCst) != current_tid() ||
+            STATE
+                .compare_exchange(REQUESTED, CAPTURING, Ordering::SeqCst, Ordering::SeqCst)
+                .is_err()
+        {
+            return;
+        }
+
+        *SLOT.0.get() = Some(FrameRegisters::from_ucontext(context as *const ffi::ucontext_t));
+        STATE.store(CAPTURED, Ordering::Release);
+
+        while STATE.load(Ordering::Acquire) != RESUME {
+            libc::sched_yield();
+        }
+        *SLOT.0.get() = None;
+        STATE.store(IDLE, Ordering::Release);
+    }
+}
+
+/// Install the handler for `signal()`.
+pub(crate) fn prepare() -> Result<()> {
+    unsafe {
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = handle_signal as usize;
+        action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART | libc::SA_ONSTACK;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(signal(), &action, ptr::null_mut()) != 0 {
+            return Err(Error::Io(io::Error::last_os_error()));
+        }
+    }
+    Ok(())
+}
+
+/// The ids of the current process's threads.
+pub(crate) fn thread_ids() -> Result<Vec<usize>> {
+    let mut tids = vec![];
+    for entry in fs::read_dir("/proc/self/task")? {
+        if let Some(tid) = entry?.file_name().to_str().and_then(|name| name.parse().ok()) {
+            tids.push(tid);
+        }
+    }
+    Ok(tids)
+}
+
+pub(crate) fn current_tid() -> usize {
+    unsafe { libc::syscall(libc::SYS_gettid) as usize }
+}
+
+/// Copy the top of the stack that `registers` were captured on into `stack`,
+/// returning how many bytes were copied.
+///
+/// This reads with `process_vm_readv` on the current process, so that running
+/// off the end of the stack's mapping ends the copy instead of faulting.
+pub(crate) fn copy_stack(registers: &FrameRegisters, stack: &mut [u8]) -> usize {
+    let sp = match registers.sp() {
+        TaggedWord::Valid(sp) if !stack.is_empty() => sp,
+        _ => return 0,
+    };
+    let local = libc::iovec {
+        iov_base: stack.as_mut_ptr() as *mut libc::c_void,
+        iov_len: stack.len(),
+    };
+    let remote = libc::iovec {
+        iov_base: sp as *mut libc::c_void,
+        iov_len: stack.len(),
+    };
+    let copied = unsafe { libc::process_vm_readv(libc::getpid(), &local, 1, &remote, 1, 0) };
+    if copied < 0 {
+        0
+    } else {
+        copied as usize
+    }
+}
@@ -0,0 +1,117 @@
+//! Stopping threads on macOS, with `thread_suspend`.
+
+use super::super::{Error, FrameRegisters, Registers, Result, TaggedWord};
+use ffi;
+use libc;
+use std::io;
+use std::mem;
+use std::ptr;
+use std::slice;
+
+/// Nothing needs preparing: any thread in the task can be suspended.
+pub(crate) fn prepare() -> Result<()> {
+    Ok(())
+}
+
+/// Suspend the thread `tid`, call `f` with the registers fetched with
+/// `thread_get_state`, and resume it.
+pub(crate) unsafe fn capture<F>(tid: usize, f: F) -> Result<()>
+where
+    F: FnOnce(&FrameRegisters),
+{
+    let thread = tid as ffi::thread_act_t;
+    if ffi::thread_suspend(thread) != ffi::KERN_SUCCESS {
+        return Err(Error::ThreadNotResponding(tid));
+    }
+
+    let mut state: ffi::thread_state_t = mem::zeroed();
+    let mut count = (mem::size_of::<ffi::thread_state_t>() / mem::size_of::<ffi::natural_t>()) as
+        ffi::mach_msg_type_number_t;
+    let got_state = ffi::thread_get_state(
+        thread,
+        ffi::THREAD_STATE_FLAVOR,
+        &mut state as *mut ffi::thread_state_t as *mut ffi::natural_t,
+        &mut count,
+    ) == ffi::KERN_SUCCESS;
+    if got_state {
+        f(&FrameRegisters::from_thread_state(&state));
+    }
+
+    ffi::thread_resume(thread);
+    if got_state {
+        Ok(())
+    } else {
+        Err(Error::ThreadNotResponding(tid))
+    }
+}
+
+/// Copy the top of the stack that `registers` were captured on into `stack`,
+/// returning how many bytes were copied.
+///
+/// This reads a page at a time with `mach_vm_read_overwrite`, so that running
+/// off the end of the stack's mapping ends the copy instead of faulting.
+pub(crate) fn copy_stack(registers: &FrameRegisters, stack: &mut [u8]) -> usize {
+    let sp = match registers.sp() {
+        TaggedWord::Valid(sp) => sp,
+        TaggedWord::Invalid => return 0,
+    };
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+
+    let mut copied = 0;
+    while copied < stack.len() {
+        let addr = sp + copied;
+        let len = (page_size - addr % page_size).min(stack.len() - copied);
+        let mut out = 0;
+        let kr = unsafe {
+            ffi::mach_vm_read_overwrite(
+                ffi::mach_task_self_,
+                addr as u64,
+                len as u64,
+                stack[copied..].as_mut_ptr() as u64,
+                &mut out,
+            )
+        };
+        if kr != ffi::KERN_SUCCESS || out == 0 {
+            break;
+        }
+        copied += out as usize;
+    }
+    copied
+}
+
+/// The mach thread ports of the current task's threads.
+pub(crate) fn thread_ids() -> Result<Vec<usize>> {
+    unsafe {
+        let task = ffi::mach_task_self_;
+        let mut threads: *mut ffi::thread_act_t = ptr::null_mut();
+        let mut count = 0;
+        if ffi::task_threads(task, &mut threads, &mut count) != ffi::KERN_SUCCESS {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::Other,
+                "task_threads failed",
+            )));
+        }
+
+        let ports = slice::from_raw_parts(threads, count as usize);
+        let tids = ports.iter().map(|&port| port as usize).collect();
+        // The threads keep their ports while they live, so the names stay
+        // valid without the references `task_threads` added.
+        for &port in ports {
+            ffi::mach_port_deallocate(task, port);
+        }
+        ffi::vm_deallocate(
+            task,
+            threads as usize,
+            ports.len() * mem::size_of::<ffi::thread_act_t>(),
+        );
+        Ok(tids)
+    }
+}
+
+pub(crate) fn current_tid() -> usize {
+    unsafe {
+        let port = ffi::mach_thread_self();
+        ffi::mach_port_deallocate(ffi::mach_task_self_, port);
+        port as usize
+    }
+}
@@ -0,0 +1,447 @@
+//! Walking the stacks of every thread in the current process.
+//!
+//! A thread's stack can only be walked while the thread is stopped, or its
+//! frames change out from under the walk. On Linux and Android, each thread
+//! is stopped by directing `signal()` at it: the handler saves the
+//! interrupted registers from its `ucontext`, then parks until the requesting
+//! thread has walked the stack. On macOS, each thread is suspended with
+//! `thread_suspend`, and its registers are fetched with `thread_get_state`.
+//! Only one thread is ever stopped at a time.
+//!
+//! The stopped thread may be holding any lock, including the allocator's, so
+//! while it is stopped the requesting thread only walks, into a buffer that
+//! was allocated beforehand. The callback is called on the walked frames
+//! after the thread is resumed.
+//!
+//! Thread ids are the kernel's thread ids on Linux and Android, and mach
+//! thread ports on macOS.
+
+// Each platform provides:
+//
+// - `prepare`, called once before the first thread is stopped;
+// - `capture(tid, f)`, which stops the thread `tid`, calls `f` with its
+//   registers, and resumes it. The caller must hold `LOCK`. It is unsafe
+//   because `f` runs while another thread is stopped at an arbitrary point:
+//   `f` must not allocate or acquire locks;
+// - `copy_stack`, which copies the top of a stack without faulting;
+// - `thread_ids` and `current_tid`.
+cfg_if! {
+    if #[cfg(any(target_os = "linux", target_os = "android"))] {
+        mod linux;
+        use self::linux as platform;
+        pub use self::linux::signal;
+    } else {
+        mod macos;
+        use self::macos as platform;
+    }
+}
+
+mod dump;
+mod sampler;
+mod watchdog;
+
+pub use self::dump::{DumpOptions, StackDumper};
+pub(crate) use self::platform::{copy_stack, current_tid, thread_ids};
+pub use self::sampler::{Sampler, SamplerOptions};
+pub use self::watchdog::{Heartbeat, Watchdog, WatchdogOptions};
+use self::platform::capture;
+use super::{process_unwinder, reader, ucontext_t, Frame, FrameRegisters, Frames, Registers,
+            Result, UnwindContext};
+use error::Error;
+use std::hint;
+use std::sync::{Mutex, MutexGuard};
+#[cfg(feature = "tracing")]
+use tracing;
+
+/// The most frames walked on each thread, unless the process-wide unwinder's
+/// `Options::max_frames` says otherwise.
+pub const MAX_FRAMES: usize = 256;
+
+/// Serializes stopping threads, and records whether the platform is
+/// prepared to.
+static LOCK: Mutex<bool> = Mutex::new(false);
+
+/// Walk the stack of every thread in the current process, calling `f` with
+/// each thread's id and its frames, outermost last.
+///
+/// The calling thread's stack is walked starting with the caller of
+/// `walk_all`. Threads that exit, or cannot be stopped in time, are
+/// skipped. Like `trace`, this walks with the process-wide `Unwinder`
+/// and allocates, so it must not be called from a signal handler.
+///
+/// ```
+/// # fn f() {
+/// pancakes::threads::walk_all(|tid, frames| {
+///     println!("Thread {} has {} frames", tid, frames.len());
+/// }).unwrap();
+/// # }
+/// ```
+#[inline(never)]
+pub fn walk_all<F>(mut f: F) -> Result<()>
+where
+    F: FnMut(usize, &[Frame]),
+{
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!("walk_all").entered();
+
+    let marker = 0u8;
+    let marker = hint::black_box(&marker) as *const u8 as usize;
+
+    let current = current_tid();
+    let _lock = lock()?;
+    for tid in thread_ids()? {
+        // Threads that exit or never respond are skipped.
+        let _ = walk_locked(tid, current, marker, &mut f);
+    }
+
+    Ok(())
+}
+
+/// Walk the stack of the thread `tid` in the current process, calling `f`
+/// with its frames, outermost last.
+///
+/// This is `walk_all` for a single thread. It fails with
+/// `Error::ThreadNotResponding` if the thread cannot be stopped in time.
+#[inline(never)]
+pub fn walk_thread<F>(tid: usize, mut f: F) -> Result<()>
+where
+    F: FnMut(usize, &[Frame]),
+{
+    let marker = 0u8;
+    let marker = hint::black_box(&marker) as *const u8 as usize;
+
+    let _lock = lock()?;
+    walk_locked(tid, current_tid(), marker, &mut f)
+}
+
+/// A thread's registers, and a copy of the top of its stack, captured by
+/// `sample`.
+#[derive(Clone, Debug)]
+pub struct Sample<'a> {
+    tid: usize,
+    registers: FrameRegisters,
+    stack: reader::SliceMemory<'a>,
+}
+
+impl<'a> Sample<'a> {
+    /// Get the id of the sampled thread.
+    pub fn tid(&self) -> usize {
+        self.tid
+    }
+
+    /// Get the registers the thread was interrupted with.
+    pub fn registers(&self) -> &FrameRegisters {
+        &self.registers
+    }
+
+    /// Get a reader for the copy of the top of the thread's stack, starting
+    /// at its stack pointer, to walk the sample with.
+    pub fn stack(&self) -> &reader::SliceMemory<'a> {
+        &self.stack
+    }
+}
+
+/// Sample the thread `tid` in the current process: stop it, capture its
+/// registers, copy as much of the top of its stack as fits in
+/// `stack`, and resume it.
+///
+/// This is how an in-process sampling profiler gets other threads' stacks.
+/// The thread only stays stopped for as long as the copy takes, and the
+/// sample can be walked later, with `Unwinder::walk` and `Sample::stack` as
+/// the reader. Pass an empty `stack` to only capture the registers. Frames
+/// whose stack lies beyond the copy cannot be walked.
+///
+/// ```
+/// # fn f() {
+/// use pancakes::{threads, UnwindContext};
+///
+/// # let tid = 0;
+/// let mut stack = vec![0; 64 * 1024];
+/// if let Ok(sample) = threads::sample(tid, &mut stack) {
+///     let unwinder = pancakes::Options::new().build_unwinder();
+///     let mut cx = UnwindContext::new();
+///     let outcome = unwinder.walk(&mut cx, sample.stack(), sample.registers(), |frame| {
+///         println!("Traversed frame {:?}", frame);
+///     });
+///     println!("Walked {} frames", outcome.frames_walked);
+/// }
+/// # }
+/// ```
+#[inline(never)]
+pub fn sample(tid: usize, stack: &mut [u8]) -> Result<Sample> {
+    let _lock = lock()?;
+
+    let mut registers = None;
+    let mut len = 0;
+    {
+        let mut copy = |captured: &FrameRegisters| {
+            len = copy_stack(captured, stack);
+            registers = Some(captured.clone());
+        };
+        if tid == current_tid() {
+            FrameRegisters::with_current(|captured| {
+                copy(captured);
+                Ok(())
+            })?;
+        } else {
+            unsafe { capture(tid, copy)? };
+        }
+    }
+
+    let registers = registers.ok_or(Error::ThreadNotResponding(tid))?;
+    let base = registers.sp().unwrap_or(0);
+    Ok(Sample {
+        tid,
+        registers,
+        stack: reader::SliceMemory::new(base, &stack[..len]),
+    })
+}
+
+/// A buffer for a thread's registers and a copy of the top of its stack,
+/// allocated up front, to capture from a signal handler and walk later.
+///
+/// Walking in a signal handler holds up the interrupted thread for as long as
+/// the walk takes, and must not allocate or take locks. Capturing a snapshot
+/// instead only costs a bounded copy, and the snapshot can be walked on a
+/// normal thread, with `StackSnapshot::stack` as the reader. Frames whose
+/// stack lies beyond the copy cannot be walked.
+///
+/// ```no_run
+/// extern crate libc;
+/// extern crate pancakes;
+///
+/// use pancakes::threads::StackSnapshot;
+///
+/// extern "C" fn on_sigprof(_: libc::c_int, _: *mut libc::siginfo_t, ctx: *mut libc::c_void) {
+///     # let snapshot: &mut StackSnapshot = unimplemented!();
+///     unsafe { snapshot.capture_from_ucontext(ctx as *const pancakes::ucontext_t) };
+/// }
+///
+/// # fn main() {
+/// # let _ = on_sigprof;
+/// // Later, on a worker thread...
+/// # let snapshot = StackSnapshot::with_capacity(64 * 1024);
+/// let unwinder = pancakes::Options::new().build_unwinder();
+/// let mut cx = pancakes::UnwindContext::new();
+/// if let Some(registers) = snapshot.registers() {
+///     unwinder.walk(&mut cx, &snapshot.stack(), registers, |frame| {
+///         println!("Traversed frame {:?}", frame);
+///     });
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct StackSnapshot {
+    tid: usize,
+    registers: Option<FrameRegisters>,
+    stack: Box<[u8]>,
+    len: usize,
+}
+
+impl StackSnapshot {
+    /// Construct an empty snapshot with room for `stack_bytes` bytes of the
+    /// top of the stack.
+    pub fn with_capacity(stack_bytes: usize) -> StackSnapshot {
+        StackSnapshot {
+            tid: 0,
+            registers: None,
+            stack: vec![0; stack_bytes].into_boxed_slice(),
+            len: 0,
+        }
+    }
+
+    /// Capture the given registers of the current thread, and copy as much of
+    /// the top of its stack as fits, replacing the previous snapshot.
+    ///
+    /// This never allocates or takes locks, so it may be called from signal
+    /// handlers.
+    pub fn capture(&mut self, registers: &FrameRegisters) {
+        self.tid = current_tid();
+        self.len = copy_stack(registers, &mut self.stack);
+        self.registers = Some(registers.clone());
+    }
+
+    /// Capture the registers the current thread was interrupted with, from
+    /// the `ucontext_t` passed to an `SA_SIGINFO` signal handler, and copy
+    /// as much of the top of its stack as fits.
+    ///
+    /// This is unsafe because `ctx` must point to a valid `ucontext_t`.
+    pub unsafe fn capture_from_ucontext(&mut self, ctx: *const ucontext_t) {
+        self.capture(&FrameRegisters::from_ucontext(ctx));
+    }
+
+    /// Empty the snapshot.
+    pub fn clear(&mut self) {
+        self.registers = None;
+        self.len = 0;
+    }
+
+    /// Get the id of the thread the snapshot was captured on.
+    pub fn tid(&self) -> usize {
+        self.tid
+    }
+
+    /// Get the captured registers, or `None` if nothing was captured.
+    pub fn registers(&self) -> Option<&FrameRegisters> {
+        self.registers.as_ref()
+    }
+
+    /// Get a reader for the copy of the top of the stack, starting at the
+    /// captured stack pointer, to walk the snapshot with.
+    pub fn stack(&self) -> reader::SliceMemory {
+        let base = self.registers.as_ref().map_or(0, |r| r.sp().unwrap_or(0));
+        reader::SliceMemory::new(base, &self.stack[..self.len])
+    }
+}
+
+/// Take the lock for stopping threads, preparing the platform the first
+/// time.
+fn lock() -> Result<MutexGuard<'static, bool>> {
+    let mut prepared = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    if !*prepared {
+        platform::prepare()?;
+        *prepared = true;
+    }
+    Ok(prepared)
+}
+
+/// Walk the thread `tid` and call `f` with its frames. The caller must hold
+/// `LOCK`, and `marker` must be the address of a local in the frame of the
+/// public entry point, for when `tid` is the current thread.
+fn walk_locked<F>(tid: usize, current: usize, marker: usize, f: &mut F) -> Result<()>
+where
+    F: FnMut(usize, &[Frame]),
+{
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!("walk_thread", tid).entered();
+
+    let unwinder = process_unwinder();
+    let capacity = unwinder.opts.max_frames.unwrap_or(MAX_FRAMES);
+    let reader = reader::ThisProcessMemory;
+
+    if tid == current {
+        return FrameRegisters::with_current(|registers| {
+            let mut cx = UnwindContext::new();
+            let mut frames = Vec::with_capacity(capacity);
+            collect(unwinder.frames(&mut cx, &reader, registers.clone()), marker, &mut frames);
+            #[cfg(feature = "tracing")]
+            tracing::debug!(frames = frames.len(), "walked stack");
+            f(tid, &frames);
+            Ok(())
+        });
+    }
+
+    let mut cx = UnwindContext::new();
+    let mut frames = Vec::with_capacity(capacity);
+    unsafe {
+        capture(tid, |registers| {
+            collect(unwinder.frames(&mut cx, &reader, registers.clone()), 0, &mut frames);
+        })?;
+    }
+    // Only once the thread is resumed, since subscribers may allocate.
+    #[cfg(feature = "tracing")]
+    tracing::debug!(frames = frames.len(), "walked stack");
+    f(tid, &frames);
+    Ok(())
+}
+
+/// Collect the frames of a walk into `frames`, without growing it, skipping
+/// the frames at or below `marker`.
+fn collect<'w>(walk: Frames<'w, 'static>, marker: usize, frames: &mut Vec<Frame<'w>>) {
+    for frame in walk {
+        let mut frame = match frame {
+            Ok(frame) => frame,
+            Err(_) => break,
+        };
+        if frame.sp().map_or(false, |sp| sp <= marker) {
+            continue;
+        }
+        if frames.len() == frames.capacity() {
+            break;
+        }
+        frame.index = frames.len();
+        frames.push(frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use MemoryReader;
+    use std::sync::mpsc;
+    use std::thread;
+
+    #[test]
+    fn walk_all_threads() {
+        let (tid_tx, tid_rx) = mpsc::channel();
+        let (done_tx, done_rx) = mpsc::channel::<()>();
+        let other = thread::spawn(move || {
+            tid_tx.send(current_tid()).unwrap();
+            let _ = done_rx.recv();
+        });
+        let other_tid = tid_rx.recv().unwrap();
+
+        let mut walked = vec![];
+        walk_all(|tid, frames| walked.push((tid, frames.len()))).unwrap();
+
+        done_tx.send(()).unwrap();
+        other.join().unwrap();
+
+        for &tid in &[current_tid(), other_tid] {
+            match walked.iter().find(|&&(walked_tid, _)| walked_tid == tid) {
+                Some(&(_, frames)) => assert!(frames > 0),
+                None => panic!("thread {} was not walked", tid),
+            }
+        }
+    }
+
+    #[test]
+    fn sample_thread() {
+        let (tid_tx, tid_rx) = mpsc::channel();
+        let (done_tx, done_rx) = mpsc::channel::<()>();
+        let other = thread::spawn(move || {
+            tid_tx.send(current_tid()).unwrap();
+            let _ = done_rx.recv();
+        });
+        let other_tid = tid_rx.recv().unwrap();
+
+        let mut stack = vec![0; 64 * 1024];
+        let sample = sample(other_tid, &mut stack).unwrap();
+        done_tx.send(()).unwrap();
+        other.join().unwrap();
+
+        assert_eq!(sample.tid(), other_tid);
+        let sp = sample.registers().sp().unwrap_or(0);
+        assert!(unsafe { sample.stack().read(sp) }.is_ok());
+
+        let mut cx = UnwindContext::new();
+        let outcome = process_unwinder().walk(&mut cx, sample.stack(), sample.registers(), |_| ());
+        assert!(outcome.frames_walked > 0);
+    }
+
+    #[test]
+    fn stack_snapshot() {
+        let mut snapshot = StackSnapshot::with_capacity(64 * 1024);
+        assert!(snapshot.registers().is_none());
+        FrameRegisters::with_current(|registers| {
+            snapshot.capture(registers);
+            Ok(())
+        }).unwrap();
+        assert_eq!(snapshot.tid(), current_tid());
+
+        let registers = snapshot.registers().unwrap().clone();
+        let mut cx = UnwindContext::new();
+        let outcome = process_unwinder().walk(&mut cx, &snapshot.stack(), &registers, |_| ());
+        assert!(outcome.frames_walked > 0);
+
+        snapshot.clear();
+        assert!(snapshot.registers().is_none());
+    }
+
+    #[test]
+    fn sample_current_thread() {
+        let sample = sample(current_tid(), &mut []).unwrap();
+        assert!(sample.registers().ip().is_valid());
+        assert!(unsafe { sample.stack().read(0) }.is_err());
+    }
+}
@@ -0,0 +1,205 @@
+//! Periodically walking every thread in the process on a background thread.
+
+use super::super::{Error, Frame, Result};
+use super::{current_tid, walk_all};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Configuration for a `Sampler`.
+#[derive(Clone, Debug)]
+pub struct SamplerOptions {
+    frequency: u32,
+    jitter: f64,
+}
+
+impl Default for SamplerOptions {
+    fn default() -> Self {
+        SamplerOptions {
+            frequency: 19,
+            jitter: 0.1,
+        }
+    }
+}
+
+impl SamplerOptions {
+    /// Construct a new `SamplerOptions` with the default configuration: 19
+    /// samples per second, each up to 10% early or late.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Walk every thread this many times per second of wall-clock time.
+    pub fn frequency(&mut self, hz: u32) -> &mut Self {
+        self.frequency = hz.max(1);
+        self
+    }
+
+    /// Move each sample up to this fraction of the period between samples
+    /// earlier or later, at random, so that sampling does not line up with
+    /// the application's own periodic work. The fraction is clamped to
+    /// between 0 and 1.
+    pub fn jitter(&mut self, fraction: f64) -> &mut Self {
+        self.jitter = fraction.max(0.0).min(1.0);
+        self
+    }
+
+    /// Start a thread that walks every other thread's stack at the
+    /// configured frequency, calling `f` with each thread's id, the time it
+    /// was walked, and its frames, outermost last.
+    ///
+    /// Walks are made with `walk_all`, so the threads that `walk_all` would
+    /// skip are skipped. The sampler's own thread is skipped too.
+    ///
+    /// ```
+    /// # fn f() {
+    /// use pancakes::threads::SamplerOptions;
+    ///
+    /// let sampler = SamplerOptions::new()
+    ///     .frequency(49)
+    ///     .start(|tid, _timestamp, frames| {
+    ///         println!("Thread {} has {} frames", tid, frames.len());
+    ///     })
+    ///     .unwrap();
+    /// // ... do some work ...
+    /// sampler.stop();
+    /// # }
+    /// ```
+    pub fn start<F>(&self, mut f: F) -> Result<Sampler>
+    where
+        F: 'static + Send + FnMut(usize, Instant, &[Frame]),
+    {
+        let period = Duration::from_secs(1) / self.frequency;
+        let jitter = self.jitter;
+        let (stop, stopped) = mpsc::channel::<()>();
+        let worker = thread::Builder::new()
+            .name("pancakes-sampler".into())
+            .spawn(move || {
+                let sampler = current_tid();
+                let mut rng = Jitter::new();
+                let mut next = Instant::now();
+                loop {
+                    next += rng.delay(period, jitter);
+                    let now = Instant::now();
+                    if next < now {
+                        // Walking took longer than the period: skip the
+                        // samples that were missed instead of catching up.
+                        next = now;
+                    }
+                    match stopped.recv_timeout(next - now) {
+                        Err(RecvTimeoutError::Timeout) => {}
+                        _ => return,
+                    }
+
+                    let _ = walk_all(|tid, frames| {
+                        if tid != sampler {
+                            f(tid, Instant::now(), frames);
+                        }
+                    });
+                }
+            })
+            .map_err(Error::Io)?;
+
+        Ok(Sampler {
+            stop: Some(stop),
+            worker: Some(worker),
+        })
+    }
+}
+
+/// A running sampler, started with `SamplerOptions::start`. Dropping it stops
+/// sampling.
+#[derive(Debug)]
+pub struct Sampler {
+    stop: Option<Sender<()>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Sampler {
+    /// Stop sampling, waiting for the walk in progress, if any, to finish.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        // Hanging up wakes the sampler thread.
+        drop(self.stop.take());
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// A xorshift generator for jitter, which needs no quality beyond not lining
+/// up with anything periodic.
+struct Jitter {
+    state: u64,
+}
+
+impl Jitter {
+    fn new() -> Jitter {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.subsec_nanos() as u64);
+        Jitter {
+            state: ((nanos << 32) ^ current_tid() as u64) | 1,
+        }
+    }
+
+    /// Get a delay uniformly within `jitter` times `period` of `period`.
+    fn delay(&mut self, period: Duration, jitter: f64) -> Duration {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        // The top 53 bits, as a fraction in [0, 1).
+        let unit = (self.state >> 11) as f64 / (1u64 << 53) as f64;
+        period.mul_f64(1.0 + jitter * (2.0 * unit - 1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn jitter_bounds() {
+        let period = Duration::from_millis(10);
+        let mut rng = Jitter::new();
+        for _ in 0..1000 {
+            let delay = rng.delay(period, 0.25);
+            assert!(delay >= Duration::from_micros(7500), "{:?}", delay);
+            assert!(delay <= Duration::from_micros(12500), "{:?}", delay);
+        }
+        assert_eq!(rng.delay(period, 0.0), period);
+    }
+
+    #[test]
+    fn sample_periodically() {
+        let samples = Arc::new(Mutex::new(vec![]));
+        let sampler = {
+            let samples = samples.clone();
+            SamplerOptions::new()
+                .frequency(100)
+                .start(move |tid, timestamp, frames| {
+                    samples.lock().unwrap().push((tid, timestamp, frames.len()));
+                })
+                .unwrap()
+        };
+        thread::sleep(Duration::from_millis(200));
+        sampler.stop();
+
+        let samples = samples.lock().unwrap();
+        let current = current_tid();
+        let mine: Vec<_> = samples.iter().filter(|sample| sample.0 == current).collect();
+        assert!(mine.len() > 1);
+        assert!(mine.iter().all(|sample| sample.2 > 0));
+        assert!(mine.windows(2).all(|pair| pair[0].1 < pair[1].1));
+    }
+}
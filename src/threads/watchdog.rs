@@ -0,0 +1,265 @@
+//! Dumping every thread's stack from a background thread when the
+//! application stops making progress.
+
+use super::super::{Error, Frame, Result};
+use super::{current_tid, walk_all};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How the application tells a `Watchdog` that it is making progress.
+///
+/// Clones share the same heartbeat, so one can be handed to each thread that
+/// does the work being watched.
+#[derive(Clone, Debug)]
+pub struct Heartbeat {
+    shared: Arc<Beats>,
+}
+
+#[derive(Debug)]
+struct Beats {
+    epoch: Instant,
+    /// The time of the last beat, in nanoseconds since `epoch`.
+    last: AtomicU64,
+}
+
+impl Heartbeat {
+    fn new() -> Heartbeat {
+        Heartbeat {
+            shared: Arc::new(Beats {
+                epoch: Instant::now(),
+                last: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Record that the application is making progress.
+    pub fn beat(&self) {
+        let since = self.shared.epoch.elapsed().as_nanos() as u64;
+        self.shared.last.store(since, Ordering::Relaxed);
+    }
+
+    /// Get how long ago the last beat was.
+    fn stale(&self) -> Duration {
+        let last = Duration::from_nanos(self.shared.last.load(Ordering::Relaxed));
+        self.shared.epoch.elapsed().saturating_sub(last)
+    }
+}
+
+/// Configuration for a `Watchdog`.
+#[derive(Clone, Debug)]
+pub struct WatchdogOptions {
+    timeout: Duration,
+    repeat: Option<Duration>,
+}
+
+impl Default for WatchdogOptions {
+    fn default() -> Self {
+        WatchdogOptions {
+            timeout: Duration::from_secs(10),
+            repeat: None,
+        }
+    }
+}
+
+impl WatchdogOptions {
+    /// Construct a new `WatchdogOptions` with the default configuration: the
+    /// stacks are dumped once when there has been no beat for 10 seconds.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Dump the stacks when there has been no beat for this long.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = timeout.max(Duration::from_millis(1));
+        self
+    }
+
+    /// Dump the stacks again every `interval` for as long as there is still
+    /// no beat, instead of only once per hang.
+    pub fn repeat(&mut self, interval: Duration) -> &mut Self {
+        self.repeat = Some(interval.max(Duration::from_millis(1)));
+        self
+    }
+
+    /// Start a thread that watches the returned watchdog's heartbeat, and
+    /// when it goes stale, walks every other thread's stack, calling `f`
+    /// with how long there has been no beat, each thread's id, and its
+    /// frames, outermost last.
+    ///
+    /// The heartbeat is checked four times per timeout, so a hang is noticed
+    /// up to a quarter of the timeout late. Walks are made with `walk_all`,
+    /// so the threads that `walk_all` would skip are skipped. The watchdog's
+    /// own thread is skipped too.
+    ///
+    /// ```
+    /// # fn f() {
+    /// use pancakes::threads::WatchdogOptions;
+    /// use std::time::Duration;
+    ///
+    /// let watchdog = WatchdogOptions::new()
+    ///     .timeout(Duration::from_secs(5))
+    ///     .repeat(Duration::from_secs(30))
+    ///     .start(|stalled, tid, frames| {
+    ///         eprintln!("No progress for {:?}: thread {} has {} frames",
+    ///                   stalled, tid, frames.len());
+    ///     })
+    ///     .unwrap();
+    /// let heartbeat = watchdog.heartbeat();
+    /// loop {
+    ///     // ... do some work ...
+    ///     heartbeat.beat();
+    /// }
+    /// # }
+    /// ```
+    pub fn start<F>(&self, mut f: F) -> Result<Watchdog>
+    where
+        F: 'static + Send + FnMut(Duration, usize, &[Frame]),
+    {
+        let timeout = self.timeout;
+        let repeat = self.repeat;
+        let heartbeat = Heartbeat::new();
+        let (stop, stopped) = mpsc::channel::<()>();
+        let worker = {
+            let heartbeat = heartbeat.clone();
+            thread::Builder::new()
+                .name("pancakes-watchdog".into())
+                .spawn(move || {
+                    let watchdog = current_tid();
+                    // When the stacks were last dumped in the current hang.
+                    let mut dumped: Option<Instant> = None;
+                    loop {
+                        match stopped.recv_timeout(timeout / 4) {
+                            Err(RecvTimeoutError::Timeout) => {}
+                            _ => return,
+                        }
+
+                        let stalled = heartbeat.stale();
+                        if stalled < timeout {
+                            dumped = None;
+                            continue;
+                        }
+                        let due = match (dumped, repeat) {
+                            (None, _) => true,
+                            (Some(at), Some(interval)) => at.elapsed() >= interval,
+                            (Some(_), None) => false,
+                        };
+                        if !due {
+                            continue;
+                        }
+
+                        dumped = Some(Instant::now());
+                        let _ = walk_all(|tid, frames| {
+                            if tid != watchdog {
+                                f(stalled, tid, frames);
+                            }
+                        });
+                    }
+                })
+                .map_err(Error::Io)?
+        };
+
+        Ok(Watchdog {
+            heartbeat,
+            stop: Some(stop),
+            worker: Some(worker),
+        })
+    }
+}
+
+/// A running watchdog, started with `WatchdogOptions::start`. Dropping it
+/// stops watching.
+#[derive(Debug)]
+pub struct Watchdog {
+    heartbeat: Heartbeat,
+    stop: Option<Sender<()>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    /// Get the heartbeat that this watchdog watches. It starts out as if it
+    /// had just beaten.
+    pub fn heartbeat(&self) -> Heartbeat {
+        self.heartbeat.clone()
+    }
+
+    /// Stop watching, waiting for the dump in progress, if any, to finish.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        // Hanging up wakes the watchdog thread.
+        drop(self.stop.take());
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn dumps(watchdog: &WatchdogOptions, beat: bool) -> Vec<(Duration, usize)> {
+        let dumps = Arc::new(Mutex::new(vec![]));
+        let watchdog = {
+            let dumps = dumps.clone();
+            watchdog
+                .start(move |stalled, tid, frames| {
+                    assert!(!frames.is_empty());
+                    dumps.lock().unwrap().push((stalled, tid));
+                })
+                .unwrap()
+        };
+        let heartbeat = watchdog.heartbeat();
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_millis(300) {
+            if beat {
+                heartbeat.beat();
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        watchdog.stop();
+
+        let current = current_tid();
+        let dumps = dumps.lock().unwrap();
+        dumps
+            .iter()
+            .filter(|dump| dump.1 == current)
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn dump_when_stale() {
+        let mut options = WatchdogOptions::new();
+        options.timeout(Duration::from_millis(50));
+        let once = dumps(&options, false);
+        assert_eq!(once.len(), 1);
+        assert!(once[0].0 >= Duration::from_millis(50));
+
+        options.repeat(Duration::from_millis(50));
+        let repeated = dumps(&options, false);
+        assert!(repeated.len() > 1);
+        assert!(repeated.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[test]
+    fn no_dump_while_beating() {
+        let mut options = WatchdogOptions::new();
+        options
+            .timeout(Duration::from_millis(100))
+            .repeat(Duration::from_millis(10));
+        assert!(dumps(&options, true).is_empty());
+    }
+}
@@ -0,0 +1,215 @@
+//! A disk cache of compiled unwind tables, keyed by build ID.
+//!
+//! Each module's compiled table is stored in `<build id>.unwind` in the cache
+//! directory, as a header followed by the rows encoded by
+//! `CompiledTable::encode_rows`. The header is 32 little-endian bytes:
+//!
+//! | Offset | Type      | Field                                           |
+//! |--------|-----------|-------------------------------------------------|
+//! | 0      | `[u8; 8]` | the magic bytes `PANCAKES`                      |
+//! | 8      | `u32`     | format version, currently 1                     |
+//! | 12     | `u32`     | reserved, zero                                  |
+//! | 16     | `u64`     | length of the module's object file              |
+//! | 24     | `u64`     | the object file's modification time, in seconds |
+//!
+//! A cache file with a different version, or whose object file has since
+//! changed length or modification time, is ignored, and replaced once the
+//! module's table is compiled again. The version must be bumped whenever the
+//! encoding or the compiled rules change.
+
+use super::Bias;
+use build_id;
+use compiled::CompiledTable;
+use object::{self, Object};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const MAGIC: &'static [u8; 8] = b"PANCAKES";
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 32;
+
+/// Identifies a module's compiled table in the cache, and when it is stale.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Key {
+    build_id: Vec<u8>,
+    len: u64,
+    mtime: u64,
+}
+
+impl Key {
+    /// The key for the given object file, read from the given path. Modules
+    /// without a build ID cannot be cached.
+    pub(crate) fn new(path: &Path, file: &object::File) -> Option<Key> {
+        let build_id = match file.build_id() {
+            Ok(Some(build_id)) if !build_id.is_empty() => build_id.to_vec(),
+            _ => return None,
+        };
+        let metadata = fs::metadata(path).ok()?;
+        let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Key {
+            build_id,
+            len: metadata.len(),
+            mtime: mtime.as_secs(),
+        })
+    }
+
+    fn path(&self, directory: &Path) -> PathBuf {
+        directory.join(build_id::to_hex(&self.build_id) + ".unwind")
+    }
+
+    fn header(&self) -> [u8; HEADER_SIZE] {
+        let mut header = [0; HEADER_SIZE];
+        header[..8].copy_from_slice(MAGIC);
+        header[8..12].copy_from_slice(&VERSION.to_le_bytes());
+        header[16..24].copy_from_slice(&self.len.to_le_bytes());
+        header[24..32].copy_from_slice(&self.mtime.to_le_bytes());
+        header
+    }
+}
+
+/// Load the cached table with the given key, for a module with the given
+/// bias.
+pub(crate) fn load(directory: &Path, key: &Key, bias: Bias) -> Option<CompiledTable> {
+    let data = map(&key.path(directory)).ok()?;
+    decode(&data, key, bias)
+}
+
+fn decode(data: &[u8], key: &Key, bias: Bias) -> Option<CompiledTable> {
+    if data.len() < HEADER_SIZE || data[..HEADER_SIZE] != key.header()[..] {
+        return None;
+    }
+    CompiledTable::decode_rows(bias, &data[HEADER_SIZE..])
+}
+
+/// Store the given table in the cache with the given key.
+pub(crate) fn store(directory: &Path, key: &Key, table: &CompiledTable) -> io::Result<()> {
+    let mut data = key.header().to_vec();
+    table.encode_rows(&mut data);
+
+    // Write to a temporary file and rename it into place, so that a
+    // concurrent reader never sees a partial file.
+    fs::create_dir_all(directory)?;
+    let path = key.path(directory);
+    let temporary = path.with_extension(format!("unwind.{}.tmp", ::std::process::id()));
+    fs::write(&temporary, &data)?;
+    fs::rename(&temporary, &path)
+}
+
+/// Map the file at the given path into memory, read-only.
+#[cfg(unix)]
+fn map(path: &Path) -> io::Result<Mapping> {
+    use libc;
+    use std::os::unix::io::AsRawFd;
+    use std::ptr;
+
+    let file = fs::File::open(path)?;
+    let len = file.metadata()?.len() as usize;
+    if len == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "empty cache file"));
+    }
+
+    let ptr = unsafe {
+        libc::mmap(
+            ptr::null_mut(),
+            len,
+            libc::PROT_READ,
+            libc::MAP_PRIVATE,
+            file.as_raw_fd(),
+            0,
+        )
+    };
+    if ptr == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Mapping { ptr, len })
+}
+
+/// A read-only memory mapping of a whole file.
+#[cfg(unix)]
+struct Mapping {
+    ptr: *mut ::libc::c_void,
+    len: usize,
+}
+
+#[cfg(unix)]
+impl ::std::ops::Deref for Mapping {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { ::std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe {
+            ::libc::munmap(self.ptr, self.len);
+        }
+    }
+}
+
+/// Without `mmap`, just read the file.
+#[cfg(not(unix))]
+fn map(path: &Path) -> io::Result<Vec<u8>> {
+    fs::read(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use compiled::{CompiledRow, CompiledRule};
+    use std::env;
+
+    fn key() -> Key {
+        Key {
+            build_id: vec![0xab, 0xcd],
+            len: 1234,
+            mtime: 5678,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let directory = env::temp_dir()
+            .join(format!("pancakes-unwind-cache-{}", ::std::process::id()));
+        let table = CompiledTable::new(
+            Bias(0x1000),
+            &[
+                CompiledRow {
+                    start: 0x10,
+                    end: 0x20,
+                    cfa_register: 7,
+                    cfa_offset: 16,
+                    bp: CompiledRule::SameValue,
+                    ra: CompiledRule::Offset(-8),
+                },
+            ],
+        );
+
+        assert_eq!(load(&directory, &key(), Bias(0x1000)), None);
+        store(&directory, &key(), &table).unwrap();
+        assert_eq!(load(&directory, &key(), Bias(0x1000)), Some(table));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn stale() {
+        let mut data = key().header().to_vec();
+        assert_eq!(
+            decode(&data, &key(), Bias(0x2000)),
+            Some(CompiledTable::new(Bias(0x2000), &[]))
+        );
+
+        let mut changed = key();
+        changed.mtime += 1;
+        assert_eq!(decode(&data, &changed, Bias(0)), None);
+
+        data[8] = 2;
+        assert_eq!(decode(&data, &key(), Bias(0)), None);
+        assert_eq!(decode(&data[..HEADER_SIZE - 1], &key(), Bias(0)), None);
+    }
+}
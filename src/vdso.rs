@@ -0,0 +1,84 @@
+//! Finding the vDSO, the shared object the kernel maps into every Linux
+//! process to implement syscalls like `clock_gettime` without entering the
+//! kernel.
+//!
+//! The vDSO has no file on disk, but the kernel maps its whole ELF image,
+//! section headers included, and passes its address in the auxiliary vector
+//! as `AT_SYSINFO_EHDR`.
+
+use libc;
+use std::slice;
+
+/// The vDSO's ELF image, or `None` if this process has no vDSO.
+pub(crate) fn image() -> Option<&'static [u8]> {
+    let base = unsafe { libc::getauxval(libc::AT_SYSINFO_EHDR) } as usize;
+    if base == 0 {
+        return None;
+    }
+
+    // The ELF header is all we can safely read before knowing the image's
+    // length.
+    let header = unsafe { slice::from_raw_parts(base as *const u8, 64) };
+    let len = image_len(header)?;
+    Some(unsafe { slice::from_raw_parts(base as *const u8, len) })
+}
+
+/// The length of the ELF image with the given header: the end of its section
+/// header table, which the kernel's vDSO linker script places last.
+fn image_len(header: &[u8]) -> Option<usize> {
+    if header.get(..4)? != b"\x7fELF" {
+        return None;
+    }
+
+    // EI_DATA: ELFDATA2LSB or ELFDATA2MSB.
+    let little_endian = match *header.get(5)? {
+        1 => true,
+        2 => false,
+        _ => return None,
+    };
+    let word_at = |offset: usize, size: usize| {
+        let bytes = header.get(offset..offset + size)?;
+        let fold = |word: usize, &b: &u8| word << 8 | b as usize;
+        Some(if little_endian {
+            bytes.iter().rev().fold(0, fold)
+        } else {
+            bytes.iter().fold(0, fold)
+        })
+    };
+    let u16_at = |offset: usize| word_at(offset, 2);
+
+    let (shoff, shentsize, shnum) = match *header.get(4)? {
+        // ELFCLASS32
+        1 => (word_at(32, 4)?, u16_at(46)?, u16_at(48)?),
+        // ELFCLASS64
+        2 => (word_at(40, 8)?, u16_at(58)?, u16_at(60)?),
+        _ => return None,
+    };
+    if shoff == 0 || shnum == 0 {
+        return None;
+    }
+    shoff.checked_add(shentsize * shnum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object::{self, Object};
+
+    #[test]
+    fn vdso_has_eh_frame() {
+        let image = match image() {
+            Some(image) => image,
+            // E.g. a kernel booted with `vdso=0`.
+            None => return,
+        };
+        let file = object::File::parse(image).unwrap();
+        assert!(file.section_by_name(".eh_frame").is_some());
+    }
+
+    #[test]
+    fn not_elf() {
+        assert_eq!(image_len(&[0; 64]), None);
+        assert_eq!(image_len(b"\x7fELF"), None);
+    }
+}
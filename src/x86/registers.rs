@@ -0,0 +1,210 @@
+//! Architecture specific concerns for 32-bit x86 registers.
+
+use super::{Error, MemoryReader, Registers, Result, TaggedWord, TargetEndianBuf};
+use expression;
+#[cfg(feature = "live")]
+use ffi;
+use gimli;
+#[cfg(feature = "live")]
+use std::io;
+#[cfg(feature = "live")]
+use std::mem;
+
+// From the Sys V i386 ABI, table 2.14 DWARF Register Number Mapping:
+//
+// > ...
+// > General Purpose Register ESP    4    %esp
+// > General Purpose Register EBP    5    %ebp
+// > ...
+// > Return Address RA               8
+// > ...
+pub(crate) const BP: u8 = 5;
+pub(crate) const SP: u8 = 4;
+pub(crate) const IP: u8 = 8;
+
+/// x86 has no pointer authentication, so return addresses are never signed.
+pub(crate) const POINTER_AUTH_MASK: usize = 0;
+
+/// The registers needed to unwind a frame on 32-bit x86.
+#[derive(Clone, Debug)]
+pub struct FrameRegisters {
+    /// The `ebp` frame base register for this frame.
+    bp: TaggedWord,
+
+    /// The `esp` stack pointer register for this frame.
+    sp: TaggedWord,
+
+    /// The `eip` instruction pointer register for this frame.
+    ip: TaggedWord,
+}
+
+impl FrameRegisters {
+    /// The name Breakpad symbol files use for the frame base register.
+    pub(crate) const BREAKPAD_BP: &'static str = "$ebp";
+
+    /// Construct a register set from its individual registers.
+    pub(crate) fn from_parts(bp: TaggedWord, sp: TaggedWord, ip: TaggedWord) -> FrameRegisters {
+        FrameRegisters { bp, sp, ip }
+    }
+
+    /// Get the register with the given Breakpad name, e.g. `$esp`.
+    pub(crate) fn breakpad_register(&self, name: &str) -> TaggedWord {
+        match name {
+            "$ebp" => self.bp,
+            "$esp" => self.sp,
+            "$eip" => self.ip,
+            _ => TaggedWord::invalid(),
+        }
+    }
+
+    /// Construct a register set from a `ucontext_t`, such as the one the
+    /// kernel passes to an `SA_SIGINFO` signal handler.
+    ///
+    /// This is unsafe because `ctx` must point to a valid `ucontext_t`.
+    #[cfg(feature = "live")]
+    pub unsafe fn from_ucontext(ctx: *const ffi::ucontext_t) -> FrameRegisters {
+        #[cfg(target_os = "macos")]
+        let (bp, sp, ip) = {
+            let mcontext = (*ctx).uc_mcontext;
+            assert!(!mcontext.is_null());
+            ((*mcontext).__ss.__ebp, (*mcontext).__ss.__esp, (*mcontext).__ss.__eip)
+        };
+
+        #[cfg(not(target_os = "macos"))]
+        let (bp, sp, ip) = {
+            let gregs = &(*ctx).uc_mcontext.gregs;
+            (
+                gregs[ffi::REG_EBP as usize],
+                gregs[ffi::REG_ESP as usize],
+                gregs[ffi::REG_EIP as usize],
+            )
+        };
+
+        FrameRegisters {
+            bp: TaggedWord::valid(bp as usize),
+            sp: TaggedWord::valid(sp as usize),
+            ip: TaggedWord::valid(ip as usize),
+        }
+    }
+
+    pub(crate) fn get_register(&self, register_num: u8) -> Result<TaggedWord> {
+        match register_num {
+            r if r == BP => Ok(self.bp),
+            r if r == SP => Ok(self.sp),
+            r if r == IP => Ok(self.ip),
+            otherwise => Err(Error::UnknownRegister(otherwise)),
+        }
+    }
+
+    pub(crate) fn set_register(&mut self, register_num: u8, value: TaggedWord) -> Result<()> {
+        match register_num {
+            r if r == BP => self.bp = value,
+            r if r == SP => self.sp = value,
+            r if r == IP => self.ip = value,
+            otherwise => return Err(Error::UnknownRegister(otherwise)),
+        }
+        Ok(())
+    }
+
+    unsafe fn eval_register_rule<R>(
+        &self,
+        register: u8,
+        rule: gimli::RegisterRule<TargetEndianBuf>,
+        cfa: usize,
+        scratch: &mut expression::Scratch,
+        reader: &R,
+    ) -> TaggedWord
+    where
+        R: MemoryReader,
+    {
+        match rule {
+            gimli::RegisterRule::Undefined |
+            gimli::RegisterRule::Architectural => TaggedWord::invalid(),
+
+            gimli::RegisterRule::SameValue => self.get_register(register).unwrap_or_default(),
+
+            gimli::RegisterRule::Offset(offset) => reader.read_offset(cfa, offset as isize).into(),
+
+            gimli::RegisterRule::ValOffset(offset) => {
+                TaggedWord::valid((cfa as isize).wrapping_add(offset as isize) as usize)
+            }
+
+            gimli::RegisterRule::Register(r) => self.get_register(r).unwrap_or_default(),
+
+            gimli::RegisterRule::Expression(expr) => {
+                expression::evaluate(expr.buf(), self, Some(cfa), scratch, reader)
+                    .and_then(|addr| reader.read(addr))
+                    .into()
+            }
+            gimli::RegisterRule::ValExpression(expr) => {
+                expression::evaluate(expr.buf(), self, Some(cfa), scratch, reader).into()
+            }
+        }
+    }
+}
+
+impl Registers for FrameRegisters {
+    unsafe fn from_unwind_table_row<R>(
+        row: &gimli::UnwindTableRow<TargetEndianBuf>,
+        return_address_register: u8,
+        scratch: &mut expression::Scratch,
+        old_registers: &FrameRegisters,
+        reader: &R,
+    ) -> Result<Self>
+    where
+        R: MemoryReader,
+    {
+        let cfa = match *row.cfa() {
+            gimli::CfaRule::RegisterAndOffset { register, offset } => {
+                let tagged_word = old_registers.get_register(register)?;
+                let word: Result<_> = tagged_word.into();
+                (word? as isize).wrapping_add(offset as isize) as usize
+            }
+            gimli::CfaRule::Expression(ref expr) => {
+                expression::evaluate(expr.buf(), old_registers, None, scratch, reader)?
+            }
+        };
+
+        // Functions that never touch the frame pointer have no rule for it,
+        // and it is callee-saved, so it still holds the caller's value.
+        let bp = match row.register(BP) {
+            gimli::RegisterRule::Undefined => old_registers.bp,
+            rule => old_registers.eval_register_rule(BP, rule, cfa, scratch, reader),
+        };
+        let ip = old_registers.eval_register_rule(
+            return_address_register,
+            row.register(return_address_register),
+            cfa,
+            scratch,
+            reader,
+        );
+
+        Ok(FrameRegisters {
+            bp,
+            // The CFA is the value of the stack pointer at the call site.
+            sp: TaggedWord::valid(cfa),
+            ip,
+        })
+    }
+
+    #[cfg(feature = "live")]
+    fn with_current<F, T>(mut f: F) -> Result<T>
+    where
+        F: FnMut(&Self) -> Result<T>,
+    {
+        unsafe {
+            let mut context: ffi::ucontext_t = mem::zeroed();
+
+            let r = ffi::getcontext(&mut context);
+            if r != 0 {
+                return Err(Error::Io(io::Error::last_os_error()));
+            }
+
+            f(&FrameRegisters::from_ucontext(&context))
+        }
+    }
+
+    fn bp(&self) -> TaggedWord { self.bp }
+    fn sp(&self) -> TaggedWord { self.sp }
+    fn ip(&self) -> TaggedWord { self.ip }
+}
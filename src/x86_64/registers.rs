@@ -1,12 +1,13 @@
-//! Architecture specific concerns for x86 and x86_64 registers.
-
-// TODO FITZGEN: split this into full unwinding and fast unwinding, with all
-// registers vs the minimal set respectively.
+//! Architecture specific concerns for x86_64 registers.
 
 use super::{Error, MemoryReader, Registers, Result, TaggedWord, TargetEndianBuf};
+use expression::{self, RegisterSource};
+#[cfg(feature = "live")]
 use ffi;
 use gimli;
+#[cfg(all(feature = "live", not(feature = "inline-asm")))]
 use std::io;
+#[cfg(all(feature = "live", not(feature = "inline-asm")))]
 use std::mem;
 
 // From the Sys V x86_64 ABI, figure 3.36 DWARF Register Number
@@ -18,25 +19,122 @@ use std::mem;
 // > ...
 // > Return Address RA               16
 // > ...
-const BP: u8 = 6;
-const SP: u8 = 7;
-const IP: u8 = 16;
+pub(crate) const BP: u8 = 6;
+pub(crate) const SP: u8 = 7;
+pub(crate) const IP: u8 = 16;
+
+/// x86_64 has no pointer authentication, so return addresses are never
+/// signed.
+pub(crate) const POINTER_AUTH_MASK: usize = 0;
+
+// Once a signal handler returns into the trampoline, the stack pointer points
+// at the `struct ucontext` in the kernel's `struct rt_sigframe`. These are the
+// offset of `uc_mcontext` in it, and the indices of the registers in that
+// `struct sigcontext`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const SIGFRAME_MCONTEXT: usize = 40;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const SIGCONTEXT_RBP: usize = 10;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const SIGCONTEXT_RSP: usize = 15;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const SIGCONTEXT_RIP: usize = 16;
 
-/// The registers needed to unwind a frame on x86.
-#[derive(Debug)]
+/// The registers needed to unwind a frame on x86_64.
+#[derive(Clone, Debug)]
 pub struct FrameRegisters {
-    /// The `ebp`/`rbp` frame base register for this frame.
+    /// The `rbp` frame base register for this frame.
     bp: TaggedWord,
 
-    /// The `esp`/`rsp` stack pointer register for this frame.
+    /// The `rsp` stack pointer register for this frame.
     sp: TaggedWord,
 
-    /// The `eip`/`rip` instruction pointer register for this frame.
+    /// The `rip` instruction pointer register for this frame.
     ip: TaggedWord,
 }
 
 impl FrameRegisters {
-    fn get_register(&self, register_num: u8) -> Result<TaggedWord> {
+    /// The name Breakpad symbol files use for the frame base register.
+    pub(crate) const BREAKPAD_BP: &'static str = "$rbp";
+
+    /// Construct a register set from its individual registers.
+    pub(crate) fn from_parts(bp: TaggedWord, sp: TaggedWord, ip: TaggedWord) -> FrameRegisters {
+        FrameRegisters { bp, sp, ip }
+    }
+
+    /// Get the register with the given Breakpad name, e.g. `$rsp`.
+    pub(crate) fn breakpad_register(&self, name: &str) -> TaggedWord {
+        match name {
+            "$rbp" => self.bp,
+            "$rsp" => self.sp,
+            "$rip" => self.ip,
+            _ => TaggedWord::invalid(),
+        }
+    }
+
+    /// Construct a register set from a `ucontext_t`, such as the one the
+    /// kernel passes to an `SA_SIGINFO` signal handler.
+    ///
+    /// This is unsafe because `ctx` must point to a valid `ucontext_t`.
+    #[cfg(feature = "live")]
+    pub unsafe fn from_ucontext(ctx: *const ffi::ucontext_t) -> FrameRegisters {
+        #[cfg(any(target_os = "macos", target_os = "ios"))]
+        let (bp, sp, ip) = {
+            let mcontext = (*ctx).uc_mcontext;
+            assert!(!mcontext.is_null());
+            ((*mcontext).__ss.__rbp, (*mcontext).__ss.__rsp, (*mcontext).__ss.__rip)
+        };
+
+        #[cfg(target_os = "netbsd")]
+        let (bp, sp, ip) = {
+            let gregs = &(*ctx).uc_mcontext.__gregs;
+            (
+                gregs[ffi::_REG_RBP as usize],
+                gregs[ffi::_REG_RSP as usize],
+                gregs[ffi::_REG_RIP as usize],
+            )
+        };
+
+        #[cfg(target_os = "freebsd")]
+        let (bp, sp, ip) = {
+            let mcontext = &(*ctx).uc_mcontext;
+            (mcontext.mc_rbp, mcontext.mc_rsp, mcontext.mc_rip)
+        };
+
+        #[cfg(not(any(
+            target_os = "macos",
+            target_os = "ios",
+            target_os = "netbsd",
+            target_os = "freebsd"
+        )))]
+        let (bp, sp, ip) = {
+            let gregs = &(*ctx).uc_mcontext.gregs;
+            (
+                gregs[ffi::REG_RBP as usize],
+                gregs[ffi::REG_RSP as usize],
+                gregs[ffi::REG_RIP as usize],
+            )
+        };
+
+        FrameRegisters {
+            bp: TaggedWord::valid(bp as usize),
+            sp: TaggedWord::valid(sp as usize),
+            ip: TaggedWord::valid(ip as usize),
+        }
+    }
+
+    /// Construct a register set from a suspended mach thread's state,
+    /// fetched with `thread_get_state`.
+    #[cfg(all(feature = "live", target_os = "macos"))]
+    pub(crate) fn from_thread_state(state: &ffi::thread_state_t) -> FrameRegisters {
+        FrameRegisters {
+            bp: TaggedWord::valid(state.__rbp as usize),
+            sp: TaggedWord::valid(state.__rsp as usize),
+            ip: TaggedWord::valid(state.__rip as usize),
+        }
+    }
+
+    pub(crate) fn get_register(&self, register_num: u8) -> Result<TaggedWord> {
         match register_num {
             r if r == BP => Ok(self.bp),
             r if r == SP => Ok(self.sp),
@@ -45,33 +143,52 @@ impl FrameRegisters {
         }
     }
 
-    unsafe fn eval_register_rule<R>(
-        &self,
-        rule: gimli::RegisterRule<TargetEndianBuf>,
-        cfa: usize,
-        reader: &R,
-    ) -> TaggedWord
-    where
-        R: MemoryReader
-    {
-        match rule {
-            gimli::RegisterRule::Undefined |
-            gimli::RegisterRule::Architectural => TaggedWord::invalid(),
+    pub(crate) fn set_register(&mut self, register_num: u8, value: TaggedWord) -> Result<()> {
+        match register_num {
+            r if r == BP => self.bp = value,
+            r if r == SP => self.sp = value,
+            r if r == IP => self.ip = value,
+            otherwise => return Err(Error::UnknownRegister(otherwise)),
+        }
+        Ok(())
+    }
+}
 
-            gimli::RegisterRule::SameValue => self.bp,
+/// Evaluate the rule for recovering the given register of the caller, from
+/// the callee's registers.
+unsafe fn eval_register_rule<Regs, R>(
+    registers: &Regs,
+    register: u8,
+    rule: gimli::RegisterRule<TargetEndianBuf>,
+    cfa: usize,
+    scratch: &mut expression::Scratch,
+    reader: &R,
+) -> TaggedWord
+where
+    Regs: RegisterSource,
+    R: MemoryReader
+{
+    match rule {
+        gimli::RegisterRule::Undefined |
+        gimli::RegisterRule::Architectural => TaggedWord::invalid(),
 
-            gimli::RegisterRule::Offset(offset) => reader.read_offset(cfa, offset as isize).into(),
+        gimli::RegisterRule::SameValue => registers.register(register).unwrap_or_default(),
 
-            gimli::RegisterRule::ValOffset(offset) => TaggedWord::valid(if offset < 0 {
-                cfa + (-offset as usize)
-            } else {
-                cfa + (offset as usize)
-            }),
+        gimli::RegisterRule::Offset(offset) => reader.read_offset(cfa, offset as isize).into(),
+
+        gimli::RegisterRule::ValOffset(offset) => {
+            TaggedWord::valid((cfa as isize).wrapping_add(offset as isize) as usize)
+        }
 
-            gimli::RegisterRule::Register(r) => self.get_register(r).unwrap_or_default(),
+        gimli::RegisterRule::Register(r) => registers.register(r).unwrap_or_default(),
 
-            gimli::RegisterRule::Expression(_expr) => unimplemented!("TODO FITZGEN"),
-            gimli::RegisterRule::ValExpression(_expr) => unimplemented!("TODO FITZGEN"),
+        gimli::RegisterRule::Expression(expr) => {
+            expression::evaluate(expr.buf(), registers, Some(cfa), scratch, reader)
+                .and_then(|addr| reader.read(addr))
+                .into()
+        }
+        gimli::RegisterRule::ValExpression(expr) => {
+            expression::evaluate(expr.buf(), registers, Some(cfa), scratch, reader).into()
         }
     }
 }
@@ -79,6 +196,8 @@ impl FrameRegisters {
 impl Registers for FrameRegisters {
     unsafe fn from_unwind_table_row<R>(
         row: &gimli::UnwindTableRow<TargetEndianBuf>,
+        return_address_register: u8,
+        scratch: &mut expression::Scratch,
         old_registers: &FrameRegisters,
         reader: &R
     ) -> Result<Self>
@@ -89,57 +208,92 @@ impl Registers for FrameRegisters {
             gimli::CfaRule::RegisterAndOffset { register, offset, } => {
                 let tagged_word = old_registers.get_register(register)?;
                 let word: Result<_> = tagged_word.into();
-                reader.read_offset(word?, offset as isize)?
+                (word? as isize).wrapping_add(offset as isize) as usize
+            }
+            gimli::CfaRule::Expression(ref expr) => {
+                expression::evaluate(expr.buf(), old_registers, None, scratch, reader)?
             }
-            gimli::CfaRule::Expression(_expr) => unimplemented!("TODO FITZGEN"),
         };
 
-        let bp = old_registers.eval_register_rule(row.register(BP), cfa, reader);
-        let sp = old_registers.eval_register_rule(row.register(SP), cfa, reader);
-        let ip = old_registers.eval_register_rule(row.register(IP), cfa, reader);
+        // Functions that never touch the frame pointer have no rule for it,
+        // and it is callee-saved, so it still holds the caller's value.
+        let bp = match row.register(BP) {
+            gimli::RegisterRule::Undefined => old_registers.bp,
+            rule => eval_register_rule(old_registers, BP, rule, cfa, scratch, reader),
+        };
+        let ip = eval_register_rule(
+            old_registers,
+            return_address_register,
+            row.register(return_address_register),
+            cfa,
+            scratch,
+            reader,
+        );
 
         Ok(FrameRegisters {
             bp,
-            sp,
+            // The CFA is the value of the stack pointer at the call site.
+            sp: TaggedWord::valid(cfa),
             ip,
         })
     }
 
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    unsafe fn from_sigframe<R>(registers: &FrameRegisters, reader: &R) -> Option<Result<Self>>
+    where
+        R: MemoryReader
+    {
+        let sp: Result<usize> = registers.sp.into();
+        Some(sp.and_then(|sp| {
+            let mcontext = sp + SIGFRAME_MCONTEXT;
+            let register = |index: usize| reader.read(mcontext + index * 8).map(TaggedWord::valid);
+            Ok(FrameRegisters {
+                bp: register(SIGCONTEXT_RBP)?,
+                sp: register(SIGCONTEXT_RSP)?,
+                ip: register(SIGCONTEXT_RIP)?,
+            })
+        }))
+    }
+
+    #[cfg(feature = "inline-asm")]
     fn with_current<F, T>(mut f: F) -> Result<T>
     where
         F: FnMut(&Self) -> Result<T>
     {
+        let (bp, sp, ip): (usize, usize, usize);
         unsafe {
-            let mut registers: ffi::ucontext_t = mem::zeroed();
+            ::std::arch::asm!(
+                "mov {bp}, rbp",
+                "mov {sp}, rsp",
+                "lea {ip}, [rip]",
+                bp = out(reg) bp,
+                sp = out(reg) sp,
+                ip = out(reg) ip,
+                options(nomem, nostack, preserves_flags),
+            );
+        }
 
-            let r = ffi::getcontext(&mut registers);
+        f(&FrameRegisters {
+            bp: TaggedWord::valid(bp),
+            sp: TaggedWord::valid(sp),
+            ip: TaggedWord::valid(ip),
+        })
+    }
+
+    #[cfg(all(feature = "live", not(feature = "inline-asm")))]
+    fn with_current<F, T>(mut f: F) -> Result<T>
+    where
+        F: FnMut(&Self) -> Result<T>
+    {
+        unsafe {
+            let mut context: ffi::ucontext_t = mem::zeroed();
+
+            let r = ffi::getcontext(&mut context);
             if r != 0 {
                 return Err(Error::Io(io::Error::last_os_error()));
             }
 
-            let registers = if cfg!(target_os = "macos") {
-                assert!(!registers.uc_mcontext.is_null());
-                let bp: u64 = (*registers.uc_mcontext).__ss.__rbp;
-                let sp: u64 = (*registers.uc_mcontext).__ss.__rsp;
-                let ip: u64 = (*registers.uc_mcontext).__ss.__rip;
-
-                debug_assert_eq!(
-                    mem::size_of::<u64>(),
-                    mem::size_of::<usize>(),
-                    "sanity check we didn't mess up configuration or something \
-                     and aren't about to truncate registers"
-                );
-
-                FrameRegisters {
-                    bp: TaggedWord::valid(bp as usize),
-                    sp: TaggedWord::valid(sp as usize),
-                    ip: TaggedWord::valid(ip as usize),
-                }
-            } else {
-                unimplemented!("TODO FITZGEN")
-            };
-
-            f(&registers)
+            f(&FrameRegisters::from_ucontext(&context))
         }
     }
 
@@ -147,3 +301,267 @@ impl Registers for FrameRegisters {
     fn sp(&self) -> TaggedWord { self.sp }
     fn ip(&self) -> TaggedWord { self.ip }
 }
+
+/// The number of registers a `FullRegisters` tracks besides the flags: the
+/// 16 general purpose registers, and the return address.
+const FULL_REGISTER_COUNT: usize = 17;
+
+/// The DWARF register number of `rflags`.
+const RFLAGS: u8 = 49;
+
+/// The DWARF register numbers of the registers in a Linux `struct
+/// sigcontext`, and so in `mcontext_t.gregs`, in the order they are stored.
+/// `eflags` follows them.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const SIGCONTEXT_REGISTERS: [u8; FULL_REGISTER_COUNT] =
+    [8, 9, 10, 11, 12, 13, 14, 15, 5, 4, BP, 3, 1, 0, 2, SP, IP];
+
+/// Whether the System V x86_64 ABI requires functions to preserve the given
+/// register for their caller: `rbx`, `rbp`, and `r12` through `r15`.
+fn is_callee_saved(register: u8) -> bool {
+    match register {
+        3 | 6 | 12..=15 => true,
+        _ => false,
+    }
+}
+
+/// Every general purpose register of a frame on x86_64, and its flags.
+///
+/// Walking a stack only needs `FrameRegisters`, but debuggers and crash
+/// reporters want the callee-saved registers of each frame too. Registers
+/// that a function need not preserve across calls, like `rax`, are unknown in
+/// every frame but the youngest, and ones interrupted by signals.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FullRegisters {
+    /// Indexed by DWARF register number, where 16 is the return address,
+    /// i.e. `rip`.
+    registers: [TaggedWord; FULL_REGISTER_COUNT],
+    rflags: TaggedWord,
+}
+
+impl FullRegisters {
+    /// Get the register with the given DWARF register number, e.g. 3 for
+    /// `rbx`, 16 for `rip`, or 49 for `rflags`.
+    pub fn get(&self, register_num: u8) -> TaggedWord {
+        self.get_register(register_num).unwrap_or_default()
+    }
+
+    /// Set the register with the given DWARF register number.
+    pub fn set(&mut self, register_num: u8, value: TaggedWord) -> Result<()> {
+        match register_num {
+            r if (r as usize) < FULL_REGISTER_COUNT => self.registers[r as usize] = value,
+            RFLAGS => self.rflags = value,
+            otherwise => return Err(Error::UnknownRegister(otherwise)),
+        }
+        Ok(())
+    }
+
+    /// Get the `rflags` register.
+    pub fn rflags(&self) -> TaggedWord {
+        self.rflags
+    }
+
+    /// Get the subset of these registers needed to walk the stack.
+    pub fn frame_registers(&self) -> FrameRegisters {
+        FrameRegisters::from_parts(self.bp(), self.sp(), self.ip())
+    }
+
+    /// Construct a register set from a `ucontext_t`, such as the one the
+    /// kernel passes to an `SA_SIGINFO` signal handler.
+    ///
+    /// This is unsafe because `ctx` must point to a valid `ucontext_t`.
+    #[cfg(all(feature = "live", any(target_os = "linux", target_os = "android")))]
+    pub unsafe fn from_ucontext(ctx: *const ffi::ucontext_t) -> FullRegisters {
+        let gregs = &(*ctx).uc_mcontext.gregs;
+        let mut registers = FullRegisters::unknown();
+        for (index, &register) in SIGCONTEXT_REGISTERS.iter().enumerate() {
+            registers.registers[register as usize] = TaggedWord::valid(gregs[index] as usize);
+        }
+        registers.rflags = TaggedWord::valid(gregs[FULL_REGISTER_COUNT] as usize);
+        registers
+    }
+
+    fn unknown() -> FullRegisters {
+        FullRegisters {
+            registers: [TaggedWord::invalid(); FULL_REGISTER_COUNT],
+            rflags: TaggedWord::invalid(),
+        }
+    }
+
+    fn get_register(&self, register_num: u8) -> Result<TaggedWord> {
+        match register_num {
+            r if (r as usize) < FULL_REGISTER_COUNT => Ok(self.registers[r as usize]),
+            RFLAGS => Ok(self.rflags),
+            otherwise => Err(Error::UnknownRegister(otherwise)),
+        }
+    }
+}
+
+impl From<FrameRegisters> for FullRegisters {
+    /// Every register but the frame's `rbp`, `rsp`, and `rip` is unknown.
+    fn from(frame: FrameRegisters) -> FullRegisters {
+        let mut registers = FullRegisters::unknown();
+        registers.registers[BP as usize] = frame.bp;
+        registers.registers[SP as usize] = frame.sp;
+        registers.registers[IP as usize] = frame.ip;
+        registers
+    }
+}
+
+impl RegisterSource for FullRegisters {
+    fn register(&self, register_num: u8) -> Result<TaggedWord> {
+        self.get_register(register_num)
+    }
+}
+
+impl Registers for FullRegisters {
+    unsafe fn from_unwind_table_row<R>(
+        row: &gimli::UnwindTableRow<TargetEndianBuf>,
+        return_address_register: u8,
+        scratch: &mut expression::Scratch,
+        old_registers: &FullRegisters,
+        reader: &R
+    ) -> Result<Self>
+    where
+        R: MemoryReader
+    {
+        let cfa = match *row.cfa() {
+            gimli::CfaRule::RegisterAndOffset { register, offset, } => {
+                let word: Result<_> = old_registers.get_register(register)?.into();
+                (word? as isize).wrapping_add(offset as isize) as usize
+            }
+            gimli::CfaRule::Expression(ref expr) => {
+                expression::evaluate(expr.buf(), old_registers, None, scratch, reader)?
+            }
+        };
+
+        let mut registers = [TaggedWord::invalid(); FULL_REGISTER_COUNT];
+        for register in 0..FULL_REGISTER_COUNT as u8 {
+            let rule = if register == IP {
+                row.register(return_address_register)
+            } else {
+                row.register(register)
+            };
+            registers[register as usize] = match rule {
+                // The table has no rule for registers that the function
+                // never saves, which the callee-saved ones still hold the
+                // caller's values of.
+                gimli::RegisterRule::Undefined if is_callee_saved(register) => {
+                    old_registers.registers[register as usize]
+                }
+                rule => {
+                    eval_register_rule(old_registers, register, rule, cfa, scratch, reader)
+                }
+            };
+        }
+        // The CFA is the value of the stack pointer at the call site.
+        registers[SP as usize] = TaggedWord::valid(cfa);
+
+        Ok(FullRegisters {
+            registers,
+            // Calls do not preserve the flags.
+            rflags: TaggedWord::invalid(),
+        })
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    unsafe fn from_sigframe<R>(registers: &FullRegisters, reader: &R) -> Option<Result<Self>>
+    where
+        R: MemoryReader
+    {
+        let sp: Result<usize> = registers.sp().into();
+        Some(sp.and_then(|sp| {
+            let mcontext = sp + SIGFRAME_MCONTEXT;
+            let word = |index: usize| reader.read(mcontext + index * 8).map(TaggedWord::valid);
+            let mut interrupted = FullRegisters::unknown();
+            for (index, &register) in SIGCONTEXT_REGISTERS.iter().enumerate() {
+                interrupted.registers[register as usize] = word(index)?;
+            }
+            interrupted.rflags = word(FULL_REGISTER_COUNT)?;
+            Ok(interrupted)
+        }))
+    }
+
+    #[cfg(all(feature = "live", any(target_os = "linux", target_os = "android")))]
+    fn with_current<F, T>(mut f: F) -> Result<T>
+    where
+        F: FnMut(&Self) -> Result<T>
+    {
+        unsafe {
+            let mut context: ffi::ucontext_t = ::std::mem::zeroed();
+
+            let r = ffi::getcontext(&mut context);
+            if r != 0 {
+                return Err(Error::Io(::std::io::Error::last_os_error()));
+            }
+
+            f(&FullRegisters::from_ucontext(&context))
+        }
+    }
+
+    #[cfg(all(feature = "live", not(any(target_os = "linux", target_os = "android"))))]
+    fn with_current<F, T>(mut f: F) -> Result<T>
+    where
+        F: FnMut(&Self) -> Result<T>
+    {
+        FrameRegisters::with_current(|registers| f(&FullRegisters::from(registers.clone())))
+    }
+
+    fn bp(&self) -> TaggedWord { self.registers[BP as usize] }
+    fn sp(&self) -> TaggedWord { self.registers[SP as usize] }
+    fn ip(&self) -> TaggedWord { self.registers[IP as usize] }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reader::SliceMemory;
+
+    #[test]
+    fn full_registers_from_frame_registers() {
+        let frame = FrameRegisters::from_parts(
+            TaggedWord::valid(0x2000),
+            TaggedWord::valid(0x1000),
+            TaggedWord::valid(0x3000),
+        );
+        let full = FullRegisters::from(frame);
+        assert_eq!(full.get(BP), TaggedWord::valid(0x2000));
+        assert_eq!(full.get(SP), TaggedWord::valid(0x1000));
+        assert_eq!(full.get(IP), TaggedWord::valid(0x3000));
+        assert_eq!(full.get(3), TaggedWord::invalid());
+        assert_eq!(full.rflags(), TaggedWord::invalid());
+        assert_eq!(full.get(100), TaggedWord::invalid());
+
+        let frame = full.frame_registers();
+        assert_eq!(frame.ip(), TaggedWord::valid(0x3000));
+    }
+
+    #[test]
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn full_registers_from_sigframe() {
+        let base = 0x1000;
+        // Each saved register's value is its index in the `struct
+        // sigcontext`, plus 0x100.
+        let mut words = vec![0usize; SIGFRAME_MCONTEXT / 8];
+        words.extend((0..FULL_REGISTER_COUNT + 1).map(|index| 0x100 + index));
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_ne_bytes().to_vec()).collect();
+        let reader = SliceMemory::new(base, &bytes);
+
+        let trampoline = FullRegisters::from(FrameRegisters::from_parts(
+            TaggedWord::invalid(),
+            TaggedWord::valid(base),
+            TaggedWord::valid(0x2000),
+        ));
+        let interrupted = unsafe { FullRegisters::from_sigframe(&trampoline, &reader) }
+            .unwrap()
+            .unwrap();
+        // r8
+        assert_eq!(interrupted.get(8), TaggedWord::valid(0x100));
+        // rbx
+        assert_eq!(interrupted.get(3), TaggedWord::valid(0x10b));
+        assert_eq!(interrupted.bp(), TaggedWord::valid(0x10a));
+        assert_eq!(interrupted.sp(), TaggedWord::valid(0x10f));
+        assert_eq!(interrupted.ip(), TaggedWord::valid(0x110));
+        assert_eq!(interrupted.rflags(), TaggedWord::valid(0x111));
+    }
+}
@@ -1,12 +1,19 @@
 extern crate diff;
 extern crate pancakes;
 
+use pancakes::alloc_guard::AllocGuard;
+#[cfg(feature = "live")]
 use pancakes::{FrameRegisters, Options, Registers};
+use std::alloc::System;
 use std::env;
 use std::fs::File;
 use std::io::Read;
 use std::process::Command;
 
+// Walking must not allocate, which `AllocGuard` checks in every walk.
+#[global_allocator]
+static ALLOCATOR: AllocGuard<System> = AllocGuard::new(System);
+
 #[test]
 fn cargo_readme_up_to_date() {
     if env::var("CI").is_ok() {
@@ -46,6 +53,7 @@ fn cargo_readme_up_to_date() {
 }
 
 #[test]
+#[cfg(feature = "live")]
 fn smoke_test_unwind() {
     #[inline(never)]
     fn one(walker: &mut pancakes::Walker) {
@@ -62,9 +70,11 @@ fn smoke_test_unwind() {
         let walk_result = FrameRegisters::with_current(|regs| {
             println!("start regs = {:#?}", regs);
 
-            walker.walk(regs, |frame| {
-                println!("FITZGEN: frame = {:#?}", frame);
-            })
+            walker
+                .walk(regs, |frame| {
+                    println!("FITZGEN: frame = {:#?}", frame);
+                })
+                .into_result()
         });
 
         panic!("temp: {:#?}", walk_result);
@@ -78,3 +88,20 @@ fn smoke_test_unwind() {
 
     one(&mut walker);
 }
+
+#[test]
+#[cfg(feature = "live")]
+fn walking_does_not_allocate() {
+    let mut opts = Options::new();
+    opts.find_eh_frame_entries()
+        .expect("should parse eh_frame entries OK");
+    let mut walker = opts.build();
+
+    let mut frames = 0;
+    FrameRegisters::with_current(|regs| {
+        // `AllocGuard` panics if walking allocates. The callback may.
+        walker.walk(regs, |_| frames += 1);
+        Ok(())
+    }).unwrap();
+    assert!(frames > 0);
+}